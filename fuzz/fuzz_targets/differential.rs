@@ -0,0 +1,67 @@
+//! Replays arbitrary sequences of operations against both a `PrefixTreeMap`
+//! and a `BTreeMap` model, and checks that they behave the same, and that the
+//! tree stays structurally valid.
+//!
+//! Run with `cargo +nightly fuzz run differential` from the root of the repository.
+
+#![no_main]
+
+use arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use pfx::testing::{ModelChecker, Op};
+
+/// Keys are short and drawn from a small alphabet (including the extreme
+/// bytes), so that they share prefixes and split edges in many ways.
+#[derive(Clone, Debug)]
+struct Key(Vec<u8>);
+
+impl<'a> Arbitrary<'a> for Key {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        const ALPHABET: &[u8] = b"abc\0\xff";
+
+        let len = u.int_in_range(0..=8)?;
+        let bytes = (0..len)
+            .map(|_| u.choose(ALPHABET).copied())
+            .collect::<arbitrary::Result<_>>()?;
+
+        Ok(Key(bytes))
+    }
+}
+
+/// The arbitrary counterpart of `Op`.
+#[derive(Clone, Debug, Arbitrary)]
+enum Action {
+    Insert(Key, u16),
+    Remove(Key),
+    Get(Key),
+    ContainsKey(Key),
+    EntryOrInsert(Key, u16),
+    EntryRemove(Key),
+    PrefixIter(Key),
+    ContainsPrefix(Key),
+    Compact,
+}
+
+impl From<Action> for Op<Vec<u8>, u16> {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Insert(Key(key), value) => Op::Insert(key, value),
+            Action::Remove(Key(key)) => Op::Remove(key),
+            Action::Get(Key(key)) => Op::Get(key),
+            Action::ContainsKey(Key(key)) => Op::ContainsKey(key),
+            Action::EntryOrInsert(Key(key), value) => Op::EntryOrInsert(key, value),
+            Action::EntryRemove(Key(key)) => Op::EntryRemove(key),
+            Action::PrefixIter(Key(prefix)) => Op::PrefixIter(prefix),
+            Action::ContainsPrefix(Key(prefix)) => Op::ContainsPrefix(prefix),
+            Action::Compact => Op::Compact,
+        }
+    }
+}
+
+fuzz_target!(|actions: Vec<Action>| {
+    let mut checker = ModelChecker::new();
+
+    for action in actions {
+        checker.apply(action.into());
+    }
+});
@@ -0,0 +1,115 @@
+//! Derive macros for the `pfx` crate. Use them through the `derive` feature of `pfx`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{TokenStream as TokenStream2, Span};
+use quote::{quote, format_ident};
+use syn::{parse_macro_input, parse_quote, DeriveInput, Data, Fields, Index, Error};
+
+
+/// Implements `pfx::key::PrefixKey` for a struct or an enum.
+///
+/// Structs are encoded as the concatenation of their fields, in declaration order.
+/// Enums are encoded as the index of the variant (in declaration order), followed
+/// by the fields of the variant. Consequently, the order of the encoded keys is the
+/// same as the order produced by `#[derive(PartialOrd, Ord)]`, provided that all
+/// fields have order-preserving encodings.
+#[proc_macro_derive(PrefixKey)]
+pub fn derive_prefix_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_prefix_key(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_prefix_key(mut input: DeriveInput) -> Result<TokenStream2, Error> {
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, encode_fields) = destructure_fields(&data.fields);
+            let name = &input.ident;
+
+            quote! {
+                let #name #pattern = self;
+                #encode_fields
+            }
+        }
+        Data::Enum(data) => {
+            let name = &input.ident;
+            let index_ty = if data.variants.len() <= 0x100 {
+                quote!(u8)
+            } else {
+                quote!(u16)
+            };
+
+            let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+                let variant_name = &variant.ident;
+                let (pattern, encode_fields) = destructure_fields(&variant.fields);
+                let index = proc_macro2::Literal::usize_unsuffixed(index);
+
+                quote! {
+                    #name::#variant_name #pattern => {
+                        ::pfx::key::PrefixKey::encode_prefix_key(&(#index as #index_ty), out);
+                        #encode_fields
+                    }
+                }
+            });
+
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "PrefixKey can only be derived for structs and enums",
+            ));
+        }
+    };
+
+    let type_params: Vec<_> = input.generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = input.generics.make_where_clause();
+
+    for param in type_params {
+        where_clause.predicates.push(parse_quote!(#param: ::pfx::key::PrefixKey));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::pfx::key::PrefixKey for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode_prefix_key(&self, out: &mut ::pfx::__private::Vec<u8>) {
+                #body
+            }
+        }
+    })
+}
+
+/// Returns a pattern binding every field to a variable, and the
+/// statements encoding those variables in declaration order.
+fn destructure_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    match fields {
+        Fields::Named(fields) => {
+            let names: Vec<_> = fields.named.iter().map(|field| &field.ident).collect();
+            let pattern = quote!({ #(#names),* });
+            let encode = quote! {
+                #(::pfx::key::PrefixKey::encode_prefix_key(#names, out);)*
+            };
+            (pattern, encode)
+        }
+        Fields::Unnamed(fields) => {
+            let names: Vec<_> = (0..fields.unnamed.len())
+                .map(|i| format_ident!("field_{}", Index::from(i)))
+                .collect();
+            let pattern = quote!(( #(#names),* ));
+            let encode = quote! {
+                #(::pfx::key::PrefixKey::encode_prefix_key(#names, out);)*
+            };
+            (pattern, encode)
+        }
+        Fields::Unit => (TokenStream2::new(), TokenStream2::new()),
+    }
+}
@@ -0,0 +1,61 @@
+use pfx::PrefixTreeSet;
+use pfx::key::PrefixKey;
+
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, PrefixKey)]
+struct Version {
+    major: u16,
+    minor: u16,
+    label: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, PrefixKey)]
+enum Event<T> {
+    Start,
+    Progress(u8, T),
+    Finish { code: i32 },
+}
+
+#[derive(PrefixKey)]
+struct Unit;
+
+#[test]
+fn struct_encoding_preserves_order() {
+    let mut versions = [
+        Version { major: 1, minor: 10, label: None },
+        Version { major: 1, minor: 2, label: Some("beta".into()) },
+        Version { major: 0, minor: 999, label: None },
+        Version { major: 1, minor: 2, label: None },
+        Version { major: 1, minor: 2, label: Some("alpha".into()) },
+        Version { major: 1, minor: 2, label: Some("alpha.1".into()) },
+    ];
+    let set: PrefixTreeSet<Vec<u8>> = versions.iter().map(PrefixKey::to_prefix_key).collect();
+
+    versions.sort();
+
+    let encoded: Vec<_> = versions.iter().map(PrefixKey::to_prefix_key).collect();
+    assert!(set.iter().eq(&encoded));
+
+    // a prefix of the fields encodes to a prefix of the key
+    let prefix = (1_u16, 2_u16).to_prefix_key();
+    assert_eq!(set.prefix_iter(&prefix).count(), 4);
+}
+
+#[test]
+fn enum_encoding_preserves_order() {
+    let mut events = [
+        Event::Finish { code: -1 },
+        Event::Progress(50, "x".to_owned()),
+        Event::Start,
+        Event::Finish { code: 0 },
+        Event::Progress(50, String::new()),
+        Event::Progress(7, "zzz".to_owned()),
+    ];
+    let set: PrefixTreeSet<Vec<u8>> = events.iter().map(PrefixKey::to_prefix_key).collect();
+
+    events.sort();
+
+    let encoded: Vec<_> = events.iter().map(PrefixKey::to_prefix_key).collect();
+    assert!(set.iter().eq(&encoded));
+    assert!(Unit.to_prefix_key().is_empty());
+}
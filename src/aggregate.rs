@@ -0,0 +1,608 @@
+//! Maps maintaining an aggregate of the values under every prefix.
+//!
+//! An [`AggregateMap`] stores, in every node, a user-defined [`Aggregate`]
+//! (e.g. the sum, the minimum, or the maximum) of the values of its subtree,
+//! and keeps it up to date on every insertion and removal. The aggregate of
+//! all values of which the key starts with a given prefix can then be read
+//! at the node of the prefix, without visiting the values themselves.
+//!
+//! ```
+//! # use pfx::aggregate::{AggregateMap, Sum};
+//! let mut sizes = AggregateMap::new(Sum);
+//!
+//! sizes.insert("logs/2024/app.log", 300_u64);
+//! sizes.insert("logs/2024/db.log", 200);
+//! sizes.insert("logs/2025/app.log", 50);
+//! sizes.insert("media/cat.png", 1000);
+//!
+//! assert_eq!(sizes.aggregate_prefix("logs/"), 550);
+//! assert_eq!(sizes.aggregate_prefix("logs/2024/"), 500);
+//! assert_eq!(sizes.aggregate_prefix("tmp/"), 0);
+//! assert_eq!(*sizes.aggregate(), 1550);
+//!
+//! sizes.remove("logs/2024/db.log");
+//! sizes.modify("logs/2025/app.log", |size| *size += 25);
+//! assert_eq!(sizes.aggregate_prefix("logs/"), 375);
+//! ```
+
+use core::mem;
+use core::iter;
+use core::slice;
+use core::ops::Add;
+use core::iter::FusedIterator;
+use core::fmt::{self, Debug, Formatter};
+use alloc::vec::Vec;
+use crate::edge::{Edge, EdgeMut};
+
+
+/// An associative operation with an identity element (a monoid) over values
+/// derived from the values of a map, used for aggregating subtrees of an
+/// [`AggregateMap`].
+///
+/// `combine` must be associative, and `identity` must be its identity
+/// element. The aggregate of a subtree is combined in the order of the keys,
+/// so the operation doesn't need to be commutative.
+pub trait Aggregate<V> {
+    /// The type of the aggregate.
+    type Output: Clone;
+
+    /// The aggregate of no values.
+    fn identity(&self) -> Self::Output;
+
+    /// The aggregate of a single value.
+    fn lift(&self, value: &V) -> Self::Output;
+
+    /// Combines the aggregates of adjacent groups of values.
+    fn combine(&self, left: &Self::Output, right: &Self::Output) -> Self::Output;
+}
+
+/// Aggregates values by adding them up. The aggregate of no values is
+/// `V::default()`, which has to be the zero of addition.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Sum;
+
+impl<V> Aggregate<V> for Sum
+where
+    V: Clone + Default + Add<Output = V>,
+{
+    type Output = V;
+
+    fn identity(&self) -> V {
+        V::default()
+    }
+
+    fn lift(&self, value: &V) -> V {
+        value.clone()
+    }
+
+    fn combine(&self, left: &V, right: &V) -> V {
+        left.clone() + right.clone()
+    }
+}
+
+/// Aggregates values by taking the smallest one, if any.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Min;
+
+impl<V: Clone + Ord> Aggregate<V> for Min {
+    type Output = Option<V>;
+
+    fn identity(&self) -> Option<V> {
+        None
+    }
+
+    fn lift(&self, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(&self, left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(left.min(right).clone()),
+            (left, right) => left.as_ref().or(right.as_ref()).cloned(),
+        }
+    }
+}
+
+/// Aggregates values by taking the largest one, if any.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Max;
+
+impl<V: Clone + Ord> Aggregate<V> for Max {
+    type Output = Option<V>;
+
+    fn identity(&self) -> Option<V> {
+        None
+    }
+
+    fn lift(&self, value: &V) -> Option<V> {
+        Some(value.clone())
+    }
+
+    fn combine(&self, left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(left.max(right).clone()),
+            (left, right) => left.as_ref().or(right.as_ref()).cloned(),
+        }
+    }
+}
+
+/// A map from byte strings to values, maintaining an [`Aggregate`] of the
+/// values under every prefix. See the [module-level documentation](self).
+///
+/// Edges without branching are path-compressed as in [`crate::PrefixTreeMap`],
+/// and every node stores the aggregate of its subtree. Looking up the
+/// aggregate under a prefix takes O(|prefix|) time. Inserting, removing, or
+/// modifying a value updates the aggregates of the nodes along its key,
+/// combining the aggregates of the children of each node, which takes
+/// O(|key| · fanout) time and calls of `combine`.
+///
+/// Values are only accessible through methods which update the aggregates,
+/// so there is no `get_mut()`; use [`AggregateMap::modify`] instead.
+#[derive(Clone)]
+pub struct AggregateMap<K, V, A: Aggregate<V>> {
+    root: AggregateNode<K, V, A::Output>,
+    len: usize,
+    aggregator: A,
+}
+
+impl<K, V, A> Default for AggregateMap<K, V, A>
+where
+    A: Aggregate<V> + Default,
+{
+    fn default() -> Self {
+        AggregateMap::new(A::default())
+    }
+}
+
+impl<K, V, A: Aggregate<V>> AggregateMap<K, V, A> {
+    /// Creates an empty map, aggregating values with `aggregator`.
+    pub fn new(aggregator: A) -> Self {
+        AggregateMap {
+            root: AggregateNode::new(0, Vec::new(), aggregator.identity()),
+            len: 0,
+            aggregator,
+        }
+    }
+
+    /// Returns the aggregator of the values.
+    pub fn aggregator(&self) -> &A {
+        &self.aggregator
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries, retaining the aggregator.
+    pub fn clear(&mut self) {
+        self.root = AggregateNode::new(0, Vec::new(), self.aggregator.identity());
+        self.len = 0;
+    }
+
+    /// Returns the aggregate of all values.
+    pub fn aggregate(&self) -> &A::Output {
+        &self.root.aggregate
+    }
+
+    /// An iterator over pairs of references to keys and values, in
+    /// lexicographic order of the keys.
+    pub fn iter(&self) -> Iter<'_, K, V, A::Output> {
+        Iter::new(&self.root, self.len)
+    }
+
+    /// An iterator over the keys, in lexicographic order.
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> + '_ {
+        self.iter().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values, in lexicographic order of the keys.
+    pub fn values(&self) -> impl ExactSizeIterator<Item = &V> + '_ {
+        self.iter().map(|(_key, value)| value)
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_key_value(key).map(|(_key, value)| value)
+    }
+
+    /// Return references to the key and the value, if found.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let (key, value) = self.root.search(key.as_ref())?.item.as_ref()?;
+        Some((key, value))
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the aggregate of the values of which the key starts with
+    /// `prefix`, which is the identity if there are none.
+    pub fn aggregate_prefix<Q>(&self, prefix: &Q) -> A::Output
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        match self.root.search_prefix(prefix.as_ref()) {
+            Some((node, _rest)) => node.aggregate.clone(),
+            None => self.aggregator.identity(),
+        }
+    }
+
+    /// Inserts a key-value pair, and returns the old value, if any. If the
+    /// key was already present, the old key is kept.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let bytes = key.as_ref().to_vec();
+        let len = &mut self.len;
+
+        self.root.update(&bytes, true, &self.aggregator, |item| match item {
+            Some((_key, old_value)) => Some(mem::replace(old_value, value)),
+            None => {
+                *item = Some((key, value));
+                *len += 1;
+                None
+            }
+        }).flatten()
+    }
+
+    /// Removes the key, returning its value, if found.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.remove_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Removes the key, returning the stored key and its value, if found.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let len = &mut self.len;
+
+        self.root.update(key.as_ref(), false, &self.aggregator, |item| {
+            let item = item.take();
+            *len -= usize::from(item.is_some());
+            item
+        }).flatten()
+    }
+
+    /// Calls `f` with a mutable reference to the value of `key`, if found,
+    /// then updates the aggregates. Returns the result of `f`.
+    ///
+    /// The value is modified in place. If `f` panics, no entry is lost, but
+    /// the aggregates along the key don't reflect the changes made by `f`
+    /// until the value is next updated.
+    pub fn modify<Q, F, R>(&mut self, key: &Q, f: F) -> Option<R>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+        F: FnOnce(&mut V) -> R,
+    {
+        self.root.update(key.as_ref(), false, &self.aggregator, |item| {
+            item.as_mut().map(|(_key, value)| f(value))
+        }).flatten()
+    }
+}
+
+impl<K, V, A> FromIterator<(K, V)> for AggregateMap<K, V, A>
+where
+    K: AsRef<[u8]>,
+    A: Aggregate<V> + Default,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = AggregateMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: AsRef<[u8]>, V, A: Aggregate<V>> Extend<(K, V)> for AggregateMap<K, V, A> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V, A: Aggregate<V>> IntoIterator for &'a AggregateMap<K, V, A> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, A::Output>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Debug, V: Debug, A: Aggregate<V>> Debug for AggregateMap<K, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A node of an [`AggregateMap`], with the edges labelled in the same way
+/// as in [`crate::PrefixTreeMap`]. Apart from the root, every node either
+/// has an item or at least two children.
+#[derive(Clone)]
+pub(crate) struct AggregateNode<K, V, O> {
+    pub(crate) item: Option<(K, V)>,
+    /// The aggregate of the item and the subtrees of the children, in order.
+    pub(crate) aggregate: O,
+    key_fragment: u8,
+    tail: Vec<u8>,
+    /// The children, sorted by their `key_fragment`.
+    pub(crate) children: Vec<AggregateNode<K, V, O>>,
+}
+
+impl<K, V, O> AggregateNode<K, V, O> {
+    pub(crate) const fn new(key_fragment: u8, tail: Vec<u8>, aggregate: O) -> Self {
+        AggregateNode {
+            item: None,
+            aggregate,
+            key_fragment,
+            tail,
+            children: Vec::new(),
+        }
+    }
+
+    fn recompute<A>(&mut self, aggregator: &A)
+    where
+        A: Aggregate<V, Output = O>,
+    {
+        let own = lift_item(&self.item, aggregator);
+
+        self.aggregate = self.children.iter().fold(own, |aggregate, child| {
+            aggregator.combine(&aggregate, &child.aggregate)
+        });
+    }
+}
+
+impl<K, V, O: Clone> AggregateNode<K, V, O> {
+    /// Calls `f` with the item of the node of `key`, then recomputes the
+    /// aggregates along the key, and removes or merges the nodes left
+    /// without items.
+    ///
+    /// Missing nodes are created if `create` is `true`, otherwise `f` isn't
+    /// called and `None` is returned if there is no item under the key.
+    ///
+    /// The item is passed to `f` in place, and the aggregates are only
+    /// recomputed afterwards, from the bottom up. Each node along the key is
+    /// split from its siblings on the way down, so that the aggregates of
+    /// all of them can be updated without recursion, and if `f` or the
+    /// aggregator panics, the tree is left intact (if not up to date).
+    pub(crate) fn update<A, F, R>(&mut self, key: &[u8], create: bool, aggregator: &A, f: F) -> Option<R>
+    where
+        A: Aggregate<V, Output = O>,
+        F: FnOnce(&mut Option<(K, V)>) -> R,
+    {
+        let mut path = Vec::new();
+
+        if create {
+            let mut node = &mut *self;
+            let mut rest = key;
+
+            while !rest.is_empty() {
+                let (index, after) = node.branch(rest, |key_fragment, tail| {
+                    AggregateNode::new(key_fragment, tail, aggregator.identity())
+                });
+
+                path.push(index);
+                node = &mut node.children[index];
+                rest = after;
+            }
+        } else if !self.locate(key, &mut path) {
+            return None;
+        }
+
+        // for each ancestor, its aggregate, item, and the children
+        // before and after the one leading towards the node of `key`
+        let mut ancestors = Vec::with_capacity(path.len());
+        let mut node = &mut *self;
+
+        for &index in &path {
+            let AggregateNode { item, aggregate, children, .. } = node;
+            let (before, rest) = children.split_at_mut(index);
+            let (child, after) = rest.split_first_mut().expect("child along the path");
+
+            ancestors.push((aggregate, &*item, &*before, &*after));
+            node = child;
+        }
+
+        let result = f(&mut node.item);
+
+        node.recompute(aggregator);
+
+        let mut below: &O = &node.aggregate;
+
+        while let Some((aggregate, item, before, after)) = ancestors.pop() {
+            let own = lift_item(item, aggregator);
+            let children = before
+                .iter()
+                .map(|child| &child.aggregate)
+                .chain(iter::once(below))
+                .chain(after.iter().map(|child| &child.aggregate));
+
+            *aggregate = children.fold(own, |acc, child| aggregator.combine(&acc, child));
+            below = aggregate;
+        }
+
+        self.tidy_at(&path);
+
+        Some(result)
+    }
+}
+
+/// Returns the aggregate of the item of a node alone.
+fn lift_item<K, V, A: Aggregate<V>>(item: &Option<(K, V)>, aggregator: &A) -> A::Output {
+    match item {
+        Some((_key, value)) => aggregator.lift(value),
+        None => aggregator.identity(),
+    }
+}
+
+impl<K, V, O> Edge for AggregateNode<K, V, O> {
+    type Symbol = u8;
+    type Child = Self;
+
+    fn key_fragment(&self) -> u8 {
+        self.key_fragment
+    }
+
+    fn tail(&self) -> &[u8] {
+        &self.tail
+    }
+
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
+
+    fn has_item(&self) -> bool {
+        self.item.is_some()
+    }
+}
+
+impl<K, V, O: Clone> EdgeMut for AggregateNode<K, V, O> {
+    fn tail_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.tail
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Self {
+        &mut self.children[index]
+    }
+
+    fn insert_child(&mut self, index: usize, child: Self) {
+        self.children.insert(index, child);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children.remove(index);
+    }
+
+    /// The aggregate of the subtree doesn't change, so it's copied down.
+    fn push_down(&mut self, key_fragment: u8, tail: Vec<u8>) {
+        let lower = AggregateNode {
+            item: self.item.take(),
+            aggregate: self.aggregate.clone(),
+            key_fragment,
+            tail,
+            children: mem::take(&mut self.children),
+        };
+
+        self.children.push(lower);
+    }
+
+    /// Without an item of its own, the aggregate of this node is that of
+    /// its only child, so it's moved up as well.
+    fn pull_up(&mut self) -> (u8, Vec<u8>) {
+        let mut child = self.children.pop().expect("no child to pull up");
+
+        self.item = child.item.take();
+        self.aggregate = child.aggregate.clone();
+        self.children = mem::take(&mut child.children);
+
+        (child.key_fragment, mem::take(&mut child.tail))
+    }
+}
+
+impl<K, V, O> Drop for AggregateNode<K, V, O> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// Iterator over the entries of an [`AggregateMap`], in lexicographic order
+/// of the keys. Created by [`AggregateMap::iter`].
+pub struct Iter<'a, K, V, O> {
+    /// The node to be visited next.
+    node: Option<&'a AggregateNode<K, V, O>>,
+    /// The remaining siblings of the nodes visited so far.
+    stack: Vec<slice::Iter<'a, AggregateNode<K, V, O>>>,
+    len: usize,
+}
+
+impl<'a, K, V, O> Iter<'a, K, V, O> {
+    /// Iterates over the subtree of `root`, which contains `len` items.
+    pub(crate) fn new(root: &'a AggregateNode<K, V, O>, len: usize) -> Self {
+        Iter {
+            node: Some(root),
+            stack: Vec::new(),
+            len,
+        }
+    }
+}
+
+impl<K, V, O> Clone for Iter<'_, K, V, O> {
+    fn clone(&self) -> Self {
+        Iter {
+            node: self.node,
+            stack: self.stack.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug, O> Debug for Iter<'_, K, V, O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V, O> Iterator for Iter<'a, K, V, O> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.node.take() {
+                self.stack.push(node.children.iter());
+
+                if let Some((key, value)) = node.item.as_ref() {
+                    self.len -= 1;
+                    return Some((key, value));
+                }
+            }
+
+            let siblings = self.stack.last_mut()?;
+
+            match siblings.next() {
+                Some(node) => self.node = Some(node),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V, O> ExactSizeIterator for Iter<'_, K, V, O> {}
+
+impl<K, V, O> FusedIterator for Iter<'_, K, V, O> {}
@@ -0,0 +1,201 @@
+//! Conversion to and from [Apache Arrow](https://arrow.apache.org/) arrays.
+//!
+//! Keys are exported as `Binary` (or `Utf8`) arrays, in the sorted order of
+//! the tree. Values are converted by a user-supplied function, since they
+//! can be of any type. Importing accepts `Binary`, `LargeBinary`, `Utf8` and
+//! `LargeUtf8` key columns.
+//!
+//! ```
+//! # use std::sync::Arc;
+//! # use arrow_array::{Array, ArrayRef, UInt32Array};
+//! # use pfx::PrefixTreeMap;
+//! let map = PrefixTreeMap::from([(b"b".to_vec(), 2_u32), (b"a".to_vec(), 1)]);
+//!
+//! let batch = map.to_record_batch(|values| {
+//!     Arc::new(values.copied().collect::<UInt32Array>()) as ArrayRef
+//! })?;
+//! assert_eq!(batch.num_rows(), 2);
+//!
+//! let column = batch.column_by_name("value").unwrap().clone();
+//! let values = column.as_any().downcast_ref::<UInt32Array>().unwrap();
+//! let copy = PrefixTreeMap::<Vec<u8>, u32>::from_record_batch(&batch, "key", |row| Ok(values.value(row)))?;
+//!
+//! assert_eq!(copy, map);
+//! # Ok::<(), arrow_schema::ArrowError>(())
+//! ```
+
+use std::sync::Arc;
+use std::str;
+use arrow_array::{
+    Array, ArrayRef, RecordBatch,
+    BinaryArray, LargeBinaryArray, StringArray, LargeStringArray,
+};
+use arrow_schema::ArrowError;
+use crate::map::{PrefixTreeMap, Values};
+use crate::set::PrefixTreeSet;
+
+
+impl<K: AsRef<[u8]>, V> PrefixTreeMap<K, V> {
+    /// Returns the keys as a `Binary` array, in lexicographic order.
+    pub fn keys_to_arrow(&self) -> BinaryArray {
+        BinaryArray::from_iter_values(self.keys())
+    }
+
+    /// Returns the keys as a `Utf8` array, in lexicographic order.
+    /// Fails if any of the keys is not valid UTF-8.
+    pub fn keys_to_arrow_utf8(&self) -> Result<StringArray, ArrowError> {
+        self.keys()
+            .map(|key| {
+                str::from_utf8(key.as_ref())
+                    .map(Some)
+                    .map_err(|error| ArrowError::InvalidArgumentError(error.to_string()))
+            })
+            .collect()
+    }
+
+    /// Exports the map as a record batch with two columns: `key`, a `Binary`
+    /// array of the keys in lexicographic order, and `value`, the array
+    /// returned by `values`, which is called with the values in the same order.
+    ///
+    /// Fails if `values` returns an array of the wrong length.
+    pub fn to_record_batch<F>(&self, values: F) -> Result<RecordBatch, ArrowError>
+    where
+        F: FnOnce(Values<'_, K, V>) -> ArrayRef,
+    {
+        let keys: ArrayRef = Arc::new(self.keys_to_arrow());
+        let values = values(self.values());
+
+        RecordBatch::try_from_iter([("key", keys), ("value", values)])
+    }
+}
+
+impl<K, V> PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]> + for<'a> From<&'a [u8]>,
+{
+    /// Builds a map from the rows of a record batch. The keys are taken from
+    /// the column named `key_column`, and the value of each row is computed
+    /// by calling `value` with the index of the row.
+    ///
+    /// Fails if the key column is missing, has an unsupported type, or
+    /// contains nulls, or if `value` returns an error.
+    pub fn from_record_batch<F>(
+        batch: &RecordBatch,
+        key_column: &str,
+        mut value: F,
+    ) -> Result<Self, ArrowError>
+    where
+        F: FnMut(usize) -> Result<V, ArrowError>,
+    {
+        let column = batch.column_by_name(key_column).ok_or_else(|| {
+            ArrowError::SchemaError(format!("no column named `{key_column}`"))
+        })?;
+
+        let mut map = PrefixTreeMap::new();
+
+        for (row, key) in byte_strings(column.as_ref())?.into_iter().enumerate() {
+            map.insert(K::from(key), value(row)?);
+        }
+
+        Ok(map)
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Returns the items as a `Binary` array, in lexicographic order.
+    pub fn to_arrow(&self) -> BinaryArray {
+        BinaryArray::from_iter_values(self)
+    }
+
+    /// Builds a set from a `Binary`, `LargeBinary`, `Utf8` or `LargeUtf8` array.
+    /// Fails if the array has any other type, or if it contains nulls.
+    pub fn from_arrow(array: &dyn Array) -> Result<Self, ArrowError>
+    where
+        T: for<'a> From<&'a [u8]>,
+    {
+        Ok(byte_strings(array)?.into_iter().map(T::from).collect())
+    }
+}
+
+/// Returns the elements of a binary or string array as byte slices.
+fn byte_strings(array: &dyn Array) -> Result<Vec<&[u8]>, ArrowError> {
+    if array.null_count() > 0 {
+        return Err(ArrowError::InvalidArgumentError(
+            String::from("keys must not be null")
+        ));
+    }
+
+    let array = array.as_any();
+
+    let strings = if let Some(array) = array.downcast_ref::<BinaryArray>() {
+        array.iter().flatten().collect()
+    } else if let Some(array) = array.downcast_ref::<LargeBinaryArray>() {
+        array.iter().flatten().collect()
+    } else if let Some(array) = array.downcast_ref::<StringArray>() {
+        array.iter().flatten().map(str::as_bytes).collect()
+    } else if let Some(array) = array.downcast_ref::<LargeStringArray>() {
+        array.iter().flatten().map(str::as_bytes).collect()
+    } else {
+        return Err(ArrowError::SchemaError(String::from(
+            "keys must be of type Binary, LargeBinary, Utf8, or LargeUtf8"
+        )));
+    };
+
+    Ok(strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Int64Array;
+    use super::*;
+
+    #[test]
+    fn map_roundtrip() {
+        let map = PrefixTreeMap::from([
+            (b"zeta".to_vec(), -3_i64),
+            (b"alpha".to_vec(), 1),
+            (b"al".to_vec(), 2),
+        ]);
+
+        assert!(map.keys_to_arrow().iter().eq([Some(&b"al"[..]), Some(b"alpha"), Some(b"zeta")]));
+        assert!(map.keys_to_arrow_utf8().unwrap().iter().eq([Some("al"), Some("alpha"), Some("zeta")]));
+
+        let batch = map.to_record_batch(|values| {
+            Arc::new(values.copied().collect::<Int64Array>())
+        }).unwrap();
+        let values = batch.column(1).as_any().downcast_ref::<Int64Array>().unwrap();
+        let copy: PrefixTreeMap<Vec<u8>, i64> = PrefixTreeMap::from_record_batch(
+            &batch,
+            "key",
+            |row| Ok(values.value(row)),
+        ).unwrap();
+
+        assert_eq!(copy, map);
+        assert!(PrefixTreeMap::<Vec<u8>, i64>::from_record_batch(&batch, "nope", |_| Ok(0)).is_err());
+
+        // wrong number of values
+        assert!(map.to_record_batch(|_| Arc::new(Int64Array::from(vec![1]))).is_err());
+
+        let bad = PrefixTreeMap::from([(vec![0xff], ())]);
+        assert!(bad.keys_to_arrow_utf8().is_err());
+    }
+
+    #[test]
+    fn set_roundtrip() {
+        let set = PrefixTreeSet::from([b"b".to_vec(), b"a".to_vec(), b"".to_vec()]);
+        let array = set.to_arrow();
+
+        assert_eq!(PrefixTreeSet::<Vec<u8>>::from_arrow(&array).unwrap(), set);
+
+        let strings = StringArray::from(vec!["x", "y", "x"]);
+        let set = PrefixTreeSet::<Vec<u8>>::from_arrow(&strings).unwrap();
+        assert_eq!(set.len(), 2);
+
+        let large = LargeBinaryArray::from_iter_values([b"q"]);
+        assert!(PrefixTreeSet::<Vec<u8>>::from_arrow(&large).unwrap().contains("q"));
+
+        let nulls = StringArray::from(vec![Some("x"), None]);
+        assert!(PrefixTreeSet::<Vec<u8>>::from_arrow(&nulls).is_err());
+        assert!(PrefixTreeSet::<Vec<u8>>::from_arrow(&Int64Array::from(vec![1])).is_err());
+    }
+}
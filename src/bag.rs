@@ -0,0 +1,236 @@
+//! A multiset, counting the occurrences of byte string items.
+//!
+//! ```
+//! # use pfx::bag::PrefixTreeBag;
+//! let text = b"abracadabra";
+//! let bigrams: PrefixTreeBag<&[u8]> = text.windows(2).collect();
+//!
+//! assert_eq!(bigrams.count(b"ab"), 2);
+//! assert_eq!(bigrams.count(b"ca"), 1);
+//! assert_eq!(bigrams.count(b"zz"), 0);
+//! assert_eq!(bigrams.len(), 7);
+//! assert_eq!(bigrams.total(), 10);
+//!
+//! let starting_with_a: Vec<_> = bigrams.prefix_iter(b"a").collect();
+//! assert_eq!(starting_with_a, [(&&b"ab"[..], 2), (&&b"ac"[..], 1), (&&b"ad"[..], 1)]);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use crate::map::{self, PrefixTreeMap};
+
+
+/// A multiset (bag) of byte strings, storing each distinct item once, along
+/// with the number of its occurrences. See the [module-level documentation](self).
+///
+/// Iteration proceeds in lexicographic order of the distinct items, which
+/// are yielded with their count. Counts are never zero: an item is removed
+/// together with its last occurrence.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PrefixTreeBag<T> {
+    /// The count of each distinct item.
+    map: PrefixTreeMap<T, u64>,
+    /// The sum of the counts.
+    total: u64,
+}
+
+impl<T> Default for PrefixTreeBag<T> {
+    fn default() -> Self {
+        PrefixTreeBag::new()
+    }
+}
+
+impl<T> PrefixTreeBag<T> {
+    /// Creates an empty bag. The same as `Default`.
+    pub const fn new() -> Self {
+        PrefixTreeBag {
+            map: PrefixTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Returns the number of distinct items.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns the total number of occurrences of all items.
+    pub const fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns `true` if and only if the bag contains no items.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all items.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.total = 0;
+    }
+
+    /// An iterator over the distinct items and their counts.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&T, u64)> + ExactSizeIterator + '_ {
+        self.map.iter().map(|(item, &count)| (item, count))
+    }
+
+    /// An iterator over the distinct items.
+    pub fn items(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_ {
+        self.map.keys()
+    }
+
+    /// Returns a reference to the underlying map from items to counts.
+    pub fn as_map(&self) -> &PrefixTreeMap<T, u64> {
+        &self.map
+    }
+
+    /// Returns the underlying map from items to counts.
+    pub fn into_map(self) -> PrefixTreeMap<T, u64> {
+        self.map
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeBag<T> {
+    /// Returns the number of occurrences of `item`, which is 0 if it's not
+    /// in the bag.
+    pub fn count<Q>(&self, item: &Q) -> u64
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get(item).copied().unwrap_or(0)
+    }
+
+    /// Returns `true` if and only if `item` occurs in the bag.
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_key(item)
+    }
+
+    /// Adds an occurrence of `item`, returning its new count.
+    ///
+    /// If the item was already in the bag, the existing item is kept.
+    pub fn insert(&mut self, item: T) -> u64 {
+        self.insert_many(item, 1)
+    }
+
+    /// Adds `n` occurrences of `item`, returning its new count.
+    ///
+    /// If the item was already in the bag, the existing item is kept.
+    /// Adding 0 occurrences doesn't insert the item.
+    ///
+    /// # Panics
+    ///
+    /// If the count of the item or the total count overflows `u64`.
+    pub fn insert_many(&mut self, item: T, n: u64) -> u64 {
+        if n == 0 {
+            return self.count(&item);
+        }
+
+        self.total = self.total.checked_add(n).expect("total count overflow");
+
+        let count = self.map.entry(item).or_insert(0);
+        *count += n;
+        *count
+    }
+
+    /// Removes an occurrence of `item`. Returns `true` if it was found.
+    pub fn remove<Q>(&mut self, item: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.remove_many(item, 1) > 0
+    }
+
+    /// Removes up to `n` occurrences of `item`, and the item itself if none
+    /// are left. Returns the number of occurrences actually removed.
+    pub fn remove_many<Q>(&mut self, item: &Q, n: u64) -> u64
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let Some(count) = self.map.get_mut(item) else {
+            return 0;
+        };
+
+        let removed = n.min(*count);
+
+        *count -= removed;
+        self.total -= removed;
+
+        if *count == 0 {
+            self.map.remove(item);
+        }
+
+        removed
+    }
+
+    /// Removes all occurrences of `item`, returning their number.
+    pub fn remove_all<Q>(&mut self, item: &Q) -> u64
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let count = self.map.remove(item).unwrap_or(0);
+        self.total -= count;
+        count
+    }
+
+    /// An iterator over the distinct items starting with `prefix`, and
+    /// their counts.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = (&T, u64)> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix_iter(prefix).map(|(item, &count)| (item, count))
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for PrefixTreeBag<T> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>
+    {
+        let mut bag = PrefixTreeBag::new();
+        bag.extend(iter);
+        bag
+    }
+}
+
+/// Counts every occurrence of each item.
+impl<T: AsRef<[u8]>> Extend<T> for PrefixTreeBag<T> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>
+    {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+/// Adds the given number of occurrences of each item.
+impl<T: AsRef<[u8]>> Extend<(T, u64)> for PrefixTreeBag<T> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (T, u64)>
+    {
+        for (item, n) in iter {
+            self.insert_many(item, n);
+        }
+    }
+}
+
+impl<T> IntoIterator for PrefixTreeBag<T> {
+    type Item = (T, u64);
+    type IntoIter = map::IntoIter<T, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<T: Debug> Debug for PrefixTreeBag<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
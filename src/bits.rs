@@ -0,0 +1,234 @@
+//! A map keyed by strings of bits, for longest-prefix matching.
+//!
+//! This is the classic data structure of IP routing tables: keys are network
+//! prefixes such as `10.0.0.0/8`, of which only the first `bit_len` bits are
+//! significant, and lookups find the longest prefix matching a full address.
+//!
+//! ```
+//! # use pfx::bits::BitPrefixTreeMap;
+//! let mut routes = BitPrefixTreeMap::new();
+//!
+//! routes.insert(&[0, 0, 0, 0], 0, "default");
+//! routes.insert(&[10, 0, 0, 0], 8, "internal");
+//! routes.insert(&[10, 1, 0, 0], 16, "lab");
+//!
+//! assert_eq!(routes.longest_prefix_match(&[10, 1, 2, 3]), Some((16, &"lab")));
+//! assert_eq!(routes.longest_prefix_match(&[10, 2, 0, 1]), Some((8, &"internal")));
+//! assert_eq!(routes.longest_prefix_match(&[192, 168, 0, 1]), Some((0, &"default")));
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use alloc::vec::Vec;
+use crate::map::{PrefixTreeMap, Label, Labeling, Packing, Tail};
+
+
+/// A map from bit strings (given as a byte string and a number of bits) to
+/// arbitrary values.
+///
+/// The tree branches on single bits, while its path compression keeps runs
+/// of bits without branching on a single edge. The prefixes are stored with
+/// their bits packed into bytes, and queries are read bit by bit in place.
+/// The labels of the edges, however, hold one bit per byte, like all labels
+/// of the tree hold one symbol per byte, so that they can be compared and
+/// split without shifting.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitPrefixTreeMap<V> {
+    /// The labels of this map are bits, one per byte, so it must only be
+    /// accessed through the methods taking a `Label`.
+    map: PrefixTreeMap<Bits, V>,
+}
+
+impl<V> Default for BitPrefixTreeMap<V> {
+    fn default() -> Self {
+        BitPrefixTreeMap::new()
+    }
+}
+
+impl<V> BitPrefixTreeMap<V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        BitPrefixTreeMap { map: PrefixTreeMap::new() }
+    }
+
+    /// Returns the number of entries (prefix-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts the value under the first `bit_len` bits of `bytes` (most
+    /// significant bit first). The rest of the bits are ignored. Returns
+    /// the old value, if any.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is shorter than `bit_len` bits.
+    pub fn insert(&mut self, bytes: &[u8], bit_len: usize, value: V) -> Option<V> {
+        self.map.insert_by::<BitLabel<'_>>(Bits::new(bytes, bit_len), value)
+    }
+
+    /// Returns the value stored under exactly the first `bit_len` bits of
+    /// `bytes`, if any.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is shorter than `bit_len` bits.
+    pub fn get(&self, bytes: &[u8], bit_len: usize) -> Option<&V> {
+        self.map
+            .get_entry_by(BitLabel::new(bytes, bit_len))
+            .map(|(_key, value)| value)
+    }
+
+    /// Returns a mutable reference to the value stored under exactly the
+    /// first `bit_len` bits of `bytes`, if any.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is shorter than `bit_len` bits.
+    pub fn get_mut(&mut self, bytes: &[u8], bit_len: usize) -> Option<&mut V> {
+        self.map.get_mut_by(BitLabel::new(bytes, bit_len))
+    }
+
+    /// Removes the value stored under exactly the first `bit_len` bits of
+    /// `bytes`, and returns it, if any.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is shorter than `bit_len` bits.
+    pub fn remove(&mut self, bytes: &[u8], bit_len: usize) -> Option<V> {
+        self.map
+            .remove_entry_by(BitLabel::new(bytes, bit_len))
+            .map(|(_key, value)| value)
+    }
+
+    /// Finds the longest prefix in the map matching `address` (all of whose
+    /// bits are significant), and returns its length in bits along with its
+    /// value. This takes time proportional to the length of `address`, in a
+    /// single pass without allocating.
+    pub fn longest_prefix_match(&self, address: &[u8]) -> Option<(usize, &V)> {
+        self.matches(address).last()
+    }
+
+    /// An iterator over all the prefixes in the map matching `address`, as
+    /// pairs of their length in bits and their value, from the shortest to
+    /// the longest one. The bits of `address` are read in place as the tree
+    /// is walked down.
+    pub fn matches<'a, 'b>(&'a self, address: &'b [u8]) -> impl Iterator<Item = (usize, &'a V)> + 'b
+    where
+        'a: 'b,
+    {
+        self.map
+            .prefixes_of_by(BitLabel::new(address, address.len() * 8))
+            .map(|(key, value)| (key.len, value))
+    }
+
+    /// An iterator over the prefixes and the corresponding values. Each
+    /// prefix is returned as the significant bits packed into bytes (padded
+    /// with zero bits), along with the number of bits.
+    ///
+    /// Iteration proceeds in lexicographic order of the bit strings, i.e.
+    /// shorter prefixes come before their extensions.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, usize, &V)> + '_ {
+        self.map.iter().map(|(key, value)| (key.bytes.clone(), key.len, value))
+    }
+}
+
+impl<V: Debug> Debug for BitPrefixTreeMap<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.map.iter())
+            .finish()
+    }
+}
+
+/// A string of bits, packed into bytes, most significant bit first. The
+/// padding bits of the last byte are zero, so that equal strings of bits
+/// are stored identically.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn new(bytes: &[u8], bit_len: usize) -> Self {
+        assert!(bit_len <= bytes.len() * 8, "{bit_len} bits out of range for {} bytes", bytes.len());
+
+        let mut bytes = bytes[..bit_len.div_ceil(8)].to_vec();
+
+        if let Some(last) = bytes.last_mut().filter(|_| bit_len % 8 != 0) {
+            *last &= 0xff << (8 - bit_len % 8);
+        }
+
+        Bits { bytes, len: bit_len }
+    }
+}
+
+/// Formats the prefix in the usual `bytes/length` notation, with the bytes
+/// in hexadecimal.
+impl Debug for Bits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in &self.bytes {
+            write!(f, "{byte:02x}")?;
+        }
+        write!(f, "/{}", self.len)
+    }
+}
+
+/// The first `end` bits of a byte string, from the `start`-th one on, most
+/// significant bit first, read in place.
+#[derive(Clone, Copy)]
+struct BitLabel<'a> {
+    bytes: &'a [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'a> BitLabel<'a> {
+    fn new(bytes: &'a [u8], bit_len: usize) -> Self {
+        assert!(bit_len <= bytes.len() * 8, "{bit_len} bits out of range for {} bytes", bytes.len());
+
+        BitLabel { bytes, start: 0, end: bit_len }
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        self.bytes[index / 8] >> (7 - index % 8) & 1
+    }
+}
+
+impl Label for BitLabel<'_> {
+    fn next_symbol(self) -> Option<(u8, Self)> {
+        (self.start < self.end).then(|| (self.get(self.start), self.skip(1)))
+    }
+
+    fn symbol_count(self) -> usize {
+        self.end - self.start
+    }
+
+    fn common_prefix_len(self, tail: Tail<'_>) -> usize {
+        (self.start..self.end)
+            .zip(tail.bytes)
+            .take_while(|&(index, &bit)| self.get(index) == bit)
+            .count()
+    }
+
+    fn skip(self, len: usize) -> Self {
+        BitLabel { start: self.start + len, ..self }
+    }
+
+    fn to_tail(self) -> (Vec<u8>, Packing) {
+        ((self.start..self.end).map(|index| self.get(index)).collect(), Packing::Bytes)
+    }
+}
+
+impl Labeling<Bits> for BitLabel<'_> {
+    type Label<'a> = BitLabel<'a>;
+
+    fn label(key: &Bits) -> BitLabel<'_> {
+        BitLabel::new(&key.bytes, key.len)
+    }
+}
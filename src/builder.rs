@@ -0,0 +1,189 @@
+//! Configurable construction of maps and sets.
+
+use core::fmt::{self, Display, Formatter};
+use crate::map::PrefixTreeMap;
+use crate::set::PrefixTreeSet;
+use crate::interned::InternedMap;
+
+
+/// Creates maps and sets with additional, non-default configuration.
+///
+/// Currently, this allows installing key validation rules, which are checked
+/// before any key is inserted. The fallible insertion methods (e.g.
+/// [`PrefixTreeMap::try_insert`] and [`PrefixTreeMap::try_entry`]) report
+/// violations as an [`InvalidKey`] error, while the infallible ones panic.
+///
+/// ```
+/// # use pfx::{Builder, PrefixTreeMap};
+/// let mut map: PrefixTreeMap<&str, u32> = Builder::new()
+///     .max_key_len(8)
+///     .allowed_bytes(|b| b.is_ascii_lowercase())
+///     .build_map();
+///
+/// assert!(map.try_insert("valid", 1).is_ok());
+/// assert!(map.try_insert("Invalid", 2).is_err());
+/// assert!(map.try_insert("waytoolong", 3).is_err());
+/// assert_eq!(map.len(), 1);
+/// ```
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Builder {
+    validator: KeyValidator,
+}
+
+impl Builder {
+    /// Creates a builder with the default configuration, which accepts all keys.
+    pub const fn new() -> Self {
+        Builder { validator: KeyValidator::new() }
+    }
+
+    /// Rejects keys longer than `max_len` bytes.
+    pub const fn max_key_len(mut self, max_len: usize) -> Self {
+        self.validator.max_len = Some(max_len);
+        self
+    }
+
+    /// Rejects keys containing a NUL (zero) byte.
+    pub const fn reject_nul(mut self) -> Self {
+        self.validator.reject_nul = true;
+        self
+    }
+
+    /// Rejects keys that are not valid UTF-8.
+    pub const fn require_utf8(mut self) -> Self {
+        self.validator.require_utf8 = true;
+        self
+    }
+
+    /// Rejects keys containing any byte for which `is_allowed` returns `false`.
+    pub const fn allowed_bytes(mut self, is_allowed: fn(u8) -> bool) -> Self {
+        self.validator.is_allowed = Some(is_allowed);
+        self
+    }
+
+    /// Creates an empty map with this configuration.
+    pub fn build_map<K, V>(self) -> PrefixTreeMap<K, V> {
+        PrefixTreeMap::with_validator(self.validator.into_option())
+    }
+
+    /// Creates an empty set with this configuration.
+    pub fn build_set<T>(self) -> PrefixTreeSet<T> {
+        PrefixTreeSet::from_map(self.build_map())
+    }
+
+    /// Creates an empty map with value interning and this configuration.
+    pub fn build_interned_map<K, V>(self) -> InternedMap<K, V> {
+        InternedMap::from_map(self.build_map())
+    }
+}
+
+/// The set of rules checked against keys before insertion.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct KeyValidator {
+    max_len: Option<usize>,
+    reject_nul: bool,
+    require_utf8: bool,
+    is_allowed: Option<fn(u8) -> bool>,
+}
+
+impl KeyValidator {
+    const fn new() -> Self {
+        KeyValidator {
+            max_len: None,
+            reject_nul: false,
+            require_utf8: false,
+            is_allowed: None,
+        }
+    }
+
+    /// Returns `None` if the validator accepts every key, so that
+    /// maps without validation rules don't pay for checking them.
+    fn into_option(self) -> Option<Self> {
+        let is_trivial = self.max_len.is_none()
+            && !self.reject_nul
+            && !self.require_utf8
+            && self.is_allowed.is_none();
+
+        if is_trivial { None } else { Some(self) }
+    }
+
+    pub(crate) fn validate(&self, key: &[u8]) -> Result<(), InvalidKey> {
+        if let Some(max_len) = self.max_len {
+            if key.len() > max_len {
+                return Err(InvalidKey::TooLong { len: key.len(), max_len });
+            }
+        }
+
+        if self.reject_nul {
+            if let Some(index) = key.iter().position(|&b| b == 0) {
+                return Err(InvalidKey::ContainsNul { index });
+            }
+        }
+
+        if self.require_utf8 {
+            if let Err(error) = core::str::from_utf8(key) {
+                return Err(InvalidKey::NotUtf8 { valid_up_to: error.valid_up_to() });
+            }
+        }
+
+        if let Some(is_allowed) = self.is_allowed {
+            if let Some(index) = key.iter().position(|&b| !is_allowed(b)) {
+                return Err(InvalidKey::ForbiddenByte { byte: key[index], index });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The reason why a key was rejected by the validation rules of a map or set.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InvalidKey {
+    /// The key was longer than the configured maximum length.
+    TooLong {
+        len: usize,
+        max_len: usize,
+    },
+    /// The key contained a NUL byte at the given index.
+    ContainsNul {
+        index: usize,
+    },
+    /// The key was not valid UTF-8; the first `valid_up_to` bytes were valid.
+    NotUtf8 {
+        valid_up_to: usize,
+    },
+    /// The key contained a byte not permitted by the configured predicate.
+    ForbiddenByte {
+        byte: u8,
+        index: usize,
+    },
+    /// The key was inserted through a [`SubTrieMut`](crate::SubTrieMut), but
+    /// it didn't start with its prefix; the first `index` bytes matched.
+    OutsidePrefix {
+        index: usize,
+    },
+}
+
+impl Display for InvalidKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            InvalidKey::TooLong { len, max_len } => {
+                write!(f, "key of length {len} exceeds maximum length {max_len}")
+            }
+            InvalidKey::ContainsNul { index } => {
+                write!(f, "key contains NUL byte at index {index}")
+            }
+            InvalidKey::NotUtf8 { valid_up_to } => {
+                write!(f, "key is not valid UTF-8 after byte {valid_up_to}")
+            }
+            InvalidKey::ForbiddenByte { byte, index } => {
+                write!(f, "key contains forbidden byte {byte:#04x} at index {index}")
+            }
+            InvalidKey::OutsidePrefix { index } => {
+                write!(f, "key differs from the required prefix at index {index}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidKey {}
@@ -0,0 +1,375 @@
+//! Minimal acyclic automata, for compact storage of large word lists.
+//!
+//! A prefix tree shares common prefixes, but every word still has its own
+//! path for the rest of its bytes, so common suffixes (e.g. inflections) are
+//! stored over and over again. A [`Dafsa`] (deterministic acyclic finite
+//! state automaton) merges all equivalent subtrees as well, which typically
+//! makes it a fraction of the size of the tree. The price is that it can only
+//! store a set of byte strings (not values), and it's immutable.
+//!
+//! ```
+//! # use pfx::PrefixTreeSet;
+//! let set = PrefixTreeSet::from(["tap", "taps", "top", "tops"]);
+//! let dafsa = set.minimize();
+//!
+//! // "t" -> {"a", "o"} -> "p" -> accepting state -> "s" -> accepting state
+//! assert_eq!(dafsa.state_count(), 5);
+//!
+//! assert!(dafsa.contains("tops"));
+//! assert!(!dafsa.contains("to"));
+//! assert_eq!(dafsa.count_prefix("ta"), 2);
+//! assert_eq!(dafsa.prefix_iter("to").collect::<Vec<_>>(), [b"top".to_vec(), b"tops".to_vec()]);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::iter;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::collections::BTreeMap;
+use crate::set::PrefixTreeSet;
+
+
+/// A minimal deterministic acyclic finite state automaton, recognizing a set
+/// of byte strings. Created by [`PrefixTreeSet::minimize()`] or collected
+/// from an iterator.
+///
+/// The automaton is minimal, so equal sets result in equal automata.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Dafsa {
+    /// The states in topological order, so the initial state is at index 0.
+    states: Box<[State]>,
+    /// The labels of all transitions, grouped by source state, each group
+    /// sorted in ascending order.
+    labels: Box<[u8]>,
+    /// The target states of all transitions, parallel to `labels`.
+    targets: Box<[u32]>,
+}
+
+impl Dafsa {
+    /// Returns the number of words in the set.
+    pub fn len(&self) -> usize {
+        self.states[0].count
+    }
+
+    /// Returns `true` if and only if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of states of the automaton.
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns the number of transitions of the automaton.
+    pub fn transition_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns `true` if the word is in the set, `false` otherwise.
+    pub fn contains<Q>(&self, word: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.walk(word.as_ref()).is_some_and(|state| self.states[state].is_final)
+    }
+
+    /// Returns the number of words starting with `prefix`. This takes time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.walk(prefix.as_ref()).map_or(0, |state| self.states[state].count)
+    }
+
+    /// An iterator over the words, in lexicographic order. Words are not
+    /// stored as such, so they are reconstructed during iteration.
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.prefix_iter(&[])
+    }
+
+    /// An iterator over the words starting with `prefix`, in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl Iterator<Item = Vec<u8>> + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+        let mut word = prefix.to_vec();
+
+        // along with each state, the length of the word leading to its
+        // predecessor and the label of the transition from the predecessor,
+        // or `None` for the state reached by the prefix
+        let mut stack: Vec<(usize, Option<(usize, u8)>)> = self
+            .walk(prefix)
+            .map(|state| (state, None))
+            .into_iter()
+            .collect();
+
+        iter::from_fn(move || {
+            while let Some((state, transition)) = stack.pop() {
+                if let Some((len, label)) = transition {
+                    word.truncate(len);
+                    word.push(label);
+                }
+
+                let len = word.len();
+                let edges = self.states[state].edges();
+
+                stack.extend(
+                    self.labels[edges.clone()]
+                        .iter()
+                        .zip(&self.targets[edges])
+                        .rev()
+                        .map(|(&label, &target)| (target as usize, Some((len, label))))
+                );
+
+                if self.states[state].is_final {
+                    return Some(word.clone());
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Follows the transitions along `bytes`, returning the state reached.
+    fn walk(&self, bytes: &[u8]) -> Option<usize> {
+        bytes.iter().try_fold(0, |state, &byte| {
+            let edges = self.states[state].edges();
+            let offset = self.labels[edges.clone()].binary_search(&byte).ok()?;
+
+            Some(self.targets[edges.start + offset] as usize)
+        })
+    }
+
+    /// Builds the automaton from words sorted in strictly ascending order.
+    fn from_sorted<I>(words: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut builder = Builder::default();
+
+        for word in words {
+            builder.insert(word.as_ref());
+        }
+
+        builder.finish()
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for Dafsa {
+    /// Collects the words, in any order and possibly with duplicates.
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>
+    {
+        let mut words: Vec<T> = iter.into_iter().collect();
+
+        words.sort_unstable_by(|lhs, rhs| lhs.as_ref().cmp(rhs.as_ref()));
+        words.dedup_by(|lhs, rhs| lhs.as_ref() == rhs.as_ref());
+
+        Dafsa::from_sorted(words)
+    }
+}
+
+impl Default for Dafsa {
+    fn default() -> Self {
+        Dafsa::from_sorted(iter::empty::<&[u8]>())
+    }
+}
+
+/// Words are formatted as strings, with invalid UTF-8 replaced.
+impl Debug for Dafsa {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.iter().map(|word| String::from_utf8_lossy(&word).into_owned()))
+            .finish()
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Builds the minimal automaton recognizing exactly the items of this set.
+    ///
+    /// This takes time proportional to the total length of the items, times
+    /// a logarithmic factor.
+    pub fn minimize(&self) -> Dafsa {
+        Dafsa::from_sorted(self.iter())
+    }
+}
+
+/// A state of the final automaton.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct State {
+    edges_start: u32,
+    edges_end: u32,
+    /// The number of words accepted starting from this state.
+    count: usize,
+    is_final: bool,
+}
+
+impl State {
+    fn edges(&self) -> core::ops::Range<usize> {
+        self.edges_start as usize..self.edges_end as usize
+    }
+}
+
+/// A state during construction. Since minimization proceeds bottom-up, the
+/// targets of the transitions of a state are already minimized by the time
+/// it is compared against the others, so equivalent states are equal.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct BuilderState {
+    is_final: bool,
+    edges: Vec<(u8, u32)>,
+}
+
+/// Incremental construction of the minimal automaton from sorted words, after
+/// Daciuk et al., "Incremental Construction of Minimal Acyclic Finite-State
+/// Automata" (2000).
+struct Builder {
+    states: Vec<BuilderState>,
+    /// The minimized states, by their contents.
+    register: BTreeMap<BuilderState, u32>,
+    /// The path of the previous word below its longest prefix shared with
+    /// an earlier word, which is not minimized yet, as transitions (source,
+    /// target). The target is always the last transition of the source.
+    unchecked: Vec<(u32, u32)>,
+    previous: Vec<u8>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            states: Vec::from([BuilderState::default()]),
+            register: BTreeMap::new(),
+            unchecked: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+}
+
+impl Builder {
+    fn insert(&mut self, word: &[u8]) {
+        debug_assert!(self.states.len() == 1 || word > self.previous.as_slice(), "words out of order");
+
+        let common_len = word.iter().zip(&self.previous).take_while(|(x, y)| x == y).count();
+
+        self.minimize(common_len);
+
+        let mut state = self.unchecked.last().map_or(0, |&(_source, target)| target);
+
+        for &byte in &word[common_len..] {
+            let target = to_index(self.states.len());
+
+            self.states.push(BuilderState::default());
+            self.states[state as usize].edges.push((byte, target));
+            self.unchecked.push((state, target));
+
+            state = target;
+        }
+
+        self.states[state as usize].is_final = true;
+        self.previous.clear();
+        self.previous.extend_from_slice(word);
+    }
+
+    /// Replaces the unchecked states below the first `len` ones with their
+    /// registered equivalents, or registers them if there are none.
+    fn minimize(&mut self, len: usize) {
+        while self.unchecked.len() > len {
+            let Some((source, target)) = self.unchecked.pop() else {
+                break;
+            };
+            let state = &self.states[target as usize];
+
+            match self.register.get(state) {
+                Some(&equivalent) => {
+                    let edge = self.states[source as usize].edges.last_mut().expect("unchecked transition");
+                    edge.1 = equivalent;
+                }
+                None => {
+                    self.register.insert(state.clone(), target);
+                }
+            }
+        }
+    }
+
+    /// Minimizes the rest of the automaton, then lays out the reachable
+    /// states in topological order, dropping the ones replaced by an equivalent.
+    fn finish(mut self) -> Dafsa {
+        self.minimize(0);
+
+        // post-order traversal, so that the successors of each state are
+        // numbered before it, and their counts are known
+        let mut post_order = Vec::new();
+        let mut visited = alloc::vec![false; self.states.len()];
+        let mut stack = Vec::from([(0_u32, 0_usize)]);
+        visited[0] = true;
+
+        while let Some((state, next)) = stack.last_mut() {
+            match self.states[*state as usize].edges.get(*next) {
+                Some(&(_label, target)) => {
+                    *next += 1;
+
+                    if !visited[target as usize] {
+                        visited[target as usize] = true;
+                        stack.push((target, 0));
+                    }
+                }
+                None => {
+                    post_order.push(*state);
+                    stack.pop();
+                }
+            }
+        }
+
+        // the initial state is last in post-order, and gets index 0
+        let mut new_ids = alloc::vec![None; self.states.len()];
+
+        for (id, &state) in post_order.iter().rev().enumerate() {
+            new_ids[state as usize] = Some(to_index(id));
+        }
+
+        let mut counts = alloc::vec![0; self.states.len()];
+
+        for &state in &post_order {
+            let old = &self.states[state as usize];
+            counts[state as usize] = usize::from(old.is_final)
+                + old.edges.iter().map(|&(_label, target)| counts[target as usize]).sum::<usize>();
+        }
+
+        let mut states = Vec::with_capacity(post_order.len());
+        let mut labels = Vec::new();
+        let mut targets = Vec::new();
+
+        // states are emitted in the order of their new indices
+        for &state in post_order.iter().rev() {
+            let old = &self.states[state as usize];
+            let edges_start = to_index(labels.len());
+
+            for &(label, target) in &old.edges {
+                labels.push(label);
+                targets.push(new_ids[target as usize].expect("reachable state"));
+            }
+
+            states.push(State {
+                edges_start,
+                edges_end: to_index(labels.len()),
+                count: counts[state as usize],
+                is_final: old.is_final,
+            });
+        }
+
+        Dafsa {
+            states: states.into_boxed_slice(),
+            labels: labels.into_boxed_slice(),
+            targets: targets.into_boxed_slice(),
+        }
+    }
+}
+
+fn to_index(index: usize) -> u32 {
+    u32::try_from(index).expect("automaton too large")
+}
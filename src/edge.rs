@@ -0,0 +1,239 @@
+//! The labelled edges of path-compressed trees.
+//!
+//! All trees in this crate label their edges in the same way: the edge
+//! leading to a node is labelled with its `key_fragment`, by which the parent
+//! finds the node among its children, followed by the rest of the label, its
+//! `tail`. A new leaf stores the whole unexplored suffix of its key in the
+//! tail, and the edge is only split when another key diverges inside it.
+//!
+//! The [`Edge`] and [`EdgeMut`] traits implement splitting and merging such
+//! edges, and following them down the tree, once for every kind of node.
+//! The walks are iterative, so that they work for arbitrarily deep trees.
+
+use core::borrow::Borrow;
+use alloc::vec::Vec;
+
+
+/// Returns the number of leading symbols which `lhs` and `rhs` have in common.
+pub(crate) fn common_prefix_len<S: PartialEq>(lhs: &[S], rhs: &[S]) -> usize {
+    lhs.iter()
+        .zip(rhs)
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+/// A node of a path-compressed tree, labelled by the edge leading to it.
+/// The label of the root is meaningless.
+pub(crate) trait Edge: Sized {
+    /// The symbols on which the tree branches.
+    type Symbol: Ord + Copy;
+
+    /// The children as stored by a node, i.e. either the nodes themselves,
+    /// or pointers to nodes shared with other trees.
+    type Child: Borrow<Self>;
+
+    fn key_fragment(&self) -> Self::Symbol;
+
+    fn tail(&self) -> &[Self::Symbol];
+
+    /// The children, sorted by their `key_fragment`.
+    fn children(&self) -> &[Self::Child];
+
+    /// Returns `true` if the node stores an item of its own, i.e. if it would
+    /// have to be kept even without children.
+    fn has_item(&self) -> bool;
+
+    /// Returns the position of the child starting with `symbol` if found, or
+    /// the position where it would have to be inserted otherwise.
+    fn child_index(&self, symbol: Self::Symbol) -> Result<usize, usize> {
+        self.children().binary_search_by_key(&symbol, |child| child.borrow().key_fragment())
+    }
+
+    /// Returns the position of the child starting with `symbol`, if any.
+    fn find_child(&self, symbol: Self::Symbol) -> Option<usize> {
+        self.child_index(symbol).ok()
+    }
+
+    fn child(&self, symbol: Self::Symbol) -> Option<&Self> {
+        let index = self.find_child(symbol)?;
+        Some(self.children()[index].borrow())
+    }
+
+    /// Follows the edges labelled with `symbols`, and returns the node at the
+    /// end of them, if `symbols` ends exactly at a node.
+    fn search(&self, symbols: &[Self::Symbol]) -> Option<&Self> {
+        let mut node = self;
+        let mut symbols = symbols;
+
+        while let Some((&symbol, rest)) = symbols.split_first() {
+            node = node.child(symbol)?;
+            symbols = rest.strip_prefix(node.tail())?;
+        }
+
+        Some(node)
+    }
+
+    /// Finds the topmost node under which all labels start with `symbols`.
+    /// Returns it along with the rest of its label after `symbols`, which is
+    /// only non-empty if `symbols` ends inside the edge leading to the node.
+    fn search_prefix(&self, symbols: &[Self::Symbol]) -> Option<(&Self, &[Self::Symbol])> {
+        let mut node = self;
+        let mut symbols = symbols;
+
+        while let Some((&symbol, rest)) = symbols.split_first() {
+            node = node.child(symbol)?;
+
+            match rest.strip_prefix(node.tail()) {
+                Some(rest) => symbols = rest,
+                None => {
+                    let tail = node.tail().strip_prefix(rest)?;
+                    return Some((node, tail));
+                }
+            }
+        }
+
+        Some((node, &[]))
+    }
+
+    /// Follows the edges labelled with `symbols`, pushing the indices of the
+    /// children taken onto `path`. Returns `true` if `symbols` ends exactly
+    /// at a node with an item, which is then found by following `path`.
+    fn locate(&self, symbols: &[Self::Symbol], path: &mut Vec<usize>) -> bool {
+        let mut node = self;
+        let mut symbols = symbols;
+
+        while let Some((&symbol, rest)) = symbols.split_first() {
+            let Some(index) = node.find_child(symbol) else {
+                return false;
+            };
+
+            node = node.children()[index].borrow();
+            path.push(index);
+
+            let Some(rest) = rest.strip_prefix(node.tail()) else {
+                return false;
+            };
+
+            symbols = rest;
+        }
+
+        node.has_item()
+    }
+}
+
+/// Structural changes to the nodes of a path-compressed tree.
+pub(crate) trait EdgeMut: Edge {
+    fn tail_mut(&mut self) -> &mut Vec<Self::Symbol>;
+
+    /// Returns the child at `index`, which must exist.
+    fn child_mut(&mut self, index: usize) -> &mut Self;
+
+    fn insert_child(&mut self, index: usize, child: Self);
+
+    fn remove_child(&mut self, index: usize);
+
+    /// Moves the contents of this node (its item, its children, and anything
+    /// derived from them) to a new, only child with the given label.
+    fn push_down(&mut self, key_fragment: Self::Symbol, tail: Vec<Self::Symbol>);
+
+    /// The inverse of `push_down()`: replaces the contents of this node by
+    /// those of its only child, and returns the label of the child.
+    fn pull_up(&mut self) -> (Self::Symbol, Vec<Self::Symbol>);
+
+    /// Follows the child indices in `path`, starting from this node.
+    fn descendant_mut(&mut self, path: &[usize]) -> &mut Self {
+        path.iter().fold(self, |node, &index| node.child_mut(index))
+    }
+
+    /// Truncates the label of the edge leading to `self` after `at` symbols
+    /// of its tail, and moves the contents of `self` to a new child node,
+    /// labelled with the rest of the tail.
+    fn split_edge(&mut self, at: usize) {
+        let mut suffix = self.tail_mut().split_off(at);
+        let key_fragment = suffix.remove(0);
+        self.push_down(key_fragment, suffix);
+    }
+
+    /// The inverse of `split_edge()`: appends the label of the only child
+    /// to the tail of `self`, and moves up the contents of the child.
+    fn merge_only_child(&mut self) {
+        let (key_fragment, tail) = self.pull_up();
+        let own = self.tail_mut();
+
+        own.push(key_fragment);
+        own.extend(tail);
+    }
+
+    /// Merges this node with its only child, for as long as it has no item
+    /// of its own. Must not be called on the root, which has no edge leading
+    /// to it.
+    fn compress(&mut self) {
+        while !self.has_item() && self.children().len() == 1 {
+            self.merge_only_child();
+        }
+    }
+
+    /// Removes the child at `index` if it became empty, or compresses it
+    /// otherwise. Returns `true` if the child was removed.
+    fn tidy_child(&mut self, index: usize) -> bool {
+        let child = self.child_mut(index);
+
+        if !child.has_item() && child.children().is_empty() {
+            self.remove_child(index);
+            true
+        } else {
+            child.compress();
+            false
+        }
+    }
+
+    /// Removes or merges the node found by following the child indices in
+    /// `path`, as well as its parent. After removing an item below the
+    /// parent, these are the only nodes which can be left empty or with a
+    /// single child, since every node above them still has another child or
+    /// an item of its own.
+    fn tidy_at(&mut self, path: &[usize]) {
+        match path {
+            [] => {}
+            [index] => {
+                self.tidy_child(*index);
+            }
+            [init @ .., parent_index, index] => {
+                let grandparent = self.descendant_mut(init);
+                grandparent.child_mut(*parent_index).tidy_child(*index);
+                grandparent.tidy_child(*parent_index);
+            }
+        }
+    }
+
+    /// Makes way for `symbols`, which must not be empty, below this node:
+    /// creates the child starting with its first symbol by calling `leaf`
+    /// with the label of the child, or splits the edge leading to the
+    /// existing child where `symbols` diverges from (or ends inside) it.
+    ///
+    /// Returns the index of the child, the label of which is then a prefix
+    /// of `symbols`, and the rest of `symbols` after that label.
+    fn branch<'s, F>(&mut self, symbols: &'s [Self::Symbol], leaf: F) -> (usize, &'s [Self::Symbol])
+    where
+        F: FnOnce(Self::Symbol, Vec<Self::Symbol>) -> Self,
+    {
+        let (&symbol, rest) = symbols.split_first().expect("no symbols to branch on");
+
+        match self.child_index(symbol) {
+            Ok(index) => {
+                let child = self.child_mut(index);
+                let common_len = common_prefix_len(child.tail(), rest);
+
+                if common_len < child.tail().len() {
+                    child.split_edge(common_len);
+                }
+
+                (index, &rest[common_len..])
+            }
+            Err(index) => {
+                self.insert_child(index, leaf(symbol, rest.to_vec()));
+                (index, &[])
+            }
+        }
+    }
+}
@@ -0,0 +1,639 @@
+//! Read-only prefix trees with a succinct memory layout.
+//!
+//! A [`PrefixTreeMap`] allocates every node separately, and every node owns
+//! two vectors. Once a map is fully built, [`PrefixTreeMap::freeze()`] turns
+//! it into a [`FrozenPrefixTreeMap`], which encodes the shape of the tree as
+//! a LOUDS (level-order unary degree sequence) bit vector instead, taking 2
+//! bits per node, and stores the rest in a few flat arrays:
+//!
+//! * the first bytes of the labels of the nodes, in breadth-first order;
+//! * the tails of all edge labels, concatenated into a single byte string,
+//!   delimited by another bit vector;
+//! * the entries, in lexicographic order, so that every subtree corresponds
+//!   to a contiguous range of entries, and the start of that range for each
+//!   node.
+//!
+//! Children and labels are found by rank and select queries on the bit
+//! vectors, which take constant and logarithmic time, respectively.
+//!
+//! A frozen map never changes, so it can be shared across threads by simply
+//! wrapping it in an `Arc`. Use [`FrozenPrefixTreeMap::thaw()`] to get back
+//! a mutable map.
+//!
+//! ```
+//! # use pfx::PrefixTreeMap;
+//! let map = PrefixTreeMap::from([("car", 1), ("cart", 2), ("carbon", 3), ("dog", 4)]);
+//! let frozen = map.freeze();
+//!
+//! assert_eq!(frozen.get("cart"), Some(&2));
+//! assert_eq!(frozen.get("ca"), None);
+//! assert_eq!(frozen.prefix_iter("car").map(|(key, _)| *key).collect::<Vec<_>>(), ["car", "carbon", "cart"]);
+//!
+//! let mut map = frozen.thaw();
+//! map.insert("cat", 5);
+//! assert_eq!(map.len(), 5);
+//! ```
+
+use core::iter;
+use core::fmt::{self, Debug, Formatter};
+use core::ops::Range;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use crate::map::PrefixTreeMap;
+use crate::set::PrefixTreeSet;
+use crate::builder::KeyValidator;
+use crate::edge;
+
+
+/// An immutable map from byte strings to arbitrary values, stored in a
+/// succinct encoding of the tree. Created by [`PrefixTreeMap::freeze()`].
+///
+/// Besides the entries and the bytes of the labels, every node takes about
+/// 3 bits for the shape of the tree, plus 1 bit for each byte of its label,
+/// plus a 32-bit index into the entries. This limits the number of entries,
+/// as well as the number of nodes, to `u32::MAX`.
+///
+/// Nodes are identified by their index in breadth-first order, the root
+/// being node 0.
+///
+/// Comparison and hashing only take the entries into account, the same as
+/// for [`PrefixTreeMap`].
+#[derive(Clone)]
+pub struct FrozenPrefixTreeMap<K, V> {
+    /// The shape of the tree: the number of children of every node, in
+    /// unary (that many ones followed by a zero). The children of a node
+    /// are consecutive, and the `i`-th one bit leads to node `i + 1`.
+    louds: BitVec,
+    /// The first byte of the label of each node. The root has none, but a
+    /// placeholder is stored for it, so that nodes index this directly.
+    fragments: Box<[u8]>,
+    /// The concatenated tails of the labels of all nodes.
+    tails: Box<[u8]>,
+    /// The length of the tail of every node, in unary (that many zeros
+    /// followed by a one), so that the `i`-th one bit ends the tail of node
+    /// `i`, after all zeros before it.
+    tail_ends: BitVec,
+    /// Whether each node has an entry of its own.
+    has_item: BitVec,
+    /// The index of the first entry in the subtree of each node. If the
+    /// node has an entry itself, then it comes first. The subtree of a node
+    /// ends where that of its next sibling starts, or where its parent's ends.
+    items_start: Box<[u32]>,
+    /// The entries in lexicographic order of their keys.
+    pub(crate) items: Box<[(K, V)]>,
+    /// The configuration of the map this was created from, restored by `thaw()`.
+    validator: Option<KeyValidator>,
+}
+
+impl<K, V> FrozenPrefixTreeMap<K, V> {
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub const fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Return a reference to the original key and value, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let index = self.search(key.as_ref())?;
+
+        self.has_item.get(index).then(|| {
+            let (key, value) = &self.items[self.items_start[index] as usize];
+            (key, value)
+        })
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// Returns the entries of the map as a slice, in lexicographic order.
+    pub fn as_slice(&self) -> &[(K, V)] {
+        &self.items
+    }
+
+    /// An iterator over pairs of references to keys and the corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator + '_ {
+        self.items.iter().map(|(key, value)| (key, value))
+    }
+
+    /// An iterator over the keys, in lexicographic order.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator + '_ {
+        self.items.iter().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values, in the lexicographic order of the keys.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator + '_ {
+        self.items.iter().map(|(_key, value)| value)
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order. Since the entries under every node are stored
+    /// contiguously, this only takes a single descent along `prefix`.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.items[self.prefix_range(prefix.as_ref())]
+            .iter()
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Returns the number of keys starting with `prefix`. This takes time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.prefix_range(prefix.as_ref()).len()
+    }
+
+    /// Converts the map back into a mutable [`PrefixTreeMap`], with the
+    /// configuration of the map it was frozen from. Since the entries are
+    /// already sorted, they are appended along the rightmost path of the
+    /// tree, without searching for their places.
+    pub fn thaw(self) -> PrefixTreeMap<K, V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mut map = PrefixTreeMap::with_validator(self.validator);
+        map.extend_sorted(self.items.into_vec());
+        map
+    }
+
+    /// Returns the index of the node reached by following exactly `bytes`.
+    fn search(&self, mut bytes: &[u8]) -> Option<usize> {
+        let mut index = 0;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            index = self.child(index, byte)?.start;
+            bytes = rest.strip_prefix(self.tail(index))?;
+        }
+
+        Some(index)
+    }
+
+    /// Returns the range of the entries of which the key starts with `bytes`.
+    fn prefix_range(&self, mut bytes: &[u8]) -> Range<usize> {
+        let mut index = 0;
+        let mut end = self.items.len();
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let Some(child) = self.child(index, byte) else {
+                return 0..0;
+            };
+            let tail = self.tail(child.start);
+
+            // the entries of the last sibling end where those of the parent do
+            if child.start + 1 < child.end {
+                end = self.items_start[child.start + 1] as usize;
+            }
+
+            index = child.start;
+            bytes = match rest.strip_prefix(tail) {
+                Some(rest) => rest,
+                None if tail.starts_with(rest) => &[],
+                None => return 0..0,
+            };
+        }
+
+        self.items_start[index] as usize..end
+    }
+
+    /// Finds the child of node `index` of which the label starts with `byte`.
+    /// Returns the range from that child to the end of its siblings.
+    fn child(&self, index: usize, byte: u8) -> Option<Range<usize>> {
+        let children = self.children(index);
+        let offset = self.fragments[children.clone()].binary_search(&byte).ok()?;
+
+        Some(children.start + offset..children.end)
+    }
+
+    /// Returns the range of the children of node `index`.
+    fn children(&self, index: usize) -> Range<usize> {
+        // the unary degree of the node follows the zero ending the previous one
+        let start = match index {
+            0 => 0,
+            _ => self.louds.select0(index - 1) + 1,
+        };
+        let end = self.louds.select0(index);
+        let first = self.louds.rank1(start) + 1;
+
+        first..first + (end - start)
+    }
+
+    fn tail(&self, index: usize) -> &[u8] {
+        // the number of zeros before the one ending the tail of a node
+        let tail_end = |index: usize| self.tail_ends.select1(index) - index;
+        let start = match index {
+            0 => 0,
+            _ => tail_end(index - 1),
+        };
+
+        &self.tails[start..tail_end(index)]
+    }
+}
+
+impl<K: AsRef<[u8]>, V> FrozenPrefixTreeMap<K, V> {
+    /// Builds the tree from the entries, which must be sorted by key and
+    /// free of duplicates.
+    ///
+    /// # Panics
+    ///
+    /// If there are more than `u32::MAX` entries or nodes.
+    pub(crate) fn from_sorted(items: Vec<(K, V)>, validator: Option<KeyValidator>) -> Self {
+        let mut louds = BitVecBuilder::default();
+        let mut fragments = Vec::new();
+        let mut tails = Vec::new();
+        let mut tail_ends = BitVecBuilder::default();
+        let mut has_item = BitVecBuilder::default();
+        let mut items_start = Vec::new();
+
+        u32::try_from(items.len()).expect("frozen prefix tree too large");
+
+        for node in BreadthFirst::new(&items) {
+            louds.extend(iter::repeat(true).take(node.child_count));
+            louds.push(false);
+            fragments.push(node.fragment);
+            tails.extend_from_slice(node.tail);
+            tail_ends.extend(iter::repeat(false).take(node.tail.len()));
+            tail_ends.push(true);
+            has_item.push(node.has_item);
+            items_start.push(node.items.start as u32);
+        }
+
+        FrozenPrefixTreeMap {
+            louds: louds.build(),
+            fragments: fragments.into_boxed_slice(),
+            tails: tails.into_boxed_slice(),
+            tail_ends: tail_ends.build(),
+            has_item: has_item.build(),
+            items_start: items_start.into_boxed_slice(),
+            items: items.into_boxed_slice(),
+            validator,
+        }
+    }
+}
+
+impl<K: AsRef<[u8]>, V> From<PrefixTreeMap<K, V>> for FrozenPrefixTreeMap<K, V> {
+    fn from(map: PrefixTreeMap<K, V>) -> Self {
+        map.freeze()
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for FrozenPrefixTreeMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for FrozenPrefixTreeMap<K, V> {}
+
+impl<K: core::hash::Hash, V: core::hash::Hash> core::hash::Hash for FrozenPrefixTreeMap<K, V> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.items.hash(state);
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for FrozenPrefixTreeMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// An immutable set of byte strings, stored in a succinct encoding of the
+/// tree. Created by [`PrefixTreeSet::freeze()`]. See [`FrozenPrefixTreeMap`]
+/// for details.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct FrozenPrefixTreeSet<T> {
+    map: FrozenPrefixTreeMap<T, ()>,
+}
+
+impl<T> FrozenPrefixTreeSet<T> {
+    /// Returns the number of items in this set.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this set is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns `true` if the item is found in the set, `false` otherwise.
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_key(item)
+    }
+
+    /// Returns a reference to the original item, if found.
+    pub fn get<Q>(&self, item: &Q) -> Option<&T>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get_entry(item).map(|(item, ())| item)
+    }
+
+    /// An iterator over the items, in lexicographic order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_ {
+        self.map.keys()
+    }
+
+    /// An iterator over the items starting with `prefix`, in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix_iter(prefix).map(|(item, ())| item)
+    }
+
+    /// Returns the number of items starting with `prefix`. This takes time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.count_prefix(prefix)
+    }
+
+    /// Converts the set back into a mutable [`PrefixTreeSet`], with the
+    /// configuration of the set it was frozen from.
+    pub fn thaw(self) -> PrefixTreeSet<T>
+    where
+        T: AsRef<[u8]>,
+    {
+        PrefixTreeSet::from_map(self.map.thaw())
+    }
+}
+
+impl<T: AsRef<[u8]>> From<PrefixTreeSet<T>> for FrozenPrefixTreeSet<T> {
+    fn from(set: PrefixTreeSet<T>) -> Self {
+        set.freeze()
+    }
+}
+
+impl<T: Debug> Debug for FrozenPrefixTreeSet<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: AsRef<[u8]>, V> PrefixTreeMap<K, V> {
+    /// Converts the map into a read-only [`FrozenPrefixTreeMap`].
+    ///
+    /// # Panics
+    ///
+    /// If the map has more than `u32::MAX` entries or nodes.
+    pub fn freeze(self) -> FrozenPrefixTreeMap<K, V> {
+        let validator = self.validator();
+        FrozenPrefixTreeMap::from_sorted(self.into_iter().collect(), validator)
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Converts the set into a read-only [`FrozenPrefixTreeSet`].
+    ///
+    /// # Panics
+    ///
+    /// If the set has more than `u32::MAX` items or nodes.
+    pub fn freeze(self) -> FrozenPrefixTreeSet<T> {
+        FrozenPrefixTreeSet { map: self.into_map().freeze() }
+    }
+}
+
+/// A bit vector with rank and select queries, which stores the number of
+/// ones before every block of 512 bits, taking another 6.25% of space.
+#[derive(Clone, Default)]
+struct BitVec {
+    words: Box<[u64]>,
+    /// The number of ones before each block, and in total at the end.
+    ranks: Box<[u32]>,
+}
+
+impl BitVec {
+    const BLOCK_WORDS: usize = 8;
+    const BLOCK_BITS: usize = BitVec::BLOCK_WORDS * 64;
+
+    fn get(&self, index: usize) -> bool {
+        self.words[index / 64] >> (index % 64) & 1 != 0
+    }
+
+    /// Returns the number of ones before position `index`.
+    fn rank1(&self, index: usize) -> usize {
+        let block = index / BitVec::BLOCK_BITS;
+        let word = index / 64;
+        let before: u32 = self.words[block * BitVec::BLOCK_WORDS..word]
+            .iter()
+            .map(|word| word.count_ones())
+            .sum();
+        let within = match index % 64 {
+            0 => 0,
+            bits => (self.words[word] << (64 - bits)).count_ones(),
+        };
+
+        (self.ranks[block] + before + within) as usize
+    }
+
+    /// Returns the position of the one with the given (zero-based) rank.
+    fn select1(&self, rank: usize) -> usize {
+        self.select(rank, |block| self.ranks[block] as usize, |word| word)
+    }
+
+    /// Returns the position of the zero with the given (zero-based) rank.
+    fn select0(&self, rank: usize) -> usize {
+        self.select(rank, |block| block * BitVec::BLOCK_BITS - self.ranks[block] as usize, |word| !word)
+    }
+
+    /// Finds the block containing the bit of the given rank by a binary
+    /// search among the counts before each block, then the word within the
+    /// block by counting bits, then the bit within the word.
+    fn select(&self, rank: usize, count_before: impl Fn(usize) -> usize, bits: impl Fn(u64) -> u64) -> usize {
+        // the last block before which at most `rank` bits are counted
+        let (mut low, mut high) = (0, self.ranks.len() - 1);
+
+        while low + 1 < high {
+            let mid = (low + high) / 2;
+
+            if count_before(mid) <= rank {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        let block = low;
+        let mut rank = rank - count_before(block);
+
+        for (offset, &word) in self.words[block * BitVec::BLOCK_WORDS..].iter().enumerate() {
+            let mut word = bits(word);
+            let ones = word.count_ones() as usize;
+
+            if rank < ones {
+                for _ in 0..rank {
+                    word &= word - 1;
+                }
+
+                return (block * BitVec::BLOCK_WORDS + offset) * 64 + word.trailing_zeros() as usize;
+            }
+
+            rank -= ones;
+        }
+
+        unreachable!("rank out of range for bit vector")
+    }
+}
+
+#[derive(Default)]
+struct BitVecBuilder {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVecBuilder {
+    fn push(&mut self, bit: bool) {
+        if self.len % 64 == 0 {
+            self.words.push(0);
+        }
+
+        if let Some(word) = self.words.last_mut() {
+            *word |= u64::from(bit) << (self.len % 64);
+        }
+
+        self.len += 1;
+    }
+
+    /// # Panics
+    ///
+    /// If there are more than `u32::MAX` ones.
+    fn build(self) -> BitVec {
+        let mut count: u32 = 0;
+        let mut ranks = Vec::with_capacity(self.words.len() / BitVec::BLOCK_WORDS + 2);
+
+        for block in self.words.chunks(BitVec::BLOCK_WORDS) {
+            let ones = block.iter().map(|word| word.count_ones()).sum();
+
+            ranks.push(count);
+            count = count.checked_add(ones).expect("frozen prefix tree too large");
+        }
+
+        ranks.push(count);
+
+        BitVec {
+            words: self.words.into_boxed_slice(),
+            ranks: ranks.into_boxed_slice(),
+        }
+    }
+}
+
+impl Extend<bool> for BitVecBuilder {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        for bit in iter {
+            self.push(bit);
+        }
+    }
+}
+
+/// A node of the tree of sorted entries, as visited by [`BreadthFirst`].
+pub(crate) struct LayoutNode<'a> {
+    /// The first byte of the label, or 0 as a placeholder for the root.
+    pub(crate) fragment: u8,
+    /// The rest of the label.
+    pub(crate) tail: &'a [u8],
+    /// The entries of which the key starts with the label of the path
+    /// leading to the node. If the node has an entry itself, then it comes
+    /// first.
+    pub(crate) items: Range<usize>,
+    pub(crate) has_item: bool,
+    pub(crate) child_count: usize,
+}
+
+/// Builds the path-compressed tree of entries sorted by key and free of
+/// duplicates, and iterates over its nodes in breadth-first order, starting
+/// from the root. The children of each node are thus consecutive, and those
+/// of the `n`-th node follow the children of all nodes before it.
+///
+/// This is the common layout of frozen maps and of serialized maps.
+pub(crate) struct BreadthFirst<'a, K, V> {
+    items: &'a [(K, V)],
+    /// The nodes found but not visited yet, with their label lengths.
+    queue: VecDeque<(LayoutNode<'a>, usize)>,
+}
+
+impl<'a, K: AsRef<[u8]>, V> BreadthFirst<'a, K, V> {
+    pub(crate) fn new(items: &'a [(K, V)]) -> Self {
+        let root = LayoutNode {
+            fragment: 0,
+            tail: &[],
+            items: 0..items.len(),
+            has_item: false,
+            child_count: 0,
+        };
+
+        BreadthFirst {
+            items,
+            queue: VecDeque::from([(root, 0)]),
+        }
+    }
+}
+
+impl<'a, K: AsRef<[u8]>, V> Iterator for BreadthFirst<'a, K, V> {
+    type Item = LayoutNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut node, depth) = self.queue.pop_front()?;
+        let items = self.items;
+        let key = |index: usize| items[index].0.as_ref();
+        let range = node.items.clone();
+
+        node.has_item = !range.is_empty() && key(range.start).len() == depth;
+
+        let mut start = range.start + usize::from(node.has_item);
+
+        while start < range.end {
+            let byte = key(start)[depth];
+            let len = items[start..range.end].partition_point(|(key, _)| key.as_ref()[depth] == byte);
+            let end = start + len;
+
+            // the common prefix of the first and the last keys is common to all of them
+            let first = &key(start)[depth + 1..];
+            let last = &key(end - 1)[depth + 1..];
+            let tail = &first[..edge::common_prefix_len(first, last)];
+            let child = LayoutNode {
+                fragment: byte,
+                tail,
+                items: start..end,
+                has_item: false,
+                child_count: 0,
+            };
+
+            self.queue.push_back((child, depth + 1 + tail.len()));
+            node.child_count += 1;
+            start = end;
+        }
+
+        Some(node)
+    }
+}
@@ -0,0 +1,380 @@
+//! A map which doesn't store its keys.
+//!
+//! A [`crate::PrefixTreeMap`] keeps the owned key of every entry next to its
+//! value, even though the labels along the path to the entry already spell out
+//! the key. [`ImplicitMap`] only keeps the labels, and reconstructs the keys
+//! during iteration, which saves the memory of the keys, at the expense of
+//! handing out freshly allocated keys instead of references to stored ones.
+//!
+//! ```
+//! # use pfx::implicit::ImplicitMap;
+//! let mut map = ImplicitMap::new();
+//!
+//! map.insert("romane", 1);
+//! map.insert("romanus", 2);
+//! map.insert("rubens", 3);
+//!
+//! assert_eq!(map.get("romanus"), Some(&2));
+//! assert_eq!(
+//!     map.prefix_iter("rom").collect::<Vec<_>>(),
+//!     [(b"romane".to_vec(), &1), (b"romanus".to_vec(), &2)],
+//! );
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::hash::{Hash, Hasher};
+use core::iter;
+use core::mem;
+use alloc::vec::Vec;
+use alloc::string::String;
+use crate::edge::{Edge, EdgeMut};
+
+
+/// A map from byte strings to arbitrary values, storing only the values and
+/// the edge labels of the tree, but not the keys themselves.
+///
+/// Edges without branching are kept path-compressed after every insertion
+/// and removal, so the shape of the tree is determined solely by the entries.
+#[derive(Clone)]
+pub struct ImplicitMap<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+impl<V> Default for ImplicitMap<V> {
+    fn default() -> Self {
+        ImplicitMap::new()
+    }
+}
+
+impl<V> ImplicitMap<V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        ImplicitMap {
+            root: Node::with_label(0, Vec::new()),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        *self = ImplicitMap::new();
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root.search(key.as_ref())?.value.as_ref()
+    }
+
+    /// Return a mutable reference to the value, if found.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut path = Vec::new();
+
+        if self.root.locate(key.as_ref(), &mut path) {
+            self.root.descendant_mut(&path).value.as_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts the value under the key. Returns the old value, if any.
+    pub fn insert<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let old_value = self.root.insert(key.as_ref(), value);
+
+        if old_value.is_none() {
+            self.len += 1;
+        }
+
+        old_value
+    }
+
+    /// If the key exists in the map, removes it and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let value = self.root.remove(key.as_ref())?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// An iterator over pairs of reconstructed keys and references to the
+    /// corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<u8>, &V)> + '_ {
+        self.root.iter(Vec::new())
+    }
+
+    /// An iterator over the reconstructed keys, in lexicographic order.
+    pub fn keys(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.iter().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values, in the lexicographic order of the keys.
+    /// This doesn't need to reconstruct the keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        let mut stack = Vec::from([&self.root]);
+
+        iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev());
+
+                if let Some(value) = node.value.as_ref() {
+                    return Some(value);
+                }
+            }
+
+            None
+        })
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl Iterator<Item = (Vec<u8>, &V)> + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+
+        // the full key of the node found, which may be longer than `prefix`
+        let found = self.root.search_prefix(prefix).map(|(node, rest)| {
+            let mut key = prefix.to_vec();
+            key.extend_from_slice(rest);
+            (node, key)
+        });
+
+        found.into_iter().flat_map(|(node, key)| node.iter(key))
+    }
+}
+
+impl<Q, V> FromIterator<(Q, V)> for ImplicitMap<V>
+where
+    Q: AsRef<[u8]>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (Q, V)>
+    {
+        let mut map = ImplicitMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<Q, V> Extend<(Q, V)> for ImplicitMap<V>
+where
+    Q: AsRef<[u8]>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (Q, V)>
+    {
+        for (key, value) in iter {
+            self.insert(&key, value);
+        }
+    }
+}
+
+impl<V: PartialEq> PartialEq for ImplicitMap<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V: Eq> Eq for ImplicitMap<V> {}
+
+impl<V: Hash> Hash for ImplicitMap<V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.iter().for_each(|entry| entry.hash(state));
+    }
+}
+
+/// Keys are formatted as strings, with invalid UTF-8 replaced.
+impl<V: Debug> Debug for ImplicitMap<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(key, value)| (String::from_utf8_lossy(&key).into_owned(), value)))
+            .finish()
+    }
+}
+
+/// A node of the tree, with the edges labelled in the same way as in
+/// [`crate::PrefixTreeMap`]. Apart from the root, every node either has
+/// a value or at least two children.
+#[derive(Clone)]
+struct Node<V> {
+    value: Option<V>,
+    key_fragment: u8,
+    tail: Vec<u8>,
+    children: Vec<Node<V>>,
+}
+
+impl<V> Node<V> {
+    const fn with_label(key_fragment: u8, tail: Vec<u8>) -> Self {
+        Node {
+            value: None,
+            key_fragment,
+            tail,
+            children: Vec::new(),
+        }
+    }
+
+    /// Iterates over the subtree in pre-order, given the full key of `self`.
+    /// The keys of the descendants are built in the same buffer, by replacing
+    /// the labels of the nodes already left behind.
+    fn iter(&self, mut key: Vec<u8>) -> impl Iterator<Item = (Vec<u8>, &V)> + '_ {
+        // along with each node, the length of the key of its parent,
+        // or `None` for `self`, the key of which is already complete
+        let mut stack = Vec::from([(self, None)]);
+
+        iter::from_fn(move || {
+            while let Some((node, parent_len)) = stack.pop() {
+                if let Some(parent_len) = parent_len {
+                    key.truncate(parent_len);
+                    key.push(node.key_fragment);
+                    key.extend_from_slice(&node.tail);
+                }
+
+                let len = key.len();
+                stack.extend(node.children.iter().rev().map(|child| (child, Some(len))));
+
+                if let Some(value) = node.value.as_ref() {
+                    return Some((key.clone(), value));
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Inserts the value under `bytes`, splitting edges as necessary.
+    /// Returns the old value, if any.
+    fn insert(&mut self, bytes: &[u8], value: V) -> Option<V> {
+        let mut node = self;
+        let mut rest = bytes;
+
+        while !rest.is_empty() {
+            let (index, after) = node.branch(rest, Node::with_label);
+
+            node = &mut node.children[index];
+            rest = after;
+        }
+
+        node.value.replace(value)
+    }
+
+    /// Removes the value under `bytes`, then removes or merges the nodes
+    /// along the path which are no longer needed.
+    fn remove(&mut self, bytes: &[u8]) -> Option<V> {
+        let mut path = Vec::new();
+
+        if !self.locate(bytes, &mut path) {
+            return None;
+        }
+
+        let value = self.descendant_mut(&path).value.take();
+        self.tidy_at(&path);
+        value
+    }
+}
+
+impl<V> Edge for Node<V> {
+    type Symbol = u8;
+    type Child = Self;
+
+    fn key_fragment(&self) -> u8 {
+        self.key_fragment
+    }
+
+    fn tail(&self) -> &[u8] {
+        &self.tail
+    }
+
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
+
+    fn has_item(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+impl<V> EdgeMut for Node<V> {
+    fn tail_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.tail
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Self {
+        &mut self.children[index]
+    }
+
+    fn insert_child(&mut self, index: usize, child: Self) {
+        self.children.insert(index, child);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children.remove(index);
+    }
+
+    fn push_down(&mut self, key_fragment: u8, tail: Vec<u8>) {
+        let lower = Node {
+            value: self.value.take(),
+            key_fragment,
+            tail,
+            children: mem::take(&mut self.children),
+        };
+
+        self.children.push(lower);
+    }
+
+    fn pull_up(&mut self) -> (u8, Vec<u8>) {
+        let mut child = self.children.pop().expect("no child to pull up");
+
+        self.value = child.value.take();
+        self.children = mem::take(&mut child.children);
+
+        (child.key_fragment, mem::take(&mut child.tail))
+    }
+}
+
+/// The descendants are detached and dropped one by one from an explicit
+/// stack, because the default recursive drop glue could overflow the call
+/// stack for trees with very long keys.
+impl<V> Drop for Node<V> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
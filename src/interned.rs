@@ -0,0 +1,239 @@
+//! A map that stores each distinct value only once.
+//!
+//! This is useful when many keys map to equal values, e.g. category labels
+//! or configuration blobs: every distinct value is allocated exactly once,
+//! and the map stores shared references to it.
+//!
+//! ```
+//! # use pfx::interned::InternedMap;
+//! let mut map = InternedMap::new();
+//!
+//! map.insert("apple", String::from("fruit"));
+//! map.insert("banana", String::from("fruit"));
+//! map.insert("carrot", String::from("vegetable"));
+//!
+//! assert_eq!(map.get("banana").map(String::as_str), Some("fruit"));
+//! assert_eq!(map.len(), 3);
+//! assert_eq!(map.distinct_values(), 2);
+//! assert_eq!(map.share_count("apple"), 2);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use alloc::sync::Arc;
+use alloc::collections::BTreeMap;
+use crate::map::{self, PrefixTreeMap};
+use crate::builder::InvalidKey;
+
+
+/// A map from byte strings to values, which deduplicates equal values.
+///
+/// Values are kept in a table along with the number of keys referring to
+/// them, and removed from the table once the last such key is removed.
+/// Lookups work the same as in [`PrefixTreeMap`], returning references to
+/// the shared values.
+#[derive(Clone)]
+pub struct InternedMap<K, V> {
+    map: PrefixTreeMap<K, Arc<V>>,
+    /// The distinct values, along with the number of keys they belong to.
+    values: BTreeMap<Arc<V>, usize>,
+}
+
+impl<K, V> Default for InternedMap<K, V> {
+    fn default() -> Self {
+        InternedMap::new()
+    }
+}
+
+impl<K, V> InternedMap<K, V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        InternedMap::from_map(PrefixTreeMap::new())
+    }
+
+    /// Wraps an empty map, retaining its configuration.
+    pub(crate) const fn from_map(map: PrefixTreeMap<K, Arc<V>>) -> Self {
+        InternedMap { map, values: BTreeMap::new() }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the number of distinct values in the map.
+    pub fn distinct_values(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get(key).map(|value| &**value)
+    }
+
+    /// Return a reference to the shared allocation of the value, if found.
+    pub fn get_shared<Q>(&self, key: &Q) -> Option<&Arc<V>>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get(key)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// An iterator over pairs of references to keys and the corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.map.iter().map(|(key, value)| (key, &**value))
+    }
+
+    /// Returns the underlying map of shared values.
+    pub fn as_map(&self) -> &PrefixTreeMap<K, Arc<V>> {
+        &self.map
+    }
+
+    /// Returns the underlying map of shared values, discarding the table of values.
+    pub fn into_map(self) -> PrefixTreeMap<K, Arc<V>> {
+        self.map
+    }
+}
+
+impl<K, V: Ord> InternedMap<K, V> {
+    /// Returns the number of keys (including `key` itself) whose value is
+    /// equal to the value of `key`, or 0 if `key` is not in the map.
+    pub fn share_count<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map
+            .get(key)
+            .and_then(|value| self.values.get(value))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// An iterator over the distinct values and the number of keys sharing each
+    /// one of them, in ascending order of the values.
+    pub fn shared_values(&self) -> impl Iterator<Item = (&V, usize)> + '_ {
+        self.values.iter().map(|(value, &count)| (&**value, count))
+    }
+
+    /// Returns the shared copy of `value`, adding it to the table if necessary.
+    fn intern(&mut self, value: V) -> Arc<V> {
+        if let Some((shared, _)) = self.values.get_key_value(&value) {
+            let shared = Arc::clone(shared);
+            *self.values.get_mut(&value).expect("value was just found") += 1;
+            return shared;
+        }
+
+        let shared = Arc::new(value);
+        self.values.insert(Arc::clone(&shared), 1);
+        shared
+    }
+
+    /// Drops a reference to the shared value, removing it from the table
+    /// if no other keys refer to it.
+    fn release(&mut self, value: &Arc<V>) {
+        let count = self.values.get_mut(&**value).expect("interned value in table");
+
+        if *count > 1 {
+            *count -= 1;
+        } else {
+            self.values.remove(&**value);
+        }
+    }
+
+    /// If the key exists in the map, return the corresponding shared value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Arc<V>>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let value = self.map.remove(key)?;
+        self.release(&value);
+        Some(value)
+    }
+}
+
+impl<K: AsRef<[u8]>, V: Ord> InternedMap<K, V> {
+    /// Inserts the key-value pair, sharing the value with other keys if an
+    /// equal value already exists in the map. Returns the old value, if any.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub fn insert(&mut self, key: K, value: V) -> Option<Arc<V>> {
+        match self.try_insert(key, value) {
+            Ok(old_value) => old_value,
+            Err(error) => panic!("invalid key: {error}"),
+        }
+    }
+
+    /// Like [`InternedMap::insert`], but returns an error instead of panicking
+    /// if `key` violates the validation rules of the map.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<Arc<V>>, InvalidKey> {
+        let value = self.intern(value);
+
+        match self.map.try_insert(key, Arc::clone(&value)) {
+            Ok(None) => Ok(None),
+            Ok(Some(old_value)) => {
+                self.release(&old_value);
+                Ok(Some(old_value))
+            }
+            Err(error) => {
+                self.release(&value);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl<K: AsRef<[u8]>, V: Ord> FromIterator<(K, V)> for InternedMap<K, V> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = InternedMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: AsRef<[u8]>, V: Ord> Extend<(K, V)> for InternedMap<K, V> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for InternedMap<K, V> {
+    type Item = (K, Arc<V>);
+    type IntoIter = map::IntoIter<K, Arc<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for InternedMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,347 @@
+//! Order-preserving byte encodings for composite keys.
+//!
+//! The prefix tree orders its keys lexicographically by their bytes. The
+//! [`PrefixKey`] trait encodes values as byte strings such that this order
+//! coincides with the natural order of the encoded values, so that e.g.
+//! integers, tuples and structs can be used as keys (after encoding them).
+//!
+//! Encodings of composite values are the concatenation of the encodings of
+//! their fields. Strings and byte strings are escaped and terminated, so that
+//! no encoded value is a prefix of another one of the same type. Consequently,
+//! encoding the first few fields of a composite key yields a prefix of the
+//! encoding of the full key, which is suitable for prefix queries.
+//!
+//! With the `derive` feature, `#[derive(PrefixKey)]` is available for structs
+//! (encoding fields in declaration order) and enums (encoding the index of the
+//! variant followed by its fields).
+//!
+//! ```
+//! # use pfx::{PrefixTreeMap, key::PrefixKey};
+//! let mut map = PrefixTreeMap::new();
+//!
+//! map.insert(("users", 1000_u32).to_prefix_key(), "alice");
+//! map.insert(("users", 20_u32).to_prefix_key(), "bob");
+//! map.insert(("groups", 5_u32).to_prefix_key(), "admins");
+//!
+//! let users: Vec<_> = map.prefix_iter(&"users".to_prefix_key()).map(|(_, v)| *v).collect();
+//! assert_eq!(users, ["bob", "alice"]);
+//! ```
+
+use alloc::vec::Vec;
+use alloc::string::String;
+use alloc::boxed::Box;
+
+#[cfg(feature = "derive")]
+pub use pfx_derive::PrefixKey;
+
+
+/// Types with a byte encoding whose lexicographic order is the same as the
+/// order of the values themselves. See the [module-level documentation](self).
+pub trait PrefixKey {
+    /// Appends the encoding of `self` to `out`.
+    fn encode_prefix_key(&self, out: &mut Vec<u8>);
+
+    /// Returns the encoding of `self` as a new byte vector.
+    fn to_prefix_key(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_prefix_key(&mut out);
+        out
+    }
+}
+
+impl<T: ?Sized + PrefixKey> PrefixKey for &T {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        T::encode_prefix_key(self, out);
+    }
+}
+
+impl<T: ?Sized + PrefixKey> PrefixKey for Box<T> {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        T::encode_prefix_key(self, out);
+    }
+}
+
+impl PrefixKey for () {
+    fn encode_prefix_key(&self, _out: &mut Vec<u8>) {}
+}
+
+impl PrefixKey for bool {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+}
+
+impl PrefixKey for char {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        u32::from(*self).encode_prefix_key(out);
+    }
+}
+
+macro_rules! impl_prefix_key_unsigned {
+    ($($ty:ty),*) => {$(
+        /// Big-endian encoding.
+        impl PrefixKey for $ty {
+            fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+    )*}
+}
+
+macro_rules! impl_prefix_key_signed {
+    ($($ty:ty),*) => {$(
+        /// Big-endian encoding with the sign bit flipped, so that
+        /// negative numbers sort before non-negative ones.
+        impl PrefixKey for $ty {
+            fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+                let mut bytes = self.to_be_bytes();
+                bytes[0] ^= 0x80;
+                out.extend_from_slice(&bytes);
+            }
+        }
+    )*}
+}
+
+impl_prefix_key_unsigned!(u8, u16, u32, u64, u128);
+impl_prefix_key_signed!(i8, i16, i32, i64, i128);
+
+/// Escapes NUL bytes as `00 FF` and appends the terminator `00 00`,
+/// which sorts before any escaped or non-NUL byte, so that shorter
+/// strings sort before longer ones with the same prefix.
+impl PrefixKey for [u8] {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        for &byte in self {
+            out.push(byte);
+
+            if byte == 0 {
+                out.push(0xff);
+            }
+        }
+
+        out.extend_from_slice(&[0, 0]);
+    }
+}
+
+impl PrefixKey for Vec<u8> {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_prefix_key(out);
+    }
+}
+
+impl PrefixKey for str {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode_prefix_key(out);
+    }
+}
+
+impl PrefixKey for String {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode_prefix_key(out);
+    }
+}
+
+/// Fixed-size arrays are encoded as the concatenation of their elements.
+impl<T: PrefixKey, const N: usize> PrefixKey for [T; N] {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        for item in self {
+            item.encode_prefix_key(out);
+        }
+    }
+}
+
+/// `None` sorts before any `Some`, like in the `Ord` impl of `Option`.
+impl<T: PrefixKey> PrefixKey for Option<T> {
+    fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_prefix_key(out);
+            }
+        }
+    }
+}
+
+macro_rules! impl_prefix_key_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: PrefixKey),+> PrefixKey for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_prefix_key(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_prefix_key(out);)+
+            }
+        }
+    }
+}
+
+impl_prefix_key_tuple!(A);
+impl_prefix_key_tuple!(A B);
+impl_prefix_key_tuple!(A B C);
+impl_prefix_key_tuple!(A B C D);
+impl_prefix_key_tuple!(A B C D E);
+impl_prefix_key_tuple!(A B C D E F);
+impl_prefix_key_tuple!(A B C D E F G);
+impl_prefix_key_tuple!(A B C D E F G H);
+
+/// A filesystem path, encoded as a byte string so that it can be used as the
+/// key of a map or set, and decoded back without loss.
+///
+/// The encoding is the platform's own encoding of the path as given by
+/// [`OsStr::as_encoded_bytes`](std::ffi::OsStr::as_encoded_bytes): the raw
+/// bytes on Unix, and WTF-8 (a superset of UTF-8 which can also represent
+/// the unpaired surrogates of ill-formed UTF-16) on Windows. Either way,
+/// paths which are valid Unicode are encoded as UTF-8, so they are ordered
+/// like strings, and the keys of paths within a directory start with the key
+/// of the directory. Paths are encoded as written, without normalizing
+/// separators, `.` or `..` components, so lookups should use the same
+/// spelling as the stored keys.
+///
+/// Since a prefix query for `/foo` also matches `/foobar`, use
+/// [`PathKey::descendants`] to find only the paths within a directory.
+///
+/// ```
+/// # use std::path::Path;
+/// # use pfx::{PrefixTreeMap, key::PathKey};
+/// let mut map = PrefixTreeMap::new();
+///
+/// map.insert(PathKey::new("/src/lib.rs"), 1);
+/// map.insert(PathKey::new("/src/map.rs"), 2);
+/// map.insert(PathKey::new("/src2/main.rs"), 3);
+///
+/// assert_eq!(map.get(&PathKey::new(Path::new("/src/map.rs"))), Some(&2));
+/// assert_eq!(map.prefix_iter(&PathKey::new("/src")).count(), 3);
+///
+/// let files: Vec<_> = map
+///     .prefix_iter(&PathKey::new("/src").descendants())
+///     .map(|(key, _)| key.to_path_buf())
+///     .collect();
+/// assert_eq!(files, [Path::new("/src/lib.rs"), Path::new("/src/map.rs")]);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PathKey(Vec<u8>);
+
+#[cfg(feature = "std")]
+impl PathKey {
+    /// Encodes a path (or any other OS string).
+    pub fn new<P: ?Sized + AsRef<std::ffi::OsStr>>(path: &P) -> Self {
+        PathKey(path.as_ref().as_encoded_bytes().to_vec())
+    }
+
+    /// Returns the encoded path.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns a prefix matching the keys of the paths within this one,
+    /// i.e. the key followed by a separator, unless it already ends in one
+    /// (in which case the path itself also matches).
+    pub fn descendants(&self) -> Self {
+        let mut bytes = self.0.clone();
+
+        if !bytes.last().is_some_and(|&byte| std::path::is_separator(char::from(byte))) {
+            bytes.push(std::path::MAIN_SEPARATOR as u8);
+        }
+
+        PathKey(bytes)
+    }
+
+    /// Decodes the path.
+    ///
+    /// This is lossless on Unix and Windows. On other platforms, where paths
+    /// are expected to be valid UTF-8, any other bytes are replaced with
+    /// U+FFFD REPLACEMENT CHARACTER.
+    pub fn to_path_buf(&self) -> std::path::PathBuf {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            std::ffi::OsStr::from_bytes(&self.0).into()
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStringExt;
+            std::ffi::OsString::from_wide(&wtf8_to_wide(&self.0)).into()
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            String::from_utf8_lossy(&self.0).into_owned().into()
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl AsRef<[u8]> for PathKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&std::path::Path> for PathKey {
+    fn from(path: &std::path::Path) -> Self {
+        PathKey::new(path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&std::ffi::OsStr> for PathKey {
+    fn from(string: &std::ffi::OsStr) -> Self {
+        PathKey::new(string)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::path::PathBuf> for PathKey {
+    fn from(path: std::path::PathBuf) -> Self {
+        PathKey(path.into_os_string().into_encoded_bytes())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::ffi::OsString> for PathKey {
+    fn from(string: std::ffi::OsString) -> Self {
+        PathKey(string.into_encoded_bytes())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<PathKey> for std::path::PathBuf {
+    fn from(key: PathKey) -> Self {
+        key.to_path_buf()
+    }
+}
+
+/// Converts WTF-8, as produced by `OsStr::as_encoded_bytes` on Windows, to
+/// the potentially ill-formed UTF-16 from which it was created.
+///
+/// The input is trusted to be valid WTF-8, so truncated sequences are
+/// silently dropped instead of being reported.
+#[cfg(all(feature = "std", any(windows, test)))]
+pub(crate) fn wtf8_to_wide(bytes: &[u8]) -> Vec<u16> {
+    let mut wide = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+
+    while let Some((&lead, tail)) = rest.split_first() {
+        let (len, init) = match lead {
+            0x00..=0x7f => (0, u32::from(lead)),
+            0xc0..=0xdf => (1, u32::from(lead & 0x1f)),
+            0xe0..=0xef => (2, u32::from(lead & 0x0f)),
+            _ => (3, u32::from(lead & 0x07)),
+        };
+        let Some(continuation) = tail.get(..len) else {
+            break;
+        };
+        let code_point = continuation
+            .iter()
+            .fold(init, |acc, &byte| acc << 6 | u32::from(byte & 0x3f));
+
+        match code_point.checked_sub(0x1_0000) {
+            Some(offset) => wide.extend([0xd800 | (offset >> 10) as u16, 0xdc00 | (offset & 0x3ff) as u16]),
+            None => wide.push(code_point as u16),
+        }
+
+        rest = &tail[len..];
+    }
+
+    wide
+}
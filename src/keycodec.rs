@@ -0,0 +1,230 @@
+//! Fixed-size, order-preserving byte encodings of numbers and timestamps.
+//!
+//! The [`KeyCodec`] trait converts values to byte arrays whose lexicographic
+//! order is the same as the order of the values, and back. Unlike the
+//! [`PrefixKey`](crate::key::PrefixKey) encodings, these can be decoded, and
+//! the arrays can be used as keys directly, which makes numeric ranges
+//! available as ranges of keys.
+//!
+//! * Unsigned integers are encoded in big-endian byte order.
+//! * Signed integers are encoded in big-endian byte order, with the sign bit
+//!   flipped, so that negative numbers sort before non-negative ones.
+//! * Floating-point numbers are encoded as their bits in big-endian byte
+//!   order, with the sign bit flipped for positive numbers, and all bits
+//!   flipped for negative ones. This results in the order of
+//!   [`f64::total_cmp`]: negative NaNs, negative infinity, negative numbers,
+//!   -0.0, +0.0, positive numbers, positive infinity, and positive NaNs.
+//! * A [`Duration`] is encoded as its whole seconds (as a `u64`) followed by
+//!   its subsecond nanoseconds (as a `u32`), and a `SystemTime` (with the
+//!   `std` feature) as the same for the signed offset from the Unix epoch.
+//!
+//! ```
+//! # use pfx::{PrefixTreeMap, keycodec::KeyCodec};
+//! let mut map = PrefixTreeMap::new();
+//!
+//! for temperature in [-12.5_f64, 3.0, -0.5, 21.25, 100.0] {
+//!     map.insert(temperature.to_key_bytes(), temperature > 0.0);
+//! }
+//!
+//! let freezing: Vec<f64> = map
+//!     .range(..0.0_f64.to_key_bytes())
+//!     .map(|(key, _)| f64::from_key_bytes(key).unwrap())
+//!     .collect();
+//!
+//! assert_eq!(freezing, [-12.5, -0.5]);
+//! ```
+
+use core::fmt::{self, Display, Formatter};
+use core::time::Duration;
+
+
+/// Types with a fixed-size byte encoding of which the lexicographic order is
+/// the same as the order of the values. See the [module-level documentation](self).
+pub trait KeyCodec: Sized {
+    /// The encoded representation, a byte array.
+    type Bytes: AsRef<[u8]>;
+
+    /// Encodes `self`.
+    fn to_key_bytes(&self) -> Self::Bytes;
+
+    /// Decodes a value previously encoded by [`KeyCodec::to_key_bytes`].
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// The reason why a byte string couldn't be decoded by [`KeyCodec::from_key_bytes`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DecodeError {
+    /// The byte string had length `len` instead of `expected`.
+    WrongLength {
+        len: usize,
+        expected: usize,
+    },
+    /// The byte string had the right length, but it doesn't encode any value
+    /// (e.g. the nanoseconds of a duration were not less than one billion).
+    Invalid,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::WrongLength { len, expected } => {
+                write!(f, "encoded key of length {len} should have length {expected}")
+            }
+            DecodeError::Invalid => {
+                f.write_str("encoded key doesn't represent any value")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Copies `bytes` into an array, if it has the right length.
+fn to_array<const N: usize>(bytes: &[u8]) -> Result<[u8; N], DecodeError> {
+    bytes.try_into().map_err(|_| DecodeError::WrongLength { len: bytes.len(), expected: N })
+}
+
+macro_rules! impl_key_codec_unsigned {
+    ($($ty:ty),*) => {$(
+        impl KeyCodec for $ty {
+            type Bytes = [u8; core::mem::size_of::<$ty>()];
+
+            fn to_key_bytes(&self) -> Self::Bytes {
+                self.to_be_bytes()
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                to_array(bytes).map(<$ty>::from_be_bytes)
+            }
+        }
+    )*}
+}
+
+macro_rules! impl_key_codec_signed {
+    ($($ty:ty),*) => {$(
+        impl KeyCodec for $ty {
+            type Bytes = [u8; core::mem::size_of::<$ty>()];
+
+            fn to_key_bytes(&self) -> Self::Bytes {
+                let mut bytes = self.to_be_bytes();
+                bytes[0] ^= 0x80;
+                bytes
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                let mut bytes = to_array(bytes)?;
+                bytes[0] ^= 0x80;
+                Ok(<$ty>::from_be_bytes(bytes))
+            }
+        }
+    )*}
+}
+
+macro_rules! impl_key_codec_float {
+    ($($ty:ty => $bits:ty),*) => {$(
+        impl KeyCodec for $ty {
+            type Bytes = [u8; core::mem::size_of::<$ty>()];
+
+            fn to_key_bytes(&self) -> Self::Bytes {
+                const SIGN: $bits = 1 << (<$bits>::BITS - 1);
+                let bits = self.to_bits();
+                let bits = if bits & SIGN == 0 { bits ^ SIGN } else { !bits };
+
+                bits.to_be_bytes()
+            }
+
+            fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+                const SIGN: $bits = 1 << (<$bits>::BITS - 1);
+                let bits = <$bits>::from_be_bytes(to_array(bytes)?);
+                let bits = if bits & SIGN == 0 { !bits } else { bits ^ SIGN };
+
+                Ok(<$ty>::from_bits(bits))
+            }
+        }
+    )*}
+}
+
+impl_key_codec_unsigned!(u8, u16, u32, u64, u128);
+impl_key_codec_signed!(i8, i16, i32, i64, i128);
+impl_key_codec_float!(f32 => u32, f64 => u64);
+
+impl KeyCodec for Duration {
+    type Bytes = [u8; 12];
+
+    fn to_key_bytes(&self) -> Self::Bytes {
+        let mut bytes = [0; 12];
+        bytes[..8].copy_from_slice(&self.as_secs().to_key_bytes());
+        bytes[8..].copy_from_slice(&self.subsec_nanos().to_key_bytes());
+        bytes
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes: [u8; 12] = to_array(bytes)?;
+        let secs = u64::from_key_bytes(&bytes[..8])?;
+        let nanos = u32::from_key_bytes(&bytes[8..])?;
+
+        if nanos >= NANOS_PER_SEC {
+            return Err(DecodeError::Invalid);
+        }
+
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Times before the Unix epoch are encoded as the negative number of whole
+/// seconds (rounded down) and the non-negative nanoseconds after that.
+///
+/// # Panics
+///
+/// Encoding panics if the time is more than `i64::MAX` seconds away from the
+/// epoch, which a `SystemTime` can't be on most platforms.
+#[cfg(feature = "std")]
+impl KeyCodec for std::time::SystemTime {
+    type Bytes = [u8; 12];
+
+    fn to_key_bytes(&self) -> Self::Bytes {
+        let (secs, nanos) = match self.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => {
+                let secs = i64::try_from(after.as_secs()).expect("time too far after the epoch");
+                (secs, after.subsec_nanos())
+            }
+            Err(error) => {
+                let before = error.duration();
+                let secs = i64::try_from(before.as_secs()).expect("time too far before the epoch");
+
+                match before.subsec_nanos() {
+                    0 => (-secs, 0),
+                    nanos => (-secs - 1, NANOS_PER_SEC - nanos),
+                }
+            }
+        };
+
+        let mut bytes = [0; 12];
+        bytes[..8].copy_from_slice(&secs.to_key_bytes());
+        bytes[8..].copy_from_slice(&nanos.to_key_bytes());
+        bytes
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes: [u8; 12] = to_array(bytes)?;
+        let secs = i64::from_key_bytes(&bytes[..8])?;
+        let nanos = u32::from_key_bytes(&bytes[8..])?;
+
+        if nanos >= NANOS_PER_SEC {
+            return Err(DecodeError::Invalid);
+        }
+
+        let time = if secs >= 0 {
+            std::time::UNIX_EPOCH.checked_add(Duration::new(secs.unsigned_abs(), nanos))
+        } else {
+            std::time::UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs.unsigned_abs()))
+                .and_then(|time| time.checked_add(Duration::from_nanos(nanos.into())))
+        };
+
+        time.ok_or(DecodeError::Invalid)
+    }
+}
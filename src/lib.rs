@@ -1,11 +1,55 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![forbid(unsafe_code)]
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", env!("CARGO_PKG_README")))]
 
+extern crate alloc;
+
+pub mod aggregate;
+pub mod bag;
+pub mod bits;
+pub mod builder;
+pub mod dafsa;
+mod edge;
+pub mod frozen;
+pub mod implicit;
+pub mod interned;
+pub mod key;
+pub mod keycodec;
+mod macros;
 pub mod map;
+pub mod nibble;
+pub mod matcher;
+pub mod normalized;
+pub mod persistent;
+pub mod router;
+pub mod scored;
 pub mod set;
+pub mod symbol;
+pub mod text;
+pub mod utf8;
 
-pub use map::{PrefixTreeMap, Entry, VacantEntry, OccupiedEntry};
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+#[cfg(feature = "mmap")]
+pub mod mapped;
+
+#[cfg(feature = "proptest")]
+pub mod proptest;
+
+pub use builder::{Builder, InvalidKey};
+pub use map::{PrefixTreeMap, Entry, VacantEntry, OccupiedEntry, EntryRef, VacantEntryRef, Finger, Cursor, CursorMut, SubTrie, SubTrieMut, Transaction, Change, InvariantViolation};
 pub use set::PrefixTreeSet;
+pub use text::Delimiter;
+
+/// Items used by the code generated by derive macros. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec::Vec;
+}
 
 
 #[cfg(test)]
@@ -102,10 +146,10 @@ mod tests {
     fn entry_api() {
         let mut pt = PrefixTreeMap::<[u8; 4], Vec<u32>>::default();
 
-        // since the entry API inserts nodes, double-check
-        // that it doesn't accidentally insert spurious values
+        // vacant entries don't insert values, nor nodes
         assert!(matches!(pt.entry([42, 43, 44, 45]), Entry::Vacant(_)));
         assert!(matches!(pt.entry([42, 43, 44, 45]), Entry::Vacant(_)));
+        assert_eq!(pt, PrefixTreeMap::new());
 
 
         let val = pt
@@ -125,6 +169,180 @@ mod tests {
         assert!(pt.entry(*b"nope").remove().is_none());
     }
 
+    #[test]
+    fn fixed_width_keys() {
+        let mut map = PrefixTreeMap::from([
+            (*b"\x01\x02\x03\x04", 'a'),
+            (*b"\x01\x02\xff\x00", 'b'),
+            (*b"\xfe\xdc\xba\x98", 'c'),
+        ]);
+
+        assert_eq!(map.get_fixed(b"\x01\x02\x03\x04"), Some(&'a'));
+        assert_eq!(map.get_fixed(b"\x01\x02\xff\x00"), Some(&'b'));
+        assert_eq!(map.get_fixed(b"\x01\x02\xff\x01"), None);
+        assert!(map.contains_fixed_key(b"\xfe\xdc\xba\x98"));
+        assert!(!map.contains_fixed_key(b"\xfe\xdc\xba\x99"));
+
+        // only the first byte of each edge is looked at on the way down
+        assert_eq!(map.get_fixed(b"\x01\x09\x03\x04"), None);
+        assert_eq!(map.get_fixed(b"\xfe\x00\x00\x00"), None);
+        assert_eq!(map.get_fixed_mut(b"\x01\x02\x09\x04"), None);
+
+        *map.get_fixed_mut(b"\xfe\xdc\xba\x98").unwrap() = 'z';
+        assert_eq!(map[b"\xfe\xdc\xba\x98"], 'z');
+
+        let set = PrefixTreeSet::from([[0, 0], [0, 1], [1, 0]]);
+
+        assert!(set.contains_fixed(&[0, 1]));
+        assert!(!set.contains_fixed(&[1, 1]));
+    }
+
+    #[test]
+    fn keys_diverging_inside_leaves() {
+        let mut map = PrefixTreeMap::new();
+
+        map.insert("international", 1);
+        assert_eq!(map.get("intern"), None);
+        assert_eq!(map.get("internationally"), None);
+        assert_eq!(map.prefix_iter("inter").count(), 1);
+        assert!(!map.contains_prefix("interval"));
+
+        map.insert("internet", 2);
+        map.insert("interval", 3);
+        map.insert("inter", 4);
+        map.insert("in", 5);
+
+        assert_eq!(map.get("international"), Some(&1));
+        assert_eq!(map.get("internet"), Some(&2));
+        assert_eq!(map.get("interval"), Some(&3));
+        assert_eq!(map.get("inter"), Some(&4));
+        assert_eq!(map.get("in"), Some(&5));
+        assert_eq!(map.get("int"), None);
+        assert_eq!(map.get("interne"), None);
+
+        let values: Vec<_> = map.prefix_iter("intern").map(|(_, &v)| v).collect();
+        assert_eq!(values, [1, 2]);
+
+        let values: Vec<_> = map.prefix_iter("inte").map(|(_, &v)| v).collect();
+        assert_eq!(values, [4, 1, 2, 3]);
+
+        let keys: Vec<_> = map.clone().into_prefix_iter("internati").map(|(k, _)| k).collect();
+        assert_eq!(keys, ["international"]);
+
+        assert_eq!(map.remove("inter"), Some(4));
+        assert_eq!(map.get("international"), Some(&1));
+        assert!(map.contains_prefix("inter"));
+    }
+
+    #[test]
+    fn finger_lookups() {
+        let map: PrefixTreeMap<String, usize> = (0..1000)
+            .map(|i| (format!("user/{i:06}"), i))
+            .collect();
+        let mut finger = map.finger();
+
+        for i in 0..1000 {
+            assert_eq!(finger.get(&format!("user/{i:06}")), Some(&i));
+        }
+        for i in (0..1000).rev().step_by(7) {
+            assert_eq!(finger.get_entry(&format!("user/{i:06}")), Some((&format!("user/{i:06}"), &i)));
+        }
+
+        assert!(!finger.contains_key("user/00"));
+        assert!(!finger.contains_key("user/001000"));
+        assert!(!finger.contains_key("user/0000000"));
+        assert!(finger.contains_key("user/000999"));
+        assert!(!finger.contains_key(""));
+        assert!(finger.contains_key("user/000000"));
+    }
+
+    #[test]
+    fn batch_membership() {
+        let set = PrefixTreeSet::from(["read", "write", "admin", "read/logs", "write/logs"]);
+
+        assert!(set.contains_all(["write/logs", "read", "read/logs"]));
+        assert!(set.contains_all(Vec::<&str>::new()));
+        assert!(!set.contains_all(["read", "delete", "write"]));
+        assert!(!set.contains_all(["read/log"]));
+
+        assert!(set.contains_any(["delete", "admin"]));
+        assert!(!set.contains_any(["delete", "rea", "admins", ""]));
+        assert!(!set.contains_any(Vec::<String>::new()));
+
+        let map = PrefixTreeMap::from([("a", 1), ("ab", 2)]);
+
+        assert!(map.contains_all(vec![b"ab".to_vec(), b"a".to_vec()]));
+        assert!(map.contains_any(["abc", "ab"]));
+    }
+
+    #[test]
+    fn from_text() {
+        let corpus = "the quick brown fox jumps over the lazy dog,, the end";
+
+        let set: PrefixTreeSet<&str> = PrefixTreeSet::from_text(corpus, [' ', ',']);
+        assert!(set.iter().eq(&["brown", "dog", "end", "fox", "jumps", "lazy", "over", "quick", "the"]));
+
+        let set: PrefixTreeSet<String> = PrefixTreeSet::from_text(corpus, char::is_whitespace);
+        assert!(set.contains("dog,,"));
+        assert!(!set.contains(""));
+
+        let counts: PrefixTreeMap<&str, usize> = PrefixTreeMap::from_text(corpus, |c: char| !c.is_alphabetic());
+        assert_eq!(counts["the"], 3);
+        assert_eq!(counts["fox"], 1);
+        assert_eq!(counts.len(), 9);
+    }
+
+    #[test]
+    fn key_validation() {
+        let mut map: PrefixTreeMap<Vec<u8>, u32> = Builder::new()
+            .max_key_len(4)
+            .reject_nul()
+            .require_utf8()
+            .build_map();
+
+        assert_eq!(map.try_insert(b"abcd".to_vec(), 1), Ok(None));
+        assert_eq!(map.try_insert(b"abcd".to_vec(), 2), Ok(Some(1)));
+        assert_eq!(
+            map.try_insert(b"abcde".to_vec(), 3),
+            Err(InvalidKey::TooLong { len: 5, max_len: 4 }),
+        );
+        assert_eq!(
+            map.try_insert(b"a\0b".to_vec(), 4),
+            Err(InvalidKey::ContainsNul { index: 1 }),
+        );
+        assert_eq!(
+            map.try_insert(b"ab\xff".to_vec(), 5),
+            Err(InvalidKey::NotUtf8 { valid_up_to: 2 }),
+        );
+        assert!(map.try_entry(b"".to_vec()).is_ok());
+        assert_eq!(map.len(), 1);
+
+        // the rules are preserved by operations producing new maps
+        let mut map = map.intersection([b"abcd"]);
+        assert!(map.try_insert(b"toolong".to_vec(), 6).is_err());
+
+        let mut set: PrefixTreeSet<&str> = Builder::new()
+            .allowed_bytes(|b| b.is_ascii_digit())
+            .build_set();
+
+        assert_eq!(set.try_insert("12345"), Ok(true));
+        assert_eq!(set.try_insert("12345"), Ok(false));
+        assert_eq!(
+            set.try_insert("12x45"),
+            Err(InvalidKey::ForbiddenByte { byte: b'x', index: 2 }),
+        );
+
+        // comparison ignores the configuration
+        assert_eq!(set, PrefixTreeSet::from(["12345"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid key")]
+    fn key_validation_panics() {
+        let mut set: PrefixTreeSet<&str> = Builder::new().max_key_len(2).build_set();
+        set.insert("abc");
+    }
+
     #[test]
     fn iteration() {
         let data = [
@@ -305,6 +523,2988 @@ mod tests {
         assert_eq!(iter_2.len(), 0);
     }
 
+    #[test]
+    fn iter_debug_default() {
+        let map = PrefixTreeMap::from([("b", 2), ("a", 1), ("ab", 3)]);
+
+        let mut iter = map.iter();
+        iter.next();
+        assert_eq!(format!("{iter:?}"), r#"[("ab", 3), ("b", 2)]"#);
+        assert_eq!(format!("{:?}", map.keys()), r#"["a", "ab", "b"]"#);
+        assert_eq!(format!("{:?}", map.values()), "[1, 3, 2]");
+        assert_eq!(format!("{:?}", map.prefix_iter("a")), r#"[("a", 1), ("ab", 3)]"#);
+
+        let mut into_iter = map.clone().into_iter();
+        into_iter.next();
+        assert_eq!(format!("{into_iter:?}"), r#"[("ab", 3), ("b", 2)]"#);
+        assert_eq!(format!("{:?}", map.clone().into_keys()), r#"["a", "ab", "b"]"#);
+        assert_eq!(format!("{:?}", map.clone().into_values()), "[1, 3, 2]");
+
+        let mut prefix_iter = map.clone().into_prefix_iter("a");
+        prefix_iter.next();
+        assert_eq!(format!("{prefix_iter:?}"), r#"[("ab", 3)]"#);
+        assert_eq!(format!("{:?}", prefix_iter.clone().collect::<Vec<_>>()), r#"[("ab", 3)]"#);
+
+        let set = PrefixTreeSet::from(["x", "y"]);
+        assert_eq!(format!("{:?}", set.iter()), r#"["x", "y"]"#);
+        assert_eq!(format!("{:?}", set.prefix_iter("y")), r#"["y"]"#);
+        assert_eq!(format!("{:?}", set.into_iter()), r#"["x", "y"]"#);
+
+        assert_eq!(map::Iter::<String, u8>::default().len(), 0);
+        assert_eq!(map::IntoValues::<String, u8>::default().next(), None);
+        assert_eq!(map::IntoPrefixIter::<String, u8>::default().next(), None);
+        assert_eq!(set::Iter::<String>::default().next(), None);
+    }
+
+    #[test]
+    fn branch_points() {
+        use map::BranchPoint;
+
+        let mut set = PrefixTreeSet::from([
+            "svc/auth/login",
+            "svc/auth/logout",
+            "svc/billing",
+            "web",
+        ]);
+
+        let points: Vec<_> = set.iter_branch_points().collect();
+        assert_eq!(points, [
+            BranchPoint { prefix: b"".to_vec(), fan_out: 2, len: 4 },
+            BranchPoint { prefix: b"svc/".to_vec(), fan_out: 2, len: 3 },
+            BranchPoint { prefix: b"svc/auth/log".to_vec(), fan_out: 2, len: 2 },
+        ]);
+
+        // removed entries don't count towards the fan-out
+        set.remove("web");
+        assert_eq!(set.iter_branch_points().len(), 2);
+        assert_eq!(PrefixTreeSet::<&str>::new().iter_branch_points().next(), None);
+    }
+
+    #[test]
+    fn distinct_prefixes() {
+        let ips = PrefixTreeSet::from([
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            [10, 0, 1, 1],
+            [192, 168, 0, 1],
+            [192, 168, 0, 7],
+        ]);
+
+        assert_eq!(ips.count_distinct_prefixes(3), 3);
+        assert_eq!(ips.count_distinct_prefixes(1), 2);
+        assert_eq!(ips.count_distinct_prefixes(0), 1);
+        assert_eq!(ips.count_distinct_prefixes(4), 5);
+        assert_eq!(ips.count_distinct_prefixes(5), 0);
+
+        let subnets: Vec<_> = ips.iter_distinct_prefixes(3).collect();
+        assert_eq!(subnets, [
+            (vec![10, 0, 0], 2),
+            (vec![10, 0, 1], 1),
+            (vec![192, 168, 0], 2),
+        ]);
+
+        let mut map = PrefixTreeMap::from([("a/x", 1), ("a/y", 2), ("bc", 3), ("d", 4)]);
+        map.remove("bc");
+
+        // short keys and removed entries don't count
+        assert!(map.iter_distinct_prefixes(2).eq([(b"a/".to_vec(), 2)]));
+        assert_eq!(map.count_distinct_prefixes(1), 2);
+    }
+
+    #[test]
+    fn absent_keys() {
+        let mut ids = PrefixTreeSet::new();
+
+        for expected in [[0, 0], [0, 1], [0, 2], [0, 3]] {
+            let id = ids.find_absent_key(2).unwrap();
+            assert_eq!(id, expected);
+            ids.insert(id);
+        }
+
+        ids.remove(&[0, 1]);
+        assert_eq!(ids.find_absent_key(2), Some(vec![0, 1]));
+
+        // full subtrees are skipped
+        let mut set: PrefixTreeSet<Vec<u8>> = (0..=255).map(|b| vec![0, b]).collect();
+        set.insert(vec![1, 0, 0]);
+        set.insert(vec![2, 7]);
+        assert_eq!(set.find_absent_key(2), Some(vec![1, 0]));
+        assert_eq!(set.find_absent_key(3), Some(vec![0, 0, 0]));
+        assert_eq!(set.find_absent_key(1), Some(vec![0]));
+
+        // keys with long leaf edges
+        let set = PrefixTreeSet::from([[0, 0, 0], [0, 0, 1], [5, 5, 5]]);
+        assert_eq!(set.find_absent_key(3), Some(vec![0, 0, 2]));
+
+        let set = PrefixTreeSet::from([[0, 0, 0, 0]]);
+        assert_eq!(set.find_absent_key(4), Some(vec![0, 0, 0, 1]));
+        assert_eq!(set.find_absent_key(2), Some(vec![0, 0]));
+
+        let full: PrefixTreeSet<[u8; 1]> = (0..=255).map(|b| [b]).collect();
+        assert_eq!(full.find_absent_key(1), None);
+        assert_eq!(full.find_absent_key(0), Some(vec![]));
+        assert_eq!(PrefixTreeSet::from([""]).find_absent_key(0), None);
+
+        // full subtrees are recognized by their size
+        let mut ids: PrefixTreeSet<[u8; 3]> = (0..=u16::MAX).map(|i| [0, (i >> 8) as u8, i as u8]).collect();
+        ids.insert([1, 0, 0]);
+        assert_eq!(ids.find_absent_key(3), Some(vec![1, 0, 1]));
+        ids.remove(&[0, 0x12, 0x34]);
+        assert_eq!(ids.find_absent_key(3), Some(vec![0, 0x12, 0x34]));
+
+        // keys of other lengths make up for missing ones in the item counts
+        let mut mixed: PrefixTreeSet<Vec<u8>> = (0..=255).filter(|&b| b != 7).map(|b| vec![b]).collect();
+        mixed.insert(vec![1, 2]);
+        assert_eq!(mixed.find_absent_key(1), Some(vec![7]));
+
+        let mut mixed: PrefixTreeSet<Vec<u8>> = (0..=255).filter(|&b| b != 5).map(|b| vec![0, b]).collect();
+        mixed.insert(vec![0, 5, 5]);
+        assert_eq!(mixed.find_absent_key(2), Some(vec![0, 5]));
+        mixed.insert(vec![0]);
+        mixed.remove(&[0, 5, 5]);
+        assert_eq!(mixed.find_absent_key(2), Some(vec![0, 5]));
+        mixed.remove(&[0]);
+        assert_eq!(mixed.find_absent_key(2), Some(vec![0, 5]));
+        mixed.insert(vec![0, 5]);
+        assert_eq!(mixed.find_absent_key(2), Some(vec![1, 0]));
+        assert_eq!(mixed.check_invariants(), Ok(()));
+
+        // the lengths of the keys merged into a map are taken into account
+        let mut ids: PrefixTreeSet<Vec<u8>> = (0..=255).filter(|&b| b != 3).map(|b| vec![b]).collect();
+        ids.append(&mut PrefixTreeSet::from([vec![3, 3]]));
+        assert_eq!(ids.find_absent_key(1), Some(vec![3]));
+        assert_eq!(ids.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn interned_values() {
+        use std::sync::Arc;
+        use interned::InternedMap;
+
+        let mut map: InternedMap<&str, String> = Builder::new().max_key_len(8).build_interned_map();
+
+        assert_eq!(map.insert("apple", "fruit".into()), None);
+        assert_eq!(map.insert("banana", "fruit".into()), None);
+        assert_eq!(map.insert("carrot", "vegetable".into()), None);
+        assert!(map.try_insert("pineapple", "fruit".into()).is_err());
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.distinct_values(), 2);
+        assert_eq!(map.share_count("apple"), 2);
+        assert_eq!(map.share_count("pineapple"), 0);
+        assert!(Arc::ptr_eq(map.get_shared("apple").unwrap(), map.get_shared("banana").unwrap()));
+        assert!(map.shared_values().map(|(v, n)| (v.as_str(), n)).eq([("fruit", 2), ("vegetable", 1)]));
+
+        assert_eq!(map.insert("carrot", "fruit".into()).as_deref().map(String::as_str), Some("vegetable"));
+        assert_eq!(map.distinct_values(), 1);
+        assert_eq!(map.share_count("carrot"), 3);
+
+        assert_eq!(map.remove("apple").as_deref().map(String::as_str), Some("fruit"));
+        assert_eq!(map.remove("apple"), None);
+        assert_eq!(map.share_count("banana"), 2);
+        assert!(map.iter().eq([(&"banana", &"fruit".to_owned()), (&"carrot", &"fruit".to_owned())]));
+
+        map.remove("banana");
+        map.remove("carrot");
+        assert!(map.is_empty());
+        assert_eq!(map.distinct_values(), 0);
+    }
+
+    #[test]
+    fn sorted_by_value() {
+        let counts: PrefixTreeMap<&str, usize> = PrefixTreeMap::from_text(
+            "b a c a b a d e e e e f",
+            ' ',
+        );
+
+        let top = counts.top_n_by_value(3);
+        assert_eq!(top, [(&"e", &4), (&"a", &3), (&"b", &2)]);
+        assert_eq!(counts.top_n_by_value(0), []);
+        assert_eq!(counts.top_n_by_value(100).len(), counts.len());
+
+        // ties are broken by key order
+        assert_eq!(counts.top_n_by_value(5)[3..], [(&"c", &1), (&"d", &1)]);
+
+        let ascending: Vec<_> = counts.iter_sorted_by_value(usize::cmp).map(|(_, &v)| v).collect();
+        assert_eq!(ascending, [1, 1, 1, 2, 3, 4]);
+
+        let mut descending = counts.iter_sorted_by_value(|x, y| y.cmp(x));
+        assert_eq!(descending.len(), 6);
+        assert_eq!(descending.next(), Some((&"e", &4)));
+        assert_eq!(descending.next(), Some((&"a", &3)));
+        assert_eq!(descending.len(), 4);
+    }
+
+    #[test]
+    fn ranges() {
+        use std::collections::BTreeMap;
+        use std::ops::Bound::{self, Included, Excluded, Unbounded};
+
+        let keys = ["", "a", "ab", "abc", "abd", "abde", "b", "ba", "user:1000", "user:1500", "user:2", "user:2000"];
+        let mut map: PrefixTreeMap<String, usize> = keys.iter().map(|k| (k.to_string(), k.len())).collect();
+        let std_map: BTreeMap<String, usize> = map.iter().map(|(k, &v)| (k.clone(), v)).collect();
+
+        // leave an empty node in the middle
+        map.insert("abb".into(), 0);
+        map.remove("abb");
+
+        let bounds = ["", "a", "aa", "ab", "abc", "abcd", "abd", "abdd", "abz", "b", "user:", "user:1500", "user:3", "z"];
+        let mut all: Vec<Bound<&str>> = vec![Unbounded];
+        all.extend(bounds.iter().map(|&b| Included(b)));
+        all.extend(bounds.iter().map(|&b| Excluded(b)));
+
+        for &start in &all {
+            for &end in &all {
+                match (start, end) {
+                    (Included(s) | Excluded(s), Included(e) | Excluded(e)) if s > e => continue,
+                    (Excluded(s), Excluded(e)) if s == e => continue,
+                    _ => {}
+                }
+
+                let expected: Vec<_> = std_map.range::<str, _>((start, end)).collect();
+                assert!(map.range::<str, _>((start, end)).eq(expected.iter().copied()), "{start:?}..{end:?}");
+                assert!(map.clone().into_range::<str, _>((start, end)).eq(expected.iter().map(|&(k, &v)| (k.clone(), v))));
+            }
+        }
+
+        let users: Vec<_> = map.range("user:1000".."user:2000").map(|(k, _)| k.as_str()).collect();
+        assert_eq!(users, ["user:1000", "user:1500", "user:2"]);
+        assert_eq!(map.range("user:1000"..="user:2000").count(), 4);
+        assert_eq!(map.range::<str, _>(..).count(), map.len());
+        assert_eq!(format!("{:?}", map.range("b"..="ba")), r#"[("b", 1), ("ba", 2)]"#);
+        assert_eq!(format!("{:?}", map.clone().into_range("ab".."abd")), r#"[("ab", 2), ("abc", 3)]"#);
+    }
+
+    #[test]
+    #[should_panic]
+    fn decreasing_range() {
+        let map = PrefixTreeMap::from([("a", 1)]);
+        map.range("b".."a");
+    }
+
+    #[test]
+    fn reverse_iteration() {
+        let mut map = PrefixTreeMap::from([
+            ("", 0), ("a", 1), ("ab", 2), ("abc", 3), ("abd", 4), ("b", 5), ("bcd", 6), ("bce", 7),
+        ]);
+        map.insert("abz", 8);
+        map.remove("abz");
+
+        let forward: Vec<_> = map.iter().collect();
+        let mut backward: Vec<_> = map.iter().rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+
+        let last: Vec<_> = map.keys().rev().take(3).copied().collect();
+        assert_eq!(last, ["bce", "bcd", "b"]);
+        assert!(map.values().rev().eq([7, 6, 5, 4, 3, 2, 1, 0].iter()));
+        assert!(map.clone().into_values().rev().eq([7, 6, 5, 4, 3, 2, 1, 0]));
+        assert!(map.prefix_iter("ab").rev().map(|(_, &v)| v).eq([4, 3, 2]));
+
+        // meeting in the middle yields every item exactly once
+        for split in 0..=map.len() {
+            let mut iter = map.clone().into_iter();
+            let mut items: Vec<_> = iter.by_ref().take(split).collect();
+            let mut rest: Vec<_> = iter.by_ref().rev().collect();
+            rest.reverse();
+            items.extend(rest);
+
+            assert!(items.iter().map(|&(k, v)| (k, v)).eq(map.iter().map(|(&k, &v)| (k, v))));
+            assert_eq!(iter.next(), None);
+        }
+
+        // alternating between the ends
+        let items: Vec<_> = map.iter().collect();
+        let mut iter = map.iter();
+        let (mut front, mut back) = (0, items.len());
+        while front < back {
+            assert_eq!(iter.next(), Some(items[front]));
+            front += 1;
+
+            if front < back {
+                back -= 1;
+                assert_eq!(iter.next_back(), Some(items[back]));
+            }
+        }
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = map.iter();
+        assert_eq!(iter.next_back(), Some((&"bce", &7)));
+        assert_eq!(iter.next(), Some((&"", &0)));
+        assert_eq!(iter.len(), 6);
+        iter.nth(2);
+        assert_eq!(format!("{iter:?}"), r#"[("abd", 4), ("b", 5), ("bcd", 6)]"#);
+
+        let set = PrefixTreeSet::from(["x", "xy", "y"]);
+        assert!(set.iter().rev().eq([&"y", &"xy", &"x"]));
+        assert!(set.prefix_iter("x").rev().eq([&"xy", &"x"]));
+        assert!(set.into_iter().rev().eq(["y", "xy", "x"]));
+    }
+
+    #[test]
+    fn mutable_iteration() {
+        let mut map = PrefixTreeMap::from([("a", 1), ("ab", 2), ("b", 3), ("bcd", 4)]);
+        map.insert("abc", 0);
+        map.remove("abc");
+
+        for (key, value) in map.iter_mut() {
+            *value *= key.len() * 10;
+        }
+        assert!(map.values().eq(&[10, 40, 30, 120]));
+
+        for value in map.values_mut().rev().take(2) {
+            *value += 1;
+        }
+        assert!(map.values().eq(&[10, 40, 31, 121]));
+
+        for (_, value) in &mut map {
+            *value = 0;
+        }
+        assert!(map.values().all(|&v| v == 0));
+
+        let mut iter = map.iter_mut();
+        assert_eq!(iter.len(), 4);
+        iter.next();
+        assert_eq!(format!("{iter:?}"), r#"[("ab", 0), ("b", 0), ("bcd", 0)]"#);
+        assert_eq!(format!("{:?}", map.values_mut()), "[0, 0, 0, 0]");
+        assert_eq!(PrefixTreeMap::<String, i32>::new().iter_mut().next(), None);
+    }
+
+    #[test]
+    fn prefix_mutable_iteration() {
+        let mut counters = PrefixTreeMap::from([
+            ("metrics", 0),
+            ("metrics/cpu", 1),
+            ("metrics/mem", 2),
+            ("metricsx", 3),
+            ("logs/app", 4),
+        ]);
+
+        for (_, count) in counters.prefix_iter_mut("metrics/") {
+            *count += 10;
+        }
+        assert!(counters.values().eq(&[4, 0, 11, 12, 3]));
+
+        // the prefix ends in the middle of an edge
+        let (key, count) = counters.prefix_iter_mut("lo").next_back().unwrap();
+        assert_eq!(*key, "logs/app");
+        *count = 40;
+        assert_eq!(counters["logs/app"], 40);
+
+        assert_eq!(counters.prefix_iter_mut("nope").next(), None);
+        assert_eq!(counters.prefix_iter_mut("metrics").count(), 4);
+        assert_eq!(format!("{:?}", counters.prefix_iter_mut("metrics/m")), r#"[("metrics/mem", 12)]"#);
+    }
+
+    #[test]
+    fn retain_entries() {
+        let mut map = PrefixTreeMap::from([
+            ("apple", 3), ("apricot", 8), ("banana", 5), ("blueberry", 2), ("cherry", 7),
+        ]);
+        let mut visited = Vec::new();
+
+        map.retain(|&key, value| {
+            visited.push(key);
+            *value += 1;
+            *value % 2 == 0
+        });
+
+        assert_eq!(visited, ["apple", "apricot", "banana", "blueberry", "cherry"]);
+        assert_eq!(map.len(), 3);
+        assert!(map.iter().eq([(&"apple", &4), (&"banana", &6), (&"cherry", &8)]));
+
+        // the nodes of removed entries are pruned
+        let mut letters = PrefixTreeMap::from([("x", 1), ("y", 2), ("z", 3)]);
+        letters.retain(|_, &mut value| value != 2);
+        assert_eq!(letters, PrefixTreeMap::from([("x", 1), ("z", 3)]));
+
+        map.retain(|_, _| false);
+        assert!(map.is_empty());
+        assert_eq!(map, PrefixTreeMap::new());
+
+        let mut set = PrefixTreeSet::from(["a", "ab", "abc", "b"]);
+        set.retain(|item| item.len() != 2);
+        assert!(set.iter().eq(&["a", "abc", "b"]));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn drain_entries() {
+        let mut map = Builder::new().max_key_len(4).build_map();
+        map.extend([("ab", 1), ("abc", 2), ("b", 3)]);
+
+        let mut drain = map.drain();
+        assert_eq!(drain.len(), 3);
+        assert_eq!(drain.next_back(), Some(("b", 3)));
+        assert_eq!(format!("{drain:?}"), r#"[("ab", 1), ("abc", 2)]"#);
+        assert_eq!(drain.next(), Some(("ab", 1)));
+        drop(drain);
+
+        assert!(map.is_empty());
+        assert_eq!(map.iter().next(), None);
+
+        // the map is still usable, and keeps its configuration
+        map.insert("xyz", 4);
+        assert!(map.try_insert("toolong", 5).is_err());
+        assert!(map.drain().eq([("xyz", 4)]));
+
+        let mut set = PrefixTreeSet::from(["q", "p"]);
+        assert!(set.drain().eq(["p", "q"]));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn split_off() {
+        use std::collections::BTreeMap;
+
+        let keys = ["", "a", "ab", "abc", "abd", "abde", "b", "ba", "shard:1", "shard:10", "shard:2"];
+        let map: PrefixTreeMap<String, usize> = keys.iter().map(|k| (k.to_string(), k.len())).collect();
+        let bounds = ["", "a", "aa", "ab", "abc", "abcd", "abd", "abdd", "abz", "b", "bb", "shard:", "shard:10", "shard:11", "z"];
+
+        for bound in bounds {
+            let mut lower = map.clone();
+            let upper = lower.split_off(bound);
+
+            let mut std_lower: BTreeMap<_, _> = map.iter().map(|(k, &v)| (k.clone(), v)).collect();
+            let std_upper = std_lower.split_off(bound);
+
+            assert!(lower.iter().eq(&std_lower), "{bound:?}");
+            assert!(upper.iter().eq(&std_upper), "{bound:?}");
+            assert_eq!(lower.len(), std_lower.len());
+            assert_eq!(upper.len(), std_upper.len());
+        }
+
+        // both halves keep working as usual, and the empty nodes are removed
+        let mut lower = PrefixTreeMap::from([("x", 1), ("y", 2), ("yz", 3)]);
+        let mut upper = lower.split_off("y");
+        assert_eq!(lower, PrefixTreeMap::from([("x", 1)]));
+        upper.insert("w", 0);
+        assert!(upper.keys().eq(&["w", "y", "yz"]));
+
+        let mut set = PrefixTreeSet::from(["1", "2", "3"]);
+        let tail = set.split_off("2");
+        assert!(set.iter().eq(&["1"]));
+        assert!(tail.iter().eq(&["2", "3"]));
+    }
+
+    #[test]
+    fn append() {
+        let left = [("", 0), ("inter", 1), ("international", 2), ("internet", 3), ("x", 4)];
+        let right = [("in", 10), ("inter", 11), ("interval", 12), ("internationally", 13), ("y", 14)];
+
+        let mut map = PrefixTreeMap::from(left);
+        let mut other = PrefixTreeMap::from(right);
+        map.append(&mut other);
+
+        assert!(other.is_empty());
+        assert_eq!(other.iter().next(), None);
+        assert_eq!(map.len(), 9);
+        assert_eq!(map["inter"], 11);
+
+        // the merged tree is the same as if all keys were inserted one by one
+        let expected: PrefixTreeMap<_, _> = left.into_iter().chain(right).collect();
+        assert_eq!(map, expected);
+
+        // `other` keeps working after being emptied
+        other.insert("z", 15);
+        map.append(&mut other);
+        assert_eq!(map.len(), 10);
+
+        let mut strict = Builder::new().max_key_len(4).build_set();
+        strict.insert("abc");
+        let mut long = PrefixTreeSet::from(["ab", "abcde"]);
+        assert!(strict.try_append(&mut long).is_err());
+        assert_eq!(strict.len(), 1);
+        assert_eq!(long.len(), 2);
+
+        long.remove("abcde");
+        strict.append(&mut long);
+        assert!(strict.iter().eq(&["ab", "abc"]));
+    }
+
+    #[test]
+    fn first_and_last() {
+        let mut map = PrefixTreeMap::from([("b", 2), ("ab", 1), ("bcd", 4), ("bc", 3)]);
+
+        // nodes without items don't count
+        map.insert("a", 0);
+        map.remove("a");
+        map.insert("bcde", 5);
+        map.remove("bcde");
+
+        assert_eq!(map.first_key_value(), Some((&"ab", &1)));
+        assert_eq!(map.last_key_value(), Some((&"bcd", &4)));
+
+        assert_eq!(map.pop_last(), Some(("bcd", 4)));
+        assert_eq!(map.pop_first(), Some(("ab", 1)));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.first_key_value(), Some((&"b", &2)));
+        assert_eq!(map.last_key_value(), Some((&"bc", &3)));
+        assert_eq!(map, PrefixTreeMap::from([("b", 2), ("bc", 3)]));
+
+        assert_eq!(map.pop_first(), Some(("b", 2)));
+        assert_eq!(map.pop_first(), Some(("bc", 3)));
+        assert_eq!(map.pop_first(), None);
+        assert_eq!(map.pop_last(), None);
+        assert_eq!(map.last_key_value(), None);
+        assert_eq!(map, PrefixTreeMap::new());
+
+        let mut set = PrefixTreeSet::from(["", "z", "zz"]);
+        assert_eq!(set.first(), Some(&""));
+        assert_eq!(set.last(), Some(&"zz"));
+        assert_eq!(set.pop_last(), Some("zz"));
+        assert_eq!(set.pop_last(), Some("z"));
+        assert_eq!(set.pop_last(), Some(""));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn first_and_last_entries() {
+        let mut queue = PrefixTreeMap::from([("job:1", 3), ("job:2", 1), ("job:3", 2)]);
+        queue.insert("job:4", 0);
+        queue.remove("job:4");
+
+        let mut first = queue.first_entry().unwrap();
+        assert_eq!(*first.key(), "job:1");
+        *first.get_mut() -= 1;
+        assert_eq!(queue["job:1"], 2);
+
+        let last = queue.last_entry().unwrap();
+        assert_eq!((*last.key(), *last.get()), ("job:3", 2));
+        assert_eq!(last.remove_entry(), ("job:3", 2));
+        assert_eq!(queue.len(), 2);
+
+        while let Some(entry) = queue.first_entry() {
+            entry.remove();
+        }
+        assert!(queue.is_empty());
+        assert!(queue.first_entry().is_none());
+        assert!(queue.last_entry().is_none());
+    }
+
+    #[test]
+    fn prefixes_of_query() {
+        let mut routes = PrefixTreeMap::from([
+            ("", "root"),
+            ("/api", "api"),
+            ("/api/v1", "v1"),
+            ("/api/v1/users", "users"),
+            ("/api/v2", "v2"),
+            ("/static", "static"),
+        ]);
+        routes.insert("/api/v1/u", "spurious");
+        routes.remove("/api/v1/u");
+
+        let matches: Vec<_> = routes.prefixes_of("/api/v1/users/42").map(|(_, &v)| v).collect();
+        assert_eq!(matches, ["root", "api", "v1", "users"]);
+
+        // the query may end in the middle of an edge
+        let matches: Vec<_> = routes.prefixes_of("/api/v").map(|(&k, _)| k).collect();
+        assert_eq!(matches, ["", "/api"]);
+
+        assert!(routes.prefixes_of("/api/v1").map(|(&k, _)| k).eq(["", "/api", "/api/v1"]));
+        assert_eq!(routes.prefixes_of("").count(), 1);
+        assert_eq!(format!("{:?}", routes.prefixes_of("/stat")), r#"[("", "root")]"#);
+
+        let words = PrefixTreeSet::from(["a", "an", "ant", "anthem", "b"]);
+        assert!(words.prefixes_of("anthems").eq(&["a", "an", "ant", "anthem"]));
+        assert_eq!(words.prefixes_of("c").next(), None);
+    }
+
+    #[test]
+    fn any_prefix_of() {
+        let mut blocklist = PrefixTreeSet::from(["https://ads.", "https://tracker.example/"]);
+        blocklist.insert("http://");
+        blocklist.remove("http://");
+
+        assert!(blocklist.contains_any_prefix_of("https://ads.example/banner.png"));
+        assert!(blocklist.contains_any_prefix_of("https://tracker.example/"));
+        assert!(!blocklist.contains_any_prefix_of("https://tracker.example"));
+        assert!(!blocklist.contains_any_prefix_of("http://ads.example/"));
+        assert!(!blocklist.contains_any_prefix_of(""));
+
+        let map = PrefixTreeMap::from([("", 0)]);
+        assert!(map.contains_any_prefix_of(""));
+        assert!(map.contains_any_prefix_of("anything"));
+        assert!(!PrefixTreeMap::<&str, i32>::new().contains_any_prefix_of("x"));
+    }
+
+    #[test]
+    fn remove_prefix() {
+        let mut map = PrefixTreeMap::from([
+            ("cfg/db/host", 1), ("cfg/db/port", 2), ("cfg/dbx", 3), ("cfg/log", 4), ("data", 5),
+        ]);
+
+        let db = map.remove_prefix("cfg/db/");
+        assert_eq!(db.len(), 2);
+        assert_eq!(db.get("cfg/db/port"), Some(&2));
+        assert!(db.keys().eq(&["cfg/db/host", "cfg/db/port"]));
+        assert_eq!(map.len(), 3);
+        assert!(map.keys().eq(&["cfg/dbx", "cfg/log", "data"]));
+
+        // the prefix ends in the middle of an edge
+        let mut cfg = map.remove_prefix("cf");
+        assert!(cfg.keys().eq(&["cfg/dbx", "cfg/log"]));
+        assert_eq!(map, PrefixTreeMap::from([("data", 5)]));
+
+        cfg.insert("cfg/db", 6);
+        assert!(cfg.keys().eq(&["cfg/db", "cfg/dbx", "cfg/log"]));
+        assert!(cfg.remove_prefix("cfg/x").is_empty());
+        assert!(cfg.remove_prefix("cfg/dbxy").is_empty());
+        assert_eq!(cfg.len(), 3);
+
+        let all = cfg.remove_prefix("");
+        assert_eq!(all.len(), 3);
+        assert!(cfg.is_empty());
+
+        let mut set = PrefixTreeSet::from(["a", "ab", "b"]);
+        assert!(set.remove_prefix("a").iter().eq(&["a", "ab"]));
+        assert!(set.iter().eq(&["b"]));
+    }
+
+    #[test]
+    fn retain_prefix() {
+        let mut dict = Builder::new().max_key_len(12).build_map();
+        dict.extend([("en:cat", 1), ("en:dog", 2), ("de:Hund", 3), ("en", 4), ("fr:chat", 5)]);
+
+        dict.retain_prefix("en:");
+        assert_eq!(dict.len(), 2);
+        assert!(dict.keys().eq(&["en:cat", "en:dog"]));
+        assert_eq!(dict.get("en:dog"), Some(&2));
+
+        // the configuration is kept
+        assert!(dict.try_insert("en:hippopotamus", 6).is_err());
+        dict.insert("en:ant", 7);
+        assert!(dict.keys().eq(&["en:ant", "en:cat", "en:dog"]));
+
+        dict.retain_prefix("en:d");
+        assert!(dict.keys().eq(&["en:dog"]));
+        dict.retain_prefix("xx");
+        assert!(dict.is_empty());
+
+        let mut set = PrefixTreeSet::from(["a", "ab", "b"]);
+        set.retain_prefix("");
+        assert_eq!(set.len(), 3);
+        set.retain_prefix("a");
+        assert!(set.iter().eq(&["a", "ab"]));
+    }
+
+    #[test]
+    fn count_prefix() {
+        fn assert_counts(map: &PrefixTreeMap<String, usize>) {
+            for prefix in ["", "a", "ap", "app", "apple", "applet", "b", "ba", "ban", "banana", "c", "x"] {
+                assert_eq!(map.count_prefix(prefix), map.prefix_iter(prefix).count(), "prefix {prefix:?}");
+            }
+        }
+
+        let keys = ["apple", "app", "applesauce", "apricot", "banana", "band", "bandana", "cherry", ""];
+        let mut map: PrefixTreeMap<String, usize> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| (key.to_owned(), i))
+            .collect();
+
+        assert_eq!(map.count_prefix(""), keys.len());
+        assert_eq!(map.count_prefix("ap"), 4);
+        assert_eq!(map.count_prefix("band"), 2);
+        assert_eq!(map.count_prefix("bandanas"), 0);
+        assert_counts(&map);
+
+        // mutations through every path must keep the counts up to date
+        map.remove("app");
+        assert_counts(&map);
+        map.entry("appl".into()).or_insert(10);
+        map.entry("bar".into());
+        assert_counts(&map);
+        if let map::Entry::Occupied(entry) = map.entry("band".into()) {
+            entry.remove();
+        }
+        assert_counts(&map);
+        map.retain(|key, _| !key.starts_with("ch"));
+        assert_counts(&map);
+        map.extract_if(|key, _| key.len() == 4).for_each(drop);
+        assert_counts(&map);
+        map.pop_first();
+        map.pop_last();
+        map.first_entry().unwrap().remove();
+        map.last_entry().unwrap().remove();
+        assert_counts(&map);
+
+        let mut other = map.split_off("apr");
+        assert_counts(&map);
+        assert_counts(&other);
+        other.insert("apply".into(), 11);
+        map.append(&mut other);
+        assert_counts(&map);
+
+        let removed = map.remove_prefix("ap");
+        assert_counts(&map);
+        assert_counts(&removed);
+        assert_eq!(map.count_prefix(""), map.len());
+        assert_eq!(removed.count_prefix(""), 3);
+
+        let set: PrefixTreeSet<&str> = ["ab", "abc", "b"].into_iter().collect();
+        assert_eq!(set.count_prefix("a"), 2);
+        assert_eq!(set.count_prefix("abcd"), 0);
+    }
+
+    #[test]
+    fn order_statistics() {
+        let mut map: PrefixTreeMap<&str, usize> = ["", "a", "ab", "abc", "abd", "b", "ba", "bcd", "c"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, key)| (key, i))
+            .collect();
+
+        for (i, (key, value)) in map.clone().iter().enumerate() {
+            assert_eq!(map.get_index(i), Some((key, value)));
+            assert_eq!(map.rank(key), i);
+        }
+        assert_eq!(map.get_index(map.len()), None);
+
+        // absent keys
+        assert_eq!(map.rank("aa"), 2);
+        assert_eq!(map.rank("abe"), 5);
+        assert_eq!(map.rank("bc"), 7);
+        assert_eq!(map.rank("bcde"), 8);
+        assert_eq!(map.rank("d"), map.len());
+
+        // nodes without items must be skipped
+        map.remove("ab");
+        map.remove("b");
+        assert_eq!(map.get_index(2), Some((&"abc", &3)));
+        assert_eq!(map.get_index(4), Some((&"ba", &6)));
+        assert_eq!(map.rank("ba"), 4);
+        assert_eq!(map.rank("abcd"), 3);
+
+        let set = PrefixTreeSet::from(["x", "xy", "z"]);
+        assert_eq!(set.get_index(1), Some(&"xy"));
+        assert_eq!(set.rank("y"), 2);
+    }
+
+    #[test]
+    fn longest_common_prefix() {
+        let mut map = PrefixTreeMap::from([
+            ("/usr/local/bin", 1),
+            ("/usr/local/lib", 2),
+            ("/usr/local/libexec", 3),
+            ("/usr/share", 4),
+        ]);
+
+        assert_eq!(map.longest_common_prefix(), b"/usr/");
+        assert_eq!(map.longest_common_prefix_under("/usr/l").unwrap(), b"/usr/local/");
+        assert_eq!(map.longest_common_prefix_under("/usr/local/li").unwrap(), b"/usr/local/lib");
+        assert_eq!(map.longest_common_prefix_under("/usr/s").unwrap(), b"/usr/share");
+        assert_eq!(map.longest_common_prefix_under("/usr/x"), None);
+        assert_eq!(map.longest_common_prefix_under("/usr/shared"), None);
+
+        // removed keys leave empty nodes behind, which must not count
+        map.remove("/usr/share");
+        map.remove("/usr/local/bin");
+        assert_eq!(map.longest_common_prefix(), b"/usr/local/lib");
+        assert_eq!(map.longest_common_prefix_under("/usr/s"), None);
+
+        assert_eq!(PrefixTreeMap::<&str, ()>::new().longest_common_prefix(), b"");
+
+        let set = PrefixTreeSet::from(["abc", "abd", "ab"]);
+        assert_eq!(set.longest_common_prefix(), b"ab");
+        assert_eq!(set.longest_common_prefix_under("abc").unwrap(), b"abc");
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        fn levenshtein(a: &[u8], b: &[u8]) -> usize {
+            let mut row: Vec<usize> = (0..=b.len()).collect();
+
+            for (i, &x) in a.iter().enumerate() {
+                let mut prev = row[0];
+                row[0] = i + 1;
+
+                for (j, &y) in b.iter().enumerate() {
+                    let next = (prev + usize::from(x != y)).min(row[j] + 1).min(row[j + 1] + 1);
+                    prev = row[j + 1];
+                    row[j + 1] = next;
+                }
+            }
+
+            row[b.len()]
+        }
+
+        let words = [
+            "", "a", "cat", "cart", "care", "card", "cast", "act", "bat", "cattle",
+            "scatter", "at", "ca", "dog", "dot", "cot", "coat", "catalog",
+        ];
+        let set = PrefixTreeSet::from(words);
+
+        for query in ["cat", "", "cot", "catlog", "xyz"] {
+            for max_edits in 0..4 {
+                let found: Vec<_> = set.fuzzy_iter(query, max_edits).collect();
+                let mut expected: Vec<_> = words
+                    .iter()
+                    .map(|word| (word, levenshtein(word.as_bytes(), query.as_bytes())))
+                    .filter(|&(_, distance)| distance <= max_edits)
+                    .collect();
+                expected.sort();
+
+                assert_eq!(found, expected, "query {query:?}, max. {max_edits} edits");
+            }
+        }
+
+        let map = PrefixTreeMap::from([("kitten", 1), ("sitting", 2), ("mitten", 3)]);
+        let mut iter = map.fuzzy_iter("kitten", 1);
+        assert_eq!(iter.size_hint(), (0, Some(3)));
+        assert_eq!(format!("{iter:?}"), r#"[("kitten", 1, 0), ("mitten", 3, 1)]"#);
+        assert_eq!(iter.next(), Some((&"kitten", &1, 0)));
+        assert_eq!(iter.next(), Some((&"mitten", &3, 1)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn scored_completions() {
+        use scored::ScoredMap;
+
+        let entries: Vec<(String, u32)> = (0..500_u32)
+            .map(|i| (format!("{:x}", i * 7919 % 4096), i * 31 % 97))
+            .collect();
+        let mut map: ScoredMap<String, u32> = entries.iter().cloned().collect();
+
+        let check = |map: &ScoredMap<String, u32>| {
+            for prefix in ["", "1", "a", "ff", "10", "abc", "zzz"] {
+                let max = map.iter().filter(|(key, _)| key.starts_with(prefix)).map(|(_, score)| score).max();
+                assert_eq!(map.max_score_with_prefix(prefix), max);
+
+                for k in [0, 1, 5, 50, 1000] {
+                    let mut expected: Vec<_> = map.iter().filter(|(key, _)| key.starts_with(prefix)).collect();
+                    expected.sort_by(|(k1, s1), (k2, s2)| s2.cmp(s1).then(k1.cmp(k2)));
+                    expected.truncate(k);
+
+                    assert_eq!(map.top_k_with_prefix(prefix, k), expected, "prefix {prefix:?}, k = {k}");
+                }
+            }
+        };
+
+        assert_eq!(map.len(), entries.len());
+        check(&map);
+
+        for (key, _) in entries.iter().step_by(3) {
+            assert!(map.remove(key).is_some());
+        }
+        assert_eq!(map.insert("ff".into(), 1000), None);
+        assert_eq!(map.insert("ff".into(), 500), Some(1000));
+        check(&map);
+
+        assert_eq!(map.max_score_with_prefix("f"), Some(&500));
+        assert_eq!(map.max_score_with_prefix("xyz"), None);
+        assert_eq!(map.get("ff"), Some(&500));
+        assert!(map.iter().zip(map.iter().skip(1)).all(|((k1, _), (k2, _))| k1 < k2));
+
+        let small = ScoredMap::from_iter([("b", 1), ("a", 2)]);
+        assert_eq!(format!("{small:?}"), r#"{"a": 2, "b": 1}"#);
+    }
+
+    #[test]
+    fn path_compression() {
+        let keys = ["https://example.com/a", "https://example.com/ab", "https://example.com/abc", "https://example.org/", "ftp://x"];
+        let full = PrefixTreeMap::from(keys.map(|key| (key, key.len())));
+
+        // removal must leave the tree in the same shape as if the
+        // remaining keys had been inserted into an empty map
+        for i in 0..keys.len() {
+            for j in 0..keys.len() {
+                let mut map = full.clone();
+                map.remove(keys[i]);
+                map.remove(keys[j]);
+
+                let expected: PrefixTreeMap<_, _> = keys
+                    .iter()
+                    .filter(|&&key| key != keys[i] && key != keys[j])
+                    .map(|&key| (key, key.len()))
+                    .collect();
+
+                assert_eq!(map, expected, "removed {:?} and {:?}", keys[i], keys[j]);
+
+                let mut map = full.clone();
+                map.retain(|&key, _| key != keys[i] && key != keys[j]);
+                assert_eq!(map, expected);
+
+                let mut map = full.clone();
+                map.extract_if(|&key, _| key == keys[i] || key == keys[j]).for_each(drop);
+                assert_eq!(map, expected);
+            }
+        }
+
+        let mut map = full.clone();
+        let upper = map.split_off("https://example.com/abc");
+        assert_eq!(upper, PrefixTreeMap::from([keys[2], keys[3]].map(|key| (key, key.len()))));
+        assert_eq!(map, PrefixTreeMap::from([keys[0], keys[1], keys[4]].map(|key| (key, key.len()))));
+
+        // removal through an entry prunes and compresses the path as well
+        for i in 0..keys.len() {
+            let mut map = full.clone();
+            if let Entry::Occupied(entry) = map.entry(keys[i]) {
+                entry.remove();
+            }
+            let expected: PrefixTreeMap<_, _> = keys
+                .iter()
+                .filter(|&&key| key != keys[i])
+                .map(|&key| (key, key.len()))
+                .collect();
+            assert_eq!(map, expected, "removed {:?}", keys[i]);
+        }
+
+        let mut map = full.clone();
+        map.first_entry().unwrap().remove();
+        map.last_entry().unwrap().remove();
+        assert_eq!(map, PrefixTreeMap::from([keys[0], keys[1], keys[2]].map(|key| (key, key.len()))));
+    }
+
+    #[test]
+    fn dense_nodes() {
+        // every byte after a common prefix, inserted in a scrambled order,
+        // so that nodes grow through all size classes of the child index
+        let keys: Vec<Vec<u8>> = (0..=255_u8)
+            .map(|i| i.wrapping_mul(167))
+            .flat_map(|b| [vec![b'x', b], vec![b'x', b, b]])
+            .collect();
+        let mut map: PrefixTreeMap<Vec<u8>, usize> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.clone(), i))
+            .collect();
+
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(map.get(key), Some(&i));
+        }
+        assert!(map.keys().zip(map.keys().skip(1)).all(|(k1, k2)| k1 < k2));
+        assert_eq!(map.count_prefix("x"), 512);
+        assert_eq!(map.rank(&[b'x', 0x80][..]), 256);
+
+        // shrink the node below the threshold and grow it again
+        for key in keys.iter().filter(|key| key[1] % 32 != 0) {
+            assert!(map.remove(key).is_some());
+        }
+        assert_eq!(map.len(), 16);
+        assert!(map.keys().all(|key| key[1] % 32 == 0));
+        assert!(map.contains_key(&[b'x', 0x40, 0x40][..]));
+        assert_eq!(map.get(&[b'x', 0x41][..]), None);
+
+        map.extend((0..=255_u8).map(|b| (vec![b'x', b], 0)));
+        assert_eq!(map.len(), 16 + 256 - 8);
+
+        let upper = map.split_off(&[b'x', 0x80][..]);
+        assert_eq!(map.get(&[b'x', 0x7f][..]), Some(&0));
+        assert!(upper.contains_key(&[b'x', 0xe0, 0xe0][..]));
+        assert_eq!(upper.get(&[b'x', 0x7f][..]), None);
+        assert_eq!(map.len() + upper.len(), 16 + 256 - 8);
+
+        map.retain(|key, _| key.len() == 2 && key[1] % 2 == 0);
+        assert_eq!(map.len(), 64);
+        assert!(map.iter().all(|(key, _)| key[1] % 2 == 0));
+    }
+
+    #[test]
+    fn nibble_keys() {
+        use std::collections::BTreeMap;
+        use nibble::NibblePrefixTreeMap;
+
+        let keys: Vec<Vec<u8>> = (0..300_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec())
+            .collect();
+        let mut btree: BTreeMap<_, _> = keys.iter().map(|key| (key.clone(), key.len())).collect();
+        let mut map: NibblePrefixTreeMap<Vec<u8>, usize> = keys.iter().map(|key| (key.clone(), key.len())).collect();
+
+        assert_eq!(map.len(), btree.len());
+        assert!(map.iter().eq(btree.iter()));
+
+        for key in &keys {
+            assert_eq!(map.get(key), Some(&key.len()));
+
+            for len in 0..=key.len() * 2 {
+                let expected = btree
+                    .keys()
+                    .filter(|other| other.len() * 2 >= len && (0..len).all(|i| nibble(other, i) == nibble(key, i)))
+                    .count();
+                assert_eq!(map.prefix_iter_nibbles(key, len).count(), expected);
+
+                if len % 2 == 0 {
+                    assert_eq!(map.count_prefix(&key[..len / 2]), expected);
+                }
+            }
+
+            let mut missing = key.clone();
+            missing.push(0xab);
+            assert_eq!(map.get(&missing), btree.get(&missing));
+        }
+
+        // nodes are split and merged at half-byte boundaries
+        for key in keys.iter().step_by(3) {
+            assert_eq!(map.remove(key), btree.remove(key));
+            assert!(!map.contains_key(key));
+        }
+        assert!(map.iter().eq(btree.iter()));
+
+        map.insert(vec![0x12, 0x30], 1);
+        map.insert(vec![0x12, 0x3f], 2);
+        assert_eq!(map.insert(vec![0x12, 0x30], 3), Some(1));
+        assert_eq!(map.prefix_iter_nibbles(&[0x12, 0x30], 3).count(), 2);
+        assert_eq!(map.prefix_iter_nibbles(&[0x12, 0x30], 4).count(), 1);
+    }
+
+    #[test]
+    fn nibble_map_memory() {
+        use nibble::NibblePrefixTreeMap;
+
+        // random keys, from a xorshift generator
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random_byte = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        };
+
+        // nodes of a byte tree below its root have well over 16 children,
+        // but not nearly 256, for this many keys
+        for (count, len) in [(10_000, 8), (10_000, 16), (30_000, 8)] {
+            let keys: Vec<Vec<u8>> = (0..count).map(|_| (0..len).map(|_| random_byte()).collect()).collect();
+            let mut bytes: PrefixTreeMap<_, _> = keys.iter().map(|key| (key.clone(), ())).collect();
+            let mut nibbles: NibblePrefixTreeMap<_, _> = keys.iter().map(|key| (key.clone(), ())).collect();
+
+            assert!(
+                nibbles.allocated_bytes() < bytes.allocated_bytes(),
+                "{count} keys of {len} bytes: {} bytes for nibbles, {} for bytes",
+                nibbles.allocated_bytes(),
+                bytes.allocated_bytes(),
+            );
+
+            // packed labels are split and merged in the middle of bytes
+            for key in keys.iter().step_by(2) {
+                assert_eq!(nibbles.remove(key), bytes.remove(key));
+            }
+            for key in &keys {
+                assert_eq!(nibbles.contains_key(key), bytes.contains_key(key));
+            }
+        }
+    }
+
+    /// Returns the `index`-th nibble of `bytes`, high nibble first.
+    fn nibble(bytes: &[u8], index: usize) -> u8 {
+        bytes[index / 2] >> (4 - index % 2 * 4) & 0x0f
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        use bits::BitPrefixTreeMap;
+
+        let mut routes = BitPrefixTreeMap::new();
+
+        assert_eq!(routes.longest_prefix_match(&[10, 0, 0, 1]), None);
+
+        assert_eq!(routes.insert(&[10, 0, 0, 0], 8, 'a'), None);
+        assert_eq!(routes.insert(&[10, 128, 0, 0], 9, 'b'), None);
+        assert_eq!(routes.insert(&[10, 255, 255, 255], 9, 'c'), Some('b'));
+        assert_eq!(routes.insert(&[172, 16, 0, 0], 12, 'd'), None);
+        assert_eq!(routes.insert(&[10, 1, 2, 3], 32, 'e'), None);
+        assert_eq!(routes.len(), 4);
+
+        assert_eq!(routes.get(&[10, 128], 9), Some(&'c'));
+        assert_eq!(routes.get(&[10, 128], 10), None);
+
+        assert_eq!(routes.longest_prefix_match(&[10, 1, 2, 3]), Some((32, &'e')));
+        assert_eq!(routes.longest_prefix_match(&[10, 1, 2, 4]), Some((8, &'a')));
+        assert_eq!(routes.longest_prefix_match(&[10, 200, 0, 1]), Some((9, &'c')));
+        assert_eq!(routes.longest_prefix_match(&[172, 31, 255, 255]), Some((12, &'d')));
+        assert_eq!(routes.longest_prefix_match(&[172, 32, 0, 0]), None);
+        assert_eq!(routes.matches(&[10, 1, 2, 3]).collect::<Vec<_>>(), [(8, &'a'), (32, &'e')]);
+
+        // the values found outlive the address, which is only read during the walk
+        let longest = routes.longest_prefix_match(&Vec::from([172, 16, 1, 1]));
+        assert_eq!(longest, Some((12, &'d')));
+
+        assert_eq!(
+            routes.iter().collect::<Vec<_>>(),
+            [
+                (vec![10], 8, &'a'),
+                (vec![10, 1, 2, 3], 32, &'e'),
+                (vec![10, 128], 9, &'c'),
+                (vec![172, 16], 12, &'d'),
+            ],
+        );
+
+        assert_eq!(routes.remove(&[10, 0, 0, 0], 8), Some('a'));
+        assert_eq!(routes.longest_prefix_match(&[10, 1, 2, 4]), None);
+        assert_eq!(format!("{routes:?}"), "{0a010203/32: 'e', 0a80/9: 'c', ac10/12: 'd'}");
+    }
+
+    #[test]
+    fn implicit_keys() {
+        use std::collections::BTreeMap;
+        use implicit::ImplicitMap;
+
+        let words = ["", "a", "ab", "abc", "abd", "b", "ba", "bab", "babe", "c", "cabbage", "cab"];
+        let mut map = ImplicitMap::new();
+        let mut btree = BTreeMap::new();
+
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(map.insert(word, i), btree.insert(word.as_bytes().to_vec(), i));
+        }
+
+        assert_eq!(map.len(), btree.len());
+        assert!(map.iter().map(|(key, &value)| (key, value)).eq(btree.clone()));
+        assert!(map.values().eq(btree.values()));
+        assert_eq!(map.get("cab"), Some(&11));
+        assert_eq!(map.get("ca"), None);
+        assert_eq!(
+            map.prefix_iter("ca").collect::<Vec<_>>(),
+            [(b"cab".to_vec(), &11), (b"cabbage".to_vec(), &10)],
+        );
+        assert_eq!(map.prefix_iter("bab").count(), 2);
+        assert_eq!(map.prefix_iter("x").count(), 0);
+
+        *map.get_mut("ab").unwrap() += 100;
+        *btree.get_mut(b"ab".as_slice()).unwrap() += 100;
+        assert_eq!(map.get("ab"), Some(&102));
+
+        // removal leaves the same tree as if the key had never been inserted
+        for (i, word) in words.iter().enumerate().rev() {
+            assert_eq!(map.remove(word), btree.remove(word.as_bytes()));
+            assert_eq!(map.remove(word), None);
+
+            let rebuilt: ImplicitMap<_> = map.iter().map(|(key, &value)| (key, value)).collect();
+            assert_eq!(map, rebuilt, "after removing {i}");
+        }
+
+        assert!(map.is_empty());
+        assert_eq!(map, ImplicitMap::new());
+    }
+
+    #[test]
+    fn frozen() {
+        let mut keys: Vec<Vec<u8>> = (0..3000_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec())
+            .collect();
+        // long labels, spanning several blocks of the bit vectors
+        keys.extend((0..4_u8).map(|i| [vec![0xfe; 1000], vec![i; 1000]].concat()));
+
+        let map: PrefixTreeMap<Vec<u8>, usize> = keys.iter().map(|key| (key.clone(), key.len())).collect();
+        let frozen = map.clone().freeze();
+
+        assert_eq!(frozen.len(), map.len());
+        assert!(frozen.iter().eq(map.iter()));
+        assert!(frozen.iter().rev().eq(map.iter().rev()));
+
+        for key in &keys {
+            assert_eq!(frozen.get_entry(key), map.get_entry(key));
+
+            for len in 0..=key.len() {
+                let prefix = &key[..len];
+                assert!(frozen.prefix_iter(prefix).eq(map.prefix_iter(prefix)));
+                assert_eq!(frozen.count_prefix(prefix), map.count_prefix(prefix));
+
+                let mut missing = prefix.to_vec();
+                missing.push(0xff);
+                assert_eq!(frozen.get(&missing), map.get(&missing));
+                assert_eq!(frozen.count_prefix(&missing), map.count_prefix(&missing));
+            }
+        }
+
+        assert_eq!(frozen.clone().thaw(), map);
+        assert_eq!(frozen, frozen::FrozenPrefixTreeMap::from(map));
+
+        let empty = PrefixTreeMap::<&str, ()>::new().freeze();
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(""), None);
+        assert_eq!(empty.prefix_iter("").count(), 0);
+
+        let mut set: PrefixTreeSet<&str> = Builder::new().max_key_len(4).build_set();
+        set.extend(["", "a", "ab", "abcd", "b"]);
+
+        let frozen = set.freeze();
+        assert!(frozen.contains(""));
+        assert!(frozen.contains("ab"));
+        assert!(!frozen.contains("abc"));
+        assert_eq!(frozen.prefix_iter("ab").copied().collect::<Vec<_>>(), ["ab", "abcd"]);
+        assert_eq!(format!("{frozen:?}"), r#"{"", "a", "ab", "abcd", "b"}"#);
+
+        // the configuration survives freezing
+        let mut set = frozen.thaw();
+        assert!(set.try_insert("abcde").is_err());
+        assert_eq!(set.len(), 5);
+    }
+
+    #[test]
+    fn dafsa() {
+        use dafsa::Dafsa;
+
+        let stems = ["walk", "talk", "jump", "bark", "park", "mark", "work", "lock"];
+        let suffixes = ["", "s", "ed", "ing", "er", "ers"];
+        let set: PrefixTreeSet<String> = stems
+            .iter()
+            .flat_map(|stem| suffixes.iter().map(move |suffix| format!("{stem}{suffix}")))
+            .collect();
+        let dafsa = set.minimize();
+
+        assert_eq!(dafsa.len(), set.len());
+        assert!(dafsa.iter().eq(set.iter().map(|word| word.as_bytes().to_vec())));
+        assert!(dafsa.state_count() < 40);
+
+        for word in &set {
+            assert!(dafsa.contains(word));
+            assert!(!dafsa.contains(&format!("{word}x")));
+
+            for len in 0..=word.len() {
+                let prefix = &word[..len];
+                assert_eq!(dafsa.count_prefix(prefix), set.prefix_iter(prefix).count());
+                assert!(dafsa.prefix_iter(prefix).eq(set.prefix_iter(prefix).map(|word| word.as_bytes().to_vec())));
+            }
+        }
+
+        assert_eq!(dafsa.count_prefix("x"), 0);
+        assert_eq!(dafsa.prefix_iter("x").count(), 0);
+
+        // minimal automata are unique, regardless of insertion order or duplicates
+        let shuffled: Dafsa = set.iter().rev().chain(set.iter()).collect();
+        assert_eq!(shuffled, dafsa);
+
+        let empty = PrefixTreeSet::<&str>::new().minimize();
+        assert!(empty.is_empty());
+        assert!(!empty.contains(""));
+        assert_eq!(empty, Dafsa::default());
+
+        let words: Dafsa = [b"".as_slice(), b"a", b"\xff"].into_iter().collect();
+        assert!(words.contains(""));
+        assert_eq!(format!("{words:?}"), "{\"\", \"a\", \"\u{fffd}\"}");
+    }
+
+    #[test]
+    fn persistent() {
+        use persistent::PersistentPrefixTreeMap;
+
+        let mut map = PrefixTreeMap::new();
+        let mut persistent = PersistentPrefixTreeMap::new();
+        let mut versions = Vec::new();
+
+        for i in 0..600_u32 {
+            let key = (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec();
+
+            if i % 3 == 2 {
+                assert_eq!(persistent.remove(&key), map.remove(&key));
+            } else {
+                assert_eq!(persistent.insert(key.clone(), i), map.insert(key, i));
+            }
+
+            if i % 50 == 0 {
+                versions.push((persistent.clone(), map.clone()));
+            }
+        }
+
+        // every snapshot still sees exactly the entries it was taken with
+        for (persistent, map) in &versions {
+            assert_eq!(persistent.len(), map.len());
+            assert!(persistent.iter().eq(map.iter()));
+            assert_eq!(PrefixTreeMap::from(persistent), *map);
+
+            for (key, _) in map {
+                for len in 0..=key.len() {
+                    assert!(persistent.prefix_iter(&key[..len]).eq(map.prefix_iter(&key[..len])));
+                    assert_eq!(persistent.count_prefix(&key[..len]), map.count_prefix(&key[..len]));
+                }
+            }
+        }
+
+        // the shape of the tree only depends on the entries
+        let rebuilt: PersistentPrefixTreeMap<Vec<u8>, u32> = PersistentPrefixTreeMap::from(map.clone());
+        assert_eq!(rebuilt, persistent);
+        assert!(!rebuilt.ptr_eq(&persistent));
+
+        let (first, _) = &versions[0];
+        let updated = first.update(b"new".to_vec(), 0);
+        assert!(updated.contains_key("new"));
+        assert!(!first.contains_key("new"));
+        assert!(updated.without("new") == *first);
+        assert!(first.without("missing").ptr_eq(first));
+    }
+
+    #[test]
+    fn transaction() {
+        let mut map: PrefixTreeMap<&str, u32> = Builder::new().reject_nul().build_map();
+        map.extend([("a", 1), ("b", 2), ("c", 3)]);
+        let original = map.clone();
+
+        let mut tx = map.transaction();
+        tx.insert("d", 4);
+        tx.remove("a");
+        tx.insert("b", 20);
+        tx.insert("e", 5);
+        tx.remove("e");
+        tx.remove("missing");
+
+        assert_eq!(tx.get("a"), None);
+        assert_eq!(tx.get("b"), Some(&20));
+        assert_eq!(tx.get("c"), Some(&3));
+        assert!(tx.contains_key("d"));
+        assert!(!tx.contains_key("e"));
+        assert_eq!(tx.staged_len(), 5);
+
+        // invalid keys are rejected without being staged
+        assert!(tx.try_insert("x\0y", 0).is_err());
+        assert!(!tx.contains_key("x\0y"));
+
+        tx.rollback();
+        assert_eq!(map, original);
+
+        {
+            let mut tx = map.transaction();
+            tx.insert("z", 26);
+            // dropped without committing
+        }
+        assert_eq!(map, original);
+
+        let mut tx = map.transaction();
+        tx.insert("d", 4);
+        tx.remove("a");
+        tx.insert("b", 20);
+        tx.commit();
+
+        assert_eq!(map, PrefixTreeMap::from([("b", 20), ("c", 3), ("d", 4)]));
+    }
+
+    #[test]
+    fn cursor() {
+        let keys: Vec<Vec<u8>> = (0..300_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec())
+            .collect();
+        let mut map: PrefixTreeMap<Vec<u8>, usize> = keys.iter().map(|key| (key.clone(), key.len())).collect();
+
+        // removed keys leave nothing behind that the cursor could enter
+        for byte in 0..=255 {
+            map.insert(vec![0x42, byte], 0);
+
+            if let Entry::Occupied(entry) = map.entry(vec![0x42, byte]) {
+                entry.remove();
+            }
+        }
+
+        let mut cursor = map.cursor();
+
+        for key in &keys {
+            cursor.reset();
+
+            for (depth, &byte) in key.iter().enumerate() {
+                let prefix = &key[..depth];
+                let mut expected: Vec<u8> = map.prefix_iter(prefix)
+                    .filter_map(|(key, _)| key.get(depth).copied())
+                    .collect();
+                expected.dedup();
+
+                assert_eq!(cursor.key(), prefix);
+                assert_eq!(cursor.next_bytes().collect::<Vec<_>>(), expected);
+                assert_eq!(cursor.len(), map.count_prefix(prefix));
+                assert!(cursor.prefix_iter().eq(map.prefix_iter(prefix)));
+                assert_eq!(cursor.get_entry(), map.get_entry(prefix));
+
+                assert!(cursor.descend(byte));
+            }
+
+            assert_eq!(cursor.value(), Some(&key.len()));
+
+            // going back up retraces the same prefixes
+            for depth in (0..key.len()).rev() {
+                assert!(cursor.ascend());
+                assert_eq!(cursor.key(), &key[..depth]);
+                assert_eq!(cursor.get_entry(), map.get_entry(&key[..depth]));
+            }
+
+            assert!(!cursor.ascend());
+        }
+
+        cursor.reset();
+        assert_eq!(cursor.descend(0x42), map.count_prefix(&[0x42]) > 0);
+
+        let empty = PrefixTreeMap::<&str, ()>::new();
+        let mut cursor = empty.cursor();
+        assert!(cursor.is_empty());
+        assert!(!cursor.descend(b'a'));
+        assert_eq!(cursor.next_bytes().count(), 0);
+    }
+
+    #[test]
+    fn cursor_mut() {
+        let mut map: PrefixTreeMap<Vec<u8>, u32> = Builder::new().max_key_len(6).build_map();
+        map.extend([(b"abc".to_vec(), 1), (b"abd".to_vec(), 2), (b"b".to_vec(), 3)]);
+
+        let mut cursor = map.cursor_mut();
+        cursor.descend(b'a');
+        cursor.descend(b'b');
+        assert_eq!(cursor.key(), b"ab");
+        assert_eq!(cursor.get(), None);
+        assert_eq!(cursor.len(), 2);
+        assert_eq!(cursor.as_cursor().unwrap().next_bytes().collect::<Vec<_>>(), b"cd");
+
+        assert_eq!(cursor.insert(10), None);
+        assert_eq!(cursor.insert(11), Some(10));
+        *cursor.get_mut().unwrap() += 1;
+        assert_eq!(cursor.get_entry(), Some((&b"ab".to_vec(), &12)));
+        assert_eq!(cursor.len(), 3);
+
+        // positions without any keys yet
+        cursor.descend(b'x');
+        assert!(cursor.as_cursor().is_none());
+        assert!(cursor.is_empty());
+        assert_eq!(cursor.insert_with(|bytes| bytes.to_vec(), 13), None);
+        assert_eq!(cursor.remove(), Some(13));
+        assert_eq!(cursor.remove(), None);
+        assert!(cursor.ascend());
+
+        let subtree = cursor.remove_subtree();
+        assert_eq!(subtree.keys().collect::<Vec<_>>(), [&b"ab"[..], b"abc", b"abd"]);
+        assert!(cursor.is_empty());
+
+        cursor.reset();
+        cursor.descend(b'z');
+        cursor.graft(PrefixTreeMap::from([(b"".to_vec(), 0), (b"yx".to_vec(), 1)]));
+        assert_eq!(cursor.key(), b"z");
+        assert_eq!(cursor.len(), 2);
+
+        assert_eq!(
+            map.iter().map(|(key, &value)| (key.as_slice(), value)).collect::<Vec<_>>(),
+            [(&b"b"[..], 3), (b"z", 0), (b"zyx", 1)],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid key")]
+    fn cursor_mut_validation() {
+        let mut map: PrefixTreeMap<Vec<u8>, u32> = Builder::new().max_key_len(2).build_map();
+        let mut cursor = map.cursor_mut();
+
+        cursor.descend(b'a');
+        cursor.descend(b'b');
+        cursor.insert(1);
+        cursor.descend(b'c');
+        cursor.insert(2);
+    }
+
+    #[test]
+    fn sub_trie() {
+        let keys: Vec<Vec<u8>> = (0..300_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec())
+            .collect();
+        let map: PrefixTreeMap<Vec<u8>, usize> = keys.iter().map(|key| (key.clone(), key.len())).collect();
+
+        for key in &keys {
+            for len in 0..=key.len() {
+                let (outer, inner) = key.split_at(len);
+
+                // every way of splitting a prefix into nested views is the same
+                for split in 0..=len {
+                    let sub = map.prefix(&outer[..split]).prefix(&outer[split..]);
+
+                    assert_eq!(sub.len(), map.count_prefix(outer));
+                    assert!(sub.iter().eq(map.prefix_iter(outer)));
+                    assert_eq!(sub.get_entry(inner), map.get_entry(key));
+                    assert!(sub.contains_key(inner));
+                    assert!(sub.prefix_iter(inner).eq(map.prefix_iter(key)));
+
+                    let mut missing = inner.to_vec();
+                    missing.push(0xff);
+                    assert_eq!(sub.get(&missing), map.get(&[outer, &missing].concat()));
+                    assert_eq!(sub.prefix(&missing).len(), map.count_prefix(&[outer, &missing].concat()));
+                }
+            }
+        }
+
+        let sub = map.prefix(b"\xff\xff\xff\xff\xff\xff");
+        assert!(sub.is_empty());
+        assert_eq!(sub.get(""), None);
+        assert_eq!(sub.prefix("").iter().count(), 0);
+    }
+
+    #[test]
+    fn sub_trie_mut() {
+        let mut map: PrefixTreeMap<String, u32> = Builder::new().max_key_len(8).build_map();
+        map.extend([("a.x".into(), 1), ("a.y".into(), 2), ("ab".into(), 3), ("b.x".into(), 4)]);
+
+        let mut sub = map.prefix_mut("a.");
+        assert_eq!(sub.prefix(), b"a.");
+        assert_eq!(sub.len(), 2);
+        assert_eq!(sub.get("x"), Some(&1));
+        assert_eq!(sub.get_entry("y"), Some((&"a.y".to_string(), &2)));
+        assert!(!sub.contains_key("b"));
+        assert_eq!(sub.remove(".x"), None);
+
+        *sub.get_mut("x").unwrap() += 10;
+        assert_eq!(sub.insert_full("a.z".into(), 5), None);
+        assert_eq!(sub.insert_full("a.y".into(), 20), Some(2));
+        assert_eq!(sub.try_insert_full("ab.c".into(), 0), Err(InvalidKey::OutsidePrefix { index: 1 }));
+        assert_eq!(sub.try_insert_full("a".into(), 0), Err(InvalidKey::OutsidePrefix { index: 1 }));
+        assert!(matches!(sub.try_insert_full("a.too_long".into(), 0), Err(InvalidKey::TooLong { .. })));
+
+        // inserted keys are complete, while looked up and removed keys are relative
+        assert_eq!(sub.try_insert_full("w".into(), 0), Err(InvalidKey::OutsidePrefix { index: 0 }));
+        assert_eq!(sub.insert_full("a.w".into(), 7), None);
+        assert_eq!(sub.get("w"), Some(&7));
+        assert_eq!(sub.get("a.w"), None);
+        assert_eq!(sub.remove("a.w"), None);
+        assert_eq!(sub.remove("w"), Some(7));
+
+        for (_, value) in sub.iter_mut() {
+            *value *= 2;
+        }
+
+        {
+            let mut nested = sub.prefix_mut("z");
+            assert_eq!(nested.remove(""), Some(10));
+            assert!(nested.is_empty());
+        }
+
+        assert_eq!(sub.len(), 2);
+        assert_eq!(sub.prefix_iter("y").count(), 1);
+        assert_eq!(map.len(), 4);
+
+        let expected: PrefixTreeMap<String, u32> = PrefixTreeMap::from([
+            ("a.x".into(), 22), ("a.y".into(), 40), ("ab".into(), 3), ("b.x".into(), 4),
+        ]);
+        assert_eq!(map, expected);
+
+        map.prefix_mut("a").clear();
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["b.x"]);
+    }
+
+    #[test]
+    fn entry_ref() {
+        let mut map: PrefixTreeMap<Vec<u8>, Vec<u32>> = PrefixTreeMap::new();
+
+        assert!(matches!(map.entry_ref(b"abc".as_slice()), EntryRef::Vacant(_)));
+        assert!(map.is_empty());
+
+        map.entry_ref(b"abc".as_slice()).or_default().push(1);
+        map.entry_ref(b"abc".as_slice())
+            .and_modify(|values| values.push(2))
+            .or_insert_with(|| unreachable!("the entry is occupied"));
+        map.entry_ref(b"abd".as_slice())
+            .and_modify(|_| unreachable!("the entry is vacant"))
+            .or_insert_with_key(|key| vec![u32::from(key[2])]);
+
+        assert_eq!(map.entry_ref(b"abc".as_slice()).key(), b"abc");
+        assert_eq!(map.get(b"abc"), Some(&vec![1, 2]));
+        assert_eq!(map.get(b"abd"), Some(&vec![100]));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.entry_ref(b"abd".as_slice()).remove(), Some(vec![100]));
+        assert_eq!(map.entry_ref(b"abd".as_slice()).remove_entry(), None);
+        assert_eq!(map.len(), 1);
+
+        let mut map: PrefixTreeMap<String, u32> = Builder::new().require_utf8().build_map();
+        assert!(map.try_entry_ref(b"\xff".as_slice()).is_err());
+
+        if let EntryRef::Vacant(entry) = map.entry_ref("key") {
+            assert_eq!(entry.key(), "key");
+            assert_eq!(*entry.insert(7), 7);
+        }
+
+        assert_eq!(map.get("key"), Some(&7));
+    }
+
+    #[test]
+    fn lazy_vacant_entries() {
+        let full = PrefixTreeMap::from([("abc", 1), ("abcdef", 2), ("abx", 3)]);
+
+        // diverging inside an edge, ending inside an edge, below a leaf,
+        // at an existing inner node, and from the root
+        for key in ["abd", "abcd", "abcdefg", "ab", "x", ""] {
+            let mut map = full.clone();
+
+            let Entry::Vacant(entry) = map.entry(key) else {
+                panic!("{key:?} should be vacant");
+            };
+            assert_eq!(*entry.key(), key);
+            drop(entry);
+            assert_eq!(map, full, "{key:?}");
+
+            let EntryRef::Vacant(_) = map.entry_ref(key) else {
+                panic!("{key:?} should be vacant");
+            };
+            assert_eq!(map, full, "{key:?}");
+
+            *map.entry(key).or_insert(0) += 10;
+            assert_eq!(map.get(key), Some(&10));
+            assert_eq!(map.len(), 4);
+            assert_eq!(map.count_prefix(key), full.count_prefix(key) + 1);
+
+            let mut expected = full.clone();
+            expected.insert(key, 10);
+            assert_eq!(map, expected);
+        }
+    }
+
+    #[test]
+    fn set_get_or_insert_with() {
+        let mut pool: PrefixTreeSet<String> = PrefixTreeSet::from_iter(["a", "abc"].map(String::from));
+        let mut calls = 0;
+
+        for word in ["ab", "abc", "ab", "b", "a"] {
+            let item = pool.get_or_insert_with(word, |word: &str| {
+                calls += 1;
+                String::from(word)
+            });
+            assert_eq!(item, word);
+        }
+
+        assert_eq!(calls, 2);
+        assert_eq!(pool, PrefixTreeSet::from_iter(["a", "ab", "abc", "b"].map(String::from)));
+        assert_eq!(pool.count_prefix("a"), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "item doesn't match the value")]
+    fn set_get_or_insert_with_mismatch() {
+        let mut set: PrefixTreeSet<String> = PrefixTreeSet::new();
+        set.get_or_insert_with("foo", |_| String::from("bar"));
+    }
+
+    #[test]
+    fn set_get_take_replace() {
+        use std::rc::Rc;
+
+        let mut set: PrefixTreeSet<Rc<[u8]>> = [&b"foo"[..], b"bar", b"foobar"].into_iter().map(Rc::from).collect();
+
+        let stored = set.get("foo").cloned().unwrap();
+        assert_eq!(&*stored, b"foo");
+        assert!(set.get("fo").is_none());
+        assert!(set.get("foob").is_none());
+
+        let new: Rc<[u8]> = Rc::from(&b"foo"[..]);
+        let old = set.replace(Rc::clone(&new)).unwrap();
+        assert!(Rc::ptr_eq(&old, &stored));
+        assert!(Rc::ptr_eq(set.get("foo").unwrap(), &new));
+        assert_eq!(set.replace(Rc::from(&b"qux"[..])), None);
+        assert_eq!(set.len(), 4);
+
+        let taken = set.take("foo").unwrap();
+        assert!(Rc::ptr_eq(&taken, &new));
+        assert_eq!(set.take("foo"), None);
+        assert_eq!(set.take("foob"), None);
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.count_prefix("foo"), 1);
+        assert!(set.iter().map(|item| &**item).eq([&b"bar"[..], b"foobar", b"qux"]));
+    }
+
+    #[test]
+    fn lazy_set_operations() {
+        let a: PrefixTreeSet<&str> = PrefixTreeSet::from(["", "a", "ab", "abc", "b", "ba"]);
+        let b: PrefixTreeSet<&str> = PrefixTreeSet::from(["ab", "abd", "b", "c"]);
+        let empty = PrefixTreeSet::new();
+
+        let cases: [(&PrefixTreeSet<&str>, &PrefixTreeSet<&str>); 4] = [(&a, &b), (&b, &a), (&a, &empty), (&empty, &b)];
+
+        for (lhs, rhs) in cases {
+            let union: Vec<_> = lhs.union_iter(rhs).copied().collect();
+            let intersection: Vec<_> = lhs.intersection_iter(rhs).copied().collect();
+            let difference: Vec<_> = lhs.difference_iter(rhs).copied().collect();
+            let symmetric_difference: Vec<_> = lhs.symmetric_difference_iter(rhs).copied().collect();
+
+            assert_eq!(union, Vec::from_iter(lhs.union_ref(rhs)));
+            assert_eq!(intersection, Vec::from_iter(lhs.intersection_ref(rhs)));
+            assert_eq!(difference, Vec::from_iter(lhs.difference_ref(rhs)));
+            assert_eq!(symmetric_difference, Vec::from_iter(lhs.symmetric_difference_ref(rhs)));
+
+            for (iter, len) in [
+                (lhs.union_iter(rhs).size_hint(), union.len()),
+                (lhs.intersection_iter(rhs).size_hint(), intersection.len()),
+                (lhs.difference_iter(rhs).size_hint(), difference.len()),
+                (lhs.symmetric_difference_iter(rhs).size_hint(), symmetric_difference.len()),
+            ] {
+                assert!(iter.0 <= len && len <= iter.1.unwrap());
+            }
+        }
+
+        // items in both sets are taken from `self`
+        let lhs = PrefixTreeSet::from([String::from("x")]);
+        let rhs = PrefixTreeSet::from([String::from("x")]);
+        assert!(core::ptr::eq(lhs.union_iter(&rhs).next().unwrap(), lhs.first().unwrap()));
+        assert!(core::ptr::eq(lhs.intersection_iter(&rhs).next().unwrap(), lhs.first().unwrap()));
+        assert_eq!(format!("{:?}", a.difference_iter(&b)), r#"["", "a", "abc", "ba"]"#);
+    }
+
+    #[test]
+    fn intersect_with() {
+        let x: PrefixTreeSet<String> = (0..300).map(|i| format!("{}", i * 2)).collect();
+        let y: PrefixTreeSet<String> = (0..300).map(|i| format!("{}", i * 3)).collect();
+
+        let mut z = x.clone();
+        z.intersect_with(&y);
+        assert_eq!(z, x.intersection_ref(&y));
+        assert_eq!(z.len(), 100);
+
+        // keys ending inside an edge of the other tree; the result must be
+        // compressed just like a tree built from scratch
+        let a = PrefixTreeMap::from([("international", 1), ("in", 2), ("inter", 3), ("io", 4), ("", 5)]);
+        let b = PrefixTreeSet::from(["internationally", "inter", "i", "international", "j"]);
+
+        let mut c = a.clone();
+        c.intersect_with(&b.clone().into_map());
+        assert_eq!(c, PrefixTreeMap::from([("inter", 3), ("international", 1)]));
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.count_prefix("i"), 2);
+
+        let mut d = b.clone();
+        d.intersect_with(&a.keys().copied().collect::<PrefixTreeSet<_>>());
+        assert_eq!(d, PrefixTreeSet::from(["inter", "international"]));
+
+        let mut e = b.clone();
+        e.intersect_with(&PrefixTreeSet::from(["internationalize"]));
+        assert!(e.is_empty());
+        assert_eq!(e, PrefixTreeSet::new());
+
+        let mut f = a.clone();
+        f.intersect_with(&a);
+        assert_eq!(f, a);
+    }
+
+    #[test]
+    fn zip_by_key() {
+        let lhs: PrefixTreeMap<String, usize> = ["", "a", "ab", "abc", "b"].into_iter().map(|k| (k.into(), k.len())).collect();
+        let rhs: PrefixTreeMap<Vec<u8>, char> = PrefixTreeMap::from([(b"ab".to_vec(), 'x'), (b"abd".to_vec(), 'y'), (b"c".to_vec(), 'z')]);
+
+        let zipped: Vec<_> = lhs
+            .zip_by_key(&rhs)
+            .map(|(l, r)| (l.map(|(k, v)| (k.as_str(), *v)), r.map(|(k, v)| (k.as_slice(), *v))))
+            .collect();
+
+        assert_eq!(zipped, [
+            (Some(("", 0)), None),
+            (Some(("a", 1)), None),
+            (Some(("ab", 2)), Some((&b"ab"[..], 'x'))),
+            (Some(("abc", 3)), None),
+            (None, Some((&b"abd"[..], 'y'))),
+            (Some(("b", 1)), None),
+            (None, Some((&b"c"[..], 'z'))),
+        ]);
+
+        let iter = lhs.zip_by_key(&rhs);
+        assert_eq!(iter.size_hint(), (5, Some(8)));
+        assert_eq!(iter.count(), 7);
+
+        let empty = PrefixTreeMap::<&str, ()>::new();
+        assert_eq!(empty.zip_by_key(&empty).next(), None);
+        assert!(lhs.zip_by_key(&empty).all(|(l, r)| l.is_some() && r.is_none()));
+    }
+
+    #[test]
+    fn diff() {
+        let old: PrefixTreeMap<String, u32> = (0..200).map(|i| (format!("{i}"), i)).collect();
+        let mut new = old.clone();
+
+        new.remove("1");
+        new.remove("150");
+        new.insert("10".into(), 0);
+        new.insert("1000".into(), 1000);
+        new.insert("15".into(), 15);
+
+        let changes: Vec<_> = old.diff(&new).collect();
+        assert_eq!(changes, [
+            Change::Removed { key: &String::from("1"), value: &1 },
+            Change::Modified { key: &String::from("10"), old: &10, new: &0 },
+            Change::Added { key: &String::from("1000"), value: &1000 },
+            Change::Removed { key: &String::from("150"), value: &150 },
+        ]);
+        assert!(changes.iter().map(Change::key).eq(["1", "10", "1000", "150"].iter()));
+
+        // the reverse diff swaps additions and removals
+        let reverse: Vec<_> = new.diff(&old).collect();
+        assert_eq!(reverse[0], Change::Added { key: &String::from("1"), value: &1 });
+        assert_eq!(reverse[1], Change::Modified { key: &String::from("10"), old: &0, new: &10 });
+
+        assert_eq!(old.diff(&old).next(), None);
+        assert_eq!(old.diff(&PrefixTreeMap::new()).count(), old.len());
+
+        let mut patched = old.clone();
+        patched.apply(changes.into_iter().map(Change::cloned));
+        assert_eq!(patched, new);
+
+        patched.apply(reverse.into_iter().map(Change::cloned));
+        assert_eq!(patched, old);
+    }
+
+    #[test]
+    fn map_values() {
+        let keys: Vec<String> = (0..500).map(|i| format!("{:x}", i * 7919)).collect();
+        let map: PrefixTreeMap<String, usize> = keys.iter().cloned().zip(0..).collect();
+
+        let mut visited = Vec::new();
+        let mapped = map.clone().map_values(|key, value| {
+            visited.push(key.clone());
+            value.to_string()
+        });
+
+        assert!(visited.iter().eq(map.keys()));
+        assert_eq!(mapped.len(), map.len());
+        assert_eq!(mapped, map.iter().map(|(k, v)| (k.clone(), v.to_string())).collect());
+        assert_eq!(mapped.count_prefix("1"), map.count_prefix("1"));
+
+        let (key, value) = map.get_index(100).unwrap();
+        assert_eq!(mapped.get_index(100), Some((key, &value.to_string())));
+
+        // the configuration is kept
+        let mut limited = Builder::new().max_key_len(3).build_map();
+        limited.insert("abc", 1);
+        let mut limited = limited.map_values(|_, value| value * 2);
+        assert_eq!(limited["abc"], 2);
+        assert!(limited.try_insert("abcd", 0).is_err());
+    }
+
+    #[test]
+    fn filter_map_values() {
+        let keys: Vec<String> = (0..500).map(|i| format!("{:x}", i * 7919)).collect();
+        let map: PrefixTreeMap<String, usize> = keys.iter().cloned().zip(0..).collect();
+
+        let mut visited = Vec::new();
+        let odd = map.clone().filter_map_values(|key, value| {
+            visited.push(key.clone());
+            (value % 2 == 1).then(|| value.to_string())
+        });
+
+        assert!(visited.iter().eq(map.keys()));
+        assert_eq!(odd.len(), 250);
+        assert_eq!(
+            odd,
+            map.iter().filter(|(_, v)| *v % 2 == 1).map(|(k, v)| (k.clone(), v.to_string())).collect(),
+        );
+        assert_eq!(odd.count_prefix("1"), keys.iter().zip(0..).filter(|(k, v)| k.starts_with('1') && v % 2 == 1).count());
+
+        // emptied and single-child subtrees are pruned and compressed
+        let map = PrefixTreeMap::from([("a", 1), ("ab", 2), ("abc", 3), ("abd", 4), ("b", 5)]);
+        let pruned = map.clone().filter_map_values(|_, value| (value > 2 && value != 4).then_some(value));
+        assert_eq!(pruned, PrefixTreeMap::from([("abc", 3), ("b", 5)]));
+        assert!(map.filter_map_values(|_, _| None::<()>).is_empty());
+    }
+
+    #[test]
+    fn get_batch() {
+        let map: PrefixTreeMap<String, usize> = (0..300).map(|i| (format!("ns.{}.{}", i % 7, i), i)).collect();
+        let mut keys: Vec<String> = (0..400).rev().map(|i| format!("ns.{}.{}", i % 7, i)).collect();
+        keys.push("ns".into());
+        keys.push("ns.0.0".into());
+
+        let expected: Vec<_> = keys.iter().map(|key| map.get(key)).collect();
+        assert_eq!(map.get_batch(&keys), expected);
+
+        keys.sort();
+        let expected: Vec<_> = keys.iter().map(|key| map.get(key)).collect();
+        assert_eq!(map.get_batch(&keys), expected);
+        assert_eq!(expected.iter().flatten().count(), 301);
+
+        assert!(map.get_batch(Vec::<&str>::new()).is_empty());
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let mut keys: Vec<String> = (0..2000_u32).map(|i| format!("{:x}", i.wrapping_mul(2_654_435_761) >> (i % 24))).collect();
+        // nodes of every size class, keys that are prefixes of others, and duplicates
+        keys.extend((0..=255_u8).map(|byte| String::from_utf8_lossy(&[b'z', byte]).into_owned()));
+        keys.extend(["", "a", "ab", "a"].map(String::from));
+        keys.sort();
+
+        let expected: PrefixTreeMap<String, usize> = keys.iter().cloned().zip(0..).collect();
+        let map = PrefixTreeMap::from_sorted_iter(keys.iter().cloned().zip(0..));
+
+        assert_eq!(map, expected);
+        assert_eq!(map.len(), expected.len());
+        assert!(keys.iter().all(|key| map.get(key) == expected.get(key)));
+        assert_eq!(map.count_prefix("z"), expected.count_prefix("z"));
+
+        // extending a non-empty map, with keys both before and after its last one
+        let (lower, upper) = keys.split_at(keys.len() / 2);
+        let mut map: PrefixTreeMap<String, usize> = lower.iter().cloned().zip(0..).collect();
+        map.extend_sorted(keys.iter().cloned().zip(0..).skip(keys.len() / 3));
+        assert_eq!(map, expected);
+        assert!(upper.iter().all(|key| map.contains_key(key)));
+
+        let set = PrefixTreeSet::from_sorted_iter(keys.iter().map(String::as_str));
+        assert_eq!(set, keys.iter().map(String::as_str).collect());
+
+        let mut limited = Builder::new().max_key_len(2).build_set();
+        limited.extend_sorted(["a", "ab", "b"]);
+        assert_eq!(limited.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "keys out of order")]
+    fn from_sorted_iter_unsorted() {
+        let _ = PrefixTreeMap::from_sorted_iter([("b", 1), ("a", 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid key")]
+    fn extend_sorted_invalid_key() {
+        let mut limited = Builder::new().max_key_len(2).build_set();
+        limited.extend_sorted(["a", "abc"]);
+    }
+
+    #[test]
+    fn extend_from_borrowed() {
+        fn extend_from_slice<C, T>(collection: &mut C, items: &[T])
+        where
+            C: for<'a> Extend<&'a T>,
+        {
+            collection.extend(items);
+        }
+
+        let entries = [(String::from("b"), 2), (String::from("a"), 1)];
+        let mut map = PrefixTreeMap::from([(String::from("a"), 0), (String::from("c"), 3)]);
+        map.extend(entries.iter().map(|(key, value)| (key, value)));
+        assert_eq!(map, PrefixTreeMap::from([("a", 1), ("b", 2), ("c", 3)].map(|(k, v)| (String::from(k), v))));
+
+        let items = [String::from("y"), String::from("x")];
+        let mut set = PrefixTreeSet::from([String::from("x")]);
+        extend_from_slice(&mut set, &items);
+        assert!(set.iter().eq(&["x", "y"]));
+
+        let mut other = PrefixTreeSet::new();
+        other.extend(&set);
+        assert_eq!(other, set);
+    }
+
+    #[test]
+    fn content_based_equality() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let state = RandomState::new();
+        let entries = [("a", 1), ("ab", 2), ("abc", 3), ("b", 4)];
+
+        let fresh = PrefixTreeMap::from(entries);
+        let mut churned = Builder::new().max_key_len(8).build_map();
+        churned.extend(entries);
+        churned.extend([("abcd", 0), ("abd", 0), ("c", 0)]);
+        for key in ["abcd", "abd", "c"] {
+            churned.entry(key).or_insert(0);
+            churned.remove(key);
+        }
+
+        assert_eq!(churned, fresh);
+        assert_eq!(state.hash_one(&churned), state.hash_one(&fresh));
+
+        // consistent with the other kinds of maps, which also hash the entries only
+        let frozen = fresh.clone().freeze();
+        let persistent = persistent::PersistentPrefixTreeMap::from(fresh.clone());
+        assert_eq!(state.hash_one(&frozen), state.hash_one(&fresh));
+        assert_eq!(state.hash_one(&persistent), state.hash_one(&fresh));
+
+        let mut other = fresh.clone();
+        other.insert("b", 5);
+        assert_ne!(other, fresh);
+        other.insert("b", 4);
+        other.insert("ba", 4);
+        other.remove("b");
+        assert_ne!(other, fresh);
+        assert_eq!(other.len(), fresh.len());
+
+        let sets = [PrefixTreeSet::from(["x", "xy"]), ["xy", "x"].into_iter().collect()];
+        assert_eq!(sets[0], sets[1]);
+        assert_eq!(state.hash_one(&sets[0]), state.hash_one(&sets[1]));
+    }
+
+    #[test]
+    fn semantic_ordering() {
+        use std::collections::BTreeMap;
+
+        let maps = [
+            vec![],
+            vec![("", 0)],
+            vec![("a", 1)],
+            vec![("a", 2)],
+            vec![("a", 1), ("ab", 0)],
+            vec![("a", 1), ("b", 0)],
+            vec![("aa", 9)],
+            vec![("ab", 0)],
+            vec![("b", 0), ("bb", 0), ("bbb", 0)],
+        ];
+
+        let mut pfx: Vec<PrefixTreeMap<&str, i32>> = maps.iter().map(|entries| entries.iter().copied().collect()).collect();
+        let mut std: Vec<BTreeMap<&str, i32>> = maps.iter().map(|entries| entries.iter().copied().collect()).collect();
+
+        pfx.reverse();
+        std.reverse();
+        pfx.sort();
+        std.sort();
+
+        for (x, y) in pfx.iter().zip(&std) {
+            assert!(x.iter().eq(y));
+        }
+
+        for (x, y) in pfx.iter().zip(&std) {
+            for (z, w) in pfx.iter().zip(&std) {
+                assert_eq!(x.cmp(z), y.cmp(w));
+                assert_eq!(x.partial_cmp(z), y.partial_cmp(w));
+            }
+        }
+
+        let sets = [PrefixTreeSet::from(["ab"]), PrefixTreeSet::from(["a", "b"])];
+        assert!(sets[1] < sets[0]);
+    }
+
+    #[test]
+    fn index_mut() {
+        let mut map = PrefixTreeMap::from([("count", 1), ("counter", 10)]);
+
+        map["count"] += 1;
+        map[&String::from("counter")] = 20;
+
+        assert_eq!(map["count"], 2);
+        assert_eq!(map["counter"], 20);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found in PrefixTreeMap")]
+    fn index_mut_missing_key() {
+        let mut map = PrefixTreeMap::from([("counter", 10)]);
+        map["count"] = 1;
+    }
+
+    /// A prefix of a shared byte string, so that the keys of a deep
+    /// tree don't take space quadratic in its depth.
+    #[derive(Clone)]
+    struct Prefix(std::sync::Arc<[u8]>, usize);
+
+    impl AsRef<[u8]> for Prefix {
+        fn as_ref(&self) -> &[u8] {
+            &self.0[..self.1]
+        }
+    }
+
+    #[test]
+    fn deep_tree() {
+        use std::sync::Arc;
+
+        const DEPTH: usize = 1_000;
+
+        // a stack far too small for recursing once per level
+        let map = std::thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(|| {
+                let bytes: Arc<[u8]> = vec![b'x'; DEPTH].into();
+                let mut map = PrefixTreeMap::new();
+
+                for len in 0..=DEPTH {
+                    map.insert(Prefix(bytes.clone(), len), len);
+                }
+
+                assert_eq!(map.get(&bytes[..]), Some(&DEPTH));
+                assert_eq!(map.get(&bytes[..DEPTH / 2]), Some(&(DEPTH / 2)));
+                assert_eq!(map.prefix(&bytes[..DEPTH - 1]).len(), 2);
+                assert!(!map.contains_key(&[b'x'; DEPTH + 1]));
+
+                *map.get_mut(&bytes[..]).unwrap() = 0;
+                assert_eq!(map.insert(Prefix(bytes.clone(), DEPTH), 1), Some(0));
+
+                assert!(map.keys().map(|key| key.1).eq(0..=DEPTH));
+                assert!(map.keys().rev().map(|key| key.1).eq((0..=DEPTH).rev()));
+                assert!(map.range(&bytes[..DEPTH / 2]..).map(|(key, _)| key.1).eq(DEPTH / 2..=DEPTH));
+
+                map
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(map.len(), DEPTH + 1);
+        assert_eq!(map.values().sum::<usize>(), DEPTH * (DEPTH - 1) / 2 + 1);
+
+        std::thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(move || drop(map))
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn check_invariants() {
+        use core::cell::Cell;
+
+        let mut map = PrefixTreeMap::new();
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        for key in ["", "a", "ab", "abc", "abd", "b", "bcd", "bce"] {
+            map.insert(key, key.len());
+        }
+        map.remove("abc");
+        map.retain(|key, _| *key != "bcd");
+        map.compact();
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        let set: PrefixTreeSet<_> = map.into_keys().collect();
+        assert_eq!(set.check_invariants(), Ok(()));
+
+        /// A key whose bytes can change after insertion, which corrupts the map.
+        struct Fickle(Cell<&'static str>);
+
+        impl AsRef<[u8]> for Fickle {
+            fn as_ref(&self) -> &[u8] {
+                self.0.get().as_bytes()
+            }
+        }
+
+        let mut map = PrefixTreeMap::new();
+        map.insert(Fickle(Cell::new("ab")), 1);
+        map.insert(Fickle(Cell::new("abc")), 2);
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        map.iter().nth(1).unwrap().0.0.set("abx");
+        let violation = map.check_invariants().unwrap_err();
+        assert_eq!(violation, InvariantViolation::KeyMismatch { path: b"abc".to_vec() });
+        assert_eq!(violation.to_string(), r#"key stored in node "abc" doesn't match its path"#);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut map: PrefixTreeMap<String, usize> = (0..1000).map(|i| (format!("key{i}"), i)).collect();
+        let expected = map.clone();
+
+        map.shrink_to_fit();
+        assert_eq!(map, expected);
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        map.insert("key".into(), 0);
+        map.retain(|_, value| *value % 3 == 0);
+        map.compact();
+        assert_eq!(map.len(), 335);
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        let mut set: PrefixTreeSet<_> = map.into_keys().collect();
+        set.shrink_to_fit();
+        assert!(set.contains("key999"));
+    }
+
+    #[test]
+    fn literal_macros() {
+        let map = pfx_map! { "a" => 1, "ab" => 2, "a" => 3 };
+        assert!(map.iter().eq([(&"a", &3), (&"ab", &2)]));
+
+        let map: PrefixTreeMap<String, Vec<u8>> = pfx_map! {
+            String::from("key") => vec![1, 2],
+        };
+        assert_eq!(map["key"], [1, 2]);
+
+        let set = pfx_set!["x", "xy", "x",];
+        assert!(set.iter().eq(&["x", "xy"]));
+    }
+
+    #[test]
+    fn keycodec_order_and_roundtrip() {
+        use core::fmt::Debug;
+        use core::time::Duration;
+        use crate::keycodec::{KeyCodec, DecodeError};
+
+        fn check<T: KeyCodec + Copy + Debug>(sorted: &[T], same: impl Fn(T, T) -> bool) {
+            for pair in sorted.windows(2) {
+                assert!(pair[0].to_key_bytes().as_ref() < pair[1].to_key_bytes().as_ref(), "{pair:?}");
+            }
+            for &value in sorted {
+                let decoded = T::from_key_bytes(value.to_key_bytes().as_ref()).unwrap();
+                assert!(same(value, decoded), "{value:?} != {decoded:?}");
+            }
+        }
+
+        check(&[0_u32, 1, 255, 256, u32::MAX], |a, b| a == b);
+        check(&[0_u64, 1, 1 << 32, u64::MAX], |a, b| a == b);
+        check(&[i64::MIN, -256, -1, 0, 1, 255, i64::MAX], |a, b| a == b);
+        check(&[i8::MIN, -1, 0, i8::MAX], |a, b| a == b);
+        check(
+            &[-f64::NAN, f64::NEG_INFINITY, f64::MIN, -1.5, -f64::MIN_POSITIVE, -0.0, 0.0, 1e-300, 2.0, f64::MAX, f64::INFINITY, f64::NAN],
+            |a, b| a.to_bits() == b.to_bits(),
+        );
+        check(&[f32::NEG_INFINITY, -1.0, -0.0, 0.0, 0.5, f32::INFINITY], |a, b| a.to_bits() == b.to_bits());
+        check(
+            &[Duration::ZERO, Duration::from_nanos(1), Duration::new(1, 999_999_999), Duration::new(2, 0), Duration::MAX],
+            |a, b| a == b,
+        );
+
+        #[cfg(feature = "std")]
+        {
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            check(
+                &[
+                    UNIX_EPOCH - Duration::new(1, 1),
+                    UNIX_EPOCH - Duration::new(1, 0),
+                    UNIX_EPOCH - Duration::from_nanos(1),
+                    UNIX_EPOCH,
+                    UNIX_EPOCH + Duration::new(1_700_000_000, 5),
+                ],
+                |a: SystemTime, b| a == b,
+            );
+        }
+
+        let map: PrefixTreeMap<_, _> = (-5_i64..5).map(|n| (n.to_key_bytes(), n)).collect();
+        assert!(map.range((-2_i64).to_key_bytes()..2_i64.to_key_bytes()).map(|(_, &n)| n).eq(-2..2));
+
+        assert_eq!(u32::from_key_bytes(&[1, 2, 3]), Err(DecodeError::WrongLength { len: 3, expected: 4 }));
+        assert_eq!(f64::from_key_bytes(&[]), Err(DecodeError::WrongLength { len: 0, expected: 8 }));
+
+        let mut invalid = Duration::ZERO.to_key_bytes();
+        invalid[8..].copy_from_slice(&1_000_000_000_u32.to_key_bytes());
+        assert_eq!(Duration::from_key_bytes(&invalid), Err(DecodeError::Invalid));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn path_keys() {
+        use std::ffi::OsStr;
+        use std::path::{Path, PathBuf};
+        use crate::key::{PathKey, wtf8_to_wide};
+
+        let paths = ["/", "/usr", "/usr/bin/cc", "/usr/lib", "/usr/lib/libc.so", "/usr/libexec/ld", "/var/log/ünïcödé"];
+        let set: PrefixTreeSet<PathKey> = paths.iter().map(PathKey::new).collect();
+
+        assert!(set.iter().map(PathKey::to_path_buf).eq(paths.iter().map(PathBuf::from)));
+        assert!(set.contains(&PathKey::from(Path::new("/usr/lib"))));
+        assert!(set.contains(&PathKey::from(OsStr::new("/var/log/ünïcödé"))));
+        assert!(!set.contains(&PathKey::new("/usr/lib/")));
+
+        let within = |dir: &str| -> Vec<PathBuf> {
+            set.prefix_iter(&PathKey::new(dir).descendants()).map(PathKey::to_path_buf).collect()
+        };
+        assert_eq!(within("/usr/lib"), [Path::new("/usr/lib/libc.so")]);
+        assert_eq!(within("/usr/lib/"), [Path::new("/usr/lib/libc.so")]);
+        assert_eq!(within("/usr").len(), 4);
+        assert_eq!(within("/").len(), paths.len());
+        assert_eq!(set.prefix_iter(&PathKey::new("/usr/lib")).count(), 3);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let invalid = OsStr::from_bytes(b"/tmp/\xff\xfe");
+            let key = PathKey::from(invalid.to_os_string());
+            assert_eq!(key.as_bytes(), b"/tmp/\xff\xfe");
+            assert_eq!(PathBuf::from(key), Path::new(invalid));
+        }
+
+        for string in ["", "abc", "ünïcödé", "\u{ffff}\u{10000}\u{10ffff}", "🦀/🦀"] {
+            assert_eq!(wtf8_to_wide(string.as_bytes()), string.encode_utf16().collect::<Vec<_>>());
+        }
+        // unpaired surrogates
+        assert_eq!(wtf8_to_wide(b"a\xed\xa0\x80b\xed\xbf\xbf"), [0x61, 0xd800, 0x62, 0xdfff]);
+    }
+
+    #[test]
+    fn keys_as_bytes() {
+        fn serialize<I: IntoIterator<Item = B>, B: AsRef<[u8]>>(keys: I) -> Vec<u8> {
+            keys.into_iter().flat_map(|key| [key.as_ref(), b";"].concat()).collect()
+        }
+
+        let map: PrefixTreeMap<String, usize> = ["abc", "ab", "", "b"].iter().map(|k| (k.to_string(), k.len())).collect();
+        let set: PrefixTreeSet<&str> = map.keys().map(String::as_str).collect();
+
+        assert_eq!(serialize(map.keys_as_bytes()), b";ab;abc;b;");
+        assert_eq!(serialize(set.keys_as_bytes().rev()), b"b;abc;ab;;");
+        assert_eq!(map.keys_as_bytes().len(), 4);
+        assert!(map.keys_as_bytes().eq(set.keys_as_bytes()));
+
+        let mut iter = set.keys_as_bytes();
+        iter.nth(1);
+        assert_eq!(format!("{iter:?}"), "[[97, 98, 99], [98]]");
+        assert!(PrefixTreeMap::<Vec<u8>, ()>::new().keys_as_bytes().next().is_none());
+    }
+
+    #[test]
+    fn prefix_free() {
+        let mut set: PrefixTreeSet<&str> = PrefixTreeSet::new();
+        assert!(set.is_prefix_free());
+
+        set.insert("");
+        assert!(set.is_prefix_free());
+
+        set.insert("a");
+        assert_eq!(set.find_prefix_pair(), Some((&"", &"a")));
+
+        set.remove("");
+        set.extend(["b0", "b10", "b110", "b111", "c", "cd", "ce"]);
+        assert_eq!(set.find_prefix_pair(), Some((&"c", &"cd")));
+
+        set.insert("b1");
+        assert_eq!(set.find_prefix_pair(), Some((&"b1", &"b10")));
+
+        set.remove("b1");
+        set.remove("c");
+        assert!(set.is_prefix_free());
+
+        // removal may leave behind nodes without items
+        set.insert("ce0");
+        set.remove("ce0");
+        assert!(set.is_prefix_free());
+
+        let map: PrefixTreeMap<_, _> = (0..1000_u32).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(map.find_prefix_pair(), Some((&String::from("1"), &String::from("10"))));
+    }
+
+    #[test]
+    fn segment_iter() {
+        use crate::map::Unmatched;
+
+        let words: PrefixTreeMap<&str, usize> = ["", "a", "ab", "abcd", "b", "bc", "cd", "d"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, word)| (word, i))
+            .collect();
+        let segments = |text: &str, unmatched| -> Vec<(&str, usize)> {
+            words.segment_iter(text, unmatched).map(|(range, &key, &value)| {
+                assert_eq!(&text[range], key);
+                (key, value)
+            }).collect()
+        };
+
+        assert_eq!(segments("", Unmatched::Skip), []);
+        // greedy matching doesn't backtrack: "abc" + "d" isn't an option
+        assert_eq!(segments("abcd", Unmatched::Skip), [("abcd", 3)]);
+        assert_eq!(segments("abcabd", Unmatched::Skip), [("ab", 2), ("ab", 2), ("d", 7)]);
+        assert_eq!(segments("xbcx-ab", Unmatched::Skip), [("bc", 5), ("ab", 2)]);
+        assert_eq!(segments("bcx-ab", Unmatched::Stop), [("bc", 5)]);
+
+        let mut iter = words.segment_iter("bcx-ab", Unmatched::Stop);
+        assert_eq!(iter.size_hint(), (0, Some(6)));
+        iter.by_ref().for_each(drop);
+        assert_eq!(iter.remainder(), b"x-ab");
+        assert_eq!(iter.next(), None);
+
+        let set: PrefixTreeSet<Vec<u8>> = [&[0xff][..], &[0xff, 0x00], &[0x01]].map(<[u8]>::to_vec).into_iter().collect();
+        let tokens: Vec<_> = set.segment_iter(&[0xff, 0x00, 0xff, 0x02, 0x01], Unmatched::Skip).collect();
+        assert_eq!(tokens, [(0..2, &vec![0xff, 0x00]), (2..3, &vec![0xff]), (4..5, &vec![0x01])]);
+    }
+
+    #[test]
+    fn aho_corasick_matcher() {
+        use crate::matcher::{Matcher, Match};
+
+        fn naive(set: &PrefixTreeSet<&str>, haystack: &str) -> Vec<Match> {
+            let mut matches: Vec<_> = (0..=haystack.len())
+                .flat_map(|end| set.iter().enumerate().filter_map(move |(pattern, item)| {
+                    let start = end.checked_sub(item.len())?;
+                    (&haystack.as_bytes()[start..end] == item.as_bytes()).then_some(Match { pattern, start, end })
+                }))
+                .collect();
+            // same end: longest first
+            matches.sort_by_key(|m| (m.end, m.start));
+            matches
+        }
+
+        let haystacks = ["", "a", "abracadabra", "aaaaab", "abcabcabd", "xyz", "bcdabcd"];
+        let sets = [
+            &["a", "ab", "bc", "bca", "c", "caa"][..],
+            &["abra", "cad", "bra", "ra", "a"],
+            &["aa", "aaa", "aab", "b"],
+            &["", "bc", "abcd"],
+            &["xyz"],
+            &[],
+        ];
+
+        for items in sets {
+            let set: PrefixTreeSet<&str> = items.iter().copied().collect();
+            let matcher = set.build_matcher();
+
+            assert_eq!(matcher.pattern_count(), set.len());
+
+            for haystack in haystacks {
+                let matches: Vec<_> = matcher.find_iter(haystack).collect();
+                assert_eq!(matches, naive(&set, haystack), "{items:?} in {haystack:?}");
+                assert_eq!(matcher.is_match(haystack), !matches.is_empty());
+            }
+        }
+
+        let matcher = Matcher::default();
+        assert_eq!(matcher.state_count(), 1);
+        assert!(!matcher.is_match("anything"));
+
+        let set = PrefixTreeSet::from([&b"\x00\xff"[..], b"\xff\xff"]);
+        let matches: Vec<_> = set.build_matcher().find_iter(b"\x00\xff\xff\xff").map(|m| (m.pattern, m.start)).collect();
+        assert_eq!(matches, [(0, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn prefix_router() {
+        use crate::router::{PrefixRouter, RouteError};
+
+        let mut router = PrefixRouter::new();
+
+        for (template, value) in [
+            ("/", 0),
+            ("/users", 1),
+            ("/users/:id", 2),
+            ("/users/me", 3),
+            ("/users/:id/posts/:post", 4),
+            ("/users/:id/posts/*rest", 5),
+            ("/users/me/posts/latest", 6),
+            ("/*path", 7),
+            ("/users/:id/", 8),
+        ] {
+            assert_eq!(router.insert(template, value), Ok(None), "{template}");
+        }
+        assert_eq!(router.len(), 9);
+
+        fn route<'a>(router: &'a PrefixRouter<usize>, path: &'a str) -> Option<(usize, Vec<(&'a str, &'a str)>)> {
+            router.route(path).map(|found| (*found.value, found.params.iter().collect()))
+        }
+
+        assert_eq!(route(&router, "/"), Some((0, vec![])));
+        assert_eq!(route(&router, ""), Some((0, vec![])));
+        assert_eq!(route(&router, "users"), Some((1, vec![])));
+        assert_eq!(route(&router, "/users/42"), Some((2, vec![("id", "42")])));
+        assert_eq!(route(&router, "/users/42/"), Some((8, vec![("id", "42")])));
+        assert_eq!(route(&router, "/users/me"), Some((3, vec![])));
+        assert_eq!(route(&router, "/users/me/posts/7"), Some((4, vec![("id", "me"), ("post", "7")])));
+        assert_eq!(route(&router, "/users/me/posts/latest"), Some((6, vec![])));
+        assert_eq!(route(&router, "/users/me/posts/7/comments"), Some((5, vec![("id", "me"), ("rest", "7/comments")])));
+        // the empty last segment is no match for a parameter or a wildcard
+        assert_eq!(route(&router, "/users/me/posts/"), Some((7, vec![("path", "users/me/posts/")])));
+        assert_eq!(route(&router, "/groups//x"), Some((7, vec![("path", "groups//x")])));
+
+        let deep = "/a".repeat(100_000);
+        assert_eq!(route(&router, &deep), Some((7, vec![("path", &deep[1..])])));
+
+        assert_eq!(router.insert("/users/:id", 20), Ok(Some(2)));
+        assert_eq!(router.insert("/*path", 70), Ok(Some(7)));
+        assert_eq!(router.len(), 9);
+        assert_eq!(route(&router, "/users/42"), Some((20, vec![("id", "42")])));
+
+        assert_eq!(router.insert("/users/:name/x", 0), Err(RouteError::ConflictingName {
+            existing: "id".into(),
+            name: "name".into(),
+        }));
+        assert_eq!(router.insert("/*rest", 0).unwrap_err().to_string(), "`rest` conflicts with existing `path` at the same position");
+        assert_eq!(router.insert("/:", 0), Err(RouteError::EmptyName));
+        assert_eq!(router.insert("/files/*", 0), Err(RouteError::EmptyName));
+        assert_eq!(router.insert("/*path/x", 0), Err(RouteError::WildcardNotLast));
+        assert_eq!(router.insert("/:a/:b/:a", 0), Err(RouteError::DuplicateName { name: "a".into() }));
+        assert_eq!(router.len(), 9);
+
+        assert!(PrefixRouter::<()>::default().route("/").is_none());
+    }
+
+    #[test]
+    fn normalized_keys() {
+        use crate::normalized::{NormalizedMap, NormalizedSet, AsciiCaseInsensitive};
+
+        let mut commands: NormalizedMap<String, usize, _> = NormalizedMap::new(|key: &[u8], out: &mut Vec<u8>| {
+            out.extend(key.iter().filter(|&&byte| byte != b'-' && byte != b'_').map(u8::to_ascii_lowercase));
+        });
+
+        assert_eq!(commands.insert("Check-Out".into(), 1), None);
+        assert_eq!(commands.insert("commit".into(), 2), None);
+        assert_eq!(commands.insert("CHECKOUT".into(), 3), Some(1));
+        assert_eq!(commands.insert("cherry_pick".into(), 4), None);
+        assert_eq!(commands.len(), 3);
+
+        assert_eq!(commands.get("check_out"), Some(&3));
+        assert_eq!(commands.get_key_value("checkout"), Some((&"Check-Out".into(), &3)));
+        assert!(commands.contains_key("Cherry-Pick"));
+        assert!(!commands.contains_key("cherry"));
+        assert_eq!(commands.normalize_key("Cherry-Pick"), b"cherrypick");
+
+        *commands.get_mut("COMMIT").unwrap() += 10;
+        assert!(commands.iter().eq([(&"Check-Out".into(), &3), (&"cherry_pick".into(), &4), (&"commit".into(), &12)]));
+        assert!(commands.prefix_iter("CH-E").map(|(_, &value)| value).eq([3, 4]));
+        assert_eq!(commands.prefix_iter("c").rev().len(), 3);
+
+        assert_eq!(commands.remove_entry("CHECK_OUT"), Some(("Check-Out".into(), 3)));
+        assert_eq!(commands.remove("checkout"), None);
+        assert_eq!(format!("{commands:?}"), r#"{"cherry_pick": 4, "commit": 12}"#);
+        assert!(commands.into_iter().eq([("cherry_pick".into(), 4), ("commit".into(), 12)]));
+
+        let mut headers: NormalizedSet<&str, AsciiCaseInsensitive> = ["Host", "Accept", "accept-encoding"].into_iter().collect();
+        assert!(!headers.insert("HOST"));
+        assert!(headers.insert("Cookie"));
+        assert_eq!(headers.get("ACCEPT-ENCODING"), Some(&"accept-encoding"));
+        assert!(headers.prefix_iter("ACC").eq(&["Accept", "accept-encoding"]));
+        assert_eq!(headers.take("cookie"), Some("Cookie"));
+        assert!(headers.remove("host"));
+        assert!(!headers.contains("Host"));
+        assert_eq!(format!("{headers:?}"), r#"{"Accept", "accept-encoding"}"#);
+
+        headers.clear();
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn utf8_wrappers() {
+        use crate::map::Unmatched;
+        use crate::utf8::{StrPrefixTreeMap, StrPrefixTreeSet};
+
+        let mut map: StrPrefixTreeMap<usize> = ["é", "è", "ê", "日本", "日本語", "日曜日"].into_iter().zip(0..).collect();
+
+        // the first byte of "é" and "è" is shared, but it's not a char
+        let accents: StrPrefixTreeMap<()> = [("é", ()), ("è", ())].into_iter().collect();
+        assert_eq!(accents.as_map().longest_common_prefix(), [0xc3]);
+        assert_eq!(accents.longest_common_prefix(), "");
+
+        assert_eq!(map.longest_common_prefix(), "");
+        assert_eq!(map.longest_common_prefix_under("日"), Some("日".into()));
+        assert_eq!(map.longest_common_prefix_under("日本"), Some("日本".into()));
+        assert_eq!(map.longest_common_prefix_under("月"), None);
+        assert_eq!(map.as_map().longest_common_prefix_under("日"), Some(["日".as_bytes(), &[0xe6]].concat()));
+
+        assert_eq!(map.count_prefix("日"), 3);
+        assert!(map.prefix_iter("日本").map(|(key, _)| key).eq(["日本", "日本語"]));
+        assert!(map.prefixes_of("日本語です").map(|(_, &value)| value).eq([3, 4]));
+        assert_eq!(map.longest_prefix_of("日本語です"), Some(("日本語", &4)));
+        assert_eq!(map.longest_prefix_of("月曜日"), None);
+
+        let text = "日曜日は日本語é!";
+        let tokens: Vec<_> = map.segment_iter(text, Unmatched::Skip).map(|(range, key, _)| {
+            assert_eq!(&text[range.clone()], key);
+            range
+        }).collect();
+        assert_eq!(tokens, [0..9, 12..21, 21..23]);
+
+        assert_eq!(map.insert("é".into(), 10), Some(0));
+        assert_eq!(map.remove("ê"), Some(2));
+        assert_eq!(map.remove_entry("è"), Some(("è".into(), 1)));
+        *map.get_mut("日本").unwrap() += 1;
+        assert_eq!(format!("{map:?}"), r#"{"é": 10, "日曜日": 5, "日本": 4, "日本語": 4}"#);
+        assert!(map.clone().into_iter().map(|(key, _)| key).eq(map.keys()));
+
+        let mut set: StrPrefixTreeSet = ["car", "card", "cárdigan"].into_iter().collect();
+        assert_eq!(set.longest_common_prefix(), "c");
+        assert_eq!(set.longest_common_prefix_under("car"), Some("car".into()));
+        assert_eq!(set.longest_prefix_of("cards"), Some("card"));
+        assert!(set.segment_iter("cárdigans", Unmatched::Stop).eq([(0..9, "cárdigan")]));
+        assert!(set.insert("cá".into()));
+        assert_eq!(set.longest_common_prefix_under("c"), Some("c".into()));
+        assert_eq!(set.take("card"), Some("card".into()));
+        assert!(!set.remove("card"));
+        assert_eq!(format!("{set:?}"), r#"{"car", "cá", "cárdigan"}"#);
+        assert!(set.into_iter().eq(["car", "cá", "cárdigan"]));
+    }
+
+    #[test]
+    fn symbol_keys() {
+        use std::collections::BTreeMap;
+        use symbol::SymbolMap;
+
+        let sentences: [&[u32]; 7] = [&[1, 2, 3], &[1, 2], &[1, 256], &[0x0100_0000], &[], &[1, 2, 3, 4], &[1, 0x0100_0000]];
+        let mut map = SymbolMap::new();
+        let mut btree = BTreeMap::new();
+
+        for &ids in &sentences {
+            assert_eq!(map.insert(ids, ids.len()), btree.insert(ids.to_vec(), ids.len()));
+        }
+
+        // symbols are compared as a whole, not byte by byte
+        assert!(map.iter().map(|(key, &len)| (key, len)).eq(btree.clone()));
+        assert_eq!(map.get(&[1, 2, 3]), Some(&3));
+        assert_eq!(map.get(&[1, 2, 3, 4, 5]), None);
+        assert_eq!(map.get(&[1]), None);
+        assert_eq!(map.prefix_iter(&[1, 2]).count(), 3);
+        assert_eq!(map.prefix_iter(&[1]).count(), 5);
+        assert_eq!(map.prefix_iter(&[2]).count(), 0);
+        assert!(map.prefix_iter(&[]).map(|(key, _)| key).eq(btree.keys().cloned()));
+
+        for ids in [&[1, 2][..], &[], &[1, 256]] {
+            assert_eq!(map.remove(ids), btree.remove(ids));
+            assert_eq!(map.remove(ids), None);
+        }
+
+        assert_eq!(map.len(), 4);
+        assert_eq!(map, btree.iter().map(|(key, &len)| (key, len)).collect());
+
+        let mut chars = SymbolMap::new();
+        chars.insert(&['a', 'ñ', '日'], 1);
+        chars.insert(&['a', 'ñ', '🦀'], 2);
+        chars.insert(&['b'], 3);
+        assert_eq!(format!("{chars:?}"), "{['a', 'ñ', '日']: 1, ['a', 'ñ', '🦀']: 2, ['b']: 3}");
+        assert!(chars.values().eq([&1, &2, &3]));
+
+        let words: Vec<char> = "añ日🦀".chars().collect();
+        assert_eq!(chars.get(&words[..3]), Some(&1));
+    }
+
+    #[test]
+    fn bag_counts() {
+        use crate::bag::PrefixTreeBag;
+
+        let mut bag = PrefixTreeBag::new();
+
+        assert_eq!(bag.insert("to"), 1);
+        assert_eq!(bag.insert("be"), 1);
+        assert_eq!(bag.insert("to"), 2);
+        assert_eq!(bag.insert_many("tea", 3), 3);
+        assert_eq!(bag.insert_many("or", 0), 0);
+        assert!(!bag.contains("or"));
+
+        assert_eq!(bag.len(), 3);
+        assert_eq!(bag.total(), 6);
+        assert_eq!(bag.count("to"), 2);
+        assert_eq!(bag.count("t"), 0);
+        assert!(bag.iter().eq([(&"be", 1), (&"tea", 3), (&"to", 2)]));
+        assert!(bag.prefix_iter("t").rev().eq([(&"to", 2), (&"tea", 3)]));
+        assert_eq!(format!("{bag:?}"), r#"{"be": 1, "tea": 3, "to": 2}"#);
+
+        assert!(bag.remove("to"));
+        assert_eq!(bag.count("to"), 1);
+        assert!(bag.remove("to"));
+        assert!(!bag.contains("to"));
+        assert!(!bag.remove("to"));
+
+        assert_eq!(bag.remove_many("tea", 2), 2);
+        assert_eq!(bag.remove_many("tea", 5), 1);
+        assert_eq!(bag.remove_all("be"), 1);
+        assert_eq!(bag.remove_all("be"), 0);
+        assert!(bag.is_empty());
+        assert_eq!(bag.total(), 0);
+
+        bag.extend([("x", 2), ("y", 0), ("x", 1)]);
+        bag.extend(["y", "x"]);
+        assert!(bag.clone().into_iter().eq([("x", 4), ("y", 1)]));
+        assert_eq!(bag.total(), 5);
+        assert_eq!(bag, ["x", "y", "x", "x", "x"].into_iter().collect());
+
+        bag.clear();
+        assert_eq!((bag.len(), bag.total()), (0, 0));
+    }
+
+    #[test]
+    fn aggregate_map() {
+        use crate::aggregate::{AggregateMap, Aggregate, Sum, Min, Max};
+
+        /// Concatenates the values in key order, which is not commutative.
+        struct Concat;
+
+        impl Aggregate<&'static str> for Concat {
+            type Output = String;
+
+            fn identity(&self) -> String {
+                String::new()
+            }
+
+            fn lift(&self, value: &&'static str) -> String {
+                value.to_string()
+            }
+
+            fn combine(&self, left: &String, right: &String) -> String {
+                left.clone() + right
+            }
+        }
+
+        let entries = [("b", 2), ("ab", 5), ("a", 1), ("abc", -3), ("ba", 7), ("", 10)];
+
+        let mut sums: AggregateMap<&str, i32, Sum> = entries.iter().copied().collect();
+        let mins: AggregateMap<&str, i32, Min> = entries.iter().copied().collect();
+        let maxes: AggregateMap<&str, i32, Max> = entries.iter().copied().collect();
+
+        for prefix in ["", "a", "ab", "abc", "abcd", "b", "c"] {
+            let values = entries.iter().filter(|(key, _)| key.starts_with(prefix)).map(|&(_, value)| value);
+
+            assert_eq!(sums.aggregate_prefix(prefix), values.clone().sum::<i32>());
+            assert_eq!(mins.aggregate_prefix(prefix), values.clone().min());
+            assert_eq!(maxes.aggregate_prefix(prefix), values.max());
+        }
+
+        assert_eq!(sums.len(), 6);
+        assert_eq!(*sums.aggregate(), 22);
+        assert!(sums.keys().eq(&["", "a", "ab", "abc", "b", "ba"]));
+        assert_eq!(sums.iter().last(), Some((&"ba", &7)));
+
+        assert_eq!(sums.insert("ab", 6), Some(5));
+        assert_eq!(sums.aggregate_prefix("a"), 4);
+        assert_eq!(sums.modify("abc", |value| core::mem::replace(value, 0)), Some(-3));
+        assert_eq!(sums.modify("abcd", |value| *value = 0), None);
+        assert_eq!(sums.aggregate_prefix("ab"), 6);
+        assert_eq!(sums.remove("ab"), Some(6));
+        assert_eq!(sums.remove("ab"), None);
+        assert_eq!(sums.remove("abcd"), None);
+        assert_eq!(sums.aggregate_prefix("a"), 1);
+        assert_eq!(sums.get("abc"), Some(&0));
+        assert_eq!(sums.len(), 5);
+
+        for key in ["", "a", "abc", "b", "ba"] {
+            assert!(sums.remove(key).is_some());
+        }
+        assert!(sums.is_empty());
+        assert_eq!(*sums.aggregate(), 0);
+        assert_eq!(format!("{sums:?}"), "{}");
+
+        let mut words = AggregateMap::new(Concat);
+        words.extend([("to", "c"), ("tea", "b"), ("ted", "a"), ("t", "d")]);
+        assert_eq!(words.aggregate(), "dbac");
+        assert_eq!(words.aggregate_prefix("te"), "ba");
+
+        let mut sums: AggregateMap<&str, i32, Sum> = entries.iter().copied().collect();
+        sums.insert("xyzzy", 100);
+        assert_eq!(sums.aggregate_prefix("xy"), 100);
+        assert_eq!(sums.aggregate_prefix("xyzzy!"), 0);
+
+        // a panic while modifying a value doesn't lose any entries
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            sums.modify("ab", |_| panic!("modification failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(sums.len(), 7);
+        assert_eq!(sums.get("ab"), Some(&5));
+        assert_eq!(sums.iter().count(), 7);
+        assert_eq!(*sums.aggregate(), 122);
+    }
+
+    #[test]
+    fn deep_tree_restructuring() {
+        use std::sync::Arc;
+
+        const DEPTH: usize = 1_000;
+
+        // a stack far too small for recursing once per level
+        std::thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(|| {
+                let bytes: Arc<[u8]> = vec![b'x'; DEPTH].into();
+                let mut map = PrefixTreeMap::new();
+
+                for len in (0..=DEPTH).step_by(2) {
+                    map.insert(Prefix(bytes.clone(), len), len);
+                }
+
+                let mut odd = PrefixTreeMap::new();
+
+                for len in (1..=DEPTH).step_by(2) {
+                    odd.insert(Prefix(bytes.clone(), len), len);
+                }
+
+                map.append(&mut odd);
+                assert_eq!(map.len(), DEPTH + 1);
+                assert!(map.keys().map(|key| key.1).eq(0..=DEPTH));
+
+                assert_eq!(map.rank(&bytes[..DEPTH / 2]), DEPTH / 2);
+                assert_eq!(map.rank(&[b'x'; DEPTH + 1]), DEPTH + 1);
+                assert_eq!(map.get_index(DEPTH).map(|(key, _)| key.1), Some(DEPTH));
+                assert_eq!(map.get_index(DEPTH + 1).map(|(key, _)| key.1), None);
+                assert_eq!(map.first_key_value().map(|(key, _)| key.1), Some(0));
+                assert_eq!(map.last_key_value().map(|(key, _)| key.1), Some(DEPTH));
+
+                assert_eq!(map.remove(&bytes[..DEPTH - 1]), Some(DEPTH - 1));
+                assert_eq!(map.pop_last().map(|(key, _)| key.1), Some(DEPTH));
+                assert_eq!(map.pop_first().map(|(key, _)| key.1), Some(0));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                let upper = map.split_off(&bytes[..DEPTH / 2]);
+                assert!(upper.keys().map(|key| key.1).eq(DEPTH / 2..DEPTH - 1));
+                assert!(map.keys().map(|key| key.1).eq(1..DEPTH / 2));
+
+                let deepest = map.remove_prefix(&bytes[..DEPTH / 4]);
+                assert!(deepest.keys().map(|key| key.1).eq(DEPTH / 4..DEPTH / 2));
+                assert!(map.keys().map(|key| key.1).eq(1..DEPTH / 4));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                (map, upper, deepest)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn deep_tree_traversals() {
+        use std::sync::Arc;
+
+        const DEPTH: usize = 1_000;
+
+        // a stack far too small for recursing once per level
+        std::thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(|| {
+                let bytes: Arc<[u8]> = vec![b'x'; DEPTH].into();
+                let mut map: PrefixTreeMap<_, _> = (0..=DEPTH).map(|len| (Prefix(bytes.clone(), len), len)).collect();
+
+                map.compact();
+                assert_eq!(map.len(), DEPTH + 1);
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                map.retain(|key, _| key.1 % 2 == 0);
+                assert!(map.keys().map(|key| key.1).eq((0..=DEPTH).step_by(2)));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                let other: PrefixTreeMap<_, _> = (0..=DEPTH).filter(|len| len % 4 != 2).map(|len| (&bytes[..len], ())).collect();
+                assert_eq!(map.intersection_len(&other), DEPTH / 4 + 1);
+
+                map.intersect_with(&other);
+                assert!(map.keys().map(|key| key.1).eq((0..=DEPTH).step_by(4)));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                let map = map.map_values(|key, len| key.1 + len);
+                assert!(map.values().copied().eq((0..=DEPTH).step_by(4).map(|len| 2 * len)));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                let map = map.filter_map_values(|_, len| (len % 16 == 0).then_some(len / 16));
+                assert!(map.values().copied().eq(0..=DEPTH / 8));
+                assert_eq!(map.check_invariants(), Ok(()));
+
+                // a chain without branching, but which has to be traversed all the way down
+                assert_eq!(map.iter_branch_points().len(), 0);
+                assert_eq!(map.count_distinct_prefixes(DEPTH), 1);
+                assert!(map.iter_distinct_prefixes(DEPTH - 1).map(|(_, len)| len).eq([1]));
+
+                map
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn deep_tree_variants() {
+        use std::sync::Arc;
+        use persistent::PersistentPrefixTreeMap;
+        use implicit::ImplicitMap;
+        use scored::ScoredMap;
+
+        const DEPTH: usize = 1_000;
+
+        // a stack far too small for recursing once per level
+        std::thread::Builder::new()
+            .stack_size(32 * 1024)
+            .spawn(|| {
+                let bytes: Arc<[u8]> = vec![b'x'; DEPTH].into();
+                let keys: Vec<_> = (0..=DEPTH).map(|len| Prefix(bytes.clone(), len)).collect();
+
+                let mut persistent: PersistentPrefixTreeMap<_, _> = keys.iter().cloned().zip(0..).collect();
+                let snapshot = persistent.clone();
+
+                for key in keys.iter().step_by(2) {
+                    assert_eq!(persistent.remove(key), Some(key.1));
+                }
+
+                assert_eq!(persistent.len(), DEPTH / 2);
+                assert_eq!(persistent.count_prefix(&bytes[..DEPTH / 2]), DEPTH / 4);
+                assert_eq!(persistent.get(&bytes[..DEPTH - 1]), Some(&(DEPTH - 1)));
+                assert_eq!(snapshot.get(&bytes[..]), Some(&DEPTH));
+                assert_eq!(snapshot.len(), DEPTH + 1);
+
+                let mut implicit: ImplicitMap<_> = keys.iter().zip(0..).collect();
+
+                for key in keys.iter().skip(1).step_by(2) {
+                    assert_eq!(implicit.remove(key), Some(key.1));
+                }
+
+                assert_eq!(implicit.len(), DEPTH / 2 + 1);
+                assert_eq!(implicit.get(&bytes[..DEPTH]), Some(&DEPTH));
+                assert!(implicit.prefix_iter(&bytes[..DEPTH - 3]).map(|(key, _)| key.len()).eq([DEPTH - 2, DEPTH]));
+                assert_eq!(implicit.iter().map(|(key, &value)| (key, value)).collect::<ImplicitMap<_>>(), implicit);
+
+                let mut scored: ScoredMap<_, _> = keys.iter().map(|key| (key.clone(), key.1 % 7)).collect();
+
+                for key in keys.iter().step_by(3) {
+                    assert_eq!(scored.remove(key), Some(key.1 % 7));
+                }
+
+                assert_eq!(scored.len(), DEPTH - DEPTH / 3);
+                assert_eq!(scored.max_score_with_prefix(&bytes[..DEPTH - 5]), Some(&6));
+                assert!(scored.top_k_with_prefix(&bytes[..DEPTH - 5], 2).iter().map(|(key, _)| key.1).eq([DEPTH, DEPTH - 2]));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = Builder::new().max_key_len(3).build_map();
+        map.extend([("", 1), ("a", 2), ("abc", 3)]);
+        map.clear();
+
+        assert!(map.is_empty());
+        assert!(!map.contains_key(""));
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(map, Builder::new().build_map());
+        assert!(map.try_insert("abcd", 4).is_err());
+
+        map.insert("b", 5);
+        assert_eq!(map.len(), 1);
+
+        let mut set = PrefixTreeSet::from(["x", "y"]);
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains("x"));
+    }
+
+    #[test]
+    fn extract_entries() {
+        let mut cache = PrefixTreeMap::from([
+            ("", 0), ("a", 10), ("ab", 3), ("abc", 12), ("abd", 1), ("b", 20), ("bc", 2),
+        ]);
+
+        let evicted: Vec<_> = cache.extract_if(|_, &mut age| age >= 10).collect();
+        assert_eq!(evicted, [("a", 10), ("abc", 12), ("b", 20)]);
+        assert_eq!(cache.len(), 4);
+        assert!(cache.iter().eq([(&"", &0), (&"ab", &3), (&"abd", &1), (&"bc", &2)]));
+
+        // lazily, keeping the entries not examined yet
+        let mut iter = cache.extract_if(|_, age| {
+            *age += 1;
+            *age % 2 == 0
+        });
+        assert_eq!(iter.size_hint(), (0, Some(4)));
+        assert_eq!(iter.next(), Some(("ab", 4)));
+        drop(iter);
+
+        assert!(cache.iter().eq([(&"", &1), (&"abd", &1), (&"bc", &2)]));
+        assert_eq!(cache.extract_if(|_, _| true).count(), 3);
+        assert!(cache.is_empty());
+        assert_eq!(cache, PrefixTreeMap::new());
+
+        let mut set = PrefixTreeSet::from(["x", "xx", "xy", "y"]);
+        let mut iter = set.extract_if(|item| item.len() > 1);
+        assert_eq!(iter.next(), Some("xx"));
+        assert_eq!(iter.next(), Some("xy"));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+        drop(iter);
+        assert!(set.iter().eq(&["x", "y"]));
+    }
+
+    #[test]
+    fn prefix_iter_size_hint() {
+        let map = PrefixTreeMap::from([("a", 1), ("ab", 2), ("abc", 3), ("b", 4)]);
+
+        let mut iter = map.prefix_iter("ab");
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        assert_eq!(iter.next(), Some((&"ab", &2)));
+        assert_eq!(iter.next(), Some((&"abc", &3)));
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+
+        let iter = map.clone().into_prefix_iter("abc");
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+        assert_eq!(map.prefix_iter("x").size_hint(), (0, Some(0)));
+
+        let mut map = PrefixTreeMap::from([("ba", 1), ("bar", 2), ("baz", 3), ("bazaar", 4), ("bc", 5), ("c", 6)]);
+
+        let mut iter = map.prefix_iter("b");
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next_back(), Some((&"bc", &5)));
+        assert_eq!(iter.next(), Some((&"ba", &1)));
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.clone().len(), 3);
+
+        let mut iter = map.prefix_iter_mut("ba");
+        assert_eq!(iter.len(), 4);
+        iter.next();
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+
+        let mut iter = map.clone().into_prefix_iter("baz");
+        assert_eq!(iter.len(), 2);
+        iter.next_back();
+        assert_eq!(iter.len(), 1);
+
+        assert_eq!(map.prefix("ba").iter().len(), 4);
+
+        let set = PrefixTreeSet::from(["ba", "bar", "baz", "bazaar", "bc", "c"]);
+        let mut iter = set.prefix_iter("ba");
+        assert_eq!(iter.len(), 4);
+        iter.next();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(set.into_prefix_iter("b").len(), 5);
+    }
+
     #[test]
     fn set_operations() {
         let x = PrefixTreeSet::from(["abc", "def", "abc", "qux"]);
@@ -330,4 +3530,67 @@ mod tests {
         );
         assert!(x.clone().symmetric_difference(x.clone()).is_empty());
     }
+
+    #[test]
+    fn set_operations_by_ref() {
+        let x = PrefixTreeSet::from(["abc", "def", "qux"]);
+        let y = PrefixTreeSet::from(["def", "qux", "what", "4lulz"]);
+
+        assert!((&x | &y).iter().eq(&["4lulz", "abc", "def", "qux", "what"]));
+        assert!((&x & &y).iter().eq(&["def", "qux"]));
+        assert!((&x ^ &y).iter().eq(&["4lulz", "abc", "what"]));
+        assert!(x.difference_ref(&y).iter().eq(&["abc"]));
+        assert!(y.difference_ref(&x).iter().eq(&["4lulz", "what"]));
+        assert!((&x - &y).iter().eq(&["abc"]));
+        assert!((&y - &x).iter().eq(&["4lulz", "what"]));
+        assert!((x.clone() - ["abc", "qux"]).iter().eq(&["def"]));
+
+        let mut z = y.clone();
+        z -= &x;
+        assert_eq!(z, &y - &x);
+
+        let m = PrefixTreeMap::from([("a", 1), ("b", 2), ("c", 3)]);
+        let n = PrefixTreeMap::from([("b", 20), ("c", 30), ("d", 40)]);
+
+        assert_eq!(&m | &n, PrefixTreeMap::from([("a", 1), ("b", 20), ("c", 30), ("d", 40)]));
+        assert_eq!(&m & &n, PrefixTreeMap::from([("b", 2), ("c", 3)]));
+        assert_eq!(&m ^ &n, PrefixTreeMap::from([("a", 1), ("d", 40)]));
+        assert_eq!(m.difference_ref(&n), PrefixTreeMap::from([("a", 1)]));
+        assert_eq!(&m - &n, PrefixTreeMap::from([("a", 1)]));
+        assert_eq!(&n - &m, PrefixTreeMap::from([("d", 40)]));
+        assert_eq!(m.clone() - ["a", "c"], PrefixTreeMap::from([("b", 2)]));
+
+        let mut o = n.clone();
+        o -= m.keys();
+        assert_eq!(o, &n - &m);
+
+        // the operands are still usable
+        assert_eq!(x.len(), 3);
+        assert_eq!(m.len(), 3);
+    }
+
+    #[test]
+    fn set_operation_cardinalities() {
+        let x: PrefixTreeSet<String> = (0..300).map(|i| format!("{}", i * 2)).collect();
+        let y: PrefixTreeSet<String> = (0..300).map(|i| format!("{}", i * 3)).collect();
+
+        assert_eq!(x.intersection_len(&y), x.intersection_ref(&y).len());
+        assert_eq!(x.union_len(&y), x.union_ref(&y).len());
+        assert_eq!(x.difference_len(&y), x.difference_ref(&y).len());
+        assert_eq!(y.difference_len(&x), y.difference_ref(&x).len());
+        assert_eq!(x.symmetric_difference_len(&y), x.symmetric_difference_ref(&y).len());
+        assert_eq!(x.intersection_len(&y), 100);
+
+        // keys ending inside an edge of the other tree
+        let a = PrefixTreeSet::from(["international", "in", "inter"]);
+        let b = PrefixTreeSet::from(["internationally", "inter", "i", "international"]);
+
+        assert_eq!(a.intersection_len(&b), 2);
+        assert_eq!(b.intersection_len(&a), 2);
+        assert_eq!(a.union_len(&b), 5);
+        assert_eq!(a.intersection_len(&PrefixTreeSet::<&str>::new()), 0);
+
+        let jaccard = a.intersection_len(&b) as f64 / a.union_len(&b) as f64;
+        assert_eq!(jaccard, 0.4);
+    }
 }
@@ -0,0 +1,47 @@
+//! Macros for constructing maps and sets from literals.
+
+/// Creates a [`PrefixTreeMap`](crate::PrefixTreeMap) containing the given
+/// key-value pairs.
+///
+/// Later entries replace earlier ones with the same key, as if they were
+/// inserted one after another.
+///
+/// ```
+/// # use pfx::{pfx_map, PrefixTreeMap};
+/// let map = pfx_map! {
+///     "apple" => 3,
+///     "apricot" => 5,
+///     "banana" => 7,
+/// };
+///
+/// assert_eq!(map, PrefixTreeMap::from([("apple", 3), ("apricot", 5), ("banana", 7)]));
+/// assert_eq!(map.prefix_iter("ap").count(), 2);
+///
+/// let empty: PrefixTreeMap<&str, u32> = pfx_map! {};
+/// assert!(empty.is_empty());
+/// ```
+#[macro_export]
+macro_rules! pfx_map {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        $crate::PrefixTreeMap::from([$(($key, $value)),*])
+    };
+}
+
+/// Creates a [`PrefixTreeSet`](crate::PrefixTreeSet) containing the given items.
+///
+/// ```
+/// # use pfx::{pfx_set, PrefixTreeSet};
+/// let set = pfx_set!["tea", "ten", "to"];
+///
+/// assert_eq!(set, PrefixTreeSet::from(["tea", "ten", "to"]));
+/// assert!(set.contains("ten"));
+///
+/// let empty: PrefixTreeSet<&str> = pfx_set![];
+/// assert!(empty.is_empty());
+/// ```
+#[macro_export]
+macro_rules! pfx_set {
+    ($($item:expr),* $(,)?) => {
+        $crate::PrefixTreeSet::from([$($item),*])
+    };
+}
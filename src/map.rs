@@ -1,16 +1,28 @@
 //! A map from byte strings to arbitrary values, based on a prefix tree.
 
 use core::mem;
-use core::iter::FusedIterator;
-use core::fmt::{self, Debug, Formatter};
-use core::ops::{Index, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+use core::cmp::{Ordering, Reverse};
+use core::hash::{Hash, Hasher};
+use core::iter::{FusedIterator, Peekable};
+use core::fmt::{self, Debug, Display, Formatter};
+use core::ops::{Bound, RangeBounds, Index, IndexMut, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Sub, SubAssign};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::collections::{BinaryHeap, VecDeque};
+use crate::builder::{KeyValidator, InvalidKey};
+use crate::text::{self, Delimiter};
+use crate::edge::{self, Edge, EdgeMut};
 
 
 /// An ordered map from byte strings to arbitrary values, based on a prefix tree.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+///
+/// Comparison and hashing only take the contents of the map into account,
+/// not its configuration (see [`Builder`](crate::Builder)).
+#[derive(Clone)]
 pub struct PrefixTreeMap<K, V> {
     root: Node<K, V>,
     len: usize,
+    validator: Option<KeyValidator>,
 }
 
 impl<K, V> Default for PrefixTreeMap<K, V> {
@@ -22,7 +34,21 @@ impl<K, V> Default for PrefixTreeMap<K, V> {
 impl<K, V> PrefixTreeMap<K, V> {
     /// Creates an empty map. The same as `Default`.
     pub const fn new() -> Self {
-        PrefixTreeMap { root: Node::root(), len: 0 }
+        PrefixTreeMap::with_validator(None)
+    }
+
+    pub(crate) const fn with_validator(validator: Option<KeyValidator>) -> Self {
+        PrefixTreeMap { root: Node::root(), len: 0, validator }
+    }
+
+    /// Creates an empty map with the same configuration as `self`.
+    pub(crate) fn new_like(&self) -> Self {
+        PrefixTreeMap::with_validator(self.validator)
+    }
+
+    /// Returns the configuration of the map.
+    pub(crate) const fn validator(&self) -> Option<KeyValidator> {
+        self.validator
     }
 
     /// Returns the number of entries (key-value pairs) in the map.
@@ -41,7 +67,7 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search(key.as_ref().iter().copied())
+            .search(key.as_ref())
             .and_then(Node::item)
     }
 
@@ -51,7 +77,7 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search_mut(key.as_ref().iter().copied())
+            .search_mut(key.as_ref())
             .and_then(Node::item_mut)
     }
 
@@ -61,7 +87,7 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search(key.as_ref().iter().copied())
+            .search(key.as_ref())
             .and_then(Node::value)
     }
 
@@ -71,7 +97,7 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search_mut(key.as_ref().iter().copied())
+            .search_mut(key.as_ref())
             .and_then(Node::value_mut)
     }
 
@@ -81,7 +107,7 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search(key.as_ref().iter().copied())
+            .search(key.as_ref())
             .is_some_and(|node| node.item.is_some())
     }
 
@@ -93,17 +119,129 @@ impl<K, V> PrefixTreeMap<K, V> {
         Q: ?Sized + AsRef<[u8]>,
     {
         self.root
-            .search(key.as_ref().iter().copied())
+            .search_prefix(key.as_ref())
             .is_some_and(Node::is_transitively_useful)
     }
 
+    /// Returns the number of keys starting with the given prefix.
+    ///
+    /// Every node keeps track of the number of items below it, so this
+    /// only takes time proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root
+            .search_prefix(prefix.as_ref())
+            .map_or(0, |node| node.count)
+    }
+
+    /// Returns the longest byte string that all keys in the map start with.
+    /// For an empty map, this is the empty string.
+    pub fn longest_common_prefix(&self) -> Vec<u8> {
+        let mut prefix = Vec::new();
+        self.root.extend_common_prefix(&mut prefix);
+        prefix
+    }
+
+    /// Returns the longest byte string that all keys starting with `prefix`
+    /// start with, or `None` if there are no such keys. The result always
+    /// starts with `prefix`.
+    pub fn longest_common_prefix_under<Q>(&self, prefix: &Q) -> Option<Vec<u8>>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut common = Vec::new();
+        let node = self.root.search_prefix_labelled(prefix.as_ref(), &mut common)?;
+
+        if !node.is_transitively_useful() {
+            return None;
+        }
+
+        node.extend_common_prefix(&mut common);
+        Some(common)
+    }
+
+    /// Returns `true` iff any of the keys in the map is a prefix of `query`
+    /// (including `query` itself). This stops at the first such key found
+    /// while descending along `query`.
+    pub fn contains_any_prefix_of<Q>(&self, query: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.prefixes_of(query).next().is_some()
+    }
+
+    /// Returns `true` if and only if all of the given keys are found in the map.
+    ///
+    /// The keys are sorted first, then looked up in order using a [`Finger`],
+    /// so that shared prefixes are only traversed once. Returns as soon as a
+    /// missing key is encountered.
+    pub fn contains_all<I>(&self, keys: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let keys = sorted_keys(keys);
+        let mut finger = self.finger();
+        keys.iter().all(|key| finger.contains_key(key))
+    }
+
+    /// Returns `true` if and only if any of the given keys is found in the map.
+    ///
+    /// The keys are sorted first, then looked up in order using a [`Finger`],
+    /// so that shared prefixes are only traversed once. Returns as soon as a
+    /// key is found.
+    pub fn contains_any<I>(&self, keys: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let keys = sorted_keys(keys);
+        let mut finger = self.finger();
+        keys.iter().any(|key| finger.contains_key(key))
+    }
+
+    /// Looks up many keys at once, returning the values in the order of the
+    /// keys. Missing keys result in `None`.
+    ///
+    /// Like [`PrefixTreeMap::contains_all`], this visits the keys in sorted
+    /// order using a [`Finger`], so the part of the descent shared with the
+    /// previous key is not repeated. Keys that are already sorted are
+    /// detected in linear time.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("app.db.host", 1), ("app.db.port", 2), ("app.log", 3)]);
+    /// let values = map.get_batch(["app.log", "app.db.port", "app.db.user", "app.db.host"]);
+    ///
+    /// assert_eq!(values, [Some(&3), Some(&2), None, Some(&1)]);
+    /// ```
+    pub fn get_batch<I>(&self, keys: I) -> Vec<Option<&V>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let keys: Vec<_> = keys.into_iter().collect();
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|&i, &j| keys[i].as_ref().cmp(keys[j].as_ref()));
+
+        let mut finger = self.finger();
+        let mut values = alloc::vec![None; keys.len()];
+
+        for index in order {
+            values[index] = finger.get(&keys[index]);
+        }
+
+        values
+    }
+
     /// If the key exists in the map, return the original key and the correpsonding value.
     pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         Q: ?Sized + AsRef<[u8]>,
     {
-        let node = self.root.search_mut(key.as_ref().iter().copied())?;
-        let item = node.item.take()?;
+        let item = self.root.remove(key.as_ref())?;
         self.len -= 1;
         Some(item)
     }
@@ -116,6 +254,125 @@ impl<K, V> PrefixTreeMap<K, V> {
         self.remove_entry(key).map(|(_key, value)| value)
     }
 
+    /// Returns the entry with the smallest key, if any.
+    ///
+    /// This follows the first child of each node, so it only takes time
+    /// proportional to the length of the key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.root.first()?.item()
+    }
+
+    /// Returns the entry with the greatest key, if any.
+    ///
+    /// This follows the last child of each node, so it only takes time
+    /// proportional to the length of the key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.root.last()?.item()
+    }
+
+    /// Returns `true` if no key is a strict prefix of another key, i.e. if the
+    /// keys form a prefix code. See [`PrefixTreeMap::find_prefix_pair`].
+    pub fn is_prefix_free(&self) -> bool {
+        self.find_prefix_pair().is_none()
+    }
+
+    /// Returns a pair of keys of which the first is a strict prefix of the
+    /// second, or `None` if there is no such pair. If there are several, the
+    /// first key is the smallest key that is a prefix of another one, and the
+    /// second key is the smallest key extending it.
+    ///
+    /// This is a single traversal which skips every subtree with fewer than
+    /// two keys, i.e. almost all of them if the map is (nearly) prefix-free.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let mut map = PrefixTreeMap::from([("10", 'a'), ("110", 'b'), ("111", 'c')]);
+    /// assert!(map.is_prefix_free());
+    ///
+    /// map.insert("11", 'd');
+    /// assert_eq!(map.find_prefix_pair(), Some((&"11", &"110")));
+    /// ```
+    pub fn find_prefix_pair(&self) -> Option<(&K, &K)> {
+        let mut stack = Vec::from([&self.root]);
+
+        while let Some(node) = stack.pop() {
+            if node.count < 2 {
+                continue;
+            }
+
+            if let Some((key, _value)) = node.item.as_ref() {
+                // the rest of the subtree has at least one item, the first of
+                // which is the smallest key extending this one
+                let (longer, _value) = node.children.iter().find_map(Node::first)?.item()?;
+                return Some((key, longer));
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+
+        None
+    }
+
+    /// Returns the `n`-th entry in lexicographic order of the keys (starting
+    /// from 0), or `None` if `n` is not less than the length of the map.
+    ///
+    /// The subtrees before the entry are skipped using their item counts,
+    /// so this takes time proportional to the length of the key (and the
+    /// number of children of the nodes along its path).
+    pub fn get_index(&self, n: usize) -> Option<(&K, &V)> {
+        self.root.nth(n)?.item()
+    }
+
+    /// Returns the number of keys less than `key`, i.e. the index that `key`
+    /// has or would have in lexicographic order. `key` need not be in the map.
+    ///
+    /// This takes time proportional to the length of `key` (and the number
+    /// of children of the nodes along its path).
+    pub fn rank<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root.rank(key.as_ref())
+    }
+
+    /// Returns the entry with the smallest key, if any, for in-place
+    /// inspection and manipulation.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        let mut path = Vec::new();
+
+        if !self.root.first_path(&mut path) {
+            return None;
+        }
+
+        Some(OccupiedEntry { root: &mut self.root, path, len: &mut self.len })
+    }
+
+    /// Returns the entry with the greatest key, if any, for in-place
+    /// inspection and manipulation.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        let mut path = Vec::new();
+
+        if !self.root.last_path(&mut path) {
+            return None;
+        }
+
+        Some(OccupiedEntry { root: &mut self.root, path, len: &mut self.len })
+    }
+
+    /// Removes and returns the entry with the smallest key, if any.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let item = self.root.pop_first()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    /// Removes and returns the entry with the greatest key, if any.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let item = self.root.pop_last()?;
+        self.len -= 1;
+        Some(item)
+    }
+
     /// An iterator over pairs of references to keys and the corresponding values.
     ///
     /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
@@ -123,6 +380,14 @@ impl<K, V> PrefixTreeMap<K, V> {
         Iter { iter: self.root.iter(), len: self.len }
     }
 
+    /// An iterator over pairs of references to keys and mutable references
+    /// to the corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { iter: self.root.iter_mut(), len: self.len }
+    }
+
     /// An iterator over the owned keys.
     ///
     /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
@@ -151,1053 +416,8279 @@ impl<K, V> PrefixTreeMap<K, V> {
         Values { iter: self.iter() }
     }
 
-    /// An iterator over owned key-value pairs of which the key starts with the given prefix.
+    /// An iterator over mutable references to the values.
     ///
     /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
-    pub fn into_prefix_iter<Q>(mut self, prefix: &Q) -> NodeIntoIter<K, V>
-    where
-        Q: ?Sized + AsRef<[u8]>
-    {
-        self.root
-            .search_mut(prefix.as_ref().iter().copied())
-            .map(|node| mem::take(node).into_iter())
-            .unwrap_or_default()
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { iter: self.iter_mut() }
     }
 
-    /// An iterator over borrowed key-value pairs of which the key starts with the given prefix.
+    /// An iterator over pairs of references to keys and the corresponding values,
+    /// ordered by the values according to `cmp` (in ascending order, like `sort_by`).
     ///
-    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
-    pub fn prefix_iter<Q>(&self, prefix: &Q) -> NodeIter<'_, K, V>
+    /// This is not lazy: the tree isn't ordered by values, so this method
+    /// collects references to all entries into a `Vec` upfront, which takes
+    /// O(n) time and allocates O(n) memory. The vector is arranged in a heap
+    /// instead of being sorted, after which each step takes logarithmic time,
+    /// so taking only the first few items is cheaper than sorting all entries.
+    /// The relative order of entries with equal values is unspecified.
+    ///
+    /// If only the greatest few values are needed, [`top_n_by_value`](Self::top_n_by_value)
+    /// takes memory proportional to their number instead.
+    pub fn iter_sorted_by_value<F>(&self, cmp: F) -> SortedByValue<'_, K, V, F>
     where
-        Q: ?Sized + AsRef<[u8]>
+        F: FnMut(&V, &V) -> Ordering,
     {
-        self.root
-            .search(prefix.as_ref().iter().copied())
-            .map(Node::iter)
-            .unwrap_or_default()
+        SortedByValue::new(self.iter().collect(), cmp)
     }
 
-    /// Removes all internal nodes that do not contain an entry.
+    /// Returns the `n` entries with the greatest values, in descending order
+    /// of their values. Among entries with equal values, those with smaller
+    /// keys come first.
     ///
-    /// This is useful for freeing up memory and speeding up iteration after
-    /// removing many key-value pairs from the map and/or after creating many
-    /// spurious nodes using the entry API (by not inserting into the nodes
-    /// created by `.entry()`).
-    pub fn compact(&mut self) {
-        self.root.compact();
-    }
-}
+    /// This only keeps `n` entries in memory at any time, instead of
+    /// collecting and sorting the whole map.
+    pub fn top_n_by_value(&self, n: usize) -> Vec<(&K, &V)>
+    where
+        V: Ord,
+    {
+        if n == 0 {
+            return Vec::new();
+        }
 
-impl<K, V> PrefixTreeMap<K, V>
-where
-    K: AsRef<[u8]>
-{
-    /// Return an object representing the (vacant or occupied) node of the tree
-    /// corresponding to the given key.
-    ///
-    /// This always creates a new node, even if you don't end up inserting into
-    /// it. Avoid creating many spurious entries, or call [`PrefixTreeMap::compact`]
-    /// to remove useless (empty) nodes.
-    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
-        let node = self.root.search_or_insert(key.as_ref().iter().copied());
-        let slot = &mut node.item;
-        let len = &mut self.len;
+        // min-heap of the best `n` entries seen so far
+        let mut heap = BinaryHeap::with_capacity(n + 1);
 
-        if slot.is_some() {
-            Entry::Occupied(OccupiedEntry { slot, len })
-        } else {
-            Entry::Vacant(VacantEntry { key, slot, len })
-        }
-    }
+        for (index, (key, value)) in self.iter().enumerate() {
+            heap.push(Reverse(Ranked { value, index, key }));
 
-    /// Replaces and returns the previous value, if any.
-    ///
-    /// This leaves the key in the map untouched if it already exists.
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        match self.entry(key) {
-            Entry::Vacant(entry) => {
-                entry.insert(value);
-                None
+            if heap.len() > n {
+                heap.pop();
             }
-            Entry::Occupied(mut entry) => Some(entry.insert(value))
         }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| (ranked.key, ranked.value))
+            .collect()
     }
 
-    /// Takes the union of `self` with another set of elements.
-    /// Elements that already exist in `self` will be overwritten by `other`.
-    pub fn union<I>(mut self, other: I) -> Self
+    /// An iterator over owned key-value pairs of which the key starts with the given prefix.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn into_prefix_iter<Q>(mut self, prefix: &Q) -> IntoPrefixIter<K, V>
     where
-        I: IntoIterator<Item = (K, V)>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        self.union_in_place(other);
-        self
+        let node = self.root.search_prefix_mut(prefix.as_ref()).map(mem::take);
+        IntoPrefixIter::new(node)
     }
 
-    /// Takes the union of `self` with another set of elements.
-    /// Elements that already exist in `self` will be overwritten by `other`.
-    pub fn union_in_place<I>(&mut self, other: I)
+    /// An iterator over borrowed key-value pairs of which the key starts with the given prefix.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> PrefixIter<'_, K, V>
     where
-        I: IntoIterator<Item = (K, V)>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        for (key, value) in other {
-            self.insert(key, value);
-        }
+        PrefixIter::new(self.root.search_prefix(prefix.as_ref()))
     }
 
-    /// Takes the intersection of `self` with another set of elements.
-    /// The intersection is solely based on the keys.
-    pub fn intersection<I>(mut self, other: I) -> Self
+    /// Returns a [`SubTrie`], a view of the entries of which the key starts
+    /// with `prefix`, on which lookups take keys relative to `prefix`.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("net.ipv4.ip_forward", 1), ("net.ipv4.tcp_syncookies", 1), ("vm.swappiness", 60)]);
+    /// let net = map.prefix("net.");
+    ///
+    /// assert_eq!(net.len(), 2);
+    /// assert_eq!(net.get("ipv4.ip_forward"), Some(&1));
+    /// assert!(!net.contains_key("vm.swappiness"));
+    ///
+    /// let ipv4 = net.prefix("ipv4.");
+    /// assert_eq!(ipv4.prefix_iter("tcp").map(|(key, _)| *key).collect::<Vec<_>>(), ["net.ipv4.tcp_syncookies"]);
+    /// ```
+    pub fn prefix<Q>(&self, prefix: &Q) -> SubTrie<'_, K, V>
     where
-        I: IntoIterator,
-        I::Item: AsRef<[u8]>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        other
-            .into_iter()
-            .filter_map(|key| self.remove_entry(&key))
-            .collect()
+        SubTrie::new(Some(&self.root), Vec::new()).prefix(prefix)
     }
 
-    /// Removes the items corresponding to keys in `other` from `self`.
-    pub fn difference<I>(mut self, other: I) -> Self
+    /// An iterator over pairs of references to keys and mutable references to
+    /// the corresponding values, of which the key starts with the given prefix.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn prefix_iter_mut<Q>(&mut self, prefix: &Q) -> PrefixIterMut<'_, K, V>
     where
-        I: IntoIterator,
-        I::Item: AsRef<[u8]>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        self.difference_in_place(other);
-        self
+        PrefixIterMut::new(self.root.search_prefix_mut(prefix.as_ref()))
     }
 
-    /// Removes the items corresponding to keys in `other` from `self`.
-    pub fn difference_in_place<I>(&mut self, other: I)
+    /// An iterator over the entries of which the key is a prefix of `query`
+    /// (including `query` itself), in increasing order of key length.
+    ///
+    /// This takes a single descent along `query`, so it takes time proportional
+    /// to the length of `query` for consuming the whole iterator.
+    pub fn prefixes_of<'a, Q>(&'a self, query: &'a Q) -> PrefixesOf<'a, K, V>
     where
-        I: IntoIterator,
-        I::Item: AsRef<[u8]>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        for key in other {
-            self.remove(&key);
+        PrefixesOf {
+            node: Some(&self.root),
+            rest: query.as_ref(),
         }
     }
 
-    /// Add elements that are missing from `self`, and remove elements contained in `self`.
+    /// Splits `text` into tokens by greedy longest-prefix matching: starting
+    /// from the beginning of `text`, this repeatedly finds the longest key
+    /// that is a prefix of the rest of the text, and yields the range of the
+    /// text it matched along with the key and the value. The empty key never
+    /// matches.
     ///
-    /// Containment is tested by comparing keys only. Values are not checked for equality.
-    pub fn symmetric_difference<I>(mut self, other: I) -> Self
+    /// If no key matches at some position, `unmatched` decides whether to
+    /// skip that byte and try again at the next one, or to stop. Either way,
+    /// the unmatched bytes are exactly those between the yielded ranges.
+    ///
+    /// ```
+    /// # use pfx::{PrefixTreeMap, map::Unmatched};
+    /// let keywords = PrefixTreeMap::from([("<", 1), ("<=", 2), ("=", 3), ("==", 4), ("if", 5)]);
+    /// let text = "if a<=b==c";
+    ///
+    /// let tokens: Vec<_> = keywords
+    ///     .segment_iter(text, Unmatched::Skip)
+    ///     .map(|(range, _key, &value)| (&text[range], value))
+    ///     .collect();
+    /// assert_eq!(tokens, [("if", 5), ("<=", 2), ("==", 4)]);
+    ///
+    /// assert_eq!(keywords.segment_iter(text, Unmatched::Stop).count(), 1);
+    /// ```
+    pub fn segment_iter<'a, Q>(&'a self, text: &'a Q, unmatched: Unmatched) -> SegmentIter<'a, K, V>
     where
-        I: IntoIterator<Item = (K, V)>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        self.symmetric_difference_in_place(other);
-        self
+        SegmentIter {
+            root: Some(&self.root),
+            text: text.as_ref(),
+            position: 0,
+            unmatched,
+        }
     }
 
-    /// Add elements that are missing from `self`, and remove elements contained in `self`.
+    /// An iterator over the entries of which the key is within `max_edits`
+    /// Levenshtein distance (single-byte insertions, deletions and
+    /// substitutions) of `query`, along with that distance. Iteration
+    /// proceeds in lexicographic order of the keys.
     ///
-    /// Containment is tested by comparing keys only. Values are not checked for equality.
-    pub fn symmetric_difference_in_place<I>(&mut self, other: I)
+    /// The distances are computed incrementally along the edges of the tree,
+    /// one row of the dynamic programming table per byte, and the subtrees
+    /// of which every key is already too far from `query` are skipped.
+    pub fn fuzzy_iter<Q>(&self, query: &Q, max_edits: usize) -> FuzzyIter<'_, K, V>
     where
-        I: IntoIterator<Item = (K, V)>,
+        Q: ?Sized + AsRef<[u8]>
     {
-        for (key, value) in other {
-            match self.entry(key) {
-                Entry::Occupied(entry) => { entry.remove(); }
-                Entry::Vacant(entry) => { entry.insert(value); }
-            }
+        let query = query.as_ref().to_vec();
+        let row = (0..=query.len()).collect();
+
+        FuzzyIter {
+            query,
+            max_edits,
+            stack: Vec::from([(&self.root, row)]),
         }
     }
-}
 
-impl<K, V, Q> Index<&Q> for PrefixTreeMap<K, V>
-where
-    K: AsRef<[u8]>,
-    Q: ?Sized + AsRef<[u8]>
-{
-    type Output = V;
+    /// Returns the number of keys found in both `self` and `other`.
+    ///
+    /// This walks the two trees side by side, only descending into subtrees
+    /// present in both, and does not build the intersection itself.
+    pub fn intersection_len<L, W>(&self, other: &PrefixTreeMap<L, W>) -> usize {
+        Node::count_common(&self.root, &other.root)
+    }
 
-    fn index(&self, key: &Q) -> &Self::Output {
-        self.get(key).expect("key not found in PrefixTreeMap")
+    /// Returns the number of keys found in `self`, `other`, or both,
+    /// without building the union itself.
+    pub fn union_len<L, W>(&self, other: &PrefixTreeMap<L, W>) -> usize {
+        self.len + other.len - self.intersection_len(other)
     }
-}
 
-impl<K, V, const N: usize> From<[(K, V); N]> for PrefixTreeMap<K, V>
-where
-    K: AsRef<[u8]>
-{
-    fn from(items: [(K, V); N]) -> Self {
-        items.into_iter().collect()
+    /// Returns the number of keys found in `self` but not in `other`,
+    /// without building the difference itself.
+    pub fn difference_len<L, W>(&self, other: &PrefixTreeMap<L, W>) -> usize {
+        self.len - self.intersection_len(other)
     }
-}
 
-impl<K, V> FromIterator<(K, V)> for PrefixTreeMap<K, V>
-where
-    K: AsRef<[u8]>
-{
-    fn from_iter<I>(iter: I) -> Self
+    /// Returns the number of keys found in exactly one of `self` and `other`,
+    /// without building the symmetric difference itself.
+    pub fn symmetric_difference_len<L, W>(&self, other: &PrefixTreeMap<L, W>) -> usize {
+        self.len + other.len - 2 * self.intersection_len(other)
+    }
+
+    /// Merge-joins `self` and `other`: visits the keys of both maps in
+    /// lexicographic order, each one only once, along with the entry of each
+    /// map for that key. At least one of the two entries is always present.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let stock = PrefixTreeMap::from([("apple", 3), ("banana", 0)]);
+    /// let prices = PrefixTreeMap::from([("banana", 0.25), ("cherry", 4.0)]);
+    ///
+    /// let rows: Vec<_> = stock
+    ///     .zip_by_key(&prices)
+    ///     .map(|(lhs, rhs)| (lhs.map(|(_, count)| *count), rhs.map(|(_, price)| *price)))
+    ///     .collect();
+    ///
+    /// assert_eq!(rows, [(Some(3), None), (Some(0), Some(0.25)), (None, Some(4.0))]);
+    /// ```
+    pub fn zip_by_key<'a, L, W>(&'a self, other: &'a PrefixTreeMap<L, W>) -> ZipByKey<'a, K, V, L, W>
     where
-        I: IntoIterator<Item = (K, V)>
+        K: AsRef<[u8]>,
+        L: AsRef<[u8]>,
     {
-        let mut map = PrefixTreeMap::default();
-        map.extend(iter);
-        map
+        ZipByKey {
+            lhs: self.iter().peekable(),
+            rhs: other.iter().peekable(),
+        }
     }
-}
 
-impl<K, V> Extend<(K, V)> for PrefixTreeMap<K, V>
-where
-    K: AsRef<[u8]>
-{
-    fn extend<I>(&mut self, iter: I)
+    /// Computes the changes leading from `self` to `other`, in the
+    /// lexicographic order of the affected keys. Entries with equal keys and
+    /// values in both maps are not reported.
+    ///
+    /// ```
+    /// # use pfx::{PrefixTreeMap, Change};
+    /// let old = PrefixTreeMap::from([("host", "localhost"), ("port", "8080")]);
+    /// let new = PrefixTreeMap::from([("port", "80"), ("tls", "on"), ("host", "localhost")]);
+    ///
+    /// let changes: Vec<_> = old.diff(&new).collect();
+    ///
+    /// assert_eq!(changes, [
+    ///     Change::Modified { key: &"port", old: &"8080", new: &"80" },
+    ///     Change::Added { key: &"tls", value: &"on" },
+    /// ]);
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, V>
     where
-        I: IntoIterator<Item = (K, V)>
+        K: AsRef<[u8]>,
+        V: PartialEq,
     {
-        self.union_in_place(iter);
+        Diff { zip: self.zip_by_key(other) }
     }
-}
-
-impl<K, V> IntoIterator for PrefixTreeMap<K, V> {
-    type IntoIter = IntoIter<K, V>;
-    type Item = (K, V);
 
-    fn into_iter(self) -> Self::IntoIter {
-        IntoIter {
-            iter: self.root.into_iter(),
-            len: self.len,
-        }
+    /// Removes the entries of which the key is not found in `other`.
+    ///
+    /// Like [`PrefixTreeMap::intersection_len`], this walks the two trees
+    /// side by side, so subtrees of `self` that have no counterpart in
+    /// `other` are dropped as a whole, without visiting their entries,
+    /// and subtrees of `other` that have no counterpart in `self` are
+    /// skipped altogether.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let mut map = PrefixTreeMap::from([("apple", 1), ("apricot", 2), ("banana", 3)]);
+    /// let other = PrefixTreeMap::from([("apricot", ()), ("banana", ()), ("cherry", ())]);
+    ///
+    /// map.intersect_with(&other);
+    /// assert_eq!(map, PrefixTreeMap::from([("apricot", 2), ("banana", 3)]));
+    /// ```
+    pub fn intersect_with<L, W>(&mut self, other: &PrefixTreeMap<L, W>) {
+        self.len -= self.root.retain_common(&other.root);
     }
-}
-
-impl<'a, K, V> IntoIterator for &'a PrefixTreeMap<K, V> {
-    type IntoIter = Iter<'a, K, V>;
-    type Item = (&'a K, &'a V);
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
+    /// Returns a [`Finger`] for performing many lookups in a row, each one
+    /// starting from where the previous one left off. This is faster than
+    /// [`PrefixTreeMap::get`] when consecutive keys share long prefixes.
+    pub fn finger(&self) -> Finger<'_, K, V> {
+        Finger::new(&self.root)
     }
-}
 
-/// Creates the intersection of `self` and `other`.
-impl<I, K, V> BitAndAssign<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator,
-    I::Item: AsRef<[u8]>,
-    K: AsRef<[u8]>,
-{
-    fn bitand_assign(&mut self, other: I) {
-        let map = mem::take(self);
-        *self = map.intersection(other);
+    /// Returns a [`Cursor`] at the root, for navigating the tree one byte
+    /// at a time.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("car", 1), ("cart", 2), ("cat", 3)]);
+    /// let mut cursor = map.cursor();
+    ///
+    /// assert!(cursor.descend(b'c'));
+    /// assert!(cursor.descend(b'a'));
+    /// assert_eq!(cursor.next_bytes().collect::<Vec<_>>(), b"rt");
+    /// assert_eq!(cursor.len(), 3);
+    ///
+    /// assert!(cursor.descend(b'r'));
+    /// assert_eq!(cursor.value(), Some(&1));
+    /// assert!(!cursor.descend(b'e'));
+    ///
+    /// assert!(cursor.ascend());
+    /// assert!(cursor.descend(b't'));
+    /// assert_eq!(cursor.key(), b"cat");
+    /// assert_eq!(cursor.value(), Some(&3));
+    /// ```
+    pub fn cursor(&self) -> Cursor<'_, K, V> {
+        Cursor::new(&self.root)
     }
-}
 
-/// Creates the union of `self` and `other`.
-impl<I, K, V> BitOrAssign<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator<Item = (K, V)>,
-    K: AsRef<[u8]>,
-{
-    fn bitor_assign(&mut self, other: I) {
-        self.union_in_place(other);
+    /// Returns the number of distinct prefixes of length `len` among the keys.
+    /// Keys shorter than `len` are not counted.
+    ///
+    /// This only traverses the tree up to depth `len`.
+    pub fn count_distinct_prefixes(&self, len: usize) -> usize {
+        let mut count = 0;
+        self.root.for_each_prefix(len, |_, _| count += 1);
+        count
     }
-}
 
-/// Creates the symmetric difference of `self` and `other`.
-impl<I, K, V> BitXorAssign<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator<Item = (K, V)>,
-    K: AsRef<[u8]>,
-{
-    fn bitxor_assign(&mut self, other: I) {
-        self.symmetric_difference_in_place(other);
-    }
-}
+    /// An iterator over the distinct prefixes of length `len` among the keys,
+    /// along with the number of entries having each prefix, in lexicographic
+    /// order. Keys shorter than `len` are not counted.
+    ///
+    /// The prefixes are collected upfront, in a single traversal of the tree.
+    pub fn iter_distinct_prefixes(&self, len: usize) -> DistinctPrefixes {
+        let mut prefixes = Vec::new();
 
-/// Creates the intersection of `self` and `other`.
-impl<I, K, V> BitAnd<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator,
-    I::Item: AsRef<[u8]>,
-    K: AsRef<[u8]>,
-{
-    type Output = Self;
+        self.root.for_each_prefix(len, |prefix, node| {
+            prefixes.push((prefix.to_vec(), node.count));
+        });
 
-    fn bitand(self, other: I) -> Self::Output {
-        self.intersection(other)
+        DistinctPrefixes { iter: prefixes.into_iter() }
     }
-}
-
-/// Creates the union of `self` and `other`.
-impl<I, K, V> BitOr<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator<Item = (K, V)>,
-    K: AsRef<[u8]>,
-{
-    type Output = Self;
 
-    fn bitor(mut self, other: I) -> Self::Output {
-        self |= other;
-        self
+    /// Returns the lexicographically smallest byte string of length `len`
+    /// that is not a key in the map, or `None` if all of them are present.
+    ///
+    /// This only descends into a subtree if it starts with the smallest
+    /// candidate prefix, and doesn't descend into subtrees which are known
+    /// to be full, i.e. to hold only keys of length `len`, and as many of
+    /// them as there are. When only keys of length `len` have been inserted
+    /// into the map, this takes time proportional to `len`, and not to the
+    /// size of the map. Subtrees which may hold keys of other lengths are
+    /// searched instead of being skipped.
+    pub fn find_absent_key(&self, len: usize) -> Option<Vec<u8>> {
+        let mut key = Vec::with_capacity(len);
+        self.root.find_absent(len, &mut key).then_some(key)
     }
-}
 
-/// Creates the symmetric difference of `self` and `other`.
-impl<I, K, V> BitXor<I> for PrefixTreeMap<K, V>
-where
-    I: IntoIterator<Item = (K, V)>,
-    K: AsRef<[u8]>,
-{
-    type Output = Self;
+    /// An iterator over the points where the tree branches, i.e., the prefixes
+    /// shared by keys that continue with at least two different bytes. There
+    /// need not be an entry with the branching prefix itself.
+    ///
+    /// Iteration proceeds in lexicographic order of the prefixes. The branch
+    /// points are collected upfront, in a single traversal of the tree.
+    pub fn iter_branch_points(&self) -> BranchPoints {
+        let mut points = Vec::new();
+        self.root.collect_branch_points(&mut points);
 
-    fn bitxor(mut self, other: I) -> Self::Output {
-        self ^= other;
-        self
+        BranchPoints { iter: points.into_iter() }
     }
-}
 
-impl<K, V> Debug for PrefixTreeMap<K, V>
-where
-    K: Debug,
-    V: Debug,
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_map().entries(self).finish()
+    /// Removes all internal nodes that do not contain an entry, and merges
+    /// chains of nodes without branching into single edges.
+    ///
+    /// All removals (by key, through entries, and in bulk) keep the tree
+    /// compact by themselves, so this is only a safety net. It also releases
+    /// excess capacity, like [`PrefixTreeMap::shrink_to_fit`].
+    pub fn compact(&mut self) {
+        self.root.compact();
+        self.root.shrink_to_fit();
     }
-}
-
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-struct Node<K, V> {
-    item: Option<(K, V)>,
-    key_fragment: u8,
-    children: Vec<Node<K, V>>,
-}
 
-impl<K, V> Node<K, V> {
-    const fn root() -> Self {
-        // key of root doesn't matter so we are free to use any value
-        Node::with_key_fragment(0)
+    /// Releases the excess capacity of the vectors holding the nodes, which
+    /// accumulates when building a map by many insertions. This is worth it
+    /// for large maps that aren't modified any further.
+    ///
+    /// This visits every node, so it takes time proportional to the size of
+    /// the tree.
+    pub fn shrink_to_fit(&mut self) {
+        self.root.shrink_to_fit();
     }
 
-    const fn with_key_fragment(key_fragment: u8) -> Self {
-        Node {
-            item: None,
-            key_fragment,
-            children: Vec::new(),
+    /// Converts the map into one with values of another type, by calling `f`
+    /// on each entry in lexicographic order of the keys.
+    ///
+    /// This reuses the existing nodes and keys instead of inserting every
+    /// entry into a new map, which is much faster than collecting
+    /// `into_iter().map(...)`. The new map has the same configuration.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("one", 1), ("two", 2)]);
+    /// let map = map.map_values(|key, value| format!("{key}={value}"));
+    ///
+    /// assert_eq!(map["two"], "two=2");
+    /// ```
+    pub fn map_values<U, F>(self, mut f: F) -> PrefixTreeMap<K, U>
+    where
+        F: FnMut(&K, V) -> U,
+    {
+        PrefixTreeMap {
+            root: self.root.map_values(&mut f),
+            len: self.len,
+            validator: self.validator,
         }
     }
 
-    fn is_transitively_useful(&self) -> bool {
-        self.item.is_some() || self.children.iter().any(Node::is_transitively_useful)
-    }
-
-    /// Deletes leaves/subtrees with only empty nodes. A node is empty
-    /// if its item is `None` and all of its children are empty.
-    fn compact(&mut self) -> bool {
-        let mut has_useful_children = false;
-
-        self.children.retain_mut(|child| {
-            let is_useful = child.compact();
-            has_useful_children |= is_useful;
-            is_useful
-        });
+    /// Like [`PrefixTreeMap::map_values`], but only keeps the entries for
+    /// which `f` returns `Some`. Subtrees left without entries are pruned
+    /// afterwards, so the result is as compact as a freshly built map.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let env = PrefixTreeMap::from([("PORT", "8080"), ("HOST", "::1"), ("WORKERS", "4")]);
+    /// let numbers = env.filter_map_values(|_, value| value.parse::<u32>().ok());
+    ///
+    /// assert_eq!(numbers, PrefixTreeMap::from([("PORT", 8080), ("WORKERS", 4)]));
+    /// ```
+    pub fn filter_map_values<U, F>(self, mut f: F) -> PrefixTreeMap<K, U>
+    where
+        F: FnMut(&K, V) -> Option<U>,
+    {
+        let root = self.root.filter_map_values(&mut f);
 
-        self.item.is_some() || has_useful_children
+        PrefixTreeMap {
+            len: root.count,
+            root,
+            validator: self.validator,
+        }
     }
 
-    fn value(&self) -> Option<&V> {
-        self.item.as_ref().map(|(_key, value)| value)
+    /// Retains only the entries for which `f` returns `true`, visiting them
+    /// in lexicographic order of their keys.
+    ///
+    /// This takes a single traversal of the tree, and removes the nodes
+    /// left empty by the removal of entries along the way.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.len -= self.root.retain(&mut f);
     }
 
-    fn value_mut(&mut self) -> Option<&mut V> {
-        self.item.as_mut().map(|(_key, value)| value)
+    /// Removes all entries from the map, keeping its configuration and the
+    /// allocated storage of its root node.
+    pub fn clear(&mut self) {
+        self.root.item = None;
+        self.root.children.clear();
+        self.root.count = 0;
+        self.len = 0;
     }
 
-    fn item(&self) -> Option<(&K, &V)> {
-        self.item.as_ref().map(|(key, value)| (key, value))
-    }
+    /// Removes all entries of which the key starts with `prefix`, and
+    /// returns them as a new map with the same configuration as `self`.
+    ///
+    /// The subtree under `prefix` is detached as a whole, so the rest of
+    /// the map is not visited.
+    pub fn remove_prefix<Q>(&mut self, prefix: &Q) -> Self
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut path = Vec::new();
 
-    fn item_mut(&mut self) -> Option<(&K, &mut V)> {
-        self.item.as_mut().map(|(key, value)| (&*key, value))
+        match self.root.remove_prefix(prefix.as_ref(), &mut path) {
+            Some(subtree) => self.adopt_detached(subtree, &path),
+            None => self.new_like(),
+        }
     }
 
-    fn search<B>(&self, mut bytes: B) -> Option<&Self>
-    where
-        B: Iterator<Item = u8>,
-    {
-        let Some(byte) = bytes.next() else {
-            return Some(self);
-        };
+    /// Moves `subtree`, just detached from this map, into a new map with the
+    /// same configuration, under the same full label `path`.
+    fn adopt_detached(&mut self, mut subtree: Node<K, V>, path: &[u8]) -> Self {
+        let mut other = self.new_like();
+
+        if let Some((&key_fragment, tail)) = path.split_first() {
+            subtree.key_fragment = key_fragment;
+            subtree.tail = tail.to_vec();
+            other.root.count = subtree.count;
+            other.root.children.push(subtree);
+        } else {
+            other.root = subtree;
+        }
 
-        let index = self.children.binary_search_by_key(&byte, |node| node.key_fragment).ok()?;
+        other.len = other.root.count;
+        self.len -= other.len;
 
-        self.children[index].search(bytes)
+        other
     }
 
-    fn search_mut<B>(&mut self, mut bytes: B) -> Option<&mut Self>
+    /// Retains only the entries of which the key starts with `prefix`.
+    ///
+    /// The subtree under `prefix` is detached and becomes the new tree,
+    /// so the entries outside of it are only visited for dropping them.
+    pub fn retain_prefix<Q>(&mut self, prefix: &Q)
     where
-        B: Iterator<Item = u8>,
+        Q: ?Sized + AsRef<[u8]>,
     {
-        let Some(byte) = bytes.next() else {
-            return Some(self);
-        };
+        let retained = self.remove_prefix(prefix);
 
-        let index = self.children.binary_search_by_key(&byte, |node| node.key_fragment).ok()?;
-
-        self.children[index].search_mut(bytes)
+        self.root = retained.root;
+        self.len = retained.len;
     }
 
-    fn search_or_insert<B>(&mut self, mut bytes: B) -> &mut Self
+    /// Splits the map in two at the given key. Returns a map with all the
+    /// entries of which the key is greater than or equal to `key`, and
+    /// leaves the rest in `self`.
+    ///
+    /// Whole subtrees are moved without rebuilding them, so only the nodes
+    /// along the path of `key` are visited. The returned map has the same
+    /// configuration as `self`.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
     where
-        B: Iterator<Item = u8>,
+        Q: ?Sized + AsRef<[u8]>,
     {
-        let Some(byte) = bytes.next() else {
-            return self;
-        };
+        let mut other = self.new_like();
 
-        let index = match self.children.binary_search_by_key(&byte, |node| node.key_fragment) {
-            Ok(index) => index,
-            Err(index) => {
-                self.children.insert(index, Node::with_key_fragment(byte));
-                index
-            }
-        };
+        other.root = self.root.split_off(key.as_ref());
+        other.len = other.root.count;
+        self.len -= other.len;
 
-        self.children[index].search_or_insert(bytes)
+        other
     }
 
-    fn into_iter(self) -> NodeIntoIter<K, V> {
-        let item = self.item;
-        let mut children_iter = self.children.into_iter();
-        let curr_child_iter = children_iter.next().map(|node| {
-            Box::new(node.into_iter())
-        });
-
-        NodeIntoIter {
-            item,
-            children_iter,
-            curr_child_iter,
+    /// Removes all entries from the map, returning them as an iterator of
+    /// owned key-value pairs, in lexicographic order of the keys.
+    ///
+    /// The map is empty as soon as this method returns, and it retains its
+    /// configuration (e.g. key validation rules), as well as the allocation
+    /// of the children of its root. Entries not consumed by the iterator are
+    /// dropped along with it.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        let root = &mut self.root;
+        let len = mem::take(&mut self.len);
+
+        root.count = 0;
+
+        Drain {
+            item: root.item.take(),
+            children: Some(root.children.drain()),
+            front: SubtreeIntoIter::default(),
+            back: SubtreeIntoIter::default(),
+            len,
         }
     }
 
-    fn iter(&self) -> NodeIter<'_, K, V> {
-        let item = self.item.as_ref();
-        let mut children_iter = self.children.iter();
-        let curr_child_iter = children_iter.next().map(|node| {
-            Box::new(node.iter())
-        });
-
-        NodeIter {
-            item,
-            children_iter,
-            curr_child_iter,
+    /// An iterator which visits the entries in lexicographic order of their
+    /// keys, and removes and yields those for which `pred` returns `true`.
+    ///
+    /// The removal happens lazily: entries are only examined as the iterator
+    /// is advanced, and the ones not yet examined are kept when the iterator
+    /// is dropped. Nodes left empty are removed along the way, and those
+    /// still on the path to the current entry are restructured when the
+    /// iterator is dropped, so the tree stays compact even if the iterator
+    /// is not run to completion.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf {
+            cursor: ExtractCursor::new(self),
+            pred,
         }
     }
 }
 
-/// The default impl returns the same value as `Node::root()`,
-/// and its only purpose is to make `mem::take()` work.
-impl<K, V> Default for Node<K, V> {
-    fn default() -> Self {
-        Node::root()
+impl<K, V> PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>
+{
+    /// Return an object representing the (vacant or occupied) node of the tree
+    /// corresponding to the given key.
+    ///
+    /// This doesn't modify the tree unless a value is inserted through the
+    /// entry: a vacant entry only remembers the deepest existing node along
+    /// the key, and the nodes for the rest of the key are only created by
+    /// [`VacantEntry::insert`].
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.try_entry(key) {
+            Ok(entry) => entry,
+            Err(error) => panic!("invalid key: {error}"),
+        }
     }
-}
-
-/// An entry, representing a vacant or occupied node in the tree,
-/// corresponding to a specific key.
-///
-/// The API is almost exactly the same as that of [`std::collections::btree_map::Entry`].
-#[derive(Debug)]
-pub enum Entry<'a, K, V> {
-    Vacant(VacantEntry<'a, K, V>),
-    Occupied(OccupiedEntry<'a, K, V>),
-}
 
-impl<'a, K, V> Entry<'a, K, V> {
-    pub fn key(&self) -> &K {
-        match self {
-            Entry::Vacant(entry) => entry.key(),
-            Entry::Occupied(entry) => entry.key(),
+    /// Like [`PrefixTreeMap::entry`], but returns an error instead of panicking
+    /// if `key` violates the validation rules of the map.
+    pub fn try_entry(&mut self, key: K) -> Result<Entry<'_, K, V>, InvalidKey> {
+        if let Some(validator) = self.validator.as_ref() {
+            validator.validate(key.as_ref())?;
         }
+
+        let mut path = Vec::new();
+        let (depth, is_occupied) = self.root.locate(key.as_ref(), &mut path);
+        let root = &mut self.root;
+        let len = &mut self.len;
+
+        let entry = if is_occupied {
+            Entry::Occupied(OccupiedEntry { root, path, len })
+        } else {
+            Entry::Vacant(VacantEntry { key, root, path, depth, len })
+        };
+
+        Ok(entry)
     }
 
-    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    /// Like [`PrefixTreeMap::entry`], but takes a borrowed key, which is only
+    /// converted into an owned `K` if a value is actually inserted.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let mut counts: PrefixTreeMap<String, usize> = PrefixTreeMap::new();
+    ///
+    /// for word in "the cat and the hat".split(' ') {
+    ///     // only allocates a `String` for the first occurrence of each word
+    ///     *counts.entry_ref(word).or_default() += 1;
+    /// }
+    ///
+    /// assert_eq!(counts["the"], 2);
+    /// assert_eq!(counts.len(), 4);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub fn entry_ref<'b, Q>(&mut self, key: &'b Q) -> EntryRef<'_, 'b, K, Q, V>
     where
-        F: FnOnce(&K) -> V
+        Q: ?Sized + AsRef<[u8]>,
     {
-        match self {
-            Entry::Vacant(entry) => {
-                let value = default(&entry.key);
-                entry.insert(value)
-            }
-            Entry::Occupied(entry) => entry.into_mut(),
+        match self.try_entry_ref(key) {
+            Ok(entry) => entry,
+            Err(error) => panic!("invalid key: {error}"),
         }
     }
 
-    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    /// Like [`PrefixTreeMap::entry_ref`], but returns an error instead of
+    /// panicking if `key` violates the validation rules of the map.
+    pub fn try_entry_ref<'b, Q>(&mut self, key: &'b Q) -> Result<EntryRef<'_, 'b, K, Q, V>, InvalidKey>
     where
-        F: FnOnce() -> V
+        Q: ?Sized + AsRef<[u8]>,
     {
-        self.or_insert_with_key(|_| default())
-    }
+        if let Some(validator) = self.validator.as_ref() {
+            validator.validate(key.as_ref())?;
+        }
 
-    // this trips Clippy up for some reason? Clearly I can't just call myself unconditionally...
-    #[allow(clippy::unwrap_or_default)]
-    pub fn or_default(self) -> &'a mut V
-    where
-        V: Default
-    {
-        self.or_insert_with(V::default)
-    }
+        let mut path = Vec::new();
+        let (depth, is_occupied) = self.root.locate(key.as_ref(), &mut path);
+        let root = &mut self.root;
+        let len = &mut self.len;
 
-    pub fn or_insert(self, value: V) -> &'a mut V {
-        self.or_insert_with_key(|_| value)
+        let entry = if is_occupied {
+            EntryRef::Occupied(OccupiedEntry { root, path, len })
+        } else {
+            EntryRef::Vacant(VacantEntryRef { key, root, path, depth, len })
+        };
+
+        Ok(entry)
     }
 
-    pub fn and_modify<F>(self, f: F) -> Self
+    /// Replaces and returns the previous value, if any.
+    ///
+    /// This leaves the key in the map untouched if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+            Entry::Occupied(mut entry) => Some(entry.insert(value))
+        }
+    }
+
+    /// Like [`PrefixTreeMap::insert`], but returns an error instead of panicking
+    /// if `key` violates the validation rules of the map.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, InvalidKey> {
+        let old_value = match self.try_entry(key)? {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+            Entry::Occupied(mut entry) => Some(entry.insert(value))
+        };
+
+        Ok(old_value)
+    }
+
+    /// Replays changes computed by [`PrefixTreeMap::diff`] on this map, so
+    /// that applying the diff from `old` to `new` to a copy of `old` yields
+    /// `new`. Additions and modifications insert the new value, and removals
+    /// remove the key, regardless of the current contents of the map.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let old = PrefixTreeMap::from([("host", "localhost"), ("port", "8080")]);
+    /// let new = PrefixTreeMap::from([("port", "80"), ("tls", "on")]);
+    ///
+    /// let mut remote = old.clone();
+    /// remote.apply(old.diff(&new).map(|change| change.cloned()));
+    ///
+    /// assert_eq!(remote, new);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and an added or modified key
+    /// violates them.
+    pub fn apply<I>(&mut self, changes: I)
     where
-        F: FnOnce(&mut V)
+        I: IntoIterator<Item = Change<K, V>>,
     {
-        if let Entry::Occupied(mut entry) = self {
-            f(entry.get_mut());
-            Entry::Occupied(entry)
+        for change in changes {
+            match change {
+                Change::Added { key, value } | Change::Modified { key, new: value, .. } => {
+                    self.insert(key, value);
+                }
+                Change::Removed { key, .. } => {
+                    self.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Like [`PrefixTreeMap::insert`], but if the key already exists, it
+    /// replaces the stored key and returns the old one, keeping the value.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub(crate) fn replace_key(&mut self, key: K, value: V) -> Option<K> {
+        if let Some(validator) = self.validator.as_ref() {
+            if let Err(error) = validator.validate(key.as_ref()) {
+                panic!("invalid key: {error}");
+            }
+        }
+
+        let mut path = Vec::new();
+        let (depth, is_occupied) = self.root.locate(key.as_ref(), &mut path);
+
+        if is_occupied {
+            let (old_key, _value) = self.root
+                .descendant_mut(&path)
+                .item
+                .as_mut()
+                .expect("item in occupied node");
+
+            Some(mem::replace(old_key, key))
         } else {
-            self
+            let root = &mut self.root;
+            let len = &mut self.len;
+            VacantEntry { key, root, path, depth, len }.insert(value);
+            None
+        }
+    }
+
+    /// Returns a [`SubTrieMut`], a mutable view of the entries of which the
+    /// key starts with `prefix`. Entries with other keys can't be inserted,
+    /// removed, or modified through it.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let mut map = PrefixTreeMap::from([("user.name", "alice"), ("system.user", "root")]);
+    ///
+    /// let mut user = map.prefix_mut("user.");
+    /// *user.get_mut("name").unwrap() = "bob";
+    /// user.insert_full("user.shell", "zsh");
+    /// assert!(user.try_insert_full("system.shell", "sh").is_err());
+    /// assert_eq!(user.remove("user"), None);
+    /// assert_eq!(user.len(), 2);
+    ///
+    /// assert_eq!(map.len(), 3);
+    /// assert_eq!(map["user.name"], "bob");
+    /// ```
+    pub fn prefix_mut<Q>(&mut self, prefix: &Q) -> SubTrieMut<'_, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        SubTrieMut {
+            map: self,
+            prefix: prefix.as_ref().to_vec(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns a [`CursorMut`] at the root, for navigating the tree one byte
+    /// at a time, and inserting or removing entries along the way.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let mut map: PrefixTreeMap<Vec<u8>, u32> = PrefixTreeMap::new();
+    /// let mut cursor = map.cursor_mut();
+    ///
+    /// for &byte in b"key" {
+    ///     cursor.descend(byte);
+    /// }
+    ///
+    /// cursor.insert(1);
+    /// cursor.ascend();
+    /// cursor.graft(PrefixTreeMap::from([(b"nel".to_vec(), 2)]));
+    ///
+    /// assert_eq!(cursor.len(), 2);
+    /// assert_eq!(map.get(b"kenel"), Some(&2));
+    /// ```
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, K, V> {
+        CursorMut::new(self)
+    }
+
+    /// Starts a [`Transaction`], which stages insertions and removals, and
+    /// applies them all at once when committed, or none of them otherwise.
+    ///
+    /// ```
+    /// # use pfx::{Builder, InvalidKey, PrefixTreeMap};
+    /// let mut map: PrefixTreeMap<&str, u32> = Builder::new().max_key_len(4).build_map();
+    /// map.insert("one", 1);
+    ///
+    /// fn apply(map: &mut PrefixTreeMap<&'static str, u32>, batch: &[(&'static str, u32)]) -> Result<(), InvalidKey> {
+    ///     let mut tx = map.transaction();
+    ///
+    ///     tx.remove("one");
+    ///
+    ///     for &(key, value) in batch {
+    ///         tx.try_insert(key, value)?; // an early return rolls back
+    ///     }
+    ///
+    ///     tx.commit();
+    ///     Ok(())
+    /// }
+    ///
+    /// assert!(apply(&mut map, &[("two", 2), ("three", 3)]).is_err());
+    /// assert_eq!(map.len(), 1);
+    ///
+    /// assert!(apply(&mut map, &[("two", 2), ("four", 4)]).is_ok());
+    /// assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["four", "two"]);
+    /// ```
+    pub fn transaction(&mut self) -> Transaction<'_, K, V> {
+        Transaction {
+            map: self,
+            staged: PrefixTreeMap::new(),
+        }
+    }
+
+    /// An iterator over the borrowed keys, viewed as byte strings.
+    ///
+    /// This lets generic code work with the keys as bytes without having to
+    /// carry the `K: AsRef<[u8]>` bound around, and without cloning them.
+    /// Iteration proceeds in lexicographic order.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("foo", 1), ("bar", 2)]);
+    /// assert!(map.keys_as_bytes().eq([b"bar", b"foo"]));
+    /// ```
+    pub fn keys_as_bytes(&self) -> KeysAsBytes<'_, K, V> {
+        KeysAsBytes { keys: self.keys() }
+    }
+
+    /// An iterator over pairs of references to keys and the corresponding
+    /// values, restricted to the keys that fall within `range`, like
+    /// `BTreeMap::range`. Bounds are compared as byte strings.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    ///
+    /// # Panics
+    ///
+    /// If the start of the range is greater than its end, or if the start
+    /// and the end are equal and both excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+        R: RangeBounds<Q>,
+    {
+        let end = validate_range(&range);
+        let iter = match range.start_bound() {
+            Bound::Included(start) => self.root.iter_from(start.as_ref(), true),
+            Bound::Excluded(start) => self.root.iter_from(start.as_ref(), false),
+            Bound::Unbounded => self.root.iter(),
+        };
+
+        Range { iter, end }
+    }
+
+    /// An iterator over the owned key-value pairs of which the key falls
+    /// within `range`. See [`PrefixTreeMap::range`] for details.
+    ///
+    /// # Panics
+    ///
+    /// If the start of the range is greater than its end, or if the start
+    /// and the end are equal and both excluded.
+    pub fn into_range<Q, R>(self, range: R) -> IntoRange<K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+        R: RangeBounds<Q>,
+    {
+        let end = validate_range(&range);
+        let iter = match range.start_bound() {
+            Bound::Included(start) => self.root.into_iter_from(start.as_ref(), true),
+            Bound::Excluded(start) => self.root.into_iter_from(start.as_ref(), false),
+            Bound::Unbounded => self.root.into_iter(),
+        };
+
+        IntoRange { iter, end }
+    }
+
+    /// Moves all entries from `other` into `self`, leaving `other` empty.
+    /// If a key is present in both maps, the value from `other` replaces
+    /// the value in `self`.
+    ///
+    /// The two trees are merged by walking them in parallel: subtrees of
+    /// `other` that don't overlap with `self` are moved over as a whole.
+    ///
+    /// # Panics
+    ///
+    /// If `self` has key validation rules, and a key in `other` violates
+    /// them. In this case, neither map is modified.
+    pub fn append(&mut self, other: &mut Self) {
+        if let Err(error) = self.try_append(other) {
+            panic!("invalid key: {error}");
+        }
+    }
+
+    /// Like [`PrefixTreeMap::append`], but returns an error instead of
+    /// panicking if a key in `other` violates the validation rules of `self`.
+    pub fn try_append(&mut self, other: &mut Self) -> Result<(), InvalidKey> {
+        if let Some(validator) = self.validator.as_ref() {
+            for key in other.keys() {
+                validator.validate(key.as_ref())?;
+            }
+        }
+
+        self.len += self.root.merge(mem::take(&mut other.root));
+        other.len = 0;
+
+        Ok(())
+    }
+
+    /// Builds a map from entries sorted in lexicographic order of their keys,
+    /// e.g. read from a sorted dump or another ordered map. This is faster
+    /// than collecting unsorted entries. See [`PrefixTreeMap::extend_sorted`].
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from_sorted_iter([("app", 1), ("apple", 2), ("banana", 3)]);
+    ///
+    /// assert_eq!(map, PrefixTreeMap::from([("banana", 3), ("apple", 2), ("app", 1)]));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// With debug assertions enabled, if the keys are not sorted.
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = PrefixTreeMap::new();
+        map.extend_sorted(iter);
+        map
+    }
+
+    /// Inserts entries sorted in lexicographic order of their keys. Entries
+    /// with keys greater than every key already in the map are appended
+    /// along the rightmost path of the tree, without searching among or
+    /// shifting the children of the nodes along the way. The other entries
+    /// are inserted as usual. Later entries overwrite the values of earlier
+    /// ones with the same key, like [`PrefixTreeMap::insert`].
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and a key violates them. With
+    /// debug assertions enabled, also if the keys are not sorted.
+    pub fn extend_sorted<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        // the greatest key in the map, or `None` if the map is empty
+        let mut last: Option<Vec<u8>> = self.last_key_value().map(|(key, _)| key.as_ref().to_vec());
+        let mut previous: Option<Vec<u8>> = None;
+
+        for (key, value) in iter {
+            if cfg!(debug_assertions) {
+                let bytes = key.as_ref();
+                assert!(previous.as_deref().map_or(true, |previous| previous <= bytes), "keys out of order");
+                let buffer = previous.get_or_insert_with(Vec::new);
+                buffer.clear();
+                buffer.extend_from_slice(bytes);
+            }
+
+            if last.as_deref().is_some_and(|last| last > key.as_ref()) {
+                self.insert(key, value);
+                continue;
+            }
+
+            if let Some(validator) = self.validator.as_ref() {
+                if let Err(error) = validator.validate(key.as_ref()) {
+                    panic!("invalid key: {error}");
+                }
+            }
+
+            let buffer = last.get_or_insert_with(Vec::new);
+            buffer.clear();
+            buffer.extend_from_slice(key.as_ref());
+
+            let mut counts = Vec::new();
+            let slot = self.root.append(key.as_ref(), &mut counts);
+
+            if let Some((_key, old)) = slot.as_mut() {
+                *old = value;
+            } else {
+                let key_len = key.as_ref().len();
+                *slot = Some((key, value));
+                counts.into_iter().for_each(|tally| tally.add(1, Some(key_len)));
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Takes the union of `self` with another set of elements.
+    /// Elements that already exist in `self` will be overwritten by `other`.
+    pub fn union<I>(mut self, other: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.union_in_place(other);
+        self
+    }
+
+    /// Takes the union of `self` with another set of elements.
+    /// Elements that already exist in `self` will be overwritten by `other`.
+    pub fn union_in_place<I>(&mut self, other: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (key, value) in other {
+            self.insert(key, value);
+        }
+    }
+
+    /// Takes the intersection of `self` with another set of elements.
+    /// The intersection is solely based on the keys.
+    pub fn intersection<I>(mut self, other: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut result = self.new_like();
+        result.extend(other.into_iter().filter_map(|key| self.remove_entry(&key)));
+        result
+    }
+
+    /// Removes the items corresponding to keys in `other` from `self`.
+    pub fn difference<I>(mut self, other: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.difference_in_place(other);
+        self
+    }
+
+    /// Removes the items corresponding to keys in `other` from `self`.
+    pub fn difference_in_place<I>(&mut self, other: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        for key in other {
+            self.remove(&key);
+        }
+    }
+
+    /// Add elements that are missing from `self`, and remove elements contained in `self`.
+    ///
+    /// Containment is tested by comparing keys only. Values are not checked for equality.
+    pub fn symmetric_difference<I>(mut self, other: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.symmetric_difference_in_place(other);
+        self
+    }
+
+    /// Add elements that are missing from `self`, and remove elements contained in `self`.
+    ///
+    /// Containment is tested by comparing keys only. Values are not checked for equality.
+    pub fn symmetric_difference_in_place<I>(&mut self, other: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (key, value) in other {
+            match self.entry(key) {
+                Entry::Occupied(entry) => { entry.remove(); }
+                Entry::Vacant(entry) => { entry.insert(value); }
+            }
+        }
+    }
+
+    /// Verifies the structural invariants of the tree, and describes the first
+    /// violation found. These are:
+    ///
+    /// * the children of every node are sorted by their first byte, without
+    ///   duplicates, and can be found by it;
+    /// * the key of every entry is the label of the path leading to its node;
+    /// * the item count of every node is the number of entries below it, and
+    ///   the length of the map is the number of entries.
+    ///
+    /// Nodes without an entry are not considered a violation, even if they
+    /// could be removed by [`PrefixTreeMap::compact`].
+    ///
+    /// A map only modified through its API always upholds these, so this is
+    /// meant as an oracle for fuzzing and debugging. It visits every node,
+    /// which takes time proportional to the size of the tree.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeMap;
+    /// let map = PrefixTreeMap::from([("ab", 1), ("abc", 2), ("b", 3)]);
+    /// assert_eq!(map.check_invariants(), Ok(()));
+    /// ```
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        let mut label = Vec::new();
+        // along with each node, the key length recorded by an ancestor
+        let mut stack = Vec::from([(&self.root, 0, None)]);
+        let mut len = 0;
+
+        while let Some((node, depth, key_len)) = stack.pop() {
+            label.truncate(depth);
+
+            // the root has no edge leading to it
+            if !core::ptr::eq(node, &self.root) {
+                label.push(node.key_fragment);
+                label.extend_from_slice(&node.tail);
+            }
+
+            if let Some((key, _value)) = node.item.as_ref() {
+                if key.as_ref() != label.as_slice() {
+                    return Err(InvariantViolation::KeyMismatch { path: label });
+                }
+            }
+
+            let key_len = if node.count > 0 { key_len.or(node.key_len) } else { key_len };
+
+            if node.count > 0 && node.key_len.is_some_and(|own| key_len != Some(own)) {
+                return Err(InvariantViolation::KeyLenMismatch { path: label, key_len: node.key_len });
+            }
+
+            if node.item.is_some() && key_len.is_some_and(|key_len| key_len != label.len()) {
+                return Err(InvariantViolation::KeyLenMismatch { path: label, key_len });
+            }
+
+            for (index, child) in node.children.iter().enumerate() {
+                if index > 0 && node.children[index - 1].key_fragment >= child.key_fragment {
+                    return Err(InvariantViolation::UnsortedChildren { path: label });
+                }
+
+                if node.children.find(child.key_fragment) != Some(index) {
+                    return Err(InvariantViolation::UnreachableChild { path: label, byte: child.key_fragment });
+                }
+            }
+
+            let own_count = usize::from(node.item.is_some());
+            let actual = own_count + node.children.iter().map(|child| child.count).sum::<usize>();
+
+            if node.count != actual {
+                return Err(InvariantViolation::CountMismatch { path: label, count: node.count, actual });
+            }
+
+            len += own_count;
+            stack.extend(node.children.iter().rev().map(|child| (child, label.len(), key_len)));
+        }
+
+        if self.len != len {
+            return Err(InvariantViolation::LenMismatch { len: self.len, actual: len });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl<K, V> PrefixTreeMap<K, V> {
+    /// Returns the number of bytes allocated by the tree itself, i.e. for
+    /// the arrays of children, their indices, and the labels, but not for
+    /// anything owned by the keys and values.
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        let mut stack = Vec::from([&self.root]);
+        let mut bytes = 0;
+
+        while let Some(node) = stack.pop() {
+            bytes += node.children.nodes.capacity() * mem::size_of::<Node<K, V>>() + node.tail.capacity();
+            bytes += match &node.children.index {
+                ChildIndex::Scan => 0,
+                ChildIndex::Bytes(bytes) => mem::size_of_val(&**bytes),
+                ChildIndex::Positions(positions) => mem::size_of_val(&**positions),
+                ChildIndex::Bitmap(bitmap) => mem::size_of_val(&**bitmap),
+            };
+            stack.extend(node.children.iter());
+        }
+
+        bytes
+    }
+}
+
+/// Lookups and insertion along a `Label` given separately from the key,
+/// for maps of which the tree branches on symbols other than the bytes of
+/// the keys. The labels of all entries of such a map must be of the same
+/// kind, and the methods which derive labels from the keys must not be used.
+impl<K, V> PrefixTreeMap<K, V> {
+    pub(crate) fn get_entry_by<L: Label>(&self, label: L) -> Option<(&K, &V)> {
+        self.root.search(label).and_then(Node::item)
+    }
+
+    pub(crate) fn get_mut_by<L: Label>(&mut self, label: L) -> Option<&mut V> {
+        self.root.search_mut(label).and_then(Node::value_mut)
+    }
+
+    /// Like [`PrefixTreeMap::insert`], keeping the existing key, if any.
+    /// The label of the key is derived from it by `L`, since it can't be
+    /// passed in along with the key, from which it borrows.
+    pub(crate) fn insert_by<L: Labeling<K>>(&mut self, key: K, value: V) -> Option<V> {
+        let label = L::label(&key);
+        let key_len = label.symbol_count();
+        let mut counts = Vec::new();
+        let slot = self.root.search_or_insert(label, &mut counts);
+
+        if let Some((_key, old)) = slot.as_mut() {
+            return Some(mem::replace(old, value));
+        }
+
+        *slot = Some((key, value));
+
+        for tally in counts {
+            tally.add(1, Some(key_len));
+        }
+
+        self.len += 1;
+
+        None
+    }
+
+    pub(crate) fn remove_entry_by<L: Label>(&mut self, label: L) -> Option<(K, V)> {
+        let item = self.root.remove(label)?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    pub(crate) fn count_prefix_by<L: Label>(&self, label: L) -> usize {
+        self.root.search_prefix(label).map_or(0, |node| node.count)
+    }
+
+    pub(crate) fn prefix_iter_by<L: Label>(&self, label: L) -> PrefixIter<'_, K, V> {
+        PrefixIter::new(self.root.search_prefix(label))
+    }
+
+    /// Like [`PrefixTreeMap::prefixes_of`], following the nodes along the
+    /// label in a single pass, from the shortest prefix to the longest one.
+    pub(crate) fn prefixes_of_by<'a, 'b, L>(&'a self, label: L) -> impl Iterator<Item = (&'a K, &'a V)> + 'b
+    where
+        'a: 'b,
+        L: Label + 'b,
+    {
+        let mut node = Some(&self.root);
+        let mut rest = label;
+
+        core::iter::from_fn(move || loop {
+            let current = node.take()?;
+
+            if let Some((symbol, after)) = rest.next_symbol() {
+                if let Some(child) = current.child(symbol) {
+                    if let Some(after) = after.strip_tail(child.stored_tail()) {
+                        node = Some(child);
+                        rest = after;
+                    }
+                }
+            }
+
+            if let Some(item) = current.item() {
+                return Some(item);
+            }
+        })
+    }
+}
+
+impl<K: AsRef<[u8]>> PrefixTreeMap<K, usize> {
+    /// Builds a map from the tokens of `corpus`, separated by `delimiter`, where
+    /// the value corresponding to each token is the number of times it occurs.
+    ///
+    /// Empty tokens (e.g. between consecutive delimiters) are skipped.
+    /// See also [`PrefixTreeSet::from_text`](crate::PrefixTreeSet::from_text).
+    pub fn from_text<'a, D>(corpus: &'a str, delimiter: D) -> Self
+    where
+        K: From<&'a str>,
+        D: Delimiter,
+    {
+        let mut map = PrefixTreeMap::new();
+
+        for token in text::tokens(corpus, delimiter) {
+            *map.entry(K::from(token)).or_insert(0) += 1;
+        }
+
+        map
+    }
+}
+
+/// Non-consuming versions of the set operations. These leave both operands
+/// intact, and only clone the entries that end up in the result.
+impl<K, V> PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    /// Takes the union of `self` and `other` without consuming either of them.
+    /// Entries that exist in both maps will have the value from `other`.
+    pub fn union_ref(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        result.union_in_place(other.iter().map(|(k, v)| (k.clone(), v.clone())));
+        result
+    }
+
+    /// Takes the intersection of `self` and `other` without consuming either of them.
+    /// The intersection is solely based on the keys; the values are taken from `self`.
+    pub fn intersection_ref(&self, other: &Self) -> Self {
+        let mut result = self.new_like();
+        result.extend(
+            self.iter()
+                .filter(|(key, _)| other.contains_key(key))
+                .map(|(k, v)| (k.clone(), v.clone()))
+        );
+        result
+    }
+
+    /// Returns the entries of `self` whose keys are not found in `other`.
+    pub fn difference_ref(&self, other: &Self) -> Self {
+        let mut result = self.new_like();
+        result.extend(
+            self.iter()
+                .filter(|(key, _)| !other.contains_key(key))
+                .map(|(k, v)| (k.clone(), v.clone()))
+        );
+        result
+    }
+
+    /// Returns the entries of either map whose keys are not found in the other map.
+    pub fn symmetric_difference_ref(&self, other: &Self) -> Self {
+        let mut result = self.difference_ref(other);
+        result.extend(
+            other
+                .iter()
+                .filter(|(key, _)| !self.contains_key(key))
+                .map(|(k, v)| (k.clone(), v.clone()))
+        );
+        result
+    }
+}
+
+/// Specialized lookups for maps keyed by fixed-width byte arrays (hashes, IDs, etc.)
+///
+/// Since every key has exactly `N` bytes, every item lives at depth `N`, and
+/// every node above that depth leads to some item. The descent thus only
+/// dispatches on the first byte of each edge, skipping the comparison of the
+/// rest of the edge, as well as the check for the end of the key, at every
+/// level. Instead, the key stored at depth `N` is compared with the query in
+/// a single, fixed-width comparison at the end.
+impl<V, const N: usize> PrefixTreeMap<[u8; N], V> {
+    /// Return a reference to the value corresponding to the fixed-width key, if found.
+    pub fn get_fixed(&self, key: &[u8; N]) -> Option<&V> {
+        self.root.search_fixed(key).and_then(Node::value)
+    }
+
+    /// Return a mutable reference to the value corresponding to the fixed-width key, if found.
+    pub fn get_fixed_mut(&mut self, key: &[u8; N]) -> Option<&mut V> {
+        self.root.search_fixed_mut(key).and_then(Node::value_mut)
+    }
+
+    /// Returns `true` if and only if the given fixed-width key is found in the map.
+    pub fn contains_fixed_key(&self, key: &[u8; N]) -> bool {
+        self.root.search_fixed(key).is_some()
+    }
+}
+
+impl<K, V, Q> Index<&Q> for PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>,
+    Q: ?Sized + AsRef<[u8]>
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &Self::Output {
+        self.get(key).expect("key not found in PrefixTreeMap")
+    }
+}
+
+/// Mutable access to the value of an existing key. This can't insert new
+/// entries: assigning to the value of a missing key panics, like reading it.
+/// Use [`PrefixTreeMap::insert`] or [`PrefixTreeMap::entry`] for that.
+impl<K, V, Q> IndexMut<&Q> for PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>,
+    Q: ?Sized + AsRef<[u8]>
+{
+    fn index_mut(&mut self, key: &Q) -> &mut Self::Output {
+        self.get_mut(key).expect("key not found in PrefixTreeMap")
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>
+{
+    fn from(items: [(K, V); N]) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = PrefixTreeMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]>
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        self.union_in_place(iter);
+    }
+}
+
+/// Clones the keys and values, like the standard collections.
+impl<'a, K, V> Extend<(&'a K, &'a V)> for PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (&'a K, &'a V)>
+    {
+        self.union_in_place(iter.into_iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+}
+
+impl<K, V> IntoIterator for PrefixTreeMap<K, V> {
+    type IntoIter = IntoIter<K, V>;
+    type Item = (K, V);
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.root.into_iter(),
+            len: self.len,
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a PrefixTreeMap<K, V> {
+    type IntoIter = Iter<'a, K, V>;
+    type Item = (&'a K, &'a V);
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut PrefixTreeMap<K, V> {
+    type IntoIter = IterMut<'a, K, V>;
+    type Item = (&'a K, &'a mut V);
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Creates the intersection of `self` and `other`.
+impl<I, K, V> BitAndAssign<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+    K: AsRef<[u8]>,
+{
+    fn bitand_assign(&mut self, other: I) {
+        let map = mem::take(self);
+        *self = map.intersection(other);
+    }
+}
+
+/// Creates the union of `self` and `other`.
+impl<I, K, V> BitOrAssign<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+{
+    fn bitor_assign(&mut self, other: I) {
+        self.union_in_place(other);
+    }
+}
+
+/// Creates the symmetric difference of `self` and `other`.
+impl<I, K, V> BitXorAssign<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+{
+    fn bitxor_assign(&mut self, other: I) {
+        self.symmetric_difference_in_place(other);
+    }
+}
+
+/// Creates the difference of `self` and `other`.
+impl<I, K, V> SubAssign<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+    K: AsRef<[u8]>,
+{
+    fn sub_assign(&mut self, other: I) {
+        self.difference_in_place(other);
+    }
+}
+
+/// Creates the intersection of `self` and `other`.
+impl<I, K, V> BitAnd<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+    K: AsRef<[u8]>,
+{
+    type Output = Self;
+
+    fn bitand(self, other: I) -> Self::Output {
+        self.intersection(other)
+    }
+}
+
+/// Creates the union of `self` and `other`.
+impl<I, K, V> BitOr<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+{
+    type Output = Self;
+
+    fn bitor(mut self, other: I) -> Self::Output {
+        self |= other;
+        self
+    }
+}
+
+/// Creates the symmetric difference of `self` and `other`.
+impl<I, K, V> BitXor<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<[u8]>,
+{
+    type Output = Self;
+
+    fn bitxor(mut self, other: I) -> Self::Output {
+        self ^= other;
+        self
+    }
+}
+
+/// Creates the difference of `self` and `other`.
+impl<I, K, V> Sub<I> for PrefixTreeMap<K, V>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+    K: AsRef<[u8]>,
+{
+    type Output = Self;
+
+    fn sub(self, other: I) -> Self::Output {
+        self.difference(other)
+    }
+}
+
+/// Creates the intersection of `self` and `other`, leaving both intact.
+impl<K, V> BitAnd<&PrefixTreeMap<K, V>> for &PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    type Output = PrefixTreeMap<K, V>;
+
+    fn bitand(self, other: &PrefixTreeMap<K, V>) -> Self::Output {
+        self.intersection_ref(other)
+    }
+}
+
+/// Creates the union of `self` and `other`, leaving both intact.
+impl<K, V> BitOr<&PrefixTreeMap<K, V>> for &PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    type Output = PrefixTreeMap<K, V>;
+
+    fn bitor(self, other: &PrefixTreeMap<K, V>) -> Self::Output {
+        self.union_ref(other)
+    }
+}
+
+/// Creates the symmetric difference of `self` and `other`, leaving both intact.
+impl<K, V> BitXor<&PrefixTreeMap<K, V>> for &PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    type Output = PrefixTreeMap<K, V>;
+
+    fn bitxor(self, other: &PrefixTreeMap<K, V>) -> Self::Output {
+        self.symmetric_difference_ref(other)
+    }
+}
+
+/// Creates the difference of `self` and `other`, leaving both intact.
+impl<K, V> Sub<&PrefixTreeMap<K, V>> for &PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    type Output = PrefixTreeMap<K, V>;
+
+    fn sub(self, other: &PrefixTreeMap<K, V>) -> Self::Output {
+        self.difference_ref(other)
+    }
+}
+
+/// Maps are equal if they have equal entries, regardless of the shape of
+/// their trees (e.g. leftover empty nodes) and their configuration.
+impl<K: PartialEq, V: PartialEq> PartialEq for PrefixTreeMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for PrefixTreeMap<K, V> {}
+
+/// Maps are compared lexicographically by their entries, in the order of
+/// the keys, like the equivalent `BTreeMap`s.
+impl<K: PartialOrd, V: PartialOrd> PartialOrd for PrefixTreeMap<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: Ord, V: Ord> Ord for PrefixTreeMap<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Only the entries are hashed, consistently with `PartialEq`.
+impl<K: Hash, V: Hash> Hash for PrefixTreeMap<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.iter().for_each(|item| item.hash(state));
+    }
+}
+
+impl<K, V> Debug for PrefixTreeMap<K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+/// Collects a batch of keys and sorts them by their bytes, so that
+/// consecutive lookups share as long a prefix as possible.
+fn sorted_keys<I>(keys: I) -> Vec<I::Item>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut keys: Vec<_> = keys.into_iter().collect();
+    keys.sort_unstable_by(|lhs, rhs| lhs.as_ref().cmp(rhs.as_ref()));
+    keys
+}
+
+/// Checks that `range` is not decreasing, and returns an owned copy of its end.
+fn validate_range<Q, R>(range: &R) -> Bound<Vec<u8>>
+where
+    Q: ?Sized + AsRef<[u8]>,
+    R: RangeBounds<Q>,
+{
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Excluded(start), Bound::Excluded(end)) if start.as_ref() == end.as_ref() => {
+            panic!("range start and end are equal and excluded")
+        }
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) if start.as_ref() > end.as_ref() => {
+            panic!("range start is greater than range end")
+        }
+        _ => {}
+    }
+
+    match range.end_bound() {
+        Bound::Included(end) => Bound::Included(end.as_ref().to_vec()),
+        Bound::Excluded(end) => Bound::Excluded(end.as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Returns `true` if `key` doesn't exceed the `end` of a range.
+fn is_before_end(key: &[u8], end: &Bound<Vec<u8>>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end.as_slice(),
+        Bound::Excluded(end) => key < end.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+/// A sequence of the symbols on which the tree branches, which lookups and
+/// insertion follow one symbol at a time. For ordinary maps, these are the
+/// bytes of the keys, but other sequences (such as the nibbles of the keys)
+/// can be followed without unpacking them into a byte slice first. Labels
+/// stored in the tree hold their symbols as described by their [`Packing`].
+pub(crate) trait Label: Copy {
+    /// Splits off the first symbol, if any.
+    fn next_symbol(self) -> Option<(u8, Self)>;
+
+    /// Returns the number of symbols.
+    fn symbol_count(self) -> usize;
+
+    /// Returns the number of leading symbols which are the same as those
+    /// of the stored label `tail`.
+    fn common_prefix_len(self, tail: Tail<'_>) -> usize;
+
+    /// Skips the first `len` symbols.
+    fn skip(self, len: usize) -> Self;
+
+    /// Returns the symbols in the form in which they are stored as a label.
+    fn to_tail(self) -> (Vec<u8>, Packing);
+
+    /// Removes the stored label `tail` from the front, if present.
+    fn strip_tail(self, tail: Tail<'_>) -> Option<Self> {
+        (self.common_prefix_len(tail) == tail.len()).then(|| self.skip(tail.len()))
+    }
+
+    /// Returns `true` if the stored label `tail` starts with all symbols.
+    fn is_prefix_of(self, tail: Tail<'_>) -> bool {
+        self.common_prefix_len(tail) == self.symbol_count()
+    }
+}
+
+impl Label for &[u8] {
+    fn next_symbol(self) -> Option<(u8, Self)> {
+        self.split_first().map(|(&byte, rest)| (byte, rest))
+    }
+
+    fn symbol_count(self) -> usize {
+        self.len()
+    }
+
+    fn common_prefix_len(self, tail: Tail<'_>) -> usize {
+        edge::common_prefix_len(self, tail.bytes)
+    }
+
+    fn skip(self, len: usize) -> Self {
+        &self[len..]
+    }
+
+    fn to_tail(self) -> (Vec<u8>, Packing) {
+        (self.to_vec(), Packing::Bytes)
+    }
+
+    fn strip_tail(self, tail: Tail<'_>) -> Option<Self> {
+        self.strip_prefix(tail.bytes)
+    }
+
+    fn is_prefix_of(self, tail: Tail<'_>) -> bool {
+        tail.bytes.starts_with(self)
+    }
+}
+
+/// How the stored label of an edge holds its symbols.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub(crate) enum Packing {
+    /// One symbol per byte.
+    #[default]
+    Bytes,
+    /// Two nibbles per byte, high nibble first. If `odd_start`, the high
+    /// nibble of the first byte is not part of the label, and if `odd_end`,
+    /// neither is the low nibble of the last byte. Such unused nibbles are
+    /// zero, and an empty label has no bytes, and neither flag set.
+    Nibbles { odd_start: bool, odd_end: bool },
+}
+
+/// A stored label, along with the way it holds its symbols.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tail<'a> {
+    pub(crate) bytes: &'a [u8],
+    packing: Packing,
+}
+
+impl Tail<'_> {
+    /// Returns the number of symbols.
+    pub(crate) fn len(self) -> usize {
+        match self.packing {
+            Packing::Bytes => self.bytes.len(),
+            Packing::Nibbles { odd_start, odd_end } => {
+                self.bytes.len() * 2 - usize::from(odd_start) - usize::from(odd_end)
+            }
+        }
+    }
+
+    /// Returns the symbol at `index`.
+    pub(crate) fn get(self, index: usize) -> u8 {
+        match self.packing {
+            Packing::Bytes => self.bytes[index],
+            Packing::Nibbles { odd_start, .. } => nibble_at(self.bytes, index + usize::from(odd_start)),
+        }
+    }
+}
+
+/// Returns the `index`-th nibble of `bytes`, high nibble first.
+pub(crate) fn nibble_at(bytes: &[u8], index: usize) -> u8 {
+    let byte = bytes[index / 2];
+
+    if index % 2 == 0 {
+        byte >> 4
+    } else {
+        byte & 0x0f
+    }
+}
+
+/// A way of deriving the `Label` of a key of type `K`.
+pub(crate) trait Labeling<K: ?Sized> {
+    type Label<'a>: Label where K: 'a;
+
+    fn label(key: &K) -> Self::Label<'_>;
+}
+
+/// The bookkeeping of a node above a new item: pushed onto a stack on the
+/// way down to the item, and updated once the item is in place.
+struct Tally<'a> {
+    count: &'a mut usize,
+    key_len: &'a mut Option<usize>,
+}
+
+impl Tally<'_> {
+    /// Accounts for `added` new items below the node, all of which have keys
+    /// of length `key_len`, if known.
+    fn add(self, added: usize, key_len: Option<usize>) {
+        add_items(self.count, self.key_len, added, key_len);
+    }
+}
+
+/// Adds `added` items, all of which have keys of length `added_len`, if
+/// known, to the `count` and `key_len` of a node.
+fn add_items(count: &mut usize, key_len: &mut Option<usize>, added: usize, added_len: Option<usize>) {
+    if added == 0 {
+        return;
+    }
+
+    if *count == 0 {
+        *key_len = added_len;
+    } else if *key_len != added_len {
+        *key_len = None;
+    }
+
+    *count += added;
+}
+
+#[derive(Clone, Debug)]
+struct Node<K, V> {
+    item: Option<(K, V)>,
+    /// The number of items in the subtree rooted at this node, including
+    /// its own. Kept up to date by every mutation, so that questions about
+    /// the size of a subtree don't require traversing it.
+    count: usize,
+    /// If `Some`, all keys in the subtree rooted at this node are of this
+    /// length (in symbols of the tree, i.e. bytes for ordinary maps). This is
+    /// set when items are added to an empty subtree, and cleared as soon as
+    /// a key of another length is added. Removing items never makes it
+    /// wrong, so it is left alone then, even if the keys left behind are of
+    /// the same length again. Meaningless if `count` is 0.
+    key_len: Option<usize>,
+    key_fragment: u8,
+    /// The rest of the label of the edge leading to this node, after
+    /// `key_fragment`. A new leaf stores the whole unexplored suffix of
+    /// its key here, and the edge is only split when another key diverges
+    /// inside it, so that long keys with unique suffixes don't require a
+    /// separate node for every byte.
+    tail: Vec<u8>,
+    /// How `tail` holds its symbols. Only maps of which the tree branches
+    /// on nibbles pack them, all other labels are stored one symbol per byte.
+    packing: Packing,
+    children: Children<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    const fn root() -> Self {
+        // key of root doesn't matter so we are free to use any value
+        Node::with_label(0, Vec::new())
+    }
+
+    const fn with_label(key_fragment: u8, tail: Vec<u8>) -> Self {
+        Node {
+            item: None,
+            count: 0,
+            key_len: None,
+            key_fragment,
+            tail,
+            packing: Packing::Bytes,
+            children: Children::new(),
+        }
+    }
+
+    /// The label of the edge leading to this node, after `key_fragment`.
+    fn stored_tail(&self) -> Tail<'_> {
+        Tail { bytes: &self.tail, packing: self.packing }
+    }
+
+    /// Truncates the tail after `at` symbols, and returns the symbol after
+    /// them, along with the rest of the tail and its packing. Packed nibbles
+    /// are split in place, without shifting them.
+    fn split_tail(&mut self, at: usize) -> (u8, Vec<u8>, Packing) {
+        let Packing::Nibbles { odd_start, odd_end } = self.packing else {
+            let mut suffix = self.tail.split_off(at);
+            let key_fragment = suffix.remove(0);
+            return (key_fragment, suffix, Packing::Bytes);
+        };
+
+        let empty = Packing::Nibbles { odd_start: false, odd_end: false };
+        let len = self.stored_tail().len();
+        // the position of the symbol at `at` among the nibbles of the bytes
+        let split = at + usize::from(odd_start);
+        let key_fragment = nibble_at(&self.tail, split);
+
+        let (suffix, suffix_packing) = if at + 1 < len {
+            let start = split + 1;
+            let mut suffix = self.tail[start / 2..].to_vec();
+            let odd_start = start % 2 == 1;
+
+            if odd_start {
+                suffix[0] &= 0x0f;
+            }
+
+            (suffix, Packing::Nibbles { odd_start, odd_end })
+        } else {
+            (Vec::new(), empty)
+        };
+
+        if at > 0 {
+            let odd_end = split % 2 == 1;
+            self.tail.truncate(split.div_ceil(2));
+
+            if odd_end {
+                self.tail[split / 2] &= 0xf0;
+            }
+
+            self.packing = Packing::Nibbles { odd_start, odd_end };
+        } else {
+            self.tail.clear();
+            self.packing = empty;
+        }
+
+        (key_fragment, suffix, suffix_packing)
+    }
+
+    /// Appends `symbol`, followed by the stored label `rest`, to the tail.
+    fn append_tail(&mut self, symbol: u8, rest: Tail<'_>) {
+        let Packing::Nibbles { odd_start, mut odd_end } = self.packing else {
+            self.tail.push(symbol);
+            self.tail.extend_from_slice(rest.bytes);
+            return;
+        };
+
+        for nibble in core::iter::once(symbol).chain((0..rest.len()).map(|index| rest.get(index))) {
+            match self.tail.last_mut() {
+                Some(last) if odd_end => *last |= nibble,
+                _ => self.tail.push(nibble << 4),
+            }
+            odd_end = !odd_end;
+        }
+
+        self.packing = Packing::Nibbles { odd_start, odd_end };
+    }
+
+    /// Detaches the child at `index`, leaving an empty node with the same
+    /// first byte in its place, to be put back with [`Children::replace`].
+    fn detach_child(&mut self, index: usize) -> Self {
+        let byte = self.children[index].key_fragment;
+        self.children.replace(index, Node::with_label(byte, Vec::new()))
+    }
+
+    fn is_transitively_useful(&self) -> bool {
+        self.count > 0
+    }
+
+    /// Deletes leaves/subtrees with only empty nodes, and compresses the
+    /// remaining children. A node is empty if its item is `None` and all of
+    /// its children are empty.
+    ///
+    /// Children are tidied up in post-order, on the way up from them, so
+    /// that the subtree of every child is already compact by then.
+    fn compact(&mut self) {
+        let mut path = DetachedPath::new(self);
+        let mut next = 0;
+
+        loop {
+            if next < path.node.children.len() {
+                path.descend(next);
+                next = 0;
+            } else if let Some(index) = path.ascend() {
+                next = index;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Recomputes `key_len` from the item of this node, the key of which
+    /// would be `depth` symbols long, and from the children.
+    #[cfg(feature = "serde")]
+    fn recompute_key_len(&mut self, depth: usize) {
+        let own = self.item.as_ref().map(|_| Some(depth));
+        let below = self.children.iter().filter(|child| child.count > 0).map(|child| child.key_len);
+        let mut lens = own.into_iter().chain(below);
+
+        self.key_len = match lens.next() {
+            Some(first) if lens.all(|len| len == first) => first,
+            _ => None,
+        };
+    }
+
+    /// Recomputes the item count of this node from those of its children.
+    fn recount(&mut self) {
+        self.count = usize::from(self.item.is_some()) + self.children.iter().map(|child| child.count).sum::<usize>();
+    }
+
+    /// Releases the excess capacity of the children and the tail of every
+    /// node in the subtree.
+    fn shrink_to_fit(&mut self) {
+        let mut stack = Vec::from([self]);
+
+        while let Some(node) = stack.pop() {
+            node.tail.shrink_to_fit();
+            node.children.shrink_to_fit();
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Converts the values of the subtree by calling `f` on each item in
+    /// lexicographic order, keeping the structure intact.
+    fn map_values<U, F>(self, f: &mut F) -> Node<K, U>
+    where
+        F: FnMut(&K, V) -> U,
+    {
+        self.convert_items(&mut |key, value| Some(f(key, value)))
+    }
+
+    /// Converts the values of the subtree by calling `f` on each item in
+    /// lexicographic order, and drops the items for which it returns `None`.
+    /// The structure is kept intact, even if nodes are left empty, but their
+    /// item counts are brought up to date.
+    ///
+    /// The new nodes are built bottom-up from an explicit stack, each one
+    /// only once all of its children have been converted.
+    fn convert_items<U, F>(self, f: &mut F) -> Node<K, U>
+    where
+        F: FnMut(&K, V) -> Option<U>,
+    {
+        /// A converted node, along with its converted children so far,
+        /// and the children of the old node not converted yet.
+        struct Frame<K, V, U> {
+            node: Node<K, U>,
+            converted: Vec<Node<K, U>>,
+            children: alloc::vec::IntoIter<Node<K, V>>,
+        }
+
+        impl<K, V, U> Frame<K, V, U> {
+            fn new<F>(mut node: Node<K, V>, f: &mut F) -> Self
+            where
+                F: FnMut(&K, V) -> Option<U>,
+            {
+                let item = node.item.take().and_then(|(key, value)| {
+                    let value = f(&key, value)?;
+                    Some((key, value))
+                });
+                let children = mem::take(&mut node.children).into_vec();
+                let mut converted = Node::with_label(node.key_fragment, mem::take(&mut node.tail));
+
+                // dropping items doesn't change the length of the keys left
+                converted.item = item;
+                converted.key_len = node.key_len;
+
+                Frame {
+                    node: converted,
+                    converted: Vec::with_capacity(children.len()),
+                    children: children.into_iter(),
+                }
+            }
+        }
+
+        let mut stack = Vec::new();
+        let mut frame = Frame::new(self, f);
+
+        loop {
+            if let Some(child) = frame.children.next() {
+                stack.push(frame);
+                frame = Frame::new(child, f);
+                continue;
+            }
+
+            let mut node = frame.node;
+            node.children = Children::from_vec(frame.converted);
+            node.recount();
+
+            let Some(parent) = stack.pop() else {
+                return node;
+            };
+
+            frame = parent;
+            frame.converted.push(node);
+        }
+    }
+
+    /// Like `map_values`, but drops the items for which `f` returns `None`,
+    /// along with the nodes that become empty, and compresses the rest.
+    /// These are pruned after converting the whole subtree, by `compact()`.
+    fn filter_map_values<U, F>(self, f: &mut F) -> Node<K, U>
+    where
+        F: FnMut(&K, V) -> Option<U>,
+    {
+        let mut node = self.convert_items(f);
+        node.compact();
+        node
+    }
+
+    /// Removes the items for which `f` returns `false`, along with the nodes
+    /// that become empty. Returns the number of removed items.
+    ///
+    /// Items are examined in pre-order, when descending to their nodes, and
+    /// nodes are tidied up on the way up from them, like in `compact()`.
+    fn retain<F>(&mut self, f: &mut F) -> usize
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let before = self.count;
+        let mut path = DetachedPath::new(self);
+        let mut next = 0;
+
+        path.node.retain_item(f);
+
+        loop {
+            if next < path.node.children.len() {
+                path.descend(next);
+                path.node.retain_item(f);
+                next = 0;
+            } else if let Some(index) = path.ascend() {
+                next = index;
+            } else {
+                break;
+            }
+        }
+
+        drop(path);
+        before - self.count
+    }
+
+    /// Removes the item of this node if `f` returns `false` for it.
+    fn retain_item<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        if let Some((key, value)) = self.item.as_mut() {
+            if !f(key, value) {
+                self.item = None;
+            }
+        }
+    }
+
+    /// Detaches the topmost node under which all keys start with `bytes`,
+    /// and removes the nodes left empty. Pushes the full label of the path
+    /// leading to the detached node onto `path`.
+    fn remove_prefix(&mut self, bytes: &[u8], path: &mut Vec<u8>) -> Option<Self> {
+        let mut indices = Vec::new();
+        let mut node = &*self;
+        let mut bytes = bytes;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let index = node.children.find(byte)?;
+            let child = &node.children[index];
+
+            bytes = match rest.strip_prefix(child.tail.as_slice()) {
+                Some(rest) => rest,
+                None if child.tail.starts_with(rest) => &[],
+                None => return None,
+            };
+
+            path.push(byte);
+            path.extend_from_slice(&child.tail);
+            indices.push(index);
+            node = child;
+        }
+
+        Some(self.detach_at(&indices))
+    }
+
+    /// Detaches the node found by following the child indices in `path`,
+    /// updating the item counts along the way, and removes or compresses
+    /// the nodes left behind.
+    fn detach_at(&mut self, path: &[usize]) -> Self {
+        let Some((&index, init)) = path.split_last() else {
+            return mem::take(self);
+        };
+
+        let removed = self.descendant(path).count;
+        let mut parent = &mut *self;
+        parent.count -= removed;
+
+        for &index in init {
+            parent = parent.children.get_mut(index).expect("path leads to a node");
+            parent.count -= removed;
+        }
+
+        let subtree = parent.children.remove(index);
+        self.tidy_at(init);
+
+        subtree
+    }
+
+    /// Moves the items with keys greater than or equal to `bytes` (relative
+    /// to this node) into a new node with the same label, and removes the
+    /// nodes left empty.
+    ///
+    /// The nodes along `bytes` are detached from their parents on the way
+    /// down, and put back on the way up, so that they can be restructured
+    /// bottom-up without recursion.
+    fn split_off(&mut self, bytes: &[u8]) -> Self {
+        // the detached nodes, their split-off parts, and the index of the
+        // child detached from each of them
+        let mut stack = Vec::new();
+        let mut lower = mem::take(self);
+        let mut bytes = bytes;
+
+        let mut upper = loop {
+            let mut upper = Node::with_label(lower.key_fragment, lower.tail.clone());
+
+            let Some((&byte, rest)) = bytes.split_first() else {
+                upper.item = lower.item.take();
+                upper.children = mem::take(&mut lower.children);
+                upper.count = mem::take(&mut lower.count);
+                break upper;
+            };
+
+            let (index, rest) = lower.lower_bound_child(byte, rest);
+            upper.children = lower.children.split_off(index + usize::from(rest.is_some()));
+
+            // the bound continues into a child, which needs to be split as well
+            let Some(rest) = rest else {
+                upper.count = upper.children.iter().map(|child| child.count).sum();
+                lower.count -= upper.count;
+                break upper;
+            };
+
+            let child = lower.detach_child(index);
+            stack.push((lower, upper, index));
+            lower = child;
+            bytes = rest;
+        };
+
+        while let Some((mut parent, mut parent_upper, index)) = stack.pop() {
+            parent.children.replace(index, lower);
+            parent.tidy_child(index);
+
+            if upper.item.is_some() || !upper.children.is_empty() {
+                upper.compress();
+                parent_upper.children.insert(0, upper);
+            }
+
+            parent_upper.count = parent_upper.children.iter().map(|child| child.count).sum();
+            parent.count -= parent_upper.count;
+
+            lower = parent;
+            upper = parent_upper;
+        }
+
+        *self = lower;
+        upper
+    }
+
+    /// Moves the items of `other`, which corresponds to the same key as this
+    /// node, into this node. Items of `other` replace existing ones. Returns
+    /// the number of new keys.
+    ///
+    /// Like `split_off()`, this detaches the nodes being merged into from
+    /// their parents, and puts them back once their children are merged.
+    fn merge(&mut self, other: Self) -> usize {
+        /// A node detached from its parent, and the children of the
+        /// corresponding node of `other` not yet merged into it.
+        struct Frame<K, V> {
+            node: Node<K, V>,
+            index: usize,
+            others: alloc::vec::IntoIter<Node<K, V>>,
+            added: usize,
+            /// the key length of all items of `other`, if known
+            key_len: Option<usize>,
+        }
+
+        impl<K, V> Frame<K, V> {
+            fn new(mut node: Node<K, V>, index: usize, mut other: Node<K, V>, replaced: &mut Vec<(K, V)>) -> Self {
+                let mut added = 0;
+
+                if let Some(item) = other.item.take() {
+                    match node.item.replace(item) {
+                        Some(old) => replaced.push(old),
+                        None => added += 1,
+                    }
+                }
+
+                let others = mem::take(&mut other.children).into_iter();
+
+                Frame { node, index, others, added, key_len: other.key_len }
+            }
+        }
+
+        // replaced items are only dropped once the tree is reassembled, so
+        // that a panicking destructor can't leave it torn apart
+        let mut replaced = Vec::new();
+        let mut stack: Vec<Frame<K, V>> = Vec::new();
+        let mut frame = Frame::new(mem::take(self), 0, other, &mut replaced);
+
+        loop {
+            let Some(mut other_child) = frame.others.next() else {
+                add_items(&mut frame.node.count, &mut frame.node.key_len, frame.added, frame.key_len);
+
+                let Some(mut parent) = stack.pop() else {
+                    *self = frame.node;
+                    drop(replaced);
+                    return frame.added;
+                };
+
+                parent.node.children.replace(frame.index, frame.node);
+                parent.added += frame.added;
+                frame = parent;
+                continue;
+            };
+
+            let index = match frame.node.children.search(other_child.key_fragment) {
+                Ok(index) => index,
+                Err(index) => {
+                    frame.added += other_child.count;
+                    frame.node.children.insert(index, other_child);
+                    continue;
+                }
+            };
+
+            // Split both edges where their labels diverge,
+            // so that the children correspond to the same key.
+            let child = frame.node.children.get_mut(index).expect("child just found");
+            let common = edge::common_prefix_len(&child.tail, &other_child.tail);
+
+            if common < child.tail.len() {
+                child.split_edge(common);
+            }
+            if common < other_child.tail.len() {
+                other_child.split_edge(common);
+            }
+
+            let child = frame.node.detach_child(index);
+            stack.push(frame);
+            frame = Frame::new(child, index, other_child, &mut replaced);
+        }
+    }
+
+    /// Finds the node of the smallest key in this subtree.
+    fn first(&self) -> Option<&Self> {
+        let mut node = self;
+
+        // nodes without items may exist (e.g. after removal), so the first
+        // child doesn't necessarily lead to an item
+        while node.item.is_none() {
+            node = node.children.iter().find(|child| child.count > 0)?;
+        }
+
+        Some(node)
+    }
+
+    /// Finds the node of the greatest key in this subtree.
+    fn last(&self) -> Option<&Self> {
+        let mut node = self;
+
+        while let Some(child) = node.children.iter().rfind(|child| child.count > 0) {
+            node = child;
+        }
+
+        node.item.is_some().then_some(node)
+    }
+
+    /// Finds the node of the `n`-th key in this subtree.
+    fn nth(&self, mut n: usize) -> Option<&Self> {
+        let mut node = self;
+
+        'descend: loop {
+            if node.item.is_some() {
+                if n == 0 {
+                    return Some(node);
+                }
+                n -= 1;
+            }
+
+            for child in &node.children {
+                if n < child.count {
+                    node = child;
+                    continue 'descend;
+                }
+                n -= child.count;
+            }
+
+            return None;
+        }
+    }
+
+    /// Returns the number of keys in this subtree less than `bytes`.
+    fn rank(&self, bytes: &[u8]) -> usize {
+        let mut node = self;
+        let mut bytes = bytes;
+        let mut rank = 0;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            // the key of this node is a proper prefix of the bound
+            rank += usize::from(node.item.is_some());
+
+            let (index, rest) = node.lower_bound_child(byte, rest);
+            rank += node.children[..index].iter().map(|child| child.count).sum::<usize>();
+
+            let Some(rest) = rest else {
+                break;
+            };
+
+            node = &node.children[index];
+            bytes = rest;
+        }
+
+        rank
+    }
+
+    /// Pushes the indices of the children leading to the node of the smallest
+    /// key onto `path`. Returns `false` if there are no items in this subtree.
+    fn first_path(&self, path: &mut Vec<usize>) -> bool {
+        let mut node = self;
+
+        while node.item.is_none() {
+            let Some(index) = node.children.iter().position(|child| child.count > 0) else {
+                return false;
+            };
+
+            path.push(index);
+            node = &node.children[index];
+        }
+
+        true
+    }
+
+    /// Pushes the indices of the children leading to the node of the greatest
+    /// key onto `path`. Returns `false` if there are no items in this subtree.
+    fn last_path(&self, path: &mut Vec<usize>) -> bool {
+        let mut node = self;
+
+        while let Some(index) = node.children.iter().rposition(|child| child.count > 0) {
+            path.push(index);
+            node = &node.children[index];
+        }
+
+        node.item.is_some()
+    }
+
+    /// Follows the child indices in `path`, and returns the node found there,
+    /// pushing the item counts of the nodes above it onto `counts`, so that
+    /// they can be updated after inserting below that node.
+    fn node_at<'a>(&'a mut self, path: &[usize], counts: &mut Vec<Tally<'a>>) -> &'a mut Self {
+        let mut node = self;
+
+        for &index in path {
+            let Node { count, key_len, children, .. } = node;
+            counts.push(Tally { count, key_len });
+            node = children.get_mut(index).expect("path leads to a node");
+        }
+
+        node
+    }
+
+    /// Removes the item of the node found by following the child indices in
+    /// `path`, updating the item counts along the way.
+    fn take_at(&mut self, path: &[usize]) -> Option<(K, V)> {
+        self.descendant(path).item.as_ref()?;
+
+        let mut node = self;
+        node.count -= 1;
+
+        for &index in path {
+            node = node.children.get_mut(index).expect("path leads to a node");
+            node.count -= 1;
+        }
+
+        node.item.take()
+    }
+
+    /// Like `take_at()`, but also removes or compresses the nodes left
+    /// behind, like `remove()`.
+    fn remove_at(&mut self, path: &[usize]) -> Option<(K, V)> {
+        let item = self.take_at(path)?;
+        self.tidy_at(path);
+        Some(item)
+    }
+
+    /// Removes the item with key `bytes`, updating the item counts along
+    /// the way, and removes or compresses the nodes left behind.
+    fn remove<L: Label>(&mut self, bytes: L) -> Option<(K, V)> {
+        let mut path = Vec::new();
+        let (_, found) = self.locate(bytes, &mut path);
+
+        if found {
+            self.remove_at(&path)
+        } else {
+            None
+        }
+    }
+
+    /// Removes the item with the smallest key in this subtree, along with
+    /// the nodes left empty.
+    fn pop_first(&mut self) -> Option<(K, V)> {
+        let mut path = Vec::new();
+
+        if self.first_path(&mut path) {
+            self.remove_at(&path)
+        } else {
+            None
+        }
+    }
+
+    /// Removes the item with the greatest key in this subtree, along with
+    /// the nodes left empty.
+    fn pop_last(&mut self) -> Option<(K, V)> {
+        let mut path = Vec::new();
+
+        if self.last_path(&mut path) {
+            self.remove_at(&path)
+        } else {
+            None
+        }
+    }
+
+    /// Follows the child indices in `path`, starting from this node.
+    fn descendant(&self, path: &[usize]) -> &Self {
+        path.iter().fold(self, |node, &index| &node.children[index])
+    }
+
+    fn value(&self) -> Option<&V> {
+        self.item.as_ref().map(|(_key, value)| value)
+    }
+
+    fn value_mut(&mut self) -> Option<&mut V> {
+        self.item.as_mut().map(|(_key, value)| value)
+    }
+
+    fn item(&self) -> Option<(&K, &V)> {
+        self.item.as_ref().map(|(key, value)| (key, value))
+    }
+
+    fn item_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.item.as_mut().map(|(key, value)| (&*key, value))
+    }
+
+    /// Finds the node corresponding exactly to `bytes`. Returns `None`
+    /// if `bytes` ends in the middle of an edge.
+    ///
+    /// Like all lookups, this is a loop rather than a recursion, so the
+    /// depth of the tree is only bounded by the available memory.
+    fn search<L: Label>(&self, bytes: L) -> Option<&Self> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((byte, rest)) = bytes.next_symbol() {
+            node = node.child(byte)?;
+            bytes = rest.strip_tail(node.stored_tail())?;
+        }
+
+        Some(node)
+    }
+
+    fn search_mut<L: Label>(&mut self, bytes: L) -> Option<&mut Self> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((byte, rest)) = bytes.next_symbol() {
+            node = node.child_mut(byte)?;
+            bytes = rest.strip_tail(node.stored_tail())?;
+        }
+
+        Some(node)
+    }
+
+    /// Finds the topmost node under which all keys start with `bytes`.
+    /// Unlike `search()`, `bytes` may end in the middle of an edge.
+    fn search_prefix<L: Label>(&self, bytes: L) -> Option<&Self> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((byte, rest)) = bytes.next_symbol() {
+            let child = node.child(byte)?;
+
+            match rest.strip_tail(child.stored_tail()) {
+                Some(rest) => {
+                    node = child;
+                    bytes = rest;
+                }
+                None => return rest.is_prefix_of(child.stored_tail()).then_some(child),
+            }
+        }
+
+        Some(node)
+    }
+
+    /// Like `search_prefix()`, but also pushes the full label of the path
+    /// leading to the node found onto `label`. The contents of `label` are
+    /// unspecified if no node is found.
+    fn search_prefix_labelled(&self, bytes: &[u8], label: &mut Vec<u8>) -> Option<&Self> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let child = node.child(byte)?;
+            label.push(byte);
+            label.extend_from_slice(&child.tail);
+
+            match rest.strip_prefix(child.tail.as_slice()) {
+                Some(rest) => {
+                    node = child;
+                    bytes = rest;
+                }
+                None => return child.tail.starts_with(rest).then_some(child),
+            }
+        }
+
+        Some(node)
+    }
+
+    /// Pushes the labels of the edges below `self` onto `prefix`, for as
+    /// long as the keys in this subtree share them, i.e. while descending
+    /// through nodes without an item and with a single non-empty child.
+    fn extend_common_prefix(&self, prefix: &mut Vec<u8>) {
+        let mut node = self;
+
+        while node.item.is_none() {
+            let mut children = node.children.iter().filter(|child| child.count > 0);
+
+            let (Some(child), None) = (children.next(), children.next()) else {
+                break;
+            };
+
+            prefix.push(child.key_fragment);
+            prefix.extend_from_slice(&child.tail);
+            node = child;
+        }
+    }
+
+    fn search_prefix_mut(&mut self, bytes: &[u8]) -> Option<&mut Self> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let child = node.child_mut(byte)?;
+
+            match rest.strip_prefix(child.tail.as_slice()) {
+                Some(rest) => {
+                    node = child;
+                    bytes = rest;
+                }
+                None => return child.tail.starts_with(rest).then_some(child),
+            }
+        }
+
+        Some(node)
+    }
+
+    /// Finds the deepest node with an item of which the key is a non-empty
+    /// prefix of `query`, along with the length of that key.
+    fn longest_nonempty_prefix_of(&self, query: &[u8]) -> Option<(usize, &Self)> {
+        let mut node = self;
+        let mut depth = 0;
+        let mut longest = None;
+
+        while let Some(&byte) = query.get(depth) {
+            let Some(child) = node.child(byte) else {
+                break;
+            };
+            if !query[depth + 1..].starts_with(&child.tail) {
+                break;
+            }
+
+            node = child;
+            depth += 1 + child.tail.len();
+
+            if node.item.is_some() {
+                longest = Some((depth, node));
+            }
+        }
+
+        longest
+    }
+
+    fn child(&self, byte: u8) -> Option<&Self> {
+        let index = self.children.find(byte)?;
+        Some(&self.children[index])
+    }
+
+    fn child_mut(&mut self, byte: u8) -> Option<&mut Self> {
+        let index = self.children.find(byte)?;
+        self.children.get_mut(index)
+    }
+
+    /// Follows `bytes` for as long as there are nodes along them, pushing
+    /// the indices of the children taken onto `path`. Stops at the deepest
+    /// node of which the full label is a prefix of `bytes`, and returns the
+    /// length of that label, and whether that node holds the entry of `bytes`.
+    fn locate<L: Label>(&self, bytes: L, path: &mut Vec<usize>) -> (usize, bool) {
+        let mut node = self;
+        let mut depth = 0;
+        let mut bytes = bytes;
+
+        while let Some((byte, rest)) = bytes.next_symbol() {
+            let Some((index, rest)) = node.children
+                .find(byte)
+                .and_then(|index| Some((index, rest.strip_tail(node.children[index].stored_tail())?)))
+            else {
+                return (depth, false);
+            };
+
+            node = &node.children[index];
+            depth += 1 + node.stored_tail().len();
+            path.push(index);
+            bytes = rest;
+        }
+
+        (depth, node.item.is_some())
+    }
+
+    /// Finds the node corresponding to `bytes`, creating it if necessary,
+    /// and returns its item slot. A missing suffix is stored in a single new
+    /// leaf, and an existing edge is split if `bytes` diverges from (or ends
+    /// inside) its label. The item counts of the nodes along the path are
+    /// pushed onto `counts`, like in `node_at()`.
+    fn search_or_insert<'a, L: Label>(&'a mut self, bytes: L, counts: &mut Vec<Tally<'a>>) -> &'a mut Option<(K, V)> {
+        let node = self.search_or_insert_node(bytes, counts);
+        counts.push(Tally { count: &mut node.count, key_len: &mut node.key_len });
+        &mut node.item
+    }
+
+    /// Like `search_or_insert()`, but returns the node itself, without
+    /// pushing its own item count onto `counts`.
+    fn search_or_insert_node<'a, L: Label>(&'a mut self, bytes: L, counts: &mut Vec<Tally<'a>>) -> &'a mut Self {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((byte, rest)) = bytes.next_symbol() {
+            let (index, rest) = match node.children.search(byte) {
+                Ok(index) => {
+                    let child = node.children.get_mut(index).expect("child just found");
+                    let common_len = rest.common_prefix_len(child.stored_tail());
+
+                    if common_len < child.stored_tail().len() {
+                        child.split_edge(common_len);
+                    }
+
+                    (index, rest.skip(common_len))
+                }
+                Err(index) => {
+                    let (tail, packing) = rest.to_tail();
+                    let mut leaf = Node::with_label(byte, tail);
+                    leaf.packing = packing;
+                    node.children.insert(index, leaf);
+                    (index, rest.skip(rest.symbol_count()))
+                }
+            };
+
+            let Node { count, key_len, children, .. } = node;
+            counts.push(Tally { count, key_len });
+            node = children.get_mut(index).expect("child just found or created");
+            bytes = rest;
+        }
+
+        node
+    }
+
+    /// Like `search_or_insert`, but for `bytes` not less than any key in the
+    /// subtree, which can only belong under the last child of each node
+    /// along the way (or a new child after it).
+    fn append<'a>(&'a mut self, bytes: &[u8], counts: &mut Vec<Tally<'a>>) -> &'a mut Option<(K, V)> {
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let rest = match node.children.iter_mut().next_back() {
+                Some(child) if child.key_fragment == byte => {
+                    let common_len = edge::common_prefix_len(&child.tail, rest);
+
+                    if common_len < child.tail.len() {
+                        child.split_edge(common_len);
+                    }
+
+                    &rest[common_len..]
+                }
+                last => {
+                    debug_assert!(last.map_or(true, |child| child.key_fragment < byte), "appended key not greatest");
+                    node.children.push(Node::with_label(byte, rest.to_vec()));
+                    &[][..]
+                }
+            };
+
+            let Node { count, key_len, children, .. } = node;
+            counts.push(Tally { count, key_len });
+            node = children.iter_mut().next_back().expect("child just found or created");
+            bytes = rest;
+        }
+
+        counts.push(Tally { count: &mut node.count, key_len: &mut node.key_len });
+        &mut node.item
+    }
+
+    /// Counts the keys present under both `lhs` and `rhs`, which must
+    /// correspond to the same key prefix, by walking the two trees side by
+    /// side, and only descending into subtrees present in both.
+    fn count_common<L, W>(lhs: &Self, rhs: &Node<L, W>) -> usize {
+        // nodes of `lhs` along with the positions in `rhs` at the same keys
+        let mut stack = Vec::from([(lhs, (rhs, 0))]);
+        let mut count = 0;
+
+        while let Some((lhs, rhs)) = stack.pop() {
+            let (rhs_node, offset) = rhs;
+
+            // there are no items in the middle of an edge
+            if lhs.item.is_some() && offset == rhs_node.tail.len() && rhs_node.item.is_some() {
+                count += 1;
+            }
+
+            stack.extend(lhs.children.iter().filter_map(|child| Some((child, child.follow_edge(rhs)?))));
+        }
+
+        count
+    }
+
+    /// Advances a position in another tree by the label of the edge leading
+    /// to this node. A position is a node and the number of bytes of its tail
+    /// already consumed, so that it can point into the middle of an edge.
+    /// Returns `None` if the other tree has no keys continuing with the label.
+    fn follow_edge<'a, L, W>(&self, position: (&'a Node<L, W>, usize)) -> Option<(&'a Node<L, W>, usize)> {
+        core::iter::once(&self.key_fragment)
+            .chain(&self.tail)
+            .try_fold(position, |(node, offset), &byte| match node.tail.get(offset) {
+                Some(&next) => (next == byte).then_some((node, offset + 1)),
+                None => Some((node.child(byte)?, 0)),
+            })
+    }
+
+    /// Removes the keys under this node which are not present under `rhs`,
+    /// which must correspond to the same key prefix. Returns the number of
+    /// keys removed. Descendants are compressed, but this node itself isn't.
+    ///
+    /// Like `retain()`, this walks along a detached path, with a parallel
+    /// stack of the positions in `rhs` (in the sense of `follow_edge()`)
+    /// corresponding to the nodes on the path. Children without a
+    /// counterpart in `rhs` are removed as a whole, without descending.
+    fn retain_common<L, W>(&mut self, rhs: &Node<L, W>) -> usize {
+        let before = self.count;
+        let mut path = DetachedPath::new(self);
+        let mut positions = Vec::from([(rhs, 0)]);
+        let mut next = 0;
+
+        path.node.retain_item_at(rhs, 0);
+
+        loop {
+            let position = positions[positions.len() - 1];
+
+            if next < path.node.children.len() {
+                match path.node.children[next].follow_edge(position) {
+                    Some((rhs, offset)) => {
+                        path.descend(next);
+                        path.node.retain_item_at(rhs, offset);
+                        positions.push((rhs, offset));
+                        next = 0;
+                    }
+                    None => {
+                        path.node.children.remove(next);
+                    }
+                }
+            } else if let Some(index) = path.ascend() {
+                positions.pop();
+                next = index;
+            } else {
+                break;
+            }
+        }
+
+        drop(path);
+        before - self.count
+    }
+
+    /// Removes the item of this node, unless the position `offset` bytes into
+    /// the tail of `rhs` has an item as well, which is only possible if it is
+    /// at the end of the tail.
+    fn retain_item_at<L, W>(&mut self, rhs: &Node<L, W>, offset: usize) {
+        if offset < rhs.tail.len() || rhs.item.is_none() {
+            self.item = None;
+        }
+    }
+
+    /// Calls `f` in lexicographic order with every distinct `len`-byte
+    /// continuation of the key of `self` that is a prefix of at least one
+    /// key, along with the topmost node under which all keys start with
+    /// that continuation.
+    ///
+    /// The nodes are visited in pre-order from an explicit stack, along with
+    /// the number of bytes of the continuation still missing before the edge
+    /// leading to them, and the length of the part of it already known.
+    fn for_each_prefix<F>(&self, len: usize, mut f: F)
+    where
+        F: FnMut(&[u8], &Self),
+    {
+        let mut prefix = Vec::with_capacity(len);
+
+        if len == 0 {
+            if self.is_transitively_useful() {
+                f(&prefix, self);
+            }
+            return;
+        }
+
+        let mut stack: Vec<_> = self.children.iter().rev().map(|child| (child, len, 0)).collect();
+
+        while let Some((node, len, prefix_len)) = stack.pop() {
+            prefix.truncate(prefix_len);
+            prefix.push(node.key_fragment);
+
+            if node.tail.len() + 1 < len {
+                let len = len - 1 - node.tail.len();
+
+                prefix.extend_from_slice(&node.tail);
+                stack.extend(node.children.iter().rev().map(|child| (child, len, prefix.len())));
+            } else {
+                // the continuation ends inside (or at the end of) the edge
+                prefix.extend_from_slice(&node.tail[..len - 1]);
+
+                if node.is_transitively_useful() {
+                    f(&prefix, node);
+                }
+            }
+        }
+    }
+
+    /// Appends to `out` the smallest `len`-byte continuation of the key of
+    /// `self` that is not in the tree, and returns `true`. If there is no
+    /// such continuation, returns `false` and leaves `out` unchanged.
+    fn find_absent(&self, len: usize, out: &mut Vec<u8>) -> bool {
+        // the subtree holds as many entries as there are continuations of
+        // length `len`, and all of them are of that length, so it is full
+        let capacity = u32::try_from(len).ok().and_then(|len| 256_usize.checked_pow(len));
+
+        if capacity == Some(self.count) && self.key_len == Some(out.len() + len) {
+            return false;
+        }
+
+        if len == 0 {
+            return self.item.is_none();
+        }
+
+        let start = out.len();
+        let mut children = self.children.iter().peekable();
+
+        for byte in 0..=u8::MAX {
+            let Some(child) = children.next_if(|child| child.key_fragment == byte) else {
+                out.push(byte);
+                out.resize(start + len, 0);
+                return true;
+            };
+            let tail = &child.tail;
+
+            // If the edge is longer than the rest of the key, or the
+            // key continues with all zeros but the edge doesn't, then
+            // no key of length `len` starts with `byte` followed by zeros.
+            if tail.len() >= len || tail.iter().any(|&b| b != 0) {
+                out.push(byte);
+                out.resize(start + len, 0);
+                return true;
+            }
+
+            out.push(byte);
+            out.extend_from_slice(tail);
+
+            if child.find_absent(len - 1 - tail.len(), out) {
+                return true;
+            }
+
+            out.truncate(start);
+
+            // The child is full, but only strings continuing with its edge
+            // label are under it; the next larger label is absent.
+            if !tail.is_empty() {
+                out.push(byte);
+                out.resize(start + tail.len(), 0);
+                out.push(1);
+                out.resize(start + len, 0);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Appends the branch points under `self` to `points`, in lexicographic
+    /// order of their prefixes, which are relative to the key of `self`.
+    ///
+    /// The nodes are visited in pre-order from an explicit stack, along with
+    /// the length of the key of their parents, at which the prefix is cut
+    /// before appending the label of the edge leading to each node.
+    fn collect_branch_points(&self, points: &mut Vec<BranchPoint>) {
+        let mut prefix = Vec::new();
+        let mut stack = Vec::new();
+        let mut node = self;
+
+        loop {
+            let fan_out = node.children.iter().filter(|child| child.count > 0).count();
+
+            if fan_out >= 2 {
+                points.push(BranchPoint { prefix: prefix.clone(), fan_out, len: node.count });
+            }
+
+            stack.extend(node.children.iter().rev().map(|child| (child, prefix.len())));
+
+            let Some((child, parent_len)) = stack.pop() else {
+                break;
+            };
+
+            prefix.truncate(parent_len);
+            prefix.push(child.key_fragment);
+            prefix.extend_from_slice(&child.tail);
+            node = child;
+        }
+    }
+
+    fn into_iter(self) -> SubtreeIntoIter<K, V> {
+        DepthFirst::new(self)
+    }
+
+    fn iter(&self) -> SubtreeIter<'_, K, V> {
+        DepthFirst::new(self)
+    }
+
+    fn iter_mut(&mut self) -> SubtreeIterMut<'_, K, V> {
+        DepthFirst::new(self)
+    }
+
+    /// Finds the children of this node that contain keys greater than or
+    /// equal to a bound consisting of `byte` followed by `rest`. Returns the
+    /// index of the first such child, along with the rest of the bound below
+    /// that child, if the bound continues into it (otherwise, all keys under
+    /// the child are greater than the bound).
+    fn lower_bound_child<'b>(&self, byte: u8, rest: &'b [u8]) -> (usize, Option<&'b [u8]>) {
+        let index = self.children.partition_point(|child| child.key_fragment < byte);
+
+        let Some(child) = self.children.get(index).filter(|child| child.key_fragment == byte) else {
+            return (index, None);
+        };
+
+        let len = child.tail.len().min(rest.len());
+
+        match child.tail[..len].cmp(&rest[..len]) {
+            Ordering::Less => (index + 1, None),
+            Ordering::Greater => (index, None),
+            Ordering::Equal => (index, rest.get(child.tail.len()..)),
+        }
+    }
+
+    /// Iterates over the items with keys greater than (or, if `inclusive`,
+    /// equal to) `bytes`, without visiting the subtrees below the bound.
+    fn into_iter_from(self, bytes: &[u8], inclusive: bool) -> SubtreeIntoIter<K, V> {
+        let mut frames = VecDeque::new();
+        let mut node = self;
+        let mut bytes = bytes;
+
+        // the frames along the bound, deepest (and thus first) in the front
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let (index, rest) = node.lower_bound_child(byte, rest);
+            let mut children = mem::take(&mut node.children).into_vec();
+            children.drain(..index);
+
+            let mut children = children.into_iter();
+            let next = rest.and_then(|rest| Some((children.next()?, rest)));
+            frames.push_front(Frame { item: None, children });
+
+            let Some((child, rest)) = next else {
+                return DepthFirst { frames };
+            };
+
+            node = child;
+            bytes = rest;
+        }
+
+        let mut frame = node.into_frame();
+        if !inclusive {
+            frame.item = None;
+        }
+        frames.push_front(frame);
+
+        DepthFirst { frames }
+    }
+
+    fn iter_from(&self, bytes: &[u8], inclusive: bool) -> SubtreeIter<'_, K, V> {
+        let mut frames = VecDeque::new();
+        let mut node = self;
+        let mut bytes = bytes;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let (index, rest) = node.lower_bound_child(byte, rest);
+            let mut children = node.children[index..].iter();
+            let next = rest.and_then(|rest| Some((children.next()?, rest)));
+            frames.push_front(Frame { item: None, children });
+
+            let Some((child, rest)) = next else {
+                return DepthFirst { frames };
+            };
+
+            node = child;
+            bytes = rest;
+        }
+
+        let mut frame = node.into_frame();
+        if !inclusive {
+            frame.item = None;
+        }
+        frames.push_front(frame);
+
+        DepthFirst { frames }
+    }
+}
+
+/// The default impl returns the same value as `Node::root()`,
+/// and its only purpose is to make `mem::take()` work.
+impl<K, V> Default for Node<K, V> {
+    fn default() -> Self {
+        Node::root()
+    }
+}
+
+impl<K, V> Edge for Node<K, V> {
+    type Symbol = u8;
+    type Child = Self;
+
+    fn key_fragment(&self) -> u8 {
+        self.key_fragment
+    }
+
+    fn tail(&self) -> &[u8] {
+        &self.tail
+    }
+
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
+
+    fn has_item(&self) -> bool {
+        self.item.is_some()
+    }
+
+    fn child_index(&self, byte: u8) -> Result<usize, usize> {
+        self.children.search(byte)
+    }
+
+    fn find_child(&self, byte: u8) -> Option<usize> {
+        self.children.find(byte)
+    }
+}
+
+impl<K, V> EdgeMut for Node<K, V> {
+    fn tail_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.tail
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Self {
+        self.children.get_mut(index).expect("child index out of bounds")
+    }
+
+    fn insert_child(&mut self, index: usize, child: Self) {
+        self.children.insert(index, child);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children.remove(index);
+    }
+
+    /// The label is stored one symbol per byte; `split_edge()` sets the
+    /// packing of the child if the labels are packed.
+    fn push_down(&mut self, key_fragment: u8, tail: Vec<u8>) {
+        let lower = Node {
+            item: self.item.take(),
+            count: self.count,
+            key_len: self.key_len,
+            key_fragment,
+            tail,
+            packing: Packing::Bytes,
+            children: mem::take(&mut self.children),
+        };
+
+        self.children.push(lower);
+    }
+
+    /// The item count doesn't change, since this node has no item.
+    fn pull_up(&mut self) -> (u8, Vec<u8>) {
+        let mut child = self.children.pop().expect("no child to pull up");
+
+        self.item = child.item.take();
+        self.children = mem::take(&mut child.children);
+
+        (child.key_fragment, mem::take(&mut child.tail))
+    }
+
+    fn split_edge(&mut self, at: usize) {
+        let (key_fragment, tail, packing) = self.split_tail(at);
+        self.push_down(key_fragment, tail);
+        self.children.get_mut(0).expect("child just pushed down").packing = packing;
+    }
+
+    fn merge_only_child(&mut self) {
+        let packing = self.children[0].packing;
+        let (key_fragment, tail) = self.pull_up();
+        self.append_tail(key_fragment, Tail { bytes: &tail, packing });
+    }
+}
+
+/// The descendants are detached and dropped one by one from an explicit
+/// stack, because the default recursive drop glue could overflow the call
+/// stack for trees with very long keys.
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        if self.children.is_empty() {
+            return;
+        }
+
+        let mut stack = mem::take(&mut self.children).into_vec();
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut mem::take(&mut node.children).into_vec());
+        }
+    }
+}
+
+impl<V, const N: usize> Node<[u8; N], V> {
+    /// Like `search()`, but only for keys of the same width as those of the
+    /// tree. Edges are followed by their first byte only, and the node found
+    /// is only returned if its key matches the whole of `key`.
+    fn search_fixed(&self, key: &[u8; N]) -> Option<&Self> {
+        let mut node = self;
+        let mut depth = 0;
+
+        while depth < N {
+            node = node.child(key[depth])?;
+            depth += 1 + node.tail.len();
+        }
+
+        node.item.as_ref().is_some_and(|(stored, _)| stored == key).then_some(node)
+    }
+
+    fn search_fixed_mut(&mut self, key: &[u8; N]) -> Option<&mut Self> {
+        let mut node = self;
+        let mut depth = 0;
+
+        while depth < N {
+            node = node.child_mut(key[depth])?;
+            depth += 1 + node.tail.len();
+        }
+
+        node.item.as_ref().is_some_and(|(stored, _)| stored == key).then_some(node)
+    }
+}
+
+/// A node and its ancestors up to the root of a subtree, each of them
+/// detached from its parent, so that the nodes of the subtree can be visited
+/// and modified one at a time, without recursion.
+///
+/// Ascending puts the current node back into its parent, recomputes its
+/// item count, and removes or compresses it, like `tidy_child()`. Dropping
+/// the path ascends all the way to the root, so even if a traversal panics
+/// halfway through, no node is lost, and the item counts stay correct.
+struct DetachedPath<'a, K, V> {
+    root: &'a mut Node<K, V>,
+    /// The detached ancestors of `node`, along with the index of the child
+    /// leading towards it.
+    ancestors: Vec<(Node<K, V>, usize)>,
+    node: Node<K, V>,
+}
+
+impl<'a, K, V> DetachedPath<'a, K, V> {
+    fn new(root: &'a mut Node<K, V>) -> Self {
+        let node = mem::take(root);
+        DetachedPath { root, ancestors: Vec::new(), node }
+    }
+
+    /// Detaches the child at `index`, which becomes the current node.
+    fn descend(&mut self, index: usize) {
+        let child = self.node.detach_child(index);
+        let parent = mem::replace(&mut self.node, child);
+        self.ancestors.push((parent, index));
+    }
+
+    /// Puts the current node back into its parent, which becomes the current
+    /// node. Returns the index of the next sibling of the child, which is
+    /// that of the child itself if it was removed, or `None` at the root.
+    fn ascend(&mut self) -> Option<usize> {
+        let (parent, index) = self.ancestors.pop()?;
+        let mut child = mem::replace(&mut self.node, parent);
+
+        child.recount();
+        self.node.children.replace(index, child);
+
+        if self.node.tidy_child(index) {
+            Some(index)
+        } else {
+            Some(index + 1)
+        }
+    }
+}
+
+impl<K, V> Drop for DetachedPath<'_, K, V> {
+    fn drop(&mut self) {
+        while self.ascend().is_some() {}
+
+        self.node.recount();
+        *self.root = mem::take(&mut self.node);
+    }
+}
+
+/// The children of a node, sorted by their `key_fragment`, along with an
+/// index for finding them by their first byte.
+///
+/// The index comes in four size classes, chosen by the number of children,
+/// so that nodes with few children stay small, while nodes with many
+/// children find a child, or its absence, without searching. The children
+/// themselves always stay in a sorted array, because ordered and positional
+/// access (iteration, range bounds, cursors) relies on that, so the index
+/// maps bytes to positions in that array:
+///
+/// * `Scan`, up to 4 children: no index, the children are scanned;
+/// * `Bytes`, up to 16: a copy of the first bytes of the children in a
+///   compact sorted array, which is searched without touching the children;
+/// * `Positions`, up to 48: a table of the positions of the children,
+///   indexed by their first bytes;
+/// * `Bitmap`, up to 256: the set of the first bytes of the children, the
+///   position of a child being the number of bytes before its own.
+///
+/// The index is updated in place on insertion and removal, and only rebuilt
+/// when the number of children crosses into another size class.
+///
+/// The array of the children grows one slot at a time while there are at
+/// most 16 of them, since moving that few of them costs little, and most
+/// nodes are that small. Larger arrays grow by doubling, as usual.
+struct Children<K, V> {
+    nodes: Vec<Node<K, V>>,
+    index: ChildIndex,
+}
+
+impl<K, V> Children<K, V> {
+    const fn new() -> Self {
+        Children { nodes: Vec::new(), index: ChildIndex::Scan }
+    }
+
+    /// Returns the position of the child starting with `byte`, if any.
+    fn find(&self, byte: u8) -> Option<usize> {
+        match &self.index {
+            ChildIndex::Scan => self.nodes.iter().position(|node| node.key_fragment == byte),
+            ChildIndex::Bytes(bytes) => bytes[..self.nodes.len()].binary_search(&byte).ok(),
+            ChildIndex::Positions(positions) => {
+                usize::from(positions[usize::from(byte)]).checked_sub(1)
+            }
+            ChildIndex::Bitmap(bitmap) => {
+                let (word, bit) = (usize::from(byte) / 64, byte % 64);
+                (bitmap[word] >> bit & 1 != 0).then(|| ChildIndex::rank(bitmap, byte))
+            }
+        }
+    }
+
+    /// Finds the child starting with `byte` for inserting one. Returns its
+    /// position, or the position where such a child would have to be
+    /// inserted.
+    fn search(&self, byte: u8) -> Result<usize, usize> {
+        self.find(byte).ok_or_else(|| match &self.index {
+            ChildIndex::Scan | ChildIndex::Positions(_) => {
+                self.nodes.partition_point(|node| node.key_fragment < byte)
+            }
+            ChildIndex::Bytes(bytes) => bytes[..self.nodes.len()].partition_point(|&b| b < byte),
+            ChildIndex::Bitmap(bitmap) => ChildIndex::rank(bitmap, byte),
+        })
+    }
+
+    /// Returns a mutable reference to the child at `index`.
+    fn get_mut(&mut self, index: usize) -> Option<&mut Node<K, V>> {
+        self.nodes.get_mut(index)
+    }
+
+    /// Mutable access to the children. The first bytes of the children must
+    /// be left alone, as the index depends on them.
+    fn iter_mut(&mut self) -> core::slice::IterMut<'_, Node<K, V>> {
+        self.nodes.iter_mut()
+    }
+
+    /// Replaces the child at `index` with `node`, which must start with the
+    /// same byte, and returns the old child.
+    fn replace(&mut self, index: usize, node: Node<K, V>) -> Node<K, V> {
+        assert_eq!(
+            self.nodes[index].key_fragment, node.key_fragment,
+            "replacement child starts with another byte",
+        );
+        mem::replace(&mut self.nodes[index], node)
+    }
+
+    /// Rebuilds the index if the number of children calls for another size
+    /// class. Returns `true` if it did.
+    fn resize(&mut self) -> bool {
+        if self.index.fits(self.nodes.len()) {
+            false
+        } else {
+            self.index = ChildIndex::new(&self.nodes);
+            true
+        }
+    }
+
+    fn insert(&mut self, index: usize, node: Node<K, V>) {
+        let byte = node.key_fragment;
+
+        if self.nodes.len() < 16 {
+            self.nodes.reserve_exact(1);
+        }
+
+        self.nodes.insert(index, node);
+
+        if self.resize() {
+            return;
+        }
+
+        match &mut self.index {
+            ChildIndex::Scan => {}
+            ChildIndex::Bytes(bytes) => {
+                bytes.copy_within(index..self.nodes.len() - 1, index + 1);
+                bytes[index] = byte;
+            }
+            ChildIndex::Positions(positions) => {
+                for node in &self.nodes[index + 1..] {
+                    positions[usize::from(node.key_fragment)] += 1;
+                }
+
+                // there are at most 48 children, so the position fits
+                positions[usize::from(byte)] = (index + 1) as u8;
+            }
+            ChildIndex::Bitmap(bitmap) => {
+                bitmap[usize::from(byte) / 64] |= 1 << (byte % 64);
+            }
+        }
+    }
+
+    fn push(&mut self, node: Node<K, V>) {
+        self.insert(self.nodes.len(), node);
+    }
+
+    fn remove(&mut self, index: usize) -> Node<K, V> {
+        let node = self.nodes.remove(index);
+        let byte = node.key_fragment;
+
+        if self.resize() {
+            return node;
+        }
+
+        match &mut self.index {
+            ChildIndex::Scan => {}
+            ChildIndex::Bytes(bytes) => {
+                bytes.copy_within(index + 1..=self.nodes.len(), index);
+            }
+            ChildIndex::Positions(positions) => {
+                positions[usize::from(byte)] = 0;
+
+                for node in &self.nodes[index..] {
+                    positions[usize::from(node.key_fragment)] -= 1;
+                }
+            }
+            ChildIndex::Bitmap(bitmap) => {
+                bitmap[usize::from(byte) / 64] &= !(1 << (byte % 64));
+            }
+        }
+
+        node
+    }
+
+    fn pop(&mut self) -> Option<Node<K, V>> {
+        let len = self.nodes.len();
+        (len > 0).then(|| self.remove(len - 1))
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.index = ChildIndex::Scan;
+    }
+
+    /// Removes all children, keeping the allocation of the array.
+    fn drain(&mut self) -> alloc::vec::Drain<'_, Node<K, V>> {
+        self.index = ChildIndex::Scan;
+        self.nodes.drain(..)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        let other = Children::from_vec(self.nodes.split_off(at));
+        self.index = ChildIndex::new(&self.nodes);
+        other
+    }
+
+    fn from_vec(nodes: Vec<Node<K, V>>) -> Self {
+        let index = ChildIndex::new(&nodes);
+        Children { nodes, index }
+    }
+
+    fn into_vec(self) -> Vec<Node<K, V>> {
+        self.nodes
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+}
+
+/// The size classes of the index of [`Children`].
+#[derive(Clone, Debug)]
+enum ChildIndex {
+    Scan,
+    /// The first bytes of the children, in order. Only as many of them are
+    /// meaningful as there are children.
+    Bytes(Box<[u8; 16]>),
+    /// For every byte, 1 more than the position of the child starting with
+    /// it, or 0 if there is no such child.
+    Positions(Box<[u8; 256]>),
+    /// The bytes with which the children start, as a bitmap, with byte `b`
+    /// at bit `b % 64` of word `b / 64`.
+    Bitmap(Box<[u64; 4]>),
+}
+
+impl ChildIndex {
+    /// Builds the index of the size class for the number of `nodes`.
+    fn new<K, V>(nodes: &[Node<K, V>]) -> Self {
+        let bytes = nodes.iter().map(|node| node.key_fragment);
+
+        match nodes.len() {
+            0..=4 => ChildIndex::Scan,
+            5..=16 => {
+                let mut array = Box::new([0; 16]);
+                array.iter_mut().zip(bytes).for_each(|(slot, byte)| *slot = byte);
+                ChildIndex::Bytes(array)
+            }
+            17..=48 => {
+                let mut positions = Box::new([0; 256]);
+
+                for (position, byte) in (1..).zip(bytes) {
+                    positions[usize::from(byte)] = position;
+                }
+
+                ChildIndex::Positions(positions)
+            }
+            _ => {
+                let mut bitmap = Box::new([0; 4]);
+
+                for byte in bytes {
+                    bitmap[usize::from(byte) / 64] |= 1 << (byte % 64);
+                }
+
+                ChildIndex::Bitmap(bitmap)
+            }
+        }
+    }
+
+    /// Returns the number of bytes in `bitmap` before `byte`.
+    fn rank(bitmap: &[u64; 4], byte: u8) -> usize {
+        let (word, bit) = (usize::from(byte) / 64, byte % 64);
+        let before = bitmap[..word].iter().map(|word| word.count_ones()).sum::<u32>()
+            + (bitmap[word] & ((1 << bit) - 1)).count_ones();
+        before as usize
+    }
+
+    /// Returns `true` if this is the size class for `len` children.
+    fn fits(&self, len: usize) -> bool {
+        match self {
+            ChildIndex::Scan => len <= 4,
+            ChildIndex::Bytes(_) => (5..=16).contains(&len),
+            ChildIndex::Positions(_) => (17..=48).contains(&len),
+            ChildIndex::Bitmap(_) => len > 48,
+        }
+    }
+}
+
+impl<K, V> core::ops::Deref for Children<K, V> {
+    type Target = [Node<K, V>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.nodes
+    }
+}
+
+impl<K, V> IntoIterator for Children<K, V> {
+    type Item = Node<K, V>;
+    type IntoIter = alloc::vec::IntoIter<Node<K, V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.into_iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a Children<K, V> {
+    type Item = &'a Node<K, V>;
+    type IntoIter = core::slice::Iter<'a, Node<K, V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.iter()
+    }
+}
+
+impl<K, V> Default for Children<K, V> {
+    fn default() -> Self {
+        Children::new()
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for Children<K, V> {
+    fn clone(&self) -> Self {
+        Children {
+            nodes: self.nodes.clone(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+/// The index is derived from the children, so it is ignored by comparison,
+/// hashing and formatting.
+impl<K: Debug, V: Debug> Debug for Children<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.nodes.fmt(f)
+    }
+}
+
+/// An entry, representing a vacant or occupied node in the tree,
+/// corresponding to a specific key.
+///
+/// The API is almost exactly the same as that of [`std::collections::btree_map::Entry`].
+#[derive(Debug)]
+pub enum Entry<'a, K, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V)
+    {
+        if let Entry::Occupied(mut entry) = self {
+            f(entry.get_mut());
+            Entry::Occupied(entry)
+        } else {
+            self
+        }
+    }
+
+    pub fn remove_entry(self) -> Option<(K, V)> {
+        if let Entry::Occupied(entry) = self {
+            Some(entry.remove_entry())
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(self) -> Option<V> {
+        if let Entry::Occupied(entry) = self {
+            Some(entry.remove())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V>
+where
+    K: AsRef<[u8]>
+{
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&K) -> V
+    {
+        match self {
+            Entry::Vacant(entry) => {
+                let value = default(&entry.key);
+                entry.insert(value)
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V
+    {
+        self.or_insert_with_key(|_| default())
+    }
+
+    // this trips Clippy up for some reason? Clearly I can't just call myself unconditionally...
+    #[allow(clippy::unwrap_or_default)]
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with_key(|_| value)
+    }
+}
+
+/// An entry that does not yet correspond to a value.
+///
+/// The tree is left untouched until a value is inserted, so dropping a
+/// vacant entry leaves no trace.
+pub struct VacantEntry<'a, K, V> {
+    key: K,
+    root: &'a mut Node<K, V>,
+    /// child indices leading to the deepest existing node along the key
+    path: Vec<usize>,
+    /// the length of the key prefix corresponding to that node
+    depth: usize,
+    len: &'a mut usize,
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    /// Creates the nodes for the rest of the key, and inserts the entry.
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: AsRef<[u8]>
+    {
+        let mut counts = Vec::with_capacity(self.path.len() + 2);
+        let node = self.root.node_at(&self.path, &mut counts);
+        let key_len = self.key.as_ref().len();
+        let slot = node.search_or_insert(&self.key.as_ref()[self.depth..], &mut counts);
+        let (_key, value) = slot.insert((self.key, value));
+        counts.into_iter().for_each(|tally| tally.add(1, Some(key_len)));
+        *self.len += 1;
+        value
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+/// An entry for a borrowed key, which is only converted into an owned key
+/// if a value is inserted. Created by [`PrefixTreeMap::entry_ref`].
+///
+/// The API is the same as that of [`Entry`], except that keys passed to
+/// the callbacks are the borrowed ones.
+#[derive(Debug)]
+pub enum EntryRef<'a, 'b, K, Q: ?Sized, V> {
+    Vacant(VacantEntryRef<'a, 'b, K, Q, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+impl<'a, 'b, K, Q, V> EntryRef<'a, 'b, K, Q, V>
+where
+    K: AsRef<[u8]> + From<&'b Q>,
+    Q: ?Sized + AsRef<[u8]>,
+{
+    /// Returns the bytes of the key.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            EntryRef::Vacant(entry) => entry.key().as_ref(),
+            EntryRef::Occupied(entry) => entry.key().as_ref(),
+        }
+    }
+
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&Q) -> V
+    {
+        match self {
+            EntryRef::Vacant(entry) => {
+                let value = default(entry.key);
+                entry.insert(value)
+            }
+            EntryRef::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V
+    {
+        self.or_insert_with_key(|_| default())
+    }
+
+    #[allow(clippy::unwrap_or_default)]
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with_key(|_| value)
+    }
+
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V)
+    {
+        if let EntryRef::Occupied(mut entry) = self {
+            f(entry.get_mut());
+            EntryRef::Occupied(entry)
+        } else {
+            self
+        }
+    }
+
+    pub fn remove_entry(self) -> Option<(K, V)> {
+        if let EntryRef::Occupied(entry) = self {
+            Some(entry.remove_entry())
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(self) -> Option<V> {
+        if let EntryRef::Occupied(entry) = self {
+            Some(entry.remove())
+        } else {
+            None
+        }
+    }
+}
+
+/// A vacant entry for a borrowed key.
+pub struct VacantEntryRef<'a, 'b, K, Q: ?Sized, V> {
+    key: &'b Q,
+    root: &'a mut Node<K, V>,
+    /// child indices leading to the deepest existing node along the key
+    path: Vec<usize>,
+    /// the length of the key prefix corresponding to that node
+    depth: usize,
+    len: &'a mut usize,
+}
+
+impl<'a, 'b, K, Q, V> VacantEntryRef<'a, 'b, K, Q, V>
+where
+    Q: ?Sized + AsRef<[u8]>
+{
+    /// Converts the key into an owned one, creates the nodes for the rest
+    /// of the key, and inserts the entry.
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: From<&'b Q>
+    {
+        let key = K::from(self.key);
+        let (_key, value) = self.insert_entry(key, value);
+        value
+    }
+
+    pub fn key(&self) -> &'b Q {
+        self.key
+    }
+
+    /// Inserts the entry with an already-created key, which must consist
+    /// of the bytes of the borrowed key, and returns both of its halves.
+    pub(crate) fn insert_entry(self, key: K, value: V) -> (&'a K, &'a mut V) {
+        let mut counts = Vec::with_capacity(self.path.len() + 2);
+        let node = self.root.node_at(&self.path, &mut counts);
+        let key_len = self.key.as_ref().len();
+        let slot = node.search_or_insert(&self.key.as_ref()[self.depth..], &mut counts);
+        let (key, value) = slot.insert((key, value));
+        counts.into_iter().for_each(|tally| tally.add(1, Some(key_len)));
+        *self.len += 1;
+        (key, value)
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for OccupiedEntry<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<K: Debug, V> Debug for VacantEntry<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.key).finish()
+    }
+}
+
+impl<K, Q: ?Sized + Debug, V> Debug for VacantEntryRef<'_, '_, K, Q, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntryRef").field(&self.key).finish()
+    }
+}
+
+/// An entry that already contains a value.
+pub struct OccupiedEntry<'a, K, V> {
+    root: &'a mut Node<K, V>,
+    /// child indices leading to the node of the entry
+    path: Vec<usize>,
+    len: &'a mut usize,
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        self.root.descendant(&self.path).item().expect("item in occupied entry").0
+    }
+
+    pub fn get(&self) -> &V {
+        self.root.descendant(&self.path).value().expect("item in occupied entry")
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.root.descendant_mut(&self.path).value_mut().expect("item in occupied entry")
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.root.descendant_mut(&self.path).value_mut().expect("item in occupied entry")
+    }
+
+    pub(crate) fn into_key(self) -> &'a K {
+        self.root.descendant(&self.path).item().expect("item in occupied entry").0
+    }
+
+    /// Replaces the inner value with `value` and returns the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, along with the nodes it leaves empty, and
+    /// compresses the edges left without branching.
+    pub fn remove_entry(self) -> (K, V) {
+        *self.len -= 1;
+        self.root.remove_at(&self.path).expect("item in occupied entry")
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+/// A lookup accelerator that remembers the path of the previous lookup.
+///
+/// Each lookup resumes from the deepest node on the remembered path that is
+/// still a prefix of the new key, instead of starting over from the root.
+/// This speeds up runs of lookups with long common prefixes, e.g. scanning
+/// sorted or sequential keys. Created by [`PrefixTreeMap::finger`].
+#[derive(Debug)]
+pub struct Finger<'a, K, V> {
+    /// Nodes along the path of the last lookup, along with the length
+    /// of the key prefix they correspond to. Never empty: the first
+    /// element is always the root.
+    path: Vec<(&'a Node<K, V>, usize)>,
+    last_key: Vec<u8>,
+}
+
+impl<K, V> Clone for Finger<'_, K, V> {
+    fn clone(&self) -> Self {
+        Finger {
+            path: self.path.clone(),
+            last_key: self.last_key.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Finger<'a, K, V> {
+    fn new(root: &'a Node<K, V>) -> Self {
+        Finger {
+            path: Vec::from([(root, 0)]),
+            last_key: Vec::new(),
+        }
+    }
+
+    /// Return a reference to the original key and value, if found.
+    pub fn get_entry<Q>(&mut self, key: &Q) -> Option<(&'a K, &'a V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.search(key.as_ref()).and_then(Node::item)
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&'a V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.search(key.as_ref()).and_then(Node::value)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.search(key.as_ref()).is_some_and(|node| node.item.is_some())
+    }
+
+    fn search(&mut self, key: &[u8]) -> Option<&'a Node<K, V>> {
+        let common_len = edge::common_prefix_len(&self.last_key, key);
+
+        // the root is at depth 0, so it is never popped
+        while self.path.last().is_some_and(|&(_, depth)| depth > common_len) {
+            self.path.pop();
+        }
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+
+        let &(mut node, mut depth) = self.path.last().expect("root in finger path");
+
+        while let Some((&byte, rest)) = key[depth..].split_first() {
+            node = node.child(byte)?;
+            rest.strip_prefix(node.tail.as_slice())?;
+            depth += 1 + node.tail.len();
+            self.path.push((node, depth));
+        }
+
+        Some(node)
+    }
+}
+
+/// A position in the tree, which can be moved down and up one byte at a
+/// time, e.g. for matching the keys incrementally as they are typed.
+///
+/// The position always corresponds to a prefix of at least one key, or to
+/// the empty prefix. Created by [`PrefixTreeMap::cursor`].
+#[derive(Debug)]
+pub struct Cursor<'a, K, V> {
+    /// The nodes along the path to the position, starting at the root.
+    /// The position is on the edge leading to the last node, or at the
+    /// last node itself if its whole label has been consumed.
+    path: Vec<&'a Node<K, V>>,
+    /// The number of bytes of the label of the last node consumed so far.
+    offset: usize,
+    key: Vec<u8>,
+}
+
+impl<K, V> Clone for Cursor<'_, K, V> {
+    fn clone(&self) -> Self {
+        Cursor {
+            path: self.path.clone(),
+            offset: self.offset,
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Cursor<'a, K, V> {
+    fn new(root: &'a Node<K, V>) -> Self {
+        Cursor {
+            path: Vec::from([root]),
+            offset: 0,
+            key: Vec::new(),
+        }
+    }
+
+    /// Returns the bytes leading from the root to the current position.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Moves down along `byte`. Returns `false` and stays in place if no
+    /// key continues with `byte` at the current position.
+    pub fn descend(&mut self, byte: u8) -> bool {
+        let node = self.node();
+
+        if self.is_at_node() {
+            let Some(child) = node.child(byte).filter(|child| child.count > 0) else {
+                return false;
+            };
+
+            self.path.push(child);
+            self.offset = 1;
+        } else {
+            if node.tail[self.offset - 1] != byte {
+                return false;
+            }
+
+            self.offset += 1;
+        }
+
+        self.key.push(byte);
+
+        true
+    }
+
+    /// Moves up by one byte. Returns `false` if already at the root.
+    pub fn ascend(&mut self) -> bool {
+        if self.key.pop().is_none() {
+            return false;
+        }
+
+        self.offset -= 1;
+
+        if self.offset == 0 {
+            self.path.pop();
+            self.offset = self.label_len();
+        }
+
+        true
+    }
+
+    /// Moves back to the root.
+    pub fn reset(&mut self) {
+        self.path.truncate(1);
+        self.offset = 0;
+        self.key.clear();
+    }
+
+    /// Returns the bytes along which the cursor can descend, in ascending order.
+    pub fn next_bytes(&self) -> impl Iterator<Item = u8> + 'a {
+        let node = self.node();
+        let (children, next_in_label) = if self.is_at_node() {
+            (&node.children[..], None)
+        } else {
+            (&[][..], Some(node.tail[self.offset - 1]))
+        };
+
+        next_in_label.into_iter().chain(
+            children
+                .iter()
+                .filter(|child| child.count > 0)
+                .map(|child| child.key_fragment)
+        )
+    }
+
+    /// Return a reference to the original key and value of the entry at the
+    /// current position, if any.
+    pub fn get_entry(&self) -> Option<(&'a K, &'a V)> {
+        self.is_at_node().then(|| self.node().item()).flatten()
+    }
+
+    /// Return a reference to the value of the entry at the current position, if any.
+    pub fn value(&self) -> Option<&'a V> {
+        self.get_entry().map(|(_key, value)| value)
+    }
+
+    /// Returns the number of keys starting with the current prefix.
+    pub fn len(&self) -> usize {
+        self.node().count
+    }
+
+    /// Returns `true` if no keys start with the current prefix. This can
+    /// only happen at the root of an empty map.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// An iterator over the entries of which the key starts with the current
+    /// prefix, in lexicographic order.
+    pub fn prefix_iter(&self) -> PrefixIter<'a, K, V> {
+        PrefixIter::new(Some(self.node()))
+    }
+
+    fn node(&self) -> &'a Node<K, V> {
+        self.path.last().expect("root in cursor path")
+    }
+
+    /// The length of the label of the last node on the path. The root has
+    /// an empty label.
+    fn label_len(&self) -> usize {
+        if self.path.len() > 1 {
+            1 + self.node().tail.len()
+        } else {
+            0
+        }
+    }
+
+    fn is_at_node(&self) -> bool {
+        self.offset == self.label_len()
+    }
+}
+
+/// A position in the tree, like [`Cursor`], through which entries can be
+/// inserted and removed. Created by [`PrefixTreeMap::cursor_mut`].
+///
+/// Unlike a [`Cursor`], this can move to positions that no key passes
+/// through yet, so that a key can be built one byte at a time before
+/// inserting the value.
+///
+/// Moving the cursor doesn't look at the tree. The cursor keeps the child
+/// indices of the nodes along the current prefix, so each operation only
+/// searches for the bytes added since the previous one, and restructures
+/// the tree below the position directly.
+#[derive(Debug)]
+pub struct CursorMut<'a, K, V> {
+    map: &'a mut PrefixTreeMap<K, V>,
+    /// The bytes leading from the root to the current position.
+    key: Vec<u8>,
+    /// The child indices leading from the root to the last node found along
+    /// `key`: the node at the position, or the one on the edge of which the
+    /// position is, or where the tree diverges from the key.
+    path: Vec<usize>,
+    /// The length of the prefix of `key` at the end of the label of each
+    /// node in `path`.
+    ends: Vec<usize>,
+    /// The length of the prefix of `key` known to be in the tree. The rest
+    /// of the bytes are either not searched for yet, or not in the tree.
+    matched: usize,
+}
+
+impl<'a, K, V> CursorMut<'a, K, V>
+where
+    K: AsRef<[u8]>
+{
+    fn new(map: &'a mut PrefixTreeMap<K, V>) -> Self {
+        CursorMut {
+            map,
+            key: Vec::new(),
+            path: Vec::new(),
+            ends: Vec::new(),
+            matched: 0,
+        }
+    }
+
+    /// Returns the bytes leading from the root to the current position.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Moves down along `byte`, even if no key continues with it.
+    pub fn descend(&mut self, byte: u8) {
+        self.key.push(byte);
+    }
+
+    /// Moves up by one byte. Returns `false` if already at the root.
+    pub fn ascend(&mut self) -> bool {
+        if self.key.pop().is_none() {
+            return false;
+        }
+
+        if self.matched > self.key.len() {
+            self.matched = self.key.len();
+
+            // moved above the label of the last node
+            while !self.path.is_empty() && self.parent_end() >= self.matched {
+                self.path.pop();
+                self.ends.pop();
+            }
+        }
+
+        true
+    }
+
+    /// Moves back to the root.
+    pub fn reset(&mut self) {
+        self.key.clear();
+        self.truncate(0);
+    }
+
+    /// Return a reference to the original key and value of the entry at the
+    /// current position, if any.
+    pub fn get_entry(&self) -> Option<(&K, &V)> {
+        match self.lookup() {
+            Some((node, true)) => node.item(),
+            _ => None,
+        }
+    }
+
+    /// Return a reference to the value of the entry at the current position, if any.
+    pub fn get(&self) -> Option<&V> {
+        self.get_entry().map(|(_key, value)| value)
+    }
+
+    /// Return a mutable reference to the value of the entry at the current
+    /// position, if any.
+    pub fn get_mut(&mut self) -> Option<&mut V> {
+        self.resolve();
+
+        if self.is_at_node() {
+            self.map.root.descendant_mut(&self.path).value_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of keys starting with the current prefix.
+    pub fn len(&self) -> usize {
+        self.lookup().map_or(0, |(node, _)| node.count)
+    }
+
+    /// Returns `true` if no keys start with the current prefix.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts the value at the current position, with a key created from
+    /// the current prefix. Returns the old value, if any, in which case the
+    /// old key is kept.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and the current prefix violates them.
+    pub fn insert(&mut self, value: V) -> Option<V>
+    where
+        K: for<'b> From<&'b [u8]>,
+    {
+        self.insert_with(|bytes| K::from(bytes), value)
+    }
+
+    /// Like [`CursorMut::insert`], but creates the key by calling `make_key`
+    /// with the current prefix, for key types which can't be converted from
+    /// arbitrary bytes. The key only needs to be created if the position is
+    /// vacant. It must consist of the bytes passed to `make_key`.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and the current prefix violates
+    /// them, or if the bytes of the key returned by `make_key` differ from
+    /// the current prefix.
+    pub fn insert_with<F>(&mut self, make_key: F, value: V) -> Option<V>
+    where
+        F: FnOnce(&[u8]) -> K,
+    {
+        if let Some(validator) = self.map.validator.as_ref() {
+            if let Err(error) = validator.validate(&self.key) {
+                panic!("invalid key: {error}");
+            }
+        }
+
+        if let Some(old) = self.get_mut() {
+            return Some(mem::replace(old, value));
+        }
+
+        let key = make_key(&self.key);
+        assert_eq!(key.as_ref(), self.key.as_slice(), "key doesn't match cursor position");
+
+        let depth = self.settle();
+        let mut counts = Vec::with_capacity(self.key.len() - depth + self.path.len() + 1);
+        let node = self.map.root.node_at(&self.path, &mut counts);
+        *node.search_or_insert(&self.key[depth..], &mut counts) = Some((key, value));
+
+        for tally in counts {
+            tally.add(1, Some(self.key.len()));
+        }
+
+        self.map.len += 1;
+        self.resolve();
+
+        None
+    }
+
+    /// Removes the entry at the current position, if any, and returns its value.
+    pub fn remove(&mut self) -> Option<V> {
+        self.resolve();
+
+        if !self.is_at_node() {
+            return None;
+        }
+
+        let (_key, value) = self.map.root.remove_at(&self.path)?;
+        self.map.len -= 1;
+
+        // the node and its parent may have been removed or compressed
+        self.truncate(self.path.len().saturating_sub(2));
+        self.resolve();
+
+        Some(value)
+    }
+
+    /// Removes all entries of which the key starts with the current prefix
+    /// (pruning the subtree below the position), and returns them in a new
+    /// map, with their keys intact.
+    pub fn remove_subtree(&mut self) -> PrefixTreeMap<K, V> {
+        self.resolve();
+
+        if self.matched < self.key.len() {
+            return self.map.new_like();
+        }
+
+        // the label of the node may continue below the position
+        let node = self.map.root.descendant(&self.path);
+        let mut label = self.key.clone();
+        label.extend_from_slice(&node.tail[node.tail.len() - (self.node_end() - self.matched)..]);
+
+        let subtree = self.map.root.detach_at(&self.path);
+        let subtree = self.map.adopt_detached(subtree, &label);
+
+        // the parent of the node may have been removed or compressed
+        self.truncate(self.path.len().saturating_sub(2));
+        self.resolve();
+
+        subtree
+    }
+
+    /// Inserts the entries of `subtree` below the current position, by
+    /// prepending the current prefix to their keys. Existing values under
+    /// the resulting keys are replaced.
+    ///
+    /// The nodes of `subtree` are merged into the tree as they are; only
+    /// the keys are rebuilt.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and a resulting key violates them.
+    pub fn graft(&mut self, mut subtree: PrefixTreeMap<K, V>)
+    where
+        K: for<'b> From<&'b [u8]>,
+    {
+        let prefix_len = self.key.len();
+
+        if let Some(validator) = self.map.validator.as_ref() {
+            for suffix in subtree.keys() {
+                self.key.truncate(prefix_len);
+                self.key.extend_from_slice(suffix.as_ref());
+
+                if let Err(error) = validator.validate(&self.key) {
+                    self.key.truncate(prefix_len);
+                    panic!("invalid key: {error}");
+                }
+            }
+        }
+
+        // rebuild the keys before touching the tree
+        let mut nodes = Vec::from([&mut subtree.root]);
+
+        while let Some(node) = nodes.pop() {
+            if let Some((key, _)) = node.item.as_mut() {
+                self.key.truncate(prefix_len);
+                self.key.extend_from_slice(key.as_ref());
+                *key = K::from(&self.key);
+            }
+
+            node.key_len = node.key_len.map(|len| prefix_len + len);
+
+            nodes.extend(node.children.iter_mut());
+        }
+
+        self.key.truncate(prefix_len);
+
+        if subtree.is_empty() {
+            return;
+        }
+
+        let depth = self.settle();
+        let parent_len = self.path.len();
+        let mut counts = Vec::with_capacity(self.key.len() - depth + self.path.len());
+        let node = self.map.root
+            .node_at(&self.path, &mut counts)
+            .search_or_insert_node(&self.key[depth..], &mut counts);
+        let key_len = subtree.root.key_len;
+        let added = node.merge(mem::take(&mut subtree.root));
+
+        for tally in counts {
+            tally.add(added, key_len);
+        }
+
+        self.map.len += added;
+
+        // the node may have just been created, with a single child
+        self.resolve();
+        self.map.root.tidy_at(&self.path);
+        self.truncate(parent_len);
+        self.resolve();
+    }
+
+    /// Returns a read-only [`Cursor`] at the same position, if any key
+    /// starts with the current prefix.
+    pub fn as_cursor(&self) -> Option<Cursor<'_, K, V>> {
+        let mut nodes = Vec::with_capacity(self.path.len() + 1);
+        let mut node = &self.map.root;
+        nodes.push(node);
+
+        for &index in &self.path {
+            node = &node.children[index];
+            nodes.push(node);
+        }
+
+        let mut end = self.node_end();
+        let (node, matched) = Self::follow(node, &self.key, self.matched, end, &mut |_, child_end, child| {
+            end = child_end;
+            nodes.push(child);
+        });
+
+        if matched < self.key.len() || (nodes.len() > 1 && node.count == 0) {
+            return None;
+        }
+
+        let offset = if nodes.len() > 1 {
+            1 + node.tail.len() - (end - matched)
+        } else {
+            0
+        };
+
+        Some(Cursor {
+            path: nodes,
+            offset,
+            key: self.key.clone(),
+        })
+    }
+
+    /// Searches for the bytes of `key` after the first `matched` ones, which
+    /// lead to `node`, for as long as they are in the tree. `end` is the
+    /// length of the prefix of `key` at the end of the label of `node`.
+    /// Calls `visit` with the index, the label end and the reference of
+    /// each child entered. Returns the last node found, and the number of
+    /// bytes matched.
+    fn follow<'n>(
+        mut node: &'n Node<K, V>,
+        key: &[u8],
+        mut matched: usize,
+        mut end: usize,
+        visit: &mut dyn FnMut(usize, usize, &'n Node<K, V>),
+    ) -> (&'n Node<K, V>, usize) {
+        while let Some(&byte) = key.get(matched) {
+            if matched == end {
+                let Some(index) = node.children.find(byte) else {
+                    break;
+                };
+
+                node = &node.children[index];
+                end = matched + 1 + node.tail.len();
+                visit(index, end, node);
+            } else if node.tail[node.tail.len() - (end - matched)] != byte {
+                break;
+            }
+
+            matched += 1;
+        }
+
+        (node, matched)
+    }
+
+    /// Searches for the rest of the current prefix, and records the nodes
+    /// found along it.
+    fn resolve(&mut self) {
+        let node = self.map.root.descendant(&self.path);
+        let CursorMut { path, ends, .. } = self;
+        let end = ends.last().copied().unwrap_or(0);
+        let (_, matched) = Self::follow(node, &self.key, self.matched, end, &mut |index, end, _| {
+            path.push(index);
+            ends.push(end);
+        });
+
+        self.matched = matched;
+    }
+
+    /// Like `resolve()`, but without recording anything. Returns the last
+    /// node found, and whether the position is exactly at it, if the whole
+    /// current prefix is in the tree.
+    fn lookup(&self) -> Option<(&Node<K, V>, bool)> {
+        let mut end = self.node_end();
+        let node = self.map.root.descendant(&self.path);
+        let (node, matched) = Self::follow(node, &self.key, self.matched, end, &mut |_, child_end, _| {
+            end = child_end;
+        });
+
+        (matched == self.key.len()).then_some((node, matched == end))
+    }
+
+    /// Forgets the recorded nodes, except for the first `len` ones, and
+    /// considers the key matched up to the end of the last one kept.
+    fn truncate(&mut self, len: usize) {
+        self.path.truncate(len);
+        self.ends.truncate(len);
+        self.matched = self.node_end();
+    }
+
+    /// Forgets the last recorded node, unless its whole label is a prefix of
+    /// the key, i.e. it is the deepest node under which the position is.
+    /// Returns the length of its full label, after resolving the key.
+    fn settle(&mut self) -> usize {
+        self.resolve();
+
+        if self.matched < self.node_end() {
+            self.truncate(self.path.len() - 1);
+        }
+
+        self.node_end()
+    }
+
+    fn node_end(&self) -> usize {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    fn parent_end(&self) -> usize {
+        self.ends.len().checked_sub(2).map_or(0, |index| self.ends[index])
+    }
+
+    /// Whether the position is at the last recorded node, after resolving.
+    fn is_at_node(&self) -> bool {
+        self.matched == self.key.len() && self.matched == self.node_end()
+    }
+}
+
+/// A read-only view of the entries of which the key starts with a given
+/// prefix. Keys passed to its methods are relative to the prefix, but the
+/// keys handed out are the original ones. Created by [`PrefixTreeMap::prefix`].
+#[derive(Debug)]
+pub struct SubTrie<'a, K, V> {
+    /// The topmost node under which all keys start with the prefix,
+    /// or `None` if there are no such keys.
+    node: Option<&'a Node<K, V>>,
+    /// The rest of the label of the edge leading to `node`, if the prefix
+    /// ends in the middle of that edge.
+    pending: Vec<u8>,
+}
+
+impl<K, V> Clone for SubTrie<'_, K, V> {
+    fn clone(&self) -> Self {
+        SubTrie {
+            node: self.node,
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> SubTrie<'a, K, V> {
+    fn new(node: Option<&'a Node<K, V>>, pending: Vec<u8>) -> Self {
+        SubTrie { node, pending }
+    }
+
+    /// Returns the number of entries under the prefix. This takes constant time.
+    pub fn len(&self) -> usize {
+        self.node.map_or(0, |node| node.count)
+    }
+
+    /// Returns `true` if no keys start with the prefix.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return a reference to the original key and value under the prefix
+    /// followed by `key`, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&'a K, &'a V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let rest = key.as_ref().strip_prefix(self.pending.as_slice())?;
+        self.node?.search(rest)?.item()
+    }
+
+    /// Return a reference to the value under the prefix followed by `key`, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&'a V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if the prefix followed by `key` is in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// An iterator over the entries under the prefix, in lexicographic order.
+    pub fn iter(&self) -> PrefixIter<'a, K, V> {
+        PrefixIter::new(self.node)
+    }
+
+    /// An iterator over the entries under the prefix followed by `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> PrefixIter<'a, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.prefix(prefix).iter()
+    }
+
+    /// Returns a narrower view, of the entries under the prefix followed by `prefix`.
+    pub fn prefix<Q>(&self, prefix: &Q) -> SubTrie<'a, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref();
+
+        let Some(node) = self.node else {
+            return SubTrie::new(None, Vec::new());
+        };
+
+        // the new prefix either ends before `node`, or continues below it
+        if let Some(pending) = self.pending.strip_prefix(prefix) {
+            return SubTrie::new(Some(node), pending.to_vec());
+        }
+
+        let Some(rest) = prefix.strip_prefix(self.pending.as_slice()) else {
+            return SubTrie::new(None, Vec::new());
+        };
+
+        let mut label = Vec::new();
+        let node = node.search_prefix_labelled(rest, &mut label);
+        let pending = match node {
+            Some(_) => label.split_off(rest.len()),
+            None => Vec::new(),
+        };
+
+        SubTrie::new(node, pending)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &SubTrie<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = PrefixIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A mutable view of the entries of which the key starts with a given
+/// prefix, which can't reach any other entries of the map. Keys passed to
+/// its lookup and removal methods are relative to the prefix. Inserted keys
+/// are owned keys of the map, so they are complete, and must start with the
+/// prefix, hence the names of [`SubTrieMut::insert_full`] and
+/// [`SubTrieMut::try_insert_full`]. Created by [`PrefixTreeMap::prefix_mut`].
+#[derive(Debug)]
+pub struct SubTrieMut<'a, K, V> {
+    map: &'a mut PrefixTreeMap<K, V>,
+    prefix: Vec<u8>,
+    /// Scratch space for building complete keys from relative ones.
+    buf: Vec<u8>,
+}
+
+impl<'a, K, V> SubTrieMut<'a, K, V>
+where
+    K: AsRef<[u8]>
+{
+    /// Returns the prefix of all keys in the view.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Returns a read-only view of the same entries.
+    pub fn as_sub_trie(&self) -> SubTrie<'_, K, V> {
+        self.map.prefix(&self.prefix)
+    }
+
+    /// Returns the number of entries under the prefix. This takes time
+    /// proportional to the length of the prefix.
+    pub fn len(&self) -> usize {
+        self.map.count_prefix(&self.prefix)
+    }
+
+    /// Returns `true` if no keys start with the prefix.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return a reference to the original key and value under the prefix
+    /// followed by `key`, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix(&self.prefix).get_entry(key)
+    }
+
+    /// Return a reference to the value under the prefix followed by `key`, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if the prefix followed by `key` is in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// Return a mutable reference to the value under the prefix followed
+    /// by `key`, if found.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get_mut(full_key(&self.prefix, key.as_ref(), &mut self.buf))
+    }
+
+    /// Inserts the value under the complete `key`, which is _not_ relative
+    /// to the prefix, unlike the keys passed to the other methods. Returns
+    /// the old value, if any. The length of the map is adjusted accordingly.
+    ///
+    /// # Panics
+    ///
+    /// If `key` doesn't start with the prefix, or if the map has key
+    /// validation rules, and `key` violates them.
+    pub fn insert_full(&mut self, key: K, value: V) -> Option<V> {
+        match self.try_insert_full(key, value) {
+            Ok(old_value) => old_value,
+            Err(error) => panic!("invalid key: {error}"),
+        }
+    }
+
+    /// Like [`SubTrieMut::insert_full`], but returns an error instead of
+    /// panicking if `key` doesn't start with the prefix, or violates the
+    /// validation rules of the map.
+    pub fn try_insert_full(&mut self, key: K, value: V) -> Result<Option<V>, InvalidKey> {
+        if !key.as_ref().starts_with(&self.prefix) {
+            let index = edge::common_prefix_len(key.as_ref(), &self.prefix);
+            return Err(InvalidKey::OutsidePrefix { index });
+        }
+
+        self.map.try_insert(key, value)
+    }
+
+    /// Removes the prefix followed by `key` from the map, and returns its
+    /// value, if it was found. The length of the map is adjusted accordingly.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.remove(full_key(&self.prefix, key.as_ref(), &mut self.buf))
+    }
+
+    /// Removes all entries under the prefix, leaving the rest of the map intact.
+    pub fn clear(&mut self) {
+        self.map.remove_prefix(&self.prefix);
+    }
+
+    /// An iterator over the entries under the prefix, in lexicographic order.
+    pub fn iter(&self) -> PrefixIter<'_, K, V> {
+        self.map.prefix_iter(&self.prefix)
+    }
+
+    /// An iterator over the entries under the prefix, with mutable references
+    /// to the values, in lexicographic order.
+    pub fn iter_mut(&mut self) -> PrefixIterMut<'_, K, V> {
+        self.map.prefix_iter_mut(&self.prefix)
+    }
+
+    /// An iterator over the entries under the prefix followed by `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> PrefixIter<'_, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix(&self.prefix).prefix_iter(prefix)
+    }
+
+    /// Returns a narrower view, of the entries under the prefix followed by `prefix`.
+    pub fn prefix_mut<Q>(&mut self, prefix: &Q) -> SubTrieMut<'_, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let prefix = [self.prefix.as_slice(), prefix.as_ref()].concat();
+        self.map.prefix_mut(&prefix)
+    }
+}
+
+/// Builds `prefix` followed by `key` in `buf`, so that its allocation
+/// can be reused for subsequent keys.
+fn full_key<'a>(prefix: &[u8], key: &[u8], buf: &'a mut Vec<u8>) -> &'a [u8] {
+    buf.clear();
+    buf.extend_from_slice(prefix);
+    buf.extend_from_slice(key);
+    buf
+}
+
+/// A batch of insertions and removals, staged without touching the map, and
+/// then either applied all at once by [`Transaction::commit`], or discarded.
+/// Created by [`PrefixTreeMap::transaction`].
+///
+/// Keys are validated against the rules of the map when they are staged, so
+/// committing can't fail halfway. Dropping the transaction without committing
+/// it (e.g. when returning early with an error) rolls it back.
+#[must_use = "a transaction is rolled back unless it is committed"]
+pub struct Transaction<'a, K, V> {
+    map: &'a mut PrefixTreeMap<K, V>,
+    /// The last staged change of each key: `None` for a removal.
+    staged: PrefixTreeMap<Vec<u8>, Option<(K, V)>>,
+}
+
+impl<'a, K, V> Transaction<'a, K, V>
+where
+    K: AsRef<[u8]>
+{
+    /// Returns the value under the key, as it will be after committing the
+    /// changes staged so far.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        match self.staged.get(key.as_ref()) {
+            Some(change) => change.as_ref().map(|(_key, value)| value),
+            None => self.map.get(key),
+        }
+    }
+
+    /// Returns `true` if the key will be in the map after committing the
+    /// changes staged so far.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of keys with staged changes.
+    pub fn staged_len(&self) -> usize {
+        self.staged.len()
+    }
+
+    /// Stages the insertion of the value under the key, replacing any change
+    /// staged earlier for the same key.
+    ///
+    /// # Panics
+    ///
+    /// If the map has key validation rules, and `key` violates them.
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Err(error) = self.try_insert(key, value) {
+            panic!("invalid key: {error}");
+        }
+    }
+
+    /// Like [`Transaction::insert`], but returns an error instead of panicking
+    /// if `key` violates the validation rules of the map. Nothing is staged
+    /// in this case.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), InvalidKey> {
+        if let Some(validator) = self.map.validator.as_ref() {
+            validator.validate(key.as_ref())?;
+        }
+
+        self.staged.insert(key.as_ref().to_vec(), Some((key, value)));
+
+        Ok(())
+    }
+
+    /// Stages the removal of the key, replacing any change staged earlier
+    /// for the same key.
+    pub fn remove<Q>(&mut self, key: &Q)
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.staged.insert(key.as_ref().to_vec(), None);
+    }
+
+    /// Applies the staged changes to the map.
+    pub fn commit(self) {
+        for (bytes, change) in self.staged {
+            match change {
+                Some((key, value)) => {
+                    self.map.insert(key, value);
+                }
+                None => {
+                    self.map.remove(&bytes);
+                }
+            }
+        }
+    }
+
+    /// Discards the staged changes, leaving the map untouched. The same as
+    /// dropping the transaction.
+    pub fn rollback(self) {}
+}
+
+impl<K: Debug, V: Debug> Debug for Transaction<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("staged", &self.staged.values().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A point where the tree branches. Yielded by [`PrefixTreeMap::iter_branch_points`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BranchPoint {
+    /// The prefix shared by all keys under this branch point.
+    pub prefix: Vec<u8>,
+    /// The number of distinct bytes following `prefix` in the keys.
+    pub fan_out: usize,
+    /// The number of entries whose key starts with `prefix`.
+    pub len: usize,
+}
+
+/// Iterator over the branch points of a tree, in lexicographic order of their prefixes.
+#[derive(Clone, Default, Debug)]
+pub struct BranchPoints {
+    iter: alloc::vec::IntoIter<BranchPoint>,
+}
+
+impl Iterator for BranchPoints {
+    type Item = BranchPoint;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for BranchPoints {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+impl FusedIterator for BranchPoints {}
+
+impl ExactSizeIterator for BranchPoints {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the distinct prefixes of a given length and the number of
+/// entries having each prefix. Created by [`PrefixTreeMap::iter_distinct_prefixes`].
+#[derive(Clone, Default, Debug)]
+pub struct DistinctPrefixes {
+    iter: alloc::vec::IntoIter<(Vec<u8>, usize)>,
+}
+
+impl Iterator for DistinctPrefixes {
+    type Item = (Vec<u8>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for DistinctPrefixes {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+impl FusedIterator for DistinctPrefixes {}
+
+impl ExactSizeIterator for DistinctPrefixes {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// An entry ranked by its value, with ties broken in favor of the entry
+/// that comes first in key order. Used by [`PrefixTreeMap::top_n_by_value`].
+struct Ranked<'a, K, V> {
+    value: &'a V,
+    index: usize,
+    key: &'a K,
+}
+
+impl<V: Ord, K> Ord for Ranked<'_, K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(other.value).then(other.index.cmp(&self.index))
+    }
+}
+
+impl<V: Ord, K> PartialOrd for Ranked<'_, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V: Ord, K> PartialEq for Ranked<'_, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<V: Ord, K> Eq for Ranked<'_, K, V> {}
+
+/// Iterator over references to the entries of the tree, ordered by value.
+/// Created by [`PrefixTreeMap::iter_sorted_by_value`].
+///
+/// This owns a binary min-heap of references to all remaining entries, with
+/// respect to the user-supplied comparator, which `BinaryHeap` doesn't support.
+pub struct SortedByValue<'a, K, V, F> {
+    heap: Vec<(&'a K, &'a V)>,
+    cmp: F,
+}
+
+impl<K, V, F: Clone> Clone for SortedByValue<'_, K, V, F> {
+    fn clone(&self) -> Self {
+        SortedByValue {
+            heap: self.heap.clone(),
+            cmp: self.cmp.clone(),
+        }
+    }
+}
+
+impl<'a, K, V, F> SortedByValue<'a, K, V, F>
+where
+    F: FnMut(&V, &V) -> Ordering,
+{
+    fn new(heap: Vec<(&'a K, &'a V)>, cmp: F) -> Self {
+        let mut iter = SortedByValue { heap, cmp };
+
+        for index in (0..iter.heap.len() / 2).rev() {
+            iter.sift_down(index);
+        }
+
+        iter
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        let len = self.heap.len();
+
+        loop {
+            let left = 2 * index + 1;
+            let right = left + 1;
+
+            if left >= len {
+                break;
+            }
+
+            let min_child = if right < len && (self.cmp)(self.heap[right].1, self.heap[left].1).is_lt() {
+                right
+            } else {
+                left
+            };
+
+            if (self.cmp)(self.heap[min_child].1, self.heap[index].1).is_lt() {
+                self.heap.swap(index, min_child);
+                index = min_child;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<K: Debug, V: Debug, F> Debug for SortedByValue<'_, K, V, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SortedByValue").field("heap", &self.heap).finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V, F> Iterator for SortedByValue<'a, K, V, F>
+where
+    F: FnMut(&V, &V) -> Ordering,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        let item = self.heap.swap_remove(0);
+        self.sift_down(0);
+
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<K, V, F> FusedIterator for SortedByValue<'_, K, V, F>
+where
+    F: FnMut(&V, &V) -> Ordering,
+{}
+
+impl<K, V, F> ExactSizeIterator for SortedByValue<'_, K, V, F>
+where
+    F: FnMut(&V, &V) -> Ordering,
+{
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+/// A node, owned or borrowed, that a [`DepthFirst`] traversal can descend into.
+trait Subtree: Sized {
+    type Item;
+    type Children: DoubleEndedIterator<Item = Self> + ExactSizeIterator;
+
+    /// Splits the node into its own item and the iterator over its children.
+    fn into_frame(self) -> Frame<Self>;
+}
+
+impl<'a, K, V> Subtree for &'a Node<K, V> {
+    type Item = (&'a K, &'a V);
+    type Children = core::slice::Iter<'a, Node<K, V>>;
+
+    fn into_frame(self) -> Frame<Self> {
+        Frame {
+            item: self.item(),
+            children: self.children.iter(),
+        }
+    }
+}
+
+impl<'a, K, V> Subtree for &'a mut Node<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type Children = core::slice::IterMut<'a, Node<K, V>>;
+
+    fn into_frame(self) -> Frame<Self> {
+        Frame {
+            item: self.item.as_mut().map(|(key, value)| (&*key, value)),
+            children: self.children.iter_mut(),
+        }
+    }
+}
+
+impl<K, V> Subtree for Node<K, V> {
+    type Item = (K, V);
+    type Children = alloc::vec::IntoIter<Node<K, V>>;
+
+    fn into_frame(mut self) -> Frame<Self> {
+        Frame {
+            item: self.item.take(),
+            children: mem::take(&mut self.children).into_iter(),
+        }
+    }
+}
+
+/// The part of a node not yet visited: its own item, if it has not been
+/// yielded yet, followed by the children not yet descended into.
+struct Frame<N: Subtree> {
+    item: Option<N::Item>,
+    children: N::Children,
+}
+
+impl<N> Clone for Frame<N>
+where
+    N: Subtree,
+    N::Item: Clone,
+    N::Children: Clone,
+{
+    fn clone(&self) -> Self {
+        Frame {
+            item: self.item.clone(),
+            children: self.children.clone(),
+        }
+    }
+}
+
+/// Pre-order traversal of a subtree, driven by an explicit stack of frames
+/// instead of recursion, so it takes neither call stack nor an allocation
+/// per level of the tree.
+///
+/// The remaining items are those of the frames, in order. Descending from
+/// the front pushes the frame of the next child to the front, and descending
+/// from the back pushes the frame of the last child to the back, so the
+/// frames always form a contiguous sequence of the remaining items.
+struct DepthFirst<N: Subtree> {
+    frames: VecDeque<Frame<N>>,
+}
+
+/// Iterator over an owned subtree.
+type SubtreeIntoIter<K, V> = DepthFirst<Node<K, V>>;
+
+/// Iterator over a borrowed subtree.
+type SubtreeIter<'a, K, V> = DepthFirst<&'a Node<K, V>>;
+
+/// Iterator over a mutably borrowed subtree.
+type SubtreeIterMut<'a, K, V> = DepthFirst<&'a mut Node<K, V>>;
+
+impl<N: Subtree> DepthFirst<N> {
+    fn new(node: N) -> Self {
+        DepthFirst { frames: VecDeque::from([node.into_frame()]) }
+    }
+}
+
+impl<N: Subtree> Default for DepthFirst<N> {
+    fn default() -> Self {
+        DepthFirst { frames: VecDeque::new() }
+    }
+}
+
+impl<N> Clone for DepthFirst<N>
+where
+    N: Subtree,
+    N::Item: Clone,
+    N::Children: Clone,
+{
+    fn clone(&self) -> Self {
+        DepthFirst { frames: self.frames.clone() }
+    }
+}
+
+impl<K, V> SubtreeIntoIter<K, V> {
+    /// Calls `f` with references to the remaining items, in iteration order.
+    pub(crate) fn for_each_remaining<'a>(&'a self, f: &mut dyn FnMut(&'a K, &'a V)) {
+        for frame in &self.frames {
+            if let Some((key, value)) = frame.item.as_ref() {
+                f(key, value);
+            }
+
+            for node in frame.children.as_slice() {
+                node.iter().for_each(|(key, value)| f(key, value));
+            }
+        }
+    }
+}
+
+impl<K, V> SubtreeIterMut<'_, K, V> {
+    /// Calls `f` with references to the remaining items, in iteration order.
+    fn for_each_remaining(&self, f: &mut dyn FnMut(&K, &V)) {
+        for frame in &self.frames {
+            if let Some((key, value)) = frame.item.as_ref() {
+                f(key, value);
+            }
+
+            for node in frame.children.as_slice() {
+                node.iter().for_each(|(key, value)| f(key, value));
+            }
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SubtreeIntoIter<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.for_each_remaining(&mut |key, value| { list.entry(&(key, value)); });
+        list.finish()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SubtreeIter<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SubtreeIterMut<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.for_each_remaining(&mut |key, value| { list.entry(&(key, value)); });
+        list.finish()
+    }
+}
+
+impl<N: Subtree> Iterator for DepthFirst<N> {
+    type Item = N::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.frames.front_mut()?;
+
+            // a node comes before its children
+            if let Some(item) = frame.item.take() {
+                return Some(item);
+            }
+
+            match frame.children.next() {
+                Some(child) => self.frames.push_front(child.into_frame()),
+                None => {
+                    self.frames.pop_front();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let items = self.frames.iter().filter(|frame| frame.item.is_some()).count();
+
+        // Nodes without an item may exist (e.g. after removal),
+        // so unvisited children don't contribute to the lower bound.
+        let upper = if self.frames.iter().all(|frame| frame.children.len() == 0) {
+            Some(items)
+        } else {
+            None
+        };
+
+        (items, upper)
+    }
+}
+
+impl<N: Subtree> DoubleEndedIterator for DepthFirst<N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.frames.back_mut()?;
+
+            // a node comes after its children when going backwards
+            match frame.children.next_back() {
+                Some(child) => self.frames.push_back(child.into_frame()),
+                None => {
+                    let item = frame.item.take();
+                    self.frames.pop_back();
+
+                    if item.is_some() {
+                        return item;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<N: Subtree> FusedIterator for DepthFirst<N> {}
+
+/// A violation of the structural invariants of a map, as reported by
+/// [`PrefixTreeMap::check_invariants`]. The path of a node is the label of
+/// the edges leading to it from the root.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum InvariantViolation {
+    /// The children of the node at `path` are not strictly ascending by
+    /// their first byte.
+    UnsortedChildren {
+        path: Vec<u8>,
+    },
+    /// The child starting with `byte` of the node at `path` isn't found when
+    /// looking it up by that byte.
+    UnreachableChild {
+        path: Vec<u8>,
+        byte: u8,
+    },
+    /// The key stored in the node at `path` is not equal to `path`.
+    KeyMismatch {
+        path: Vec<u8>,
+    },
+    /// The node at `path` records `count` entries below it, but there are
+    /// `actual` ones.
+    CountMismatch {
+        path: Vec<u8>,
+        count: usize,
+        actual: usize,
+    },
+    /// The node at `path`, or one of its ancestors, records that all keys
+    /// below it are `key_len` bytes long, but the node or its own key isn't.
+    KeyLenMismatch {
+        path: Vec<u8>,
+        key_len: Option<usize>,
+    },
+    /// The map records a length of `len`, but there are `actual` entries.
+    LenMismatch {
+        len: usize,
+        actual: usize,
+    },
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::UnsortedChildren { path } => {
+                write!(f, "children of node \"{}\" are not sorted", path.escape_ascii())
+            }
+            InvariantViolation::UnreachableChild { path, byte } => {
+                write!(f, "child {byte:#04x} of node \"{}\" cannot be looked up", path.escape_ascii())
+            }
+            InvariantViolation::KeyMismatch { path } => {
+                write!(f, "key stored in node \"{}\" doesn't match its path", path.escape_ascii())
+            }
+            InvariantViolation::CountMismatch { path, count, actual } => {
+                write!(f, "node \"{}\" has an item count of {count} instead of {actual}", path.escape_ascii())
+            }
+            InvariantViolation::KeyLenMismatch { path, key_len } => {
+                write!(f, "node \"{}\" contradicts the key length {key_len:?} recorded for it", path.escape_ascii())
+            }
+            InvariantViolation::LenMismatch { len, actual } => {
+                write!(f, "map has a length of {len} instead of {actual}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvariantViolation {}
+
+/// A difference between two maps. [`PrefixTreeMap::diff`] reports changes
+/// with the keys and values borrowed from the maps being compared.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Change<K, V> {
+    /// The key is only found in the new map.
+    Added { key: K, value: V },
+    /// The key is only found in the old map.
+    Removed { key: K, value: V },
+    /// The key is found in both maps, with different values.
+    Modified { key: K, old: V, new: V },
+}
+
+impl<K, V> Change<K, V> {
+    /// Returns the key affected by the change.
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Added { key, .. } | Change::Removed { key, .. } | Change::Modified { key, .. } => key,
+        }
+    }
+}
+
+impl<K: Clone, V: Clone> Change<&K, &V> {
+    /// Clones the borrowed key and values, e.g. for replaying the change
+    /// on another map using [`PrefixTreeMap::apply`].
+    pub fn cloned(self) -> Change<K, V> {
+        match self {
+            Change::Added { key, value } => Change::Added { key: key.clone(), value: value.clone() },
+            Change::Removed { key, value } => Change::Removed { key: key.clone(), value: value.clone() },
+            Change::Modified { key, old, new } => Change::Modified {
+                key: key.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            },
+        }
+    }
+}
+
+/// Iterator over the differences between two maps.
+/// Created by [`PrefixTreeMap::diff`].
+pub struct Diff<'a, K, V> {
+    zip: ZipByKey<'a, K, V, K, V>,
+}
+
+impl<K, V> Clone for Diff<'_, K, V> {
+    fn clone(&self) -> Self {
+        Diff { zip: self.zip.clone() }
+    }
+}
+
+impl<K, V> Debug for Diff<'_, K, V>
+where
+    K: AsRef<[u8]> + Debug,
+    V: PartialEq + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for Diff<'a, K, V>
+where
+    K: AsRef<[u8]>,
+    V: PartialEq,
+{
+    type Item = Change<&'a K, &'a V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.zip.find_map(|pair| match pair {
+            (Some((key, value)), None) => Some(Change::Removed { key, value }),
+            (None, Some((key, value))) => Some(Change::Added { key, value }),
+            (Some((key, old)), Some((_, new))) => (old != new).then_some(Change::Modified { key, old, new }),
+            (None, None) => None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.zip.size_hint().1)
+    }
+}
+
+impl<K, V> FusedIterator for Diff<'_, K, V>
+where
+    K: AsRef<[u8]>,
+    V: PartialEq,
+{}
+
+/// Merge-join of two maps by key. Created by [`PrefixTreeMap::zip_by_key`].
+pub struct ZipByKey<'a, K, V, L, W> {
+    lhs: Peekable<Iter<'a, K, V>>,
+    rhs: Peekable<Iter<'a, L, W>>,
+}
+
+impl<K, V, L, W> Clone for ZipByKey<'_, K, V, L, W> {
+    fn clone(&self) -> Self {
+        ZipByKey {
+            lhs: self.lhs.clone(),
+            rhs: self.rhs.clone(),
+        }
+    }
+}
+
+impl<K, V, L, W> ZipByKey<'_, K, V, L, W> {
+    /// The number of entries not yet visited in each map.
+    pub(crate) fn remaining(&self) -> (usize, usize) {
+        (self.lhs.len(), self.rhs.len())
+    }
+}
+
+impl<K, V, L, W> Debug for ZipByKey<'_, K, V, L, W>
+where
+    K: AsRef<[u8]> + Debug,
+    V: Debug,
+    L: AsRef<[u8]> + Debug,
+    W: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V, L, W> Iterator for ZipByKey<'a, K, V, L, W>
+where
+    K: AsRef<[u8]>,
+    L: AsRef<[u8]>,
+{
+    type Item = (Option<(&'a K, &'a V)>, Option<(&'a L, &'a W)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ordering = match (self.lhs.peek(), self.rhs.peek()) {
+            (Some((lhs, _)), Some((rhs, _))) => lhs.as_ref().cmp(rhs.as_ref()),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+
+        Some(match ordering {
+            Ordering::Less => (self.lhs.next(), None),
+            Ordering::Equal => (self.lhs.next(), self.rhs.next()),
+            Ordering::Greater => (None, self.rhs.next()),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lhs, rhs) = self.remaining();
+        (lhs.max(rhs), Some(lhs + rhs))
+    }
+}
+
+impl<K, V, L, W> FusedIterator for ZipByKey<'_, K, V, L, W>
+where
+    K: AsRef<[u8]>,
+    L: AsRef<[u8]>,
+{}
+
+/// Iterator over all the values of the tree.
+#[derive(Clone)]
+pub struct IntoIter<K, V> {
+    iter: SubtreeIntoIter<K, V>,
+    len: usize,
+}
+
+impl<K, V> Default for IntoIter<K, V> {
+    fn default() -> Self {
+        IntoIter {
+            iter: SubtreeIntoIter::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for IntoIter<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.iter, f)
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over the entries removed from a map by [`PrefixTreeMap::drain`].
+///
+/// The children of the root are drained in place, so that the map keeps
+/// their allocation for the entries inserted afterwards.
+pub struct Drain<'a, K, V> {
+    /// The item of the root, if it has not been yielded yet.
+    item: Option<(K, V)>,
+    /// The children of the root not yet descended into.
+    children: Option<alloc::vec::Drain<'a, Node<K, V>>>,
+    /// The subtrees of the children being visited from the front and the back.
+    front: SubtreeIntoIter<K, V>,
+    back: SubtreeIntoIter<K, V>,
+    len: usize,
+}
+
+impl<K, V> Default for Drain<'_, K, V> {
+    fn default() -> Self {
+        Drain {
+            item: None,
+            children: None,
+            front: SubtreeIntoIter::default(),
+            back: SubtreeIntoIter::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K, V> Drain<'_, K, V> {
+    /// Calls `f` with references to the remaining items, in iteration order.
+    pub(crate) fn for_each_remaining<'a>(&'a self, f: &mut dyn FnMut(&'a K, &'a V)) {
+        if let Some((key, value)) = self.item.as_ref() {
+            f(key, value);
+        }
+
+        self.front.for_each_remaining(f);
+
+        for node in self.children.iter().flat_map(|children| children.as_slice()) {
+            node.iter().for_each(|(key, value)| f(key, value));
+        }
+
+        self.back.for_each_remaining(f);
+    }
+
+    fn next_item(&mut self) -> Option<(K, V)> {
+        // the root comes before its children
+        if let Some(item) = self.item.take() {
+            return Some(item);
+        }
+
+        loop {
+            if let Some(item) = self.front.next() {
+                return Some(item);
+            }
+
+            match self.children.as_mut().and_then(Iterator::next) {
+                Some(child) => self.front = DepthFirst::new(child),
+                None => return self.back.next(),
+            }
+        }
+    }
+
+    fn next_item_back(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Some(item) = self.back.next_back() {
+                return Some(item);
+            }
+
+            match self.children.as_mut().and_then(DoubleEndedIterator::next_back) {
+                Some(child) => self.back = DepthFirst::new(child),
+                None => return self.front.next_back().or_else(|| self.item.take()),
+            }
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for Drain<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.for_each_remaining(&mut |key, value| { list.entry(&(key, value)); });
+        list.finish()
+    }
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.next_item()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Drain<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.next_item_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for Drain<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for Drain<'_, K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Pre-order traversal of a mutably borrowed tree, removing the items
+/// selected by a predicate. The state of the traversal is kept as a path
+/// of child indices, because the tree is modified between steps.
+pub(crate) struct ExtractCursor<'a, K, V> {
+    map: &'a mut PrefixTreeMap<K, V>,
+    /// Indices of the children leading from the root to the current node.
+    path: Vec<usize>,
+    /// Whether the item of the current node has already been examined.
+    examined: bool,
+    finished: bool,
+}
+
+impl<'a, K, V> ExtractCursor<'a, K, V> {
+    pub(crate) fn new(map: &'a mut PrefixTreeMap<K, V>) -> Self {
+        ExtractCursor {
+            map,
+            path: Vec::new(),
+            examined: false,
+            finished: false,
+        }
+    }
+
+    /// Removes and returns the next item for which `pred` returns `true`.
+    pub(crate) fn next_matching(&mut self, pred: &mut dyn FnMut(&K, &mut V) -> bool) -> Option<(K, V)> {
+        while !self.finished {
+            let node = self.map.root.descendant_mut(&self.path);
+
+            if !mem::replace(&mut self.examined, true) {
+                if let Some((key, value)) = node.item.as_mut() {
+                    if pred(key, value) {
+                        self.map.len -= 1;
+                        return self.map.root.take_at(&self.path);
+                    }
+                }
+            }
+
+            if node.children.is_empty() {
+                self.ascend();
+            } else {
+                self.path.push(0);
+                self.examined = false;
+            }
+        }
+
+        None
+    }
+
+    /// Moves to the next sibling of the current node, or that of its closest
+    /// ancestor which has one. Removes or compresses the nodes left behind
+    /// on the way up.
+    fn ascend(&mut self) {
+        while let Some(index) = self.path.pop() {
+            let parent = self.map.root.descendant_mut(&self.path);
+
+            // the child has been fully visited, so it can be restructured
+            let next = if parent.tidy_child(index) {
+                index
+            } else {
+                index + 1
+            };
+
+            if next < parent.children.len() {
+                self.path.push(next);
+                self.examined = false;
+                return;
+            }
+        }
+
+        self.finished = true;
+    }
+
+    /// The number of items that can still be removed.
+    pub(crate) fn max_remaining(&self) -> usize {
+        if self.finished { 0 } else { self.map.len }
+    }
+}
+
+/// Removes or compresses the nodes along the path to the current node, which
+/// may have been left empty, or with a single child, by removals below them.
+/// The nodes before the path have already been restructured on the way up.
+impl<K, V> Drop for ExtractCursor<'_, K, V> {
+    fn drop(&mut self) {
+        while let Some(index) = self.path.pop() {
+            self.map.root.descendant_mut(&self.path).tidy_child(index);
+        }
+    }
+}
+
+/// Iterator removing and yielding the entries selected by a predicate.
+/// Created by [`PrefixTreeMap::extract_if`].
+pub struct ExtractIf<'a, K, V, F> {
+    cursor: ExtractCursor<'a, K, V>,
+    pred: F,
+}
+
+impl<K, V, F> Debug for ExtractIf<'_, K, V, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractIf").finish_non_exhaustive()
+    }
+}
+
+impl<K, V, F> Iterator for ExtractIf<'_, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_matching(&mut self.pred)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cursor.max_remaining()))
+    }
+}
+
+impl<K, V, F> FusedIterator for ExtractIf<'_, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{}
+
+/// Iterator over references to the values of the tree.
+pub struct Iter<'a, K, V> {
+    iter: SubtreeIter<'a, K, V>,
+    len: usize,
+}
+
+impl<K, V> Default for Iter<'_, K, V> {
+    fn default() -> Self {
+        Iter {
+            iter: SubtreeIter::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for Iter<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over references to the keys and mutable references to the values of the tree.
+pub struct IterMut<'a, K, V> {
+    iter: SubtreeIterMut<'a, K, V>,
+    len: usize,
+}
+
+impl<K, V> Default for IterMut<'_, K, V> {
+    fn default() -> Self {
+        IterMut {
+            iter: SubtreeIterMut::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for IterMut<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.iter, f)
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over the owned keys.
+#[derive(Clone)]
+pub struct IntoKeys<K, V> {
+    iter: IntoIter<K, V>,
+}
+
+impl<K, V> Default for IntoKeys<K, V> {
+    fn default() -> Self {
+        IntoKeys {
+            iter: IntoIter::default(),
+        }
+    }
+}
+
+impl<K: Debug, V> Debug for IntoKeys<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.iter.for_each_remaining(&mut |key, _value| { list.entry(key); });
+        list.finish()
+    }
+}
+
+impl<K, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _v)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoKeys<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _v)| k)
+    }
+}
+
+impl<K, V> FusedIterator for IntoKeys<K, V> {}
+
+impl<K, V> ExactSizeIterator for IntoKeys<K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the borrowed keys.
+pub struct Keys<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<K, V> Default for Keys<'_, K, V> {
+    fn default() -> Self {
+        Keys {
+            iter: Iter::default(),
+        }
+    }
+}
+
+impl<K, V> Clone for Keys<'_, K, V> {
+    fn clone(&self) -> Self {
+        Keys { iter: self.iter.clone() }
+    }
+}
+
+impl<K: Debug, V> Debug for Keys<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _v)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, _v)| k)
+    }
+}
+
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the borrowed keys as byte strings.
+pub struct KeysAsBytes<'a, K, V> {
+    keys: Keys<'a, K, V>,
+}
+
+impl<K, V> Default for KeysAsBytes<'_, K, V> {
+    fn default() -> Self {
+        KeysAsBytes { keys: Keys::default() }
+    }
+}
+
+impl<K, V> Clone for KeysAsBytes<'_, K, V> {
+    fn clone(&self) -> Self {
+        KeysAsBytes { keys: self.keys.clone() }
+    }
+}
+
+impl<K: AsRef<[u8]>, V> Debug for KeysAsBytes<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K: AsRef<[u8]>, V> Iterator for KeysAsBytes<'a, K, V> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.keys.next().map(AsRef::as_ref)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K: AsRef<[u8]>, V> DoubleEndedIterator for KeysAsBytes<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.keys.next_back().map(AsRef::as_ref)
+    }
+}
+
+impl<K: AsRef<[u8]>, V> FusedIterator for KeysAsBytes<'_, K, V> {}
+
+impl<K: AsRef<[u8]>, V> ExactSizeIterator for KeysAsBytes<'_, K, V> {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// Iterator over the owned values.
+#[derive(Clone)]
+pub struct IntoValues<K, V> {
+    iter: IntoIter<K, V>,
+}
+
+impl<K, V> Default for IntoValues<K, V> {
+    fn default() -> Self {
+        IntoValues {
+            iter: IntoIter::default(),
+        }
+    }
+}
+
+impl<K, V: Debug> Debug for IntoValues<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.iter.for_each_remaining(&mut |_key, value| { list.entry(value); });
+        list.finish()
+    }
+}
+
+impl<K, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_k, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoValues<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_k, v)| v)
+    }
+}
+
+impl<K, V> FusedIterator for IntoValues<K, V> {}
+
+impl<K, V> ExactSizeIterator for IntoValues<K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the borrowed values.
+pub struct Values<'a, K, V> {
+    iter: Iter<'a, K, V>,
+}
+
+impl<K, V> Default for Values<'_, K, V> {
+    fn default() -> Self {
+        Values {
+            iter: Iter::default(),
+        }
+    }
+}
+
+impl<K, V> Clone for Values<'_, K, V> {
+    fn clone(&self) -> Self {
+        Values { iter: self.iter.clone() }
+    }
+}
+
+impl<K, V: Debug> Debug for Values<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_k, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_k, v)| v)
+    }
+}
+
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over mutable references to the values.
+pub struct ValuesMut<'a, K, V> {
+    iter: IterMut<'a, K, V>,
+}
+
+impl<K, V> Default for ValuesMut<'_, K, V> {
+    fn default() -> Self {
+        ValuesMut {
+            iter: IterMut::default(),
+        }
+    }
+}
+
+impl<K, V: Debug> Debug for ValuesMut<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.iter.for_each_remaining(&mut |_key, value| { list.entry(value); });
+        list.finish()
+    }
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_k, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for ValuesMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_k, v)| v)
+    }
+}
+
+impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// Iterator over the owned entries of which the key starts with a given prefix.
+/// Created by [`PrefixTreeMap::into_prefix_iter`].
+#[derive(Clone)]
+pub struct IntoPrefixIter<K, V> {
+    iter: SubtreeIntoIter<K, V>,
+    len: usize,
+}
+
+impl<K, V> Default for IntoPrefixIter<K, V> {
+    fn default() -> Self {
+        IntoPrefixIter {
+            iter: SubtreeIntoIter::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K, V> IntoPrefixIter<K, V> {
+    fn new(node: Option<Node<K, V>>) -> Self {
+        match node {
+            Some(node) => IntoPrefixIter {
+                len: node.count,
+                iter: node.into_iter(),
+            },
+            None => IntoPrefixIter::default(),
+        }
+    }
+
+    /// Calls `f` with references to the remaining items, in iteration order.
+    pub(crate) fn for_each_remaining<'a>(&'a self, f: &mut dyn FnMut(&'a K, &'a V)) {
+        self.iter.for_each_remaining(f);
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for IntoPrefixIter<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.iter, f)
+    }
+}
+
+impl<K, V> Iterator for IntoPrefixIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoPrefixIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for IntoPrefixIter<K, V> {}
+
+impl<K, V> ExactSizeIterator for IntoPrefixIter<K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over the borrowed entries of which the key starts with a given prefix.
+/// Created by [`PrefixTreeMap::prefix_iter`].
+pub struct PrefixIter<'a, K, V> {
+    iter: SubtreeIter<'a, K, V>,
+    len: usize,
+}
+
+impl<'a, K, V> PrefixIter<'a, K, V> {
+    fn new(node: Option<&'a Node<K, V>>) -> Self {
+        match node {
+            Some(node) => PrefixIter {
+                iter: node.iter(),
+                len: node.count,
+            },
+            None => PrefixIter::default(),
+        }
+    }
+}
+
+impl<K, V> Default for PrefixIter<'_, K, V> {
+    fn default() -> Self {
+        PrefixIter {
+            iter: SubtreeIter::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K, V> Clone for PrefixIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        PrefixIter {
+            iter: self.iter.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for PrefixIter<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.iter, f)
+    }
+}
+
+impl<'a, K, V> Iterator for PrefixIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for PrefixIter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for PrefixIter<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for PrefixIter<'_, K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over the mutably borrowed entries of which the key starts with a given prefix.
+/// Created by [`PrefixTreeMap::prefix_iter_mut`].
+pub struct PrefixIterMut<'a, K, V> {
+    iter: SubtreeIterMut<'a, K, V>,
+    len: usize,
+}
+
+impl<'a, K, V> PrefixIterMut<'a, K, V> {
+    fn new(node: Option<&'a mut Node<K, V>>) -> Self {
+        match node {
+            Some(node) => PrefixIterMut {
+                len: node.count,
+                iter: node.iter_mut(),
+            },
+            None => PrefixIterMut::default(),
+        }
+    }
+}
+
+impl<K, V> Default for PrefixIterMut<'_, K, V> {
+    fn default() -> Self {
+        PrefixIterMut {
+            iter: SubtreeIterMut::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for PrefixIterMut<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.iter, f)
+    }
+}
+
+impl<'a, K, V> Iterator for PrefixIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for PrefixIterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<K, V> FusedIterator for PrefixIterMut<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for PrefixIterMut<'_, K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Iterator over the entries of which the key is a prefix of a given query.
+/// Created by [`PrefixTreeMap::prefixes_of`].
+pub struct PrefixesOf<'a, K, V> {
+    /// The next node along the query to be visited.
+    node: Option<&'a Node<K, V>>,
+    /// The part of the query below `node`.
+    rest: &'a [u8],
+}
+
+impl<K, V> Default for PrefixesOf<'_, K, V> {
+    fn default() -> Self {
+        PrefixesOf { node: None, rest: &[] }
+    }
+}
+
+impl<K, V> Clone for PrefixesOf<'_, K, V> {
+    fn clone(&self) -> Self {
+        PrefixesOf { node: self.node, rest: self.rest }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for PrefixesOf<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for PrefixesOf<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node.take()?;
+
+            if let Some((&byte, rest)) = self.rest.split_first() {
+                if let Some(child) = node.child(byte) {
+                    if let Some(rest) = rest.strip_prefix(child.tail.as_slice()) {
+                        self.node = Some(child);
+                        self.rest = rest;
+                    }
+                }
+            }
+
+            if let Some(item) = node.item() {
+                return Some(item);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let upper = if self.node.is_some() { self.rest.len() + 1 } else { 0 };
+        (0, Some(upper))
+    }
+}
+
+impl<K, V> FusedIterator for PrefixesOf<'_, K, V> {}
+
+/// What [`PrefixTreeMap::segment_iter`] does at a position of the text where
+/// no key matches.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Unmatched {
+    /// Skip the byte at that position, and continue matching at the next one.
+    #[default]
+    Skip,
+    /// End the iteration. The rest of the text is available from
+    /// [`SegmentIter::remainder`].
+    Stop,
+}
+
+/// Iterator over the tokens of a text, split by greedy longest-prefix
+/// matching. Created by [`PrefixTreeMap::segment_iter`].
+pub struct SegmentIter<'a, K, V> {
+    root: Option<&'a Node<K, V>>,
+    text: &'a [u8],
+    /// The start of the rest of the text, which is yet to be matched.
+    position: usize,
+    unmatched: Unmatched,
+}
+
+impl<'a, K, V> SegmentIter<'a, K, V> {
+    /// Returns the part of the text which hasn't been matched or skipped yet.
+    /// After the iteration stopped at an unmatched byte, this starts with
+    /// that byte.
+    pub fn remainder(&self) -> &'a [u8] {
+        &self.text[self.position..]
+    }
+}
+
+impl<K, V> Default for SegmentIter<'_, K, V> {
+    fn default() -> Self {
+        SegmentIter {
+            root: None,
+            text: &[],
+            position: 0,
+            unmatched: Unmatched::default(),
+        }
+    }
+}
+
+impl<K, V> Clone for SegmentIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        SegmentIter {
+            root: self.root,
+            text: self.text,
+            position: self.position,
+            unmatched: self.unmatched,
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SegmentIter<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K, V> Iterator for SegmentIter<'a, K, V> {
+    type Item = (core::ops::Range<usize>, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let root = self.root?;
+
+        while self.position < self.text.len() {
+            let start = self.position;
+
+            if let Some((len, node)) = root.longest_nonempty_prefix_of(&self.text[start..]) {
+                let (key, value) = node.item()?;
+                self.position += len;
+                return Some((start..self.position, key, value));
+            }
+
+            match self.unmatched {
+                Unmatched::Skip => self.position += 1,
+                Unmatched::Stop => return None,
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every token is at least one byte long
+        let upper = if self.root.is_some() { self.text.len() - self.position } else { 0 };
+        (0, Some(upper))
+    }
+}
+
+impl<K, V> FusedIterator for SegmentIter<'_, K, V> {}
+
+/// Iterator over the entries of which the key is within a given edit
+/// distance of a query. Created by [`PrefixTreeMap::fuzzy_iter`].
+pub struct FuzzyIter<'a, K, V> {
+    query: Vec<u8>,
+    max_edits: usize,
+    /// Nodes still to be visited, the next one on the top, along with the
+    /// last row of the edit distance table corresponding to their key.
+    stack: Vec<(&'a Node<K, V>, Vec<usize>)>,
+}
+
+impl<K, V> Default for FuzzyIter<'_, K, V> {
+    fn default() -> Self {
+        FuzzyIter {
+            query: Vec::new(),
+            max_edits: 0,
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> Clone for FuzzyIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        FuzzyIter {
+            query: self.query.clone(),
+            max_edits: self.max_edits,
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for FuzzyIter<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<K, V> FuzzyIter<'_, K, V> {
+    /// Computes the row of the edit distance table following `row`, for a
+    /// key extended by `byte`.
+    fn next_row(&self, row: &[usize], byte: u8) -> Vec<usize> {
+        let mut next = Vec::with_capacity(row.len());
+        next.push(row[0] + 1);
+
+        for (i, &query_byte) in self.query.iter().enumerate() {
+            let substitution = row[i] + usize::from(query_byte != byte);
+            let insertion = next[i] + 1;
+            let deletion = row[i + 1] + 1;
+            next.push(substitution.min(insertion).min(deletion));
+        }
+
+        next
+    }
+}
+
+impl<'a, K, V> Iterator for FuzzyIter<'a, K, V> {
+    type Item = (&'a K, &'a V, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, row)) = self.stack.pop() {
+            // pushed in reverse, so that the smallest child is popped first
+            'children: for child in node.children.iter().rev() {
+                let mut child_row = self.next_row(&row, child.key_fragment);
+
+                for &byte in &child.tail {
+                    // distances never decrease along a path, so prune early
+                    if child_row.iter().all(|&distance| distance > self.max_edits) {
+                        continue 'children;
+                    }
+                    child_row = self.next_row(&child_row, byte);
+                }
+
+                if child_row.iter().any(|&distance| distance <= self.max_edits) {
+                    self.stack.push((child, child_row));
+                }
+            }
+
+            let distance = row[self.query.len()];
+
+            if distance <= self.max_edits {
+                if let Some((key, value)) = node.item() {
+                    return Some((key, value, distance));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let upper = self.stack.iter().map(|(node, _)| node.count).sum();
+        (0, Some(upper))
+    }
+}
+
+impl<K, V> FusedIterator for FuzzyIter<'_, K, V> {}
+
+/// Iterator over the owned entries of which the key falls within a range.
+/// Created by [`PrefixTreeMap::into_range`].
+#[derive(Clone)]
+pub struct IntoRange<K, V> {
+    iter: SubtreeIntoIter<K, V>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<K, V> Default for IntoRange<K, V> {
+    fn default() -> Self {
+        IntoRange {
+            iter: SubtreeIntoIter::default(),
+            end: Bound::Unbounded,
+        }
+    }
+}
+
+impl<K: Debug + AsRef<[u8]>, V: Debug> Debug for IntoRange<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.for_each_remaining(&mut |key, value| {
+            if is_before_end(key.as_ref(), &self.end) {
+                list.entry(&(key, value));
+            }
+        });
+        list.finish()
+    }
+}
+
+impl<K: AsRef<[u8]>, V> Iterator for IntoRange<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+
+        if is_before_end(item.0.as_ref(), &self.end) {
+            Some(item)
+        } else {
+            self.iter = SubtreeIntoIter::default();
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<K: AsRef<[u8]>, V> FusedIterator for IntoRange<K, V> {}
+
+/// Iterator over the borrowed entries of which the key falls within a range.
+/// Created by [`PrefixTreeMap::range`].
+pub struct Range<'a, K, V> {
+    iter: SubtreeIter<'a, K, V>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<K, V> Default for Range<'_, K, V> {
+    fn default() -> Self {
+        Range {
+            iter: SubtreeIter::default(),
+            end: Bound::Unbounded,
+        }
+    }
+}
+
+impl<K, V> Clone for Range<'_, K, V> {
+    fn clone(&self) -> Self {
+        Range {
+            iter: self.iter.clone(),
+            end: self.end.clone(),
+        }
+    }
+}
+
+impl<K: Debug + AsRef<[u8]>, V: Debug> Debug for Range<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, K: AsRef<[u8]>, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+
+        if is_before_end(item.0.as_ref(), &self.end) {
+            Some(item)
+        } else {
+            self.iter = SubtreeIter::default();
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<K: AsRef<[u8]>, V> FusedIterator for Range<'_, K, V> {}
+
+/// Former name of [`IntoPrefixIter`], which used to expose the internal node iterator.
+#[deprecated(since = "0.5.0", note = "use `IntoPrefixIter` instead")]
+pub type NodeIntoIter<K, V> = IntoPrefixIter<K, V>;
+
+/// Former name of [`PrefixIter`], which used to expose the internal node iterator.
+#[deprecated(since = "0.5.0", note = "use `PrefixIter` instead")]
+pub type NodeIter<'a, K, V> = PrefixIter<'a, K, V>;
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+pub mod defmt {
+    use defmt::{Format, Formatter};
+    use crate::map::PrefixTreeMap;
+
+
+    /// The maximal number of keys printed when logging a map or a set.
+    pub(crate) const MAX_SAMPLE_LEN: usize = 8;
+
+    /// Formats a summary of the map: its length and the first few keys.
+    impl<K, V> Format for PrefixTreeMap<K, V>
+    where
+        K: AsRef<[u8]>,
+    {
+        fn format(&self, fmt: Formatter<'_>) {
+            defmt::write!(fmt, "PrefixTreeMap {{ len: {=usize}, keys: ", self.len());
+            write_key_sample(fmt, self.keys().map(AsRef::as_ref), self.len());
+            defmt::write!(fmt, " }}");
+        }
+    }
+
+    pub(crate) fn write_key_sample<'a, I>(fmt: Formatter<'_>, keys: I, len: usize)
+    where
+        I: Iterator<Item = &'a [u8]>,
+    {
+        defmt::write!(fmt, "[");
+
+        for (i, key) in keys.take(MAX_SAMPLE_LEN).enumerate() {
+            if i > 0 {
+                defmt::write!(fmt, ", ");
+            }
+            defmt::write!(fmt, "{=[u8]:a}", key);
+        }
+
+        if len > MAX_SAMPLE_LEN {
+            defmt::write!(fmt, ", ...");
+        }
+
+        defmt::write!(fmt, "]");
+    }
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub mod serde {
+    use core::fmt::{self, Formatter};
+    use core::marker::PhantomData;
+    use serde::{
+        ser::{Serialize, Serializer, SerializeTupleVariant},
+        de::{self, Deserialize, Deserializer, Visitor, MapAccess, EnumAccess, VariantAccess, SeqAccess, Unexpected},
+    };
+    use crate::map::{PrefixTreeMap, Change};
+
+
+    impl<K, V> Serialize for PrefixTreeMap<K, V>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            ser.collect_map(self)
+        }
+    }
+
+    impl<'de, K, V> Deserialize<'de> for PrefixTreeMap<K, V>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+            de.deserialize_map(PrefixTreeMapVisitor(PhantomData))
+        }
+    }
+
+
+    struct PrefixTreeMapVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for PrefixTreeMapVisitor<K, V>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+    {
+        type Value = PrefixTreeMap<K, V>;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str("map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut acc: A) -> Result<Self::Value, A::Error> {
+            let mut map = PrefixTreeMap::new();
+
+            while let Some((key, value)) = acc.next_entry()? {
+                map.insert(key, value);
+            }
+
+            Ok(map)
+        }
+    }
+
+    const CHANGE_VARIANTS: &[&str] = &["Added", "Removed", "Modified"];
+
+    /// Changes are serialized as tuple variants, e.g. `{"Added":[key,value]}`
+    /// in JSON, and `Modified` changes as `[key,old,new]`.
+    impl<K, V> Serialize for Change<K, V>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Change::Added { key, value } => {
+                    let mut fields = ser.serialize_tuple_variant("Change", 0, "Added", 2)?;
+                    fields.serialize_field(key)?;
+                    fields.serialize_field(value)?;
+                    fields.end()
+                }
+                Change::Removed { key, value } => {
+                    let mut fields = ser.serialize_tuple_variant("Change", 1, "Removed", 2)?;
+                    fields.serialize_field(key)?;
+                    fields.serialize_field(value)?;
+                    fields.end()
+                }
+                Change::Modified { key, old, new } => {
+                    let mut fields = ser.serialize_tuple_variant("Change", 2, "Modified", 3)?;
+                    fields.serialize_field(key)?;
+                    fields.serialize_field(old)?;
+                    fields.serialize_field(new)?;
+                    fields.end()
+                }
+            }
+        }
+    }
+
+    impl<'de, K, V> Deserialize<'de> for Change<K, V>
+    where
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+            de.deserialize_enum("Change", CHANGE_VARIANTS, ChangeVisitor(PhantomData))
+        }
+    }
+
+    /// The variant of a [`Change`], identified by its name or index.
+    #[derive(Clone, Copy)]
+    enum ChangeKind {
+        Added,
+        Removed,
+        Modified,
+    }
+
+    impl<'de> Deserialize<'de> for ChangeKind {
+        fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+            de.deserialize_identifier(ChangeKindVisitor)
+        }
+    }
+
+    struct ChangeKindVisitor;
+
+    impl Visitor<'_> for ChangeKindVisitor {
+        type Value = ChangeKind;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("variant of Change")
+        }
+
+        fn visit_u64<E: de::Error>(self, index: u64) -> Result<Self::Value, E> {
+            match index {
+                0 => Ok(ChangeKind::Added),
+                1 => Ok(ChangeKind::Removed),
+                2 => Ok(ChangeKind::Modified),
+                _ => Err(E::invalid_value(Unexpected::Unsigned(index), &self)),
+            }
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+            match name {
+                "Added" => Ok(ChangeKind::Added),
+                "Removed" => Ok(ChangeKind::Removed),
+                "Modified" => Ok(ChangeKind::Modified),
+                _ => Err(E::unknown_variant(name, CHANGE_VARIANTS)),
+            }
+        }
+    }
+
+    struct ChangeVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K, V> Visitor<'de> for ChangeVisitor<K, V>
+    where
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = Change<K, V>;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("enum Change")
+        }
+
+        fn visit_enum<A: EnumAccess<'de>>(self, acc: A) -> Result<Self::Value, A::Error> {
+            let (kind, variant) = acc.variant()?;
+            let len = match kind {
+                ChangeKind::Added | ChangeKind::Removed => 2,
+                ChangeKind::Modified => 3,
+            };
+
+            variant.tuple_variant(len, ChangeFieldsVisitor { kind, marker: PhantomData })
+        }
+    }
+
+    struct ChangeFieldsVisitor<K, V> {
+        kind: ChangeKind,
+        marker: PhantomData<(K, V)>,
+    }
+
+    impl<'de, K, V> Visitor<'de> for ChangeFieldsVisitor<K, V>
+    where
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = Change<K, V>;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self.kind {
+                ChangeKind::Added | ChangeKind::Removed => f.write_str("key and value"),
+                ChangeKind::Modified => f.write_str("key, old and new value"),
+            }
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let key = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            let value = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+            match self.kind {
+                ChangeKind::Added => Ok(Change::Added { key, value }),
+                ChangeKind::Removed => Ok(Change::Removed { key, value }),
+                ChangeKind::Modified => {
+                    let new = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                    Ok(Change::Modified { key, old: value, new })
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+        use crate::map::{PrefixTreeMap, Change};
+
+        #[test]
+        fn serde_roundtrip() {
+            let orig = PrefixTreeMap::from([
+                ("hey".to_owned(), 123),
+                ("hay".to_owned(), 456),
+                ("how".to_owned(), 789),
+                ("hog".to_owned(), 444),
+            ]);
+            let json = serde_json::to_string_pretty(&orig).unwrap();
+            let dupe: PrefixTreeMap<String, u64> = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(orig, dupe);
+        }
+
+        #[test]
+        fn changeset_roundtrip() {
+            let old = PrefixTreeMap::from([("a".to_owned(), 1), ("ab".to_owned(), 2), ("b".to_owned(), 3)]);
+            let new = PrefixTreeMap::from([("a".to_owned(), 1), ("ab".to_owned(), 20), ("c".to_owned(), 4)]);
+
+            let changes: Vec<_> = old.diff(&new).collect();
+            let json = serde_json::to_string(&changes).unwrap();
+            assert_eq!(json, r#"[{"Modified":["ab",2,20]},{"Removed":["b",3]},{"Added":["c",4]}]"#);
+
+            let changes: Vec<Change<String, u32>> = serde_json::from_str(&json).unwrap();
+            let mut remote = old.clone();
+            remote.apply(changes);
+            assert_eq!(remote, new);
+
+            let errors = [
+                (r#"[{"Renamed":["a",1]}]"#, "unknown variant"),
+                (r#"[{"Modified":["a",1]}]"#, "invalid length 2"),
+                (r#"[{"Added":["a"]}]"#, "invalid length 1"),
+            ];
+
+            for (json, message) in errors {
+                let error = serde_json::from_str::<Vec<Change<String, u32>>>(json).unwrap_err().to_string();
+                assert!(error.contains(message), "{error}");
+            }
         }
-    }
 
-    pub fn remove_entry(self) -> Option<(K, V)> {
-        if let Entry::Occupied(entry) = self {
-            Some(entry.remove_entry())
-        } else {
-            None
+        #[test]
+        fn std_to_pfx() {
+            let std_map = BTreeMap::from([
+                ("a".to_owned(), 100),
+                ("ab".to_owned(), 110),
+                ("abc".to_owned(), 111),
+                ("d".to_owned(), 200),
+                ("de".to_owned(), 210),
+                ("def".to_owned(), 211),
+            ]);
+            let json = serde_json::to_string_pretty(&std_map).unwrap();
+            let pfx_map: PrefixTreeMap<String, i32> = serde_json::from_str(&json).unwrap();
+
+            assert!(pfx_map.iter().eq(&std_map));
         }
-    }
 
-    pub fn remove(self) -> Option<V> {
-        if let Entry::Occupied(entry) = self {
-            Some(entry.remove())
-        } else {
-            None
+        #[test]
+        fn pfx_to_std() {
+            let pfx_map = PrefixTreeMap::from([
+                ("a".to_owned(), 100),
+                ("ab".to_owned(), 110),
+                ("abc".to_owned(), 111),
+                ("d".to_owned(), 200),
+                ("de".to_owned(), 210),
+                ("def".to_owned(), 211),
+            ]);
+            let json = serde_json::to_string_pretty(&pfx_map).unwrap();
+            let std_map: BTreeMap<String, i32> = serde_json::from_str(&json).unwrap();
+
+            assert!(std_map.iter().eq(&pfx_map));
+        }
+
+        #[test]
+        fn borrowed_keys() {
+            let json = r#"{"apple": 1, "apricot": 2, "banana": 3}"#;
+            let input = json.as_bytes().as_ptr_range();
+
+            // the keys point into the input, so none of them was allocated
+            let map: PrefixTreeMap<&str, u32> = serde_json::from_str(json).unwrap();
+            assert_eq!(map.len(), 3);
+            assert!(map.keys().all(|key| input.contains(&key.as_ptr())));
+            assert_eq!(map.get("apricot"), Some(&2));
+
+            let bytes: PrefixTreeMap<&[u8], u32> = serde_json::from_str(json).unwrap();
+            assert!(bytes.keys().all(|key| input.contains(&key.as_ptr())));
+            assert_eq!(bytes.get("banana"), Some(&3));
+
+            let mut structural = Vec::new();
+            crate::map::structural::serialize(&map, &mut serde_json::Serializer::new(&mut structural)).unwrap();
+            let input = structural.as_ptr_range();
+            let copy: PrefixTreeMap<&str, u32> =
+                crate::map::structural::deserialize(&mut serde_json::Deserializer::from_slice(&structural)).unwrap();
+            assert!(copy.keys().all(|key| input.contains(&key.as_ptr())));
+
+            // keys which can't be borrowed as-is are rejected, not copied
+            assert!(serde_json::from_str::<PrefixTreeMap<&str, u32>>(r#"{"a\nb": 1}"#).is_err());
         }
     }
 }
 
-/// An entry that does not yet correspond to a value.
-#[derive(Debug)]
-pub struct VacantEntry<'a, K, V> {
-    key: K,
-    /// always starts out as `None` upon construction
-    slot: &'a mut Option<(K, V)>,
-    len: &'a mut usize,
-}
+/// Zero-copy archiving with `rkyv`. Maps are archived as an
+/// `ArchivedBTreeMap`, which supports lookups without deserialization.
+///
+/// The entries are written in the order of the tree, so the ordering of the
+/// archived keys has to agree with the lexicographic ordering of their bytes
+/// (as it does for strings, byte vectors and byte arrays).
+#[cfg(feature = "rkyv")]
+#[doc(hidden)]
+pub mod rkyv {
+    use core::ops::ControlFlow;
+    use rkyv::{
+        Archive, Serialize, Deserialize, Place,
+        collections::btree_map::{ArchivedBTreeMap, BTreeMapResolver},
+        rancor::{Fallible, Source},
+        ser::{Allocator, Writer},
+    };
+    use crate::map::PrefixTreeMap;
 
-impl<'a, K, V> VacantEntry<'a, K, V> {
-    pub fn insert(self, value: V) -> &'a mut V {
-        let (_key, value) = self.slot.insert((self.key, value));
-        *self.len += 1;
-        value
-    }
 
-    pub fn into_key(self) -> K {
-        self.key
-    }
+    impl<K, V> Archive for PrefixTreeMap<K, V>
+    where
+        K: Archive,
+        K::Archived: Ord,
+        V: Archive,
+    {
+        type Archived = ArchivedBTreeMap<K::Archived, V::Archived>;
+        type Resolver = BTreeMapResolver;
 
-    pub fn key(&self) -> &K {
-        &self.key
+        fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+            ArchivedBTreeMap::resolve_from_len(self.len(), resolver, out);
+        }
     }
-}
-
-/// An entry that already contains a value.
-#[derive(Debug)]
-pub struct OccupiedEntry<'a, K, V> {
-    /// always starts out as `Some` upon construction
-    slot: &'a mut Option<(K, V)>,
-    len: &'a mut usize,
-}
 
-impl<'a, K, V> OccupiedEntry<'a, K, V> {
-    pub fn key(&self) -> &K {
-        &self.slot.as_ref().expect("item in occupied entry").0
+    impl<K, V, S> Serialize<S> for PrefixTreeMap<K, V>
+    where
+        K: Serialize<S>,
+        K::Archived: Ord,
+        V: Serialize<S>,
+        S: Fallible + Allocator + Writer + ?Sized,
+        S::Error: Source,
+    {
+        fn serialize(&self, ser: &mut S) -> Result<Self::Resolver, S::Error> {
+            ArchivedBTreeMap::<K::Archived, V::Archived>::serialize_from_ordered_iter::<_, _, _, K, V, _>(
+                self.iter(),
+                ser,
+            )
+        }
     }
 
-    pub fn get(&self) -> &V {
-        &self.slot.as_ref().expect("item in occupied entry").1
+    impl<K, V, D> Deserialize<PrefixTreeMap<K, V>, D> for ArchivedBTreeMap<K::Archived, V::Archived>
+    where
+        K: Archive + AsRef<[u8]>,
+        K::Archived: Deserialize<K, D> + Ord,
+        V: Archive,
+        V::Archived: Deserialize<V, D>,
+        D: Fallible + ?Sized,
+    {
+        fn deserialize(&self, de: &mut D) -> Result<PrefixTreeMap<K, V>, D::Error> {
+            let mut map = PrefixTreeMap::new();
+            let error = self.visit(|key, value| {
+                let entry = key.deserialize(de).and_then(|key| Ok((key, value.deserialize(de)?)));
+
+                match entry {
+                    Ok((key, value)) => {
+                        map.insert(key, value);
+                        ControlFlow::Continue(())
+                    }
+                    Err(error) => ControlFlow::Break(error),
+                }
+            });
+
+            match error {
+                Some(error) => Err(error),
+                None => Ok(map),
+            }
+        }
     }
 
-    pub fn get_mut(&mut self) -> &mut V {
-        &mut self.slot.as_mut().expect("item in occupied entry").1
-    }
+    #[cfg(test)]
+    mod tests {
+        use rkyv::{rancor::Error, collections::btree_map::ArchivedBTreeMap, string::ArchivedString};
+        use crate::map::PrefixTreeMap;
 
-    pub fn into_mut(self) -> &'a mut V {
-        &mut self.slot.as_mut().expect("item in occupied entry").1
-    }
+        #[test]
+        fn rkyv_roundtrip() {
+            let map: PrefixTreeMap<String, u32> = (0..300_u32)
+                .map(|i| (format!("{:x}", i.wrapping_mul(2_654_435_761) >> (i % 20)), i))
+                .collect();
+            let bytes = rkyv::to_bytes::<Error>(&map).unwrap();
+            let archived = rkyv::access::<ArchivedBTreeMap<ArchivedString, rkyv::Archived<u32>>, Error>(&bytes).unwrap();
 
-    /// Replaces the inner value with `value` and returns the old value.
-    pub fn insert(&mut self, value: V) -> V {
-        mem::replace(self.get_mut(), value)
-    }
+            assert_eq!(archived.len(), map.len());
 
-    pub fn remove_entry(self) -> (K, V) {
-        *self.len -= 1;
-        self.slot.take().expect("item in occupied entry")
-    }
+            for (key, value) in &map {
+                assert_eq!(archived.get(key.as_str()).map(|value| value.to_native()), Some(*value));
+            }
 
-    pub fn remove(self) -> V {
-        self.remove_entry().1
-    }
-}
+            assert!(archived.get("not a key").is_none());
 
-/// Iterator over an owned subtree.
-#[derive(Clone, Debug)]
-pub struct NodeIntoIter<K, V> {
-    item: Option<(K, V)>,
-    children_iter: std::vec::IntoIter<Node<K, V>>,
-    curr_child_iter: Option<Box<NodeIntoIter<K, V>>>,
-}
+            let copy: PrefixTreeMap<String, u32> = rkyv::deserialize::<_, Error>(archived).unwrap();
+            assert_eq!(copy, map);
 
-impl<K, V> Default for NodeIntoIter<K, V> {
-    fn default() -> Self {
-        NodeIntoIter {
-            item: None,
-            children_iter: Vec::new().into_iter(),
-            curr_child_iter: None,
+            let empty = rkyv::to_bytes::<Error>(&PrefixTreeMap::<Vec<u8>, ()>::new()).unwrap();
+            let archived = rkyv::access::<rkyv::Archived<PrefixTreeMap<Vec<u8>, ()>>, Error>(&empty).unwrap();
+            assert!(archived.is_empty());
         }
     }
 }
 
-impl<K, V> Iterator for NodeIntoIter<K, V> {
-    type Item = (K, V);
+/// Native `bincode` 2 encoding, without going through `serde`. Maps are
+/// encoded in the same way as a `BTreeMap`: the number of entries followed
+/// by the keys and values, so the two are interchangeable on the wire.
+#[cfg(feature = "bincode")]
+#[doc(hidden)]
+pub mod bincode {
+    use core::mem;
+    use bincode::{
+        Encode, Decode, BorrowDecode,
+        enc::Encoder,
+        de::{Decoder, BorrowDecoder},
+        error::{EncodeError, DecodeError},
+    };
+    use crate::map::PrefixTreeMap;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // First, we yield our own item
-        if let Some(item) = self.item.take() {
-            return Some(item);
-        }
 
-        // Failing that (either because there was no value in the first place,
-        // or because we already emitted the item), we recurse into our current
-        // child.
-        if let Some(curr_child_next_item) = self.curr_child_iter.as_mut().and_then(Iterator::next) {
-            return Some(curr_child_next_item);
-        }
+    impl<K, V> Encode for PrefixTreeMap<K, V>
+    where
+        K: Encode,
+        V: Encode,
+    {
+        fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+            (self.len() as u64).encode(encoder)?;
 
-        // Once we exhaused the current child, move on to the next child.
-        // If there aren't more children left, terminate the iteration.
-        // Otherwise, find the next child with recurse and call next once more, to try again.
-        //
-        let next_child = self.children_iter.next()?;
-        let next_child_into_iter = next_child.into_iter();
+            for (key, value) in self {
+                key.encode(encoder)?;
+                value.encode(encoder)?;
+            }
 
-        // reuse the allocation if possible
-        if let Some(curr_child_iter) = self.curr_child_iter.as_mut() {
-            **curr_child_iter = next_child_into_iter;
-        } else {
-            self.curr_child_iter = Some(Box::new(next_child_into_iter));
+            Ok(())
         }
-
-        self.next()
     }
-}
 
-impl<K, V> FusedIterator for NodeIntoIter<K, V> {}
+    impl<K, V, C> Decode<C> for PrefixTreeMap<K, V>
+    where
+        K: Decode<C> + AsRef<[u8]>,
+        V: Decode<C>,
+    {
+        fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let len = decode_len::<(K, V), _>(decoder)?;
+            let mut map = PrefixTreeMap::new();
+
+            for _ in 0..len {
+                decoder.unclaim_bytes_read(mem::size_of::<(K, V)>());
 
-/// Iterator over a borrowed subtree.
-#[derive(Debug)]
-pub struct NodeIter<'a, K, V> {
-    item: Option<&'a (K, V)>,
-    children_iter: core::slice::Iter<'a, Node<K, V>>,
-    curr_child_iter: Option<Box<NodeIter<'a, K, V>>>,
-}
+                let key = K::decode(decoder)?;
+                let value = V::decode(decoder)?;
+                map.insert(key, value);
+            }
 
-impl<K, V> Default for NodeIter<'_, K, V> {
-    fn default() -> Self {
-        NodeIter {
-            item: None,
-            children_iter: [].iter(),
-            curr_child_iter: None,
+            Ok(map)
         }
     }
-}
 
-impl<K, V> Clone for NodeIter<'_, K, V> {
-    fn clone(&self) -> Self {
-        NodeIter {
-            item: self.item,
-            children_iter: self.children_iter.clone(),
-            curr_child_iter: self.curr_child_iter.clone(),
-        }
-    }
-}
+    impl<'de, K, V, C> BorrowDecode<'de, C> for PrefixTreeMap<K, V>
+    where
+        K: BorrowDecode<'de, C> + AsRef<[u8]>,
+        V: BorrowDecode<'de, C>,
+    {
+        fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let len = decode_len::<(K, V), _>(decoder)?;
+            let mut map = PrefixTreeMap::new();
 
-impl<'a, K, V> Iterator for NodeIter<'a, K, V> {
-    type Item = (&'a K, &'a V);
+            for _ in 0..len {
+                decoder.unclaim_bytes_read(mem::size_of::<(K, V)>());
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // First, we yield our own item
-        if let Some((key, value)) = self.item.take() {
-            return Some((key, value));
-        }
+                let key = K::borrow_decode(decoder)?;
+                let value = V::borrow_decode(decoder)?;
+                map.insert(key, value);
+            }
 
-        // Failing that (either because there was no value in the first place,
-        // or because we already emitted the item), we recurse into our current
-        // child.
-        if let Some(curr_child_next_item) = self.curr_child_iter.as_mut().and_then(Iterator::next) {
-            return Some(curr_child_next_item);
+            Ok(map)
         }
+    }
 
-        // Once we exhaused the current child, move on to the next child.
-        // If there aren't more children left, terminate the iteration.
-        // Otherwise, find the next child with recurse and call next once more, to try again.
-        //
-        let next_child = self.children_iter.next()?;
-        let next_child_iter = next_child.iter();
+    /// Decodes the number of elements of a container, and claims the memory
+    /// of that many elements of type `T` against the limit of the decoder.
+    /// Each element then gives back its share before it's decoded, so that
+    /// only its own contents count towards the limit.
+    pub(crate) fn decode_len<T, D: Decoder>(decoder: &mut D) -> Result<usize, DecodeError> {
+        let len = u64::decode(decoder)?;
+        let len = usize::try_from(len).map_err(|_| DecodeError::OutsideUsizeRange(len))?;
 
-        // reuse the allocation if possible
-        if let Some(curr_child_iter) = self.curr_child_iter.as_mut() {
-            **curr_child_iter = next_child_iter;
-        } else {
-            self.curr_child_iter = Some(Box::new(next_child_iter));
-        }
+        decoder.claim_container_read::<T>(len)?;
 
-        self.next()
+        Ok(len)
     }
-}
-
-impl<K, V> FusedIterator for NodeIter<'_, K, V> {}
 
-/// Iterator over all the values of the tree.
-#[derive(Clone, Debug)]
-pub struct IntoIter<K, V> {
-    iter: NodeIntoIter<K, V>,
-    len: usize,
-}
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+        use bincode::config;
+        use crate::map::PrefixTreeMap;
 
-impl<K, V> Default for IntoIter<K, V> {
-    fn default() -> Self {
-        IntoIter {
-            iter: NodeIntoIter::default(),
-            len: 0,
+        #[test]
+        fn bincode_roundtrip() {
+            let map: PrefixTreeMap<String, u32> = (0..300_u32)
+                .map(|i| (format!("{:x}", i.wrapping_mul(2_654_435_761) >> (i % 20)), i))
+                .collect();
+            let bytes = bincode::encode_to_vec(&map, config::standard()).unwrap();
+
+            let (copy, len): (PrefixTreeMap<String, u32>, _) = bincode::decode_from_slice(&bytes, config::standard()).unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(copy, map);
+
+            // the encoding is the same as that of a `BTreeMap`
+            let btree: BTreeMap<String, u32> = map.iter().map(|(key, &value)| (key.clone(), value)).collect();
+            assert_eq!(bincode::encode_to_vec(&btree, config::standard()).unwrap(), bytes);
+
+            // keys borrowed from the input
+            let (borrowed, _): (PrefixTreeMap<&str, u32>, _) = bincode::borrow_decode_from_slice(&bytes, config::standard()).unwrap();
+            assert!(borrowed.iter().map(|(&key, &value)| (key, value)).eq(map.iter().map(|(key, &value)| (key.as_str(), value))));
+
+            // truncated input and exceeded limits are errors, not panics
+            let result: Result<(PrefixTreeMap<String, u32>, _), _> = bincode::decode_from_slice(&bytes[..bytes.len() - 1], config::standard());
+            assert!(result.is_err());
+
+            let result: Result<(PrefixTreeMap<String, u32>, _), _> = bincode::decode_from_slice(&bytes, config::standard().with_limit::<64>());
+            assert!(result.is_err());
         }
     }
 }
 
-impl<K, V> Iterator for IntoIter<K, V> {
-    type Item = (K, V);
+/// A serialized format which preserves the structure of the tree, so that
+/// loading it is a streaming reconstruction of the nodes, without searching
+/// for the place of each key.
+///
+/// The map is serialized as a tuple of the format version (currently 1), the
+/// number of entries, and the sequence of nodes in pre-order. Each node is a
+/// tuple of the first byte of its label, the rest of its label (as bytes),
+/// its entry (if any), and the number of its children. Deserialization checks
+/// that the nodes form a valid tree, and that each key matches its position.
+///
+/// Use the functions of this module with `#[serde(with = "pfx::map::structural")]`,
+/// or call them directly:
+///
+/// ```
+/// # use pfx::PrefixTreeMap;
+/// let map = PrefixTreeMap::from([("tea", 1), ("ten", 2), ("to", 3)]);
+///
+/// let mut json = Vec::new();
+/// pfx::map::structural::serialize(&map, &mut serde_json::Serializer::new(&mut json))?;
+///
+/// let copy: PrefixTreeMap<String, u32> =
+///     pfx::map::structural::deserialize(&mut serde_json::Deserializer::from_slice(&json))?;
+///
+/// assert!(copy.iter().map(|(key, &value)| (key.as_str(), value)).eq(map.iter().map(|(&key, &value)| (key, value))));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[cfg(feature = "serde")]
+pub mod structural {
+    use core::fmt::{self, Formatter};
+    use core::marker::PhantomData;
+    use alloc::vec::Vec;
+    use serde::{
+        ser::{Serialize, Serializer, SerializeSeq, SerializeTuple},
+        de::{self, Deserialize, DeserializeSeed, Deserializer, Visitor, SeqAccess, IgnoredAny},
+    };
+    use crate::map::{PrefixTreeMap, Node, Children};
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let item = self.iter.next()?;
-        self.len -= 1;
-        Some(item)
+
+    /// The version of the format written by [`serialize()`].
+    pub const VERSION: u32 = 1;
+
+    /// Serializes the map along with its structure.
+    pub fn serialize<K, V, S>(map: &PrefixTreeMap<K, V>, ser: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        let mut tuple = ser.serialize_tuple(3)?;
+        tuple.serialize_element(&VERSION)?;
+        tuple.serialize_element(&(map.len() as u64))?;
+        tuple.serialize_element(&Nodes(&map.root))?;
+        tuple.end()
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len, Some(self.len))
+    /// Deserializes a map serialized by [`serialize()`]. The map has the
+    /// default configuration, the same as when deserialized via `Deserialize`.
+    pub fn deserialize<'de, K, V, D>(de: D) -> Result<PrefixTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        de.deserialize_tuple(3, MapVisitor(PhantomData))
     }
-}
 
-impl<K, V> FusedIterator for IntoIter<K, V> {}
+    /// The nodes of the subtree, in pre-order.
+    struct Nodes<'a, K, V>(&'a Node<K, V>);
 
-impl<K, V> ExactSizeIterator for IntoIter<K, V> {
-    fn len(&self) -> usize {
-        self.len
-    }
-}
+    impl<K: Serialize, V: Serialize> Serialize for Nodes<'_, K, V> {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            let mut stack = Vec::from([self.0]);
+            let mut count = 0;
 
-/// Iterator over references to the values of the tree.
-#[derive(Debug)]
-pub struct Iter<'a, K, V> {
-    iter: NodeIter<'a, K, V>,
-    len: usize,
-}
+            while let Some(node) = stack.pop() {
+                count += 1;
+                stack.extend(node.children.iter());
+            }
 
-impl<K, V> Default for Iter<'_, K, V> {
-    fn default() -> Self {
-        Iter {
-            iter: NodeIter::default(),
-            len: 0,
+            let mut seq = ser.serialize_seq(Some(count))?;
+            stack.push(self.0);
+
+            while let Some(node) = stack.pop() {
+                seq.serialize_element(&(
+                    node.key_fragment,
+                    Bytes(&node.tail),
+                    node.item(),
+                    node.children.len() as u64,
+                ))?;
+                stack.extend(node.children.iter().rev());
+            }
+
+            seq.end()
         }
     }
-}
 
-impl<K, V> Clone for Iter<'_, K, V> {
-    fn clone(&self) -> Self {
-        Iter {
-            iter: self.iter.clone(),
-            len: self.len,
+    struct Bytes<'a>(&'a [u8]);
+
+    impl Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+            ser.serialize_bytes(self.0)
         }
     }
-}
 
-impl<'a, K, V> Iterator for Iter<'a, K, V> {
-    type Item = (&'a K, &'a V);
+    /// Accepts byte strings in any form the deserializer may produce them.
+    struct ByteBuf(Vec<u8>);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let item = self.iter.next()?;
-        self.len -= 1;
-        Some(item)
+    impl<'de> Deserialize<'de> for ByteBuf {
+        fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+            de.deserialize_byte_buf(ByteBufVisitor)
+        }
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len, Some(self.len))
-    }
-}
+    struct ByteBufVisitor;
 
-impl<K, V> FusedIterator for Iter<'_, K, V> {}
+    impl<'de> Visitor<'de> for ByteBufVisitor {
+        type Value = ByteBuf;
 
-impl<K, V> ExactSizeIterator for Iter<'_, K, V> {
-    fn len(&self) -> usize {
-        self.len
-    }
-}
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("byte string")
+        }
 
-/// Iterator over the owned keys.
-#[derive(Clone, Debug)]
-pub struct IntoKeys<K, V> {
-    iter: IntoIter<K, V>,
-}
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+            Ok(ByteBuf(bytes.to_vec()))
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(ByteBuf(bytes))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut acc: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(acc.size_hint().unwrap_or(0));
+
+            while let Some(byte) = acc.next_element()? {
+                bytes.push(byte);
+            }
 
-impl<K, V> Default for IntoKeys<K, V> {
-    fn default() -> Self {
-        IntoKeys {
-            iter: IntoIter::default(),
+            Ok(ByteBuf(bytes))
         }
     }
-}
 
-impl<K, V> Iterator for IntoKeys<K, V> {
-    type Item = K;
+    struct MapVisitor<K, V>(PhantomData<(K, V)>);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(k, _v)| k)
-    }
+    impl<'de, K, V> Visitor<'de> for MapVisitor<K, V>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+    {
+        type Value = PrefixTreeMap<K, V>;
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
-}
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("structurally serialized prefix tree map")
+        }
 
-impl<K, V> FusedIterator for IntoKeys<K, V> {}
+        fn visit_seq<A: SeqAccess<'de>>(self, mut acc: A) -> Result<Self::Value, A::Error> {
+            let version: u32 = acc.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
 
-impl<K, V> ExactSizeIterator for IntoKeys<K, V> {
-    fn len(&self) -> usize {
-        self.iter.len()
-    }
-}
+            if version != VERSION {
+                return Err(de::Error::custom(format_args!("unsupported format version {version}")));
+            }
 
-/// Iterator over the borrowed keys.
-#[derive(Debug)]
-pub struct Keys<'a, K, V> {
-    iter: Iter<'a, K, V>,
-}
+            let len: u64 = acc.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+            let root = acc
+                .next_element_seed(NodesSeed(PhantomData))?
+                .ok_or_else(|| de::Error::invalid_length(2, &self))?;
 
-impl<K, V> Default for Keys<'_, K, V> {
-    fn default() -> Self {
-        Keys {
-            iter: Iter::default(),
+            if root.count as u64 != len {
+                return Err(de::Error::custom(format_args!("expected {len} entries, found {}", root.count)));
+            }
+
+            let mut map = PrefixTreeMap::new();
+            map.len = root.count;
+            map.root = root;
+
+            Ok(map)
         }
     }
-}
 
-impl<K, V> Clone for Keys<'_, K, V> {
-    fn clone(&self) -> Self {
-        Keys { iter: self.iter.clone() }
-    }
-}
+    /// Deserializes the sequence of nodes into the root node.
+    struct NodesSeed<K, V>(PhantomData<(K, V)>);
 
-impl<'a, K, V> Iterator for Keys<'a, K, V> {
-    type Item = &'a K;
+    impl<'de, K, V> DeserializeSeed<'de> for NodesSeed<K, V>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+    {
+        type Value = Node<K, V>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(k, _v)| k)
+        fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+            de.deserialize_seq(self)
+        }
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+    /// A node of which not all children have been deserialized yet.
+    struct Frame<K, V> {
+        node: Node<K, V>,
+        children: Vec<Node<K, V>>,
+        /// The number of children still to come.
+        remaining: u64,
+        /// The length of the key of the node.
+        key_len: usize,
     }
-}
 
-impl<K, V> FusedIterator for Keys<'_, K, V> {}
+    impl<'de, K, V> Visitor<'de> for NodesSeed<K, V>
+    where
+        K: Deserialize<'de> + AsRef<[u8]>,
+        V: Deserialize<'de>,
+    {
+        type Value = Node<K, V>;
 
-impl<K, V> ExactSizeIterator for Keys<'_, K, V> {
-    fn len(&self) -> usize {
-        self.iter.len()
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("sequence of prefix tree nodes")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut acc: A) -> Result<Self::Value, A::Error> {
+            type Record<K, V> = (u8, ByteBuf, Option<(K, V)>, u64);
+
+            let mut key = Vec::new();
+            let mut stack: Vec<Frame<K, V>> = Vec::new();
+
+            loop {
+                // the children of the node on the top are complete, so move it to its parent
+                if let Some(frame) = stack.last() {
+                    if frame.remaining == 0 {
+                        let Frame { mut node, children, key_len, .. } = stack.pop().expect("frame on stack");
+
+                        node.count = usize::from(node.item.is_some())
+                            + children.iter().map(|child| child.count).sum::<usize>();
+                        node.children = Children::from_vec(children);
+                        node.recompute_key_len(key_len);
+
+                        let Some(parent) = stack.last_mut() else {
+                            if acc.next_element::<IgnoredAny>()?.is_some() {
+                                return Err(de::Error::custom("nodes after the end of the tree"));
+                            }
+
+                            return Ok(node);
+                        };
+
+                        if parent.children.last().is_some_and(|last| last.key_fragment >= node.key_fragment) {
+                            return Err(de::Error::custom("children out of order"));
+                        }
+
+                        if node.item.is_none() && node.children.len() < 2 {
+                            return Err(de::Error::custom("node without item has fewer than 2 children"));
+                        }
+
+                        parent.children.push(node);
+                        continue;
+                    }
+                }
+
+                let Some((key_fragment, ByteBuf(tail), item, remaining)) = acc.next_element::<Record<K, V>>()? else {
+                    return Err(de::Error::custom("unexpected end of nodes"));
+                };
+
+                match stack.last_mut() {
+                    Some(parent) => {
+                        parent.remaining -= 1;
+                        key.truncate(parent.key_len);
+                        key.push(key_fragment);
+                        key.extend_from_slice(&tail);
+                    }
+                    None if tail.is_empty() => {}
+                    None => return Err(de::Error::custom("root node with a label")),
+                }
+
+                if remaining > 256 {
+                    return Err(de::Error::custom("node with more than 256 children"));
+                }
+
+                if item.as_ref().is_some_and(|(item_key, _)| item_key.as_ref() != key) {
+                    return Err(de::Error::custom("key doesn't match its position in the tree"));
+                }
+
+                let mut node = Node::with_label(key_fragment, tail);
+                node.item = item;
+
+                stack.push(Frame {
+                    node,
+                    children: Vec::new(),
+                    remaining,
+                    key_len: key.len(),
+                });
+            }
+        }
     }
-}
 
-/// Iterator over the owned values.
-#[derive(Clone, Debug)]
-pub struct IntoValues<K, V> {
-    iter: IntoIter<K, V>,
-}
+    #[cfg(test)]
+    mod tests {
+        use crate::map::PrefixTreeMap;
 
-impl<K, V> Default for IntoValues<K, V> {
-    fn default() -> Self {
-        IntoValues {
-            iter: IntoIter::default(),
+        fn roundtrip(map: &PrefixTreeMap<String, u32>) -> PrefixTreeMap<String, u32> {
+            let mut json = Vec::new();
+            super::serialize(map, &mut serde_json::Serializer::new(&mut json)).unwrap();
+            super::deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap()
         }
-    }
-}
 
-impl<K, V> Iterator for IntoValues<K, V> {
-    type Item = V;
+        fn load(json: &str) -> Result<PrefixTreeMap<String, u32>, serde_json::Error> {
+            super::deserialize(&mut serde_json::Deserializer::from_str(json))
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(_k, v)| v)
-    }
+        #[test]
+        fn structural_roundtrip() {
+            let mut map: PrefixTreeMap<String, u32> = (0..1000_u32)
+                .map(|i| (format!("{:x}", i.wrapping_mul(2_654_435_761) >> (i % 20)), i))
+                .collect();
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
-}
+            map.insert(String::new(), 0);
 
-impl<K, V> FusedIterator for IntoValues<K, V> {}
+            let copy = roundtrip(&map);
 
-impl<K, V> ExactSizeIterator for IntoValues<K, V> {
-    fn len(&self) -> usize {
-        self.iter.len()
-    }
-}
+            assert_eq!(copy, map);
+            assert_eq!(copy.len(), map.len());
+            assert_eq!(copy.count_prefix("a"), map.count_prefix("a"));
+            assert_eq!(copy.get_index(500), map.get_index(500));
+            assert!(copy.keys().all(|key| copy.contains_key(key)));
 
-/// Iterator over the borrowed values.
-#[derive(Debug)]
-pub struct Values<'a, K, V> {
-    iter: Iter<'a, K, V>,
-}
+            assert_eq!(roundtrip(&PrefixTreeMap::new()), PrefixTreeMap::new());
+        }
 
-impl<K, V> Default for Values<'_, K, V> {
-    fn default() -> Self {
-        Values {
-            iter: Iter::default(),
+        #[test]
+        fn structural_errors() {
+            let map = PrefixTreeMap::from([("ab".to_owned(), 1), ("ac".to_owned(), 2)]);
+            let mut json = Vec::new();
+            super::serialize(&map, &mut serde_json::Serializer::new(&mut json)).unwrap();
+            let json = String::from_utf8(json).unwrap();
+
+            assert_eq!(json, r#"[1,2,[[0,[],null,1],[97,[],null,2],[98,[],["ab",1],0],[99,[],["ac",2],0]]]"#);
+            assert!(load(&json).is_ok());
+
+            let errors = [
+                (r#"[2,2,[[0,[],null,1],[97,[],null,2],[98,[],["ab",1],0],[99,[],["ac",2],0]]]"#, "unsupported format version"),
+                (r#"[1,3,[[0,[],null,1],[97,[],null,2],[98,[],["ab",1],0],[99,[],["ac",2],0]]]"#, "expected 3 entries"),
+                (r#"[1,2,[[0,[],null,1],[97,[],null,2],[99,[],["ab",1],0],[98,[],["ac",2],0]]]"#, "doesn't match"),
+                (r#"[1,2,[[0,[],null,1],[97,[],null,2],[99,[],["ac",1],0],[98,[],["ab",2],0]]]"#, "out of order"),
+                (r#"[1,2,[[0,[],null,1],[97,[],null,2],[98,[],["ab",1],0]]]"#, "unexpected end"),
+                (r#"[1,2,[[0,[],null,1],[97,[],null,2],[98,[],["ab",1],0],[99,[],["ac",2],0],[0,[],null,0]]]"#, "after the end"),
+                (r#"[1,1,[[0,[],null,1],[97,[],null,1],[98,[],["ab",1],0]]]"#, "fewer than 2 children"),
+                (r#"[1,0,[[0,[120],null,0]]]"#, "root node with a label"),
+            ];
+
+            for (json, message) in errors {
+                let error = load(json).unwrap_err().to_string();
+                assert!(error.contains(message), "{error}");
+            }
         }
     }
 }
 
-impl<K, V> Clone for Values<'_, K, V> {
-    fn clone(&self) -> Self {
-        Values { iter: self.iter.clone() }
+/// Iteration over the keys accepted by a deterministic finite automaton,
+/// such as a regular expression compiled into a DFA by `regex-automata`.
+///
+/// The automaton is run along the edges of the tree, so the state reached
+/// at each node is shared by all keys below it, and subtrees are skipped
+/// as soon as the automaton can no longer match.
+///
+/// ```
+/// # use pfx::PrefixTreeSet;
+/// use regex_automata::dfa::dense::DFA;
+///
+/// let set = PrefixTreeSet::from(["cat", "cart", "coat", "dog", "cattle"]);
+/// let dfa = DFA::new("c[a-z]?a[a-z]*t")?;
+/// let words: Vec<&str> = set.automaton_iter(&dfa)?.copied().collect();
+///
+/// assert_eq!(words, ["cart", "cat", "coat"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg(feature = "regex-automata")]
+pub mod automaton {
+    use core::fmt::{self, Debug, Formatter};
+    use core::iter::FusedIterator;
+    use alloc::vec::Vec;
+    use regex_automata::{
+        Anchored,
+        dfa::{Automaton, StartError},
+        util::{primitives::StateID, start},
+    };
+    use crate::map::{PrefixTreeMap, Node};
+
+
+    impl<K, V> PrefixTreeMap<K, V> {
+        /// An iterator over the entries of which the whole key is matched by
+        /// `dfa`, in lexicographic order of the keys.
+        ///
+        /// The search is anchored at the beginning of each key, and a key is
+        /// accepted if a match ends at its end. Pass the DFA by reference in
+        /// order to reuse it. Subtrees in which the DFA enters a quit state
+        /// are skipped. Fails if the DFA doesn't support anchored searches.
+        pub fn automaton_iter<A: Automaton>(&self, dfa: A) -> Result<AutomatonIter<'_, K, V, A>, StartError> {
+            let config = start::Config::new().anchored(Anchored::Yes);
+            let state = dfa.start_state(&config)?;
+
+            Ok(AutomatonIter {
+                dfa,
+                stack: Vec::from([(&self.root, state)]),
+            })
+        }
     }
-}
 
-impl<'a, K, V> Iterator for Values<'a, K, V> {
-    type Item = &'a V;
+    /// Iterator over the entries of which the key is accepted by an automaton.
+    /// Created by [`PrefixTreeMap::automaton_iter`].
+    pub struct AutomatonIter<'a, K, V, A> {
+        dfa: A,
+        /// Nodes still to be visited, the next one on the top, along with
+        /// the state of the automaton after reading their key.
+        stack: Vec<(&'a Node<K, V>, StateID)>,
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next().map(|(_k, v)| v)
+    impl<K, V, A: Clone> Clone for AutomatonIter<'_, K, V, A> {
+        fn clone(&self) -> Self {
+            AutomatonIter {
+                dfa: self.dfa.clone(),
+                stack: self.stack.clone(),
+            }
+        }
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+    impl<K: Debug, V: Debug, A: Automaton + Clone> Debug for AutomatonIter<'_, K, V, A> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries(self.clone()).finish()
+        }
     }
-}
 
-impl<K, V> FusedIterator for Values<'_, K, V> {}
+    impl<K, V, A: Automaton> AutomatonIter<'_, K, V, A> {
+        /// Feeds the label of the edge leading to `node` into the automaton.
+        /// Returns `None` if no key under `node` can be accepted.
+        fn follow_edge(&self, state: StateID, node: &Node<K, V>) -> Option<StateID> {
+            let mut state = self.dfa.next_state(state, node.key_fragment);
+
+            for &byte in &node.tail {
+                if self.dfa.is_dead_state(state) || self.dfa.is_quit_state(state) {
+                    return None;
+                }
+                state = self.dfa.next_state(state, byte);
+            }
 
-impl<K, V> ExactSizeIterator for Values<'_, K, V> {
-    fn len(&self) -> usize {
-        self.iter.len()
+            let is_alive = !self.dfa.is_dead_state(state) && !self.dfa.is_quit_state(state);
+            is_alive.then_some(state)
+        }
     }
-}
-
-#[cfg(feature = "serde")]
-#[doc(hidden)]
-pub mod serde {
-    use core::marker::PhantomData;
-    use serde::{
-        ser::{Serialize, Serializer},
-        de::{Deserialize, Deserializer, Visitor, MapAccess},
-    };
-    use crate::map::PrefixTreeMap;
 
+    impl<'a, K, V, A: Automaton> Iterator for AutomatonIter<'a, K, V, A> {
+        type Item = (&'a K, &'a V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while let Some((node, state)) = self.stack.pop() {
+                // pushed in reverse, so that the smallest child is popped first
+                for child in node.children.iter().rev() {
+                    if let Some(child_state) = self.follow_edge(state, child) {
+                        self.stack.push((child, child_state));
+                    }
+                }
+
+                // matches are reported with a delay of one byte,
+                // so the end of the key has to be fed in explicitly
+                if self.dfa.is_match_state(self.dfa.next_eoi_state(state)) {
+                    if let Some(item) = node.item() {
+                        return Some(item);
+                    }
+                }
+            }
 
-    impl<K, V> Serialize for PrefixTreeMap<K, V>
-    where
-        K: Serialize,
-        V: Serialize,
-    {
-        fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
-            ser.collect_map(self)
+            None
         }
-    }
 
-    impl<'de, K, V> Deserialize<'de> for PrefixTreeMap<K, V>
-    where
-        K: Deserialize<'de> + AsRef<[u8]>,
-        V: Deserialize<'de>,
-    {
-        fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
-            de.deserialize_map(PrefixTreeMapVisitor(PhantomData))
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let upper = self.stack.iter().map(|(node, _)| node.count).sum();
+            (0, Some(upper))
         }
     }
 
+    impl<K, V, A: Automaton> FusedIterator for AutomatonIter<'_, K, V, A> {}
 
-    struct PrefixTreeMapVisitor<K, V>(PhantomData<(K, V)>);
+    #[cfg(test)]
+    mod tests {
+        use regex_automata::dfa::{dense, sparse};
+        use crate::map::PrefixTreeMap;
 
-    impl<'de, K, V> Visitor<'de> for PrefixTreeMapVisitor<K, V>
-    where
-        K: Deserialize<'de> + AsRef<[u8]>,
-        V: Deserialize<'de>,
-    {
-        type Value = PrefixTreeMap<K, V>;
+        #[test]
+        fn regex_search() {
+            let map = PrefixTreeMap::from([
+                ("", 0),
+                ("foo", 1),
+                ("foobar", 2),
+                ("fob", 3),
+                ("bar", 4),
+                ("f00", 5),
+                ("food", 6),
+            ]);
 
-        fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-            f.write_str("map")
+            let dfa = dense::DFA::new("fo+(bar)?").unwrap();
+            let keys: Vec<_> = map.automaton_iter(&dfa).unwrap().map(|(key, _)| *key).collect();
+            assert_eq!(keys, ["foo", "foobar"]);
+
+            let dfa = sparse::DFA::new("f.*|").unwrap();
+            let values: Vec<_> = map.automaton_iter(&dfa).unwrap().map(|(_, &value)| value).collect();
+            assert_eq!(values, [0, 5, 3, 1, 2, 6]);
+
+            let dfa = dense::DFA::new("x").unwrap();
+            let mut iter = map.automaton_iter(&dfa).unwrap();
+            assert_eq!(format!("{iter:?}"), "[]");
+            assert_eq!(iter.next(), None);
+
+            // anchored searches are not supported by this DFA
+            let dfa = dense::Builder::new()
+                .configure(dense::Config::new().start_kind(regex_automata::dfa::StartKind::Unanchored))
+                .build("foo")
+                .unwrap();
+            assert!(map.automaton_iter(&dfa).is_err());
         }
+    }
+}
 
-        fn visit_map<A: MapAccess<'de>>(self, mut acc: A) -> Result<Self::Value, A::Error> {
-            let mut map = PrefixTreeMap::new();
+/// Uniform random sampling of entries.
+#[cfg(feature = "rand")]
+mod sample {
+    use rand::Rng;
+    use crate::map::PrefixTreeMap;
 
-            while let Some((key, value)) = acc.next_entry()? {
-                map.insert(key, value);
+
+    impl<K, V> PrefixTreeMap<K, V> {
+        /// Returns an entry chosen uniformly at random, or `None` if the map
+        /// is empty.
+        ///
+        /// The entry is found by its index, skipping subtrees by their item
+        /// counts, so this takes time proportional to the length of its key
+        /// (and the number of children of the nodes along its path).
+        ///
+        /// ```
+        /// # use pfx::PrefixTreeMap;
+        /// use rand::{SeedableRng, rngs::SmallRng};
+        ///
+        /// let mut rng = SmallRng::seed_from_u64(42);
+        /// let map = PrefixTreeMap::from([("GET /", 1), ("GET /index", 2), ("POST /login", 3)]);
+        /// let (key, _) = map.sample(&mut rng).unwrap();
+        ///
+        /// assert!(map.contains_key(key));
+        /// ```
+        pub fn sample<R>(&self, rng: &mut R) -> Option<(&K, &V)>
+        where
+            R: ?Sized + Rng,
+        {
+            if self.len == 0 {
+                return None;
             }
 
-            Ok(map)
+            self.get_index(rng.random_range(0..self.len))
+        }
+
+        /// Returns an entry chosen uniformly at random among those of which
+        /// the key starts with `prefix`, or `None` if there are none.
+        ///
+        /// ```
+        /// # use pfx::PrefixTreeMap;
+        /// # use rand::{SeedableRng, rngs::SmallRng};
+        /// let mut rng = SmallRng::seed_from_u64(42);
+        /// let map = PrefixTreeMap::from([("GET /", 1), ("GET /index", 2), ("POST /login", 3)]);
+        /// let (key, _) = map.sample_with_prefix("GET", &mut rng).unwrap();
+        ///
+        /// assert!(key.starts_with("GET"));
+        /// assert_eq!(map.sample_with_prefix("PUT", &mut rng), None);
+        /// ```
+        pub fn sample_with_prefix<Q, R>(&self, prefix: &Q, rng: &mut R) -> Option<(&K, &V)>
+        where
+            Q: ?Sized + AsRef<[u8]>,
+            R: ?Sized + Rng,
+        {
+            let node = self.root.search_prefix(prefix.as_ref()).filter(|node| node.count > 0)?;
+            node.nth(rng.random_range(0..node.count))?.item()
         }
     }
 
     #[cfg(test)]
     mod tests {
-        use std::collections::BTreeMap;
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
         use crate::map::PrefixTreeMap;
 
         #[test]
-        fn serde_roundtrip() {
-            let orig = PrefixTreeMap::from([
-                ("hey".to_owned(), 123),
-                ("hay".to_owned(), 456),
-                ("how".to_owned(), 789),
-                ("hog".to_owned(), 444),
-            ]);
-            let json = serde_json::to_string_pretty(&orig).unwrap();
-            let dupe: PrefixTreeMap<String, u64> = serde_json::from_str(&json).unwrap();
+        fn sample_is_uniform() {
+            let mut rng = SmallRng::seed_from_u64(0x5eed);
+            let mut map = PrefixTreeMap::from([("a", 0), ("ab", 0), ("abc", 0), ("abd", 0), ("b", 0), ("bcd", 0)]);
+            let keys: Vec<_> = map.keys().copied().collect();
+            let mut counts = [0_u32; 6];
+
+            for _ in 0..6000 {
+                let (key, _) = map.sample(&mut rng).unwrap();
+                counts[keys.iter().position(|k| k == key).unwrap()] += 1;
+            }
+            assert!(counts.iter().all(|&count| (800..1200).contains(&count)), "{counts:?}");
 
-            assert_eq!(orig, dupe);
+            let mut counts = [0_u32; 6];
+
+            for _ in 0..3000 {
+                let (key, _) = map.sample_with_prefix("ab", &mut rng).unwrap();
+                counts[keys.iter().position(|k| k == key).unwrap()] += 1;
+            }
+            assert_eq!(counts[0] + counts[4] + counts[5], 0);
+            assert!(counts[1..4].iter().all(|&count| (800..1200).contains(&count)), "{counts:?}");
+
+            // a prefix ending inside an edge
+            assert_eq!(map.sample_with_prefix("bc", &mut rng), Some((&"bcd", &0)));
+            assert_eq!(map.sample_with_prefix("abx", &mut rng), None);
+
+            // nodes left without an item by removals are skipped
+            map.remove("bcd");
+            map.remove("b");
+            assert_eq!(map.sample_with_prefix("b", &mut rng), None);
+
+            map.clear();
+            assert_eq!(map.sample(&mut rng), None);
         }
+    }
+}
 
-        #[test]
-        fn std_to_pfx() {
-            let std_map = BTreeMap::from([
-                ("a".to_owned(), 100),
-                ("ab".to_owned(), 110),
-                ("abc".to_owned(), 111),
-                ("d".to_owned(), 200),
-                ("de".to_owned(), 210),
-                ("def".to_owned(), 211),
-            ]);
-            let json = serde_json::to_string_pretty(&std_map).unwrap();
-            let pfx_map: PrefixTreeMap<String, i32> = serde_json::from_str(&json).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_keeps_capacity() {
+        let mut map: PrefixTreeMap<Vec<u8>, u8> = (0..=255_u8).map(|b| (vec![b, b], b)).collect();
+        let capacity = map.root.children.nodes.capacity();
+        assert!(capacity >= 256);
+
+        let mut drain = map.drain();
+        assert_eq!(drain.next(), Some((vec![0, 0], 0)));
+        assert_eq!(drain.next_back(), Some((vec![255, 255], 255)));
+        assert_eq!(drain.len(), 254);
+        drop(drain);
+
+        assert!(map.is_empty());
+        assert!(map.root.children.is_empty());
+        assert_eq!(map.root.children.nodes.capacity(), capacity);
+
+        // the index grows back through the size classes
+        map.extend((0..=255_u8).rev().map(|b| (vec![b], b)));
+        assert_eq!(map.root.children.nodes.capacity(), capacity);
+        assert_eq!(map.get(&[0x42]), Some(&0x42));
+        assert_eq!(map.check_invariants(), Ok(()));
+    }
 
-            assert!(pfx_map.iter().eq(&std_map));
+    #[test]
+    fn child_index_size_classes() {
+        fn check(children: &Children<(), ()>) {
+            let class = match children.index {
+                ChildIndex::Scan => 4,
+                ChildIndex::Bytes(_) => 16,
+                ChildIndex::Positions(_) => 48,
+                ChildIndex::Bitmap(_) => 256,
+            };
+            assert_eq!(class, [4, 16, 48, 256].into_iter().find(|&n| children.len() <= n).unwrap());
+
+            for byte in 0..=255 {
+                let expected = children.binary_search_by_key(&byte, |node| node.key_fragment);
+                assert_eq!(children.search(byte), expected, "byte {byte} of {} children", children.len());
+                assert_eq!(children.find(byte), expected.ok(), "byte {byte} of {} children", children.len());
+            }
         }
 
-        #[test]
-        fn pfx_to_std() {
-            let pfx_map = PrefixTreeMap::from([
-                ("a".to_owned(), 100),
-                ("ab".to_owned(), 110),
-                ("abc".to_owned(), 111),
-                ("d".to_owned(), 200),
-                ("de".to_owned(), 210),
-                ("def".to_owned(), 211),
-            ]);
-            let json = serde_json::to_string_pretty(&pfx_map).unwrap();
-            let std_map: BTreeMap<String, i32> = serde_json::from_str(&json).unwrap();
+        // every byte exactly once, in scrambled order
+        let bytes: Vec<u8> = (0..=255_u8).map(|b| b.wrapping_mul(167).wrapping_add(13)).collect();
+        let mut children = Children::new();
+        check(&children);
+
+        for &byte in &bytes {
+            let index = children.search(byte).unwrap_err();
+            let mut node = Node::default();
+            node.key_fragment = byte;
+            children.insert(index, node);
+            check(&children);
+        }
 
-            assert!(std_map.iter().eq(&pfx_map));
+        // replacing a child with one starting with the same byte keeps the index
+        let index = children.find(0x42).unwrap();
+        let mut node = Node::default();
+        node.key_fragment = 0x42;
+        node.tail = vec![1, 2, 3];
+        assert!(children.replace(index, node).tail.is_empty());
+        assert_eq!(children[index].tail, [1, 2, 3]);
+        check(&children);
+
+        for &byte in bytes.iter().rev().step_by(2).chain(bytes.iter().step_by(2)) {
+            let index = children.search(byte).unwrap();
+            assert_eq!(children.remove(index).key_fragment, byte);
+            check(&children);
+        }
+
+        assert!(children.is_empty());
+    }
+
+    /// Asserts that no node other than the root is empty, or has a single
+    /// child without having an item of its own.
+    fn assert_compact<K, V>(map: &PrefixTreeMap<K, V>) {
+        let mut stack: Vec<&Node<K, V>> = map.root.children.iter().collect();
+
+        while let Some(node) = stack.pop() {
+            assert!(node.item.is_some() || node.children.len() >= 2);
+            stack.extend(node.children.iter());
+        }
+    }
+
+    #[test]
+    fn cursor_mut_keeps_tree_compact() {
+        use alloc::collections::BTreeMap;
+
+        let mut map: PrefixTreeMap<Vec<u8>, usize> = PrefixTreeMap::new();
+        let mut expected = BTreeMap::new();
+        let mut cursor = map.cursor_mut();
+        let mut state = 0x2545_f491_u32;
+
+        for step in 0..5000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            let key = cursor.key().to_vec();
+
+            match state % 16 {
+                0..=5 if key.len() < 6 => cursor.descend(b"abc"[state as usize / 16 % 3]),
+                0..=8 => {
+                    cursor.ascend();
+                }
+                9 | 10 => {
+                    assert_eq!(cursor.insert(step), expected.insert(key, step));
+                }
+                11 | 12 => {
+                    assert_eq!(cursor.remove(), expected.remove(&key));
+                }
+                13 => {
+                    let removed = cursor.remove_subtree();
+                    expected.retain(|stored, _| !stored.starts_with(&key));
+                    assert_eq!(removed.check_invariants(), Ok(()));
+                    assert_compact(&removed);
+                    assert!(removed.keys().all(|stored| stored.starts_with(&key)));
+                }
+                _ => {
+                    let subtree = PrefixTreeMap::from([(b"".to_vec(), step), (b"b".to_vec(), step), (b"bca".to_vec(), step)]);
+                    for (suffix, &value) in &subtree {
+                        expected.insert([key.as_slice(), suffix].concat(), value);
+                    }
+                    cursor.graft(subtree);
+                }
+            }
+
+            assert_eq!(cursor.map.check_invariants(), Ok(()));
+            assert_compact(cursor.map);
+            assert_eq!(cursor.get(), expected.get(cursor.key()));
+            assert_eq!(cursor.len(), expected.keys().filter(|stored| stored.starts_with(cursor.key())).count());
+            assert!(cursor.map.iter().eq(expected.iter()));
+
+            if let Some(read) = cursor.as_cursor() {
+                assert_eq!(read.key(), cursor.key());
+                assert_eq!(read.len(), cursor.len());
+            }
         }
     }
+
+    #[test]
+    fn extract_if_dropped_midway() {
+        let keys = ["a", "ab", "abc", "abcd", "abd", "b", "bcd", "bce", "c"];
+        let mut map: PrefixTreeMap<&str, usize> = keys.iter().map(|&key| (key, key.len())).collect();
+
+        // stops right after removing "abc" and "abcd", in the middle of the subtree of "a"
+        let mut iter = map.extract_if(|key, _| key.starts_with("abc"));
+        assert_eq!(iter.next(), Some(("abc", 3)));
+        assert_eq!(iter.next(), Some(("abcd", 4)));
+        drop(iter);
+
+        assert_compact(&map);
+        assert_eq!(map.check_invariants(), Ok(()));
+        assert!(map.keys().copied().eq(["a", "ab", "abd", "b", "bcd", "bce", "c"]));
+
+        // stops at a leaf of a chain which becomes a single edge
+        let mut iter = map.extract_if(|key, _| matches!(*key, "b" | "bcd"));
+        assert_eq!(iter.next(), Some(("b", 1)));
+        assert_eq!(iter.next(), Some(("bcd", 3)));
+        drop(iter);
+
+        assert_compact(&map);
+        assert_eq!(map.check_invariants(), Ok(()));
+        assert_eq!(map.get("bce"), Some(&3));
+        assert!(map.keys().copied().eq(["a", "ab", "abd", "bce", "c"]));
+    }
 }
@@ -0,0 +1,625 @@
+//! A serialized format for maps which can be queried in place, e.g. from a
+//! memory-mapped file.
+//!
+//! [`PrefixTreeMap::to_mapped_bytes()`] writes the map (with byte string
+//! values) as a flat array of nodes, and [`MappedMap`] answers queries
+//! directly against those bytes, without deserializing them
+//! into nodes. Opening a map only checks the header, so it takes constant
+//! time regardless of the size of the map, and only the parts of the file
+//! actually visited by the queries are ever read.
+//!
+//! This crate doesn't use `unsafe`, so it doesn't map files itself. Any
+//! `AsRef<[u8]>` works as the storage, e.g. a `memmap2::Mmap`:
+//!
+//! ```
+//! # use std::fs::File;
+//! # use memmap2::Mmap;
+//! # use pfx::PrefixTreeMap;
+//! # use pfx::mapped::MappedMap;
+//! let map = PrefixTreeMap::from([("apple", "fruit"), ("apricot", "fruit"), ("asparagus", "vegetable")]);
+//! let path = std::env::temp_dir().join("pfx-mapped-doctest.bin");
+//! std::fs::write(&path, map.to_mapped_bytes())?;
+//!
+//! let file = File::open(&path)?;
+//! let mapped = MappedMap::new(unsafe { Mmap::map(&file)? })?;
+//!
+//! assert_eq!(mapped.get("apricot"), Some(b"fruit".as_slice()));
+//! assert_eq!(mapped.prefix_iter("ap").map(|(key, _)| key).collect::<Vec<_>>(), [b"apple".as_slice(), b"apricot"]);
+//! # drop(mapped);
+//! # std::fs::remove_file(&path)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Format
+//!
+//! All integers are little-endian. The file consists of the following
+//! sections, without any padding between them:
+//!
+//! * the header, 48 bytes:
+//!
+//!   | offset | type      | contents                                   |
+//!   |--------|-----------|--------------------------------------------|
+//!   | 0      | `[u8; 8]` | the magic bytes `pfxmap\0\0`               |
+//!   | 8      | `u32`     | the format version, currently 1            |
+//!   | 12     | `u32`     | reserved, must be 0                        |
+//!   | 16     | `u64`     | the number of nodes                        |
+//!   | 24     | `u64`     | the number of entries                      |
+//!   | 32     | `u64`     | the total length of the tails of labels    |
+//!   | 40     | `u64`     | the length of the data section             |
+//!
+//! * the nodes, in breadth-first order, 25 bytes each: the range of its
+//!   tail in the tails section, the range of its children in the nodes
+//!   section, the range of the entries in its subtree (each range as two
+//!   `u32`s), and whether the node has an entry itself (a byte, 0 or 1);
+//! * the first bytes of the labels of the nodes, one byte per node;
+//! * the tails of the labels, concatenated;
+//! * the entries in lexicographic order, 24 bytes each: the offsets of the
+//!   start of the key, of the start of the value, and of the end of the
+//!   value within the data section (each as a `u64`), the key ending where
+//!   the value starts;
+//! * the data section, containing the keys and the values.
+//!
+//! The children of a node are consecutive, in the order of the first bytes
+//! of their labels. The first node is the root, of which the label is empty,
+//! but which still has a placeholder first byte.
+
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+use alloc::vec::Vec;
+use crate::map::PrefixTreeMap;
+use crate::set::PrefixTreeSet;
+use crate::frozen::{FrozenPrefixTreeMap, BreadthFirst};
+
+
+const MAGIC: &[u8; 8] = b"pfxmap\0\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 48;
+const NODE_LEN: usize = 25;
+const ITEM_LEN: usize = 24;
+
+/// A read-only map stored in the serialized format of this module. Keys and
+/// values are returned as byte slices borrowed from the underlying storage.
+///
+/// Only the header is validated when the map is created. If the rest of the
+/// data is corrupted, queries may return wrong results, but they never panic.
+#[derive(Clone, Debug)]
+pub struct MappedMap<B> {
+    bytes: B,
+    node_count: usize,
+    item_count: usize,
+    /// The start offsets of the sections after the header.
+    fragments_start: usize,
+    tails_start: usize,
+    items_start: usize,
+    data_start: usize,
+}
+
+impl<B: AsRef<[u8]>> MappedMap<B> {
+    /// Wraps the serialized map, after checking the header and the length.
+    pub fn new(bytes: B) -> Result<Self, InvalidFormat> {
+        let data = bytes.as_ref();
+
+        if data.len() < HEADER_LEN || &data[..MAGIC.len()] != MAGIC {
+            return Err(InvalidFormat::BadMagic);
+        }
+
+        let version = read_u32(data, 8).unwrap_or_default();
+
+        if version != VERSION {
+            return Err(InvalidFormat::UnsupportedVersion { version });
+        }
+
+        let reserved = read_u32(data, 12).unwrap_or_default();
+
+        if reserved != 0 {
+            return Err(InvalidFormat::NonZeroReserved { value: reserved });
+        }
+
+        let lengths = [16, 24, 32, 40].map(|offset| read_u64(data, offset).unwrap_or_default());
+        let [node_count, item_count, tails_len, data_len] = lengths;
+        let expected = (HEADER_LEN as u128)
+            + u128::from(node_count) * (NODE_LEN as u128 + 1)
+            + u128::from(tails_len)
+            + u128::from(item_count) * ITEM_LEN as u128
+            + u128::from(data_len);
+
+        if expected != data.len() as u128 || node_count == 0 {
+            return Err(InvalidFormat::WrongLength { expected, actual: data.len() });
+        }
+
+        // all of these fit in `usize` now, since they add up to `data.len()`
+        let node_count = node_count as usize;
+        let item_count = item_count as usize;
+        let fragments_start = HEADER_LEN + node_count * NODE_LEN;
+        let tails_start = fragments_start + node_count;
+        let items_start = tails_start + tails_len as usize;
+        let data_start = items_start + item_count * ITEM_LEN;
+
+        Ok(MappedMap {
+            bytes,
+            node_count,
+            item_count,
+            fragments_start,
+            tails_start,
+            items_start,
+            data_start,
+        })
+    }
+
+    /// Returns the underlying storage.
+    pub fn into_inner(self) -> B {
+        self.bytes
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub fn len(&self) -> usize {
+        self.item_count
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    /// Return the key and the value, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&[u8], &[u8])>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let node = self.node(self.search(key.as_ref())?)?;
+
+        if node.has_item {
+            self.item(node.items_start as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Return the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&[u8]>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// An iterator over pairs of keys and the corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], &[u8])> + ExactSizeIterator + '_ {
+        self.items(0..self.item_count)
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order. This only takes a single descent along `prefix`.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = (&[u8], &[u8])> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.items(self.prefix_range(prefix.as_ref()))
+    }
+
+    /// Returns the number of keys starting with `prefix`. This takes time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.prefix_range(prefix.as_ref()).len()
+    }
+
+    /// Returns the index of the node reached by following exactly `bytes`.
+    fn search(&self, mut bytes: &[u8]) -> Option<usize> {
+        let mut index = 0;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            index = self.child(index, byte)?;
+            bytes = rest.strip_prefix(self.tail(index)?)?;
+        }
+
+        Some(index)
+    }
+
+    /// Returns the range of the entries of which the key starts with `bytes`.
+    fn prefix_range(&self, mut bytes: &[u8]) -> Range<usize> {
+        let mut index = 0;
+
+        while let Some((&byte, rest)) = bytes.split_first() {
+            let Some(child) = self.child(index, byte) else {
+                return 0..0;
+            };
+            let Some(tail) = self.tail(child) else {
+                return 0..0;
+            };
+
+            index = child;
+            bytes = match rest.strip_prefix(tail) {
+                Some(rest) => rest,
+                None if tail.starts_with(rest) => &[],
+                None => return 0..0,
+            };
+        }
+
+        match self.node(index) {
+            Some(node) => {
+                let range = node.items();
+                let end = range.end.min(self.item_count);
+                range.start.min(end)..end
+            }
+            None => 0..0,
+        }
+    }
+
+    fn items(&self, range: Range<usize>) -> impl DoubleEndedIterator<Item = (&[u8], &[u8])> + ExactSizeIterator + '_ {
+        range.map(|index| self.item(index).unwrap_or_default())
+    }
+
+    fn node(&self, index: usize) -> Option<Node> {
+        if index >= self.node_count {
+            return None;
+        }
+
+        let data = self.bytes.as_ref();
+        let offset = HEADER_LEN + index * NODE_LEN;
+        let field = |i: usize| read_u32(data, offset + 4 * i);
+
+        Some(Node {
+            tail_start: field(0)?,
+            tail_end: field(1)?,
+            children_start: field(2)?,
+            children_end: field(3)?,
+            items_start: field(4)?,
+            items_end: field(5)?,
+            has_item: *data.get(offset + 24)? != 0,
+        })
+    }
+
+    fn child(&self, index: usize, byte: u8) -> Option<usize> {
+        let children = self.node(index)?.children();
+
+        if children.end > self.node_count {
+            return None;
+        }
+
+        let fragments = self.bytes.as_ref().get(self.fragments_start..)?;
+        let offset = fragments.get(children.clone())?.binary_search(&byte).ok()?;
+
+        Some(children.start + offset)
+    }
+
+    fn tail(&self, index: usize) -> Option<&[u8]> {
+        let tail = self.node(index)?.tail();
+        let tails = self.bytes.as_ref().get(self.tails_start..self.items_start)?;
+
+        tails.get(tail)
+    }
+
+    fn item(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        if index >= self.item_count {
+            return None;
+        }
+
+        let data = self.bytes.as_ref();
+        let offset = self.items_start + index * ITEM_LEN;
+        let field = |i: usize| usize::try_from(read_u64(data, offset + 8 * i)?).ok();
+        let [key_start, value_start, value_end] = [field(0)?, field(1)?, field(2)?];
+        let data = data.get(self.data_start..)?;
+
+        Some((data.get(key_start..value_start)?, data.get(value_start..value_end)?))
+    }
+}
+
+/// The reason why bytes could not be interpreted as a [`MappedMap`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InvalidFormat {
+    /// The data didn't start with the magic bytes of the format.
+    BadMagic,
+    /// The data was written by an unknown version of the format.
+    UnsupportedVersion {
+        version: u32,
+    },
+    /// The reserved field of the header wasn't zero.
+    NonZeroReserved {
+        value: u32,
+    },
+    /// The length of the data didn't match the lengths in the header.
+    WrongLength {
+        expected: u128,
+        actual: usize,
+    },
+}
+
+impl Display for InvalidFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            InvalidFormat::BadMagic => {
+                write!(f, "not a serialized prefix tree")
+            }
+            InvalidFormat::UnsupportedVersion { version } => {
+                write!(f, "unsupported format version {version}")
+            }
+            InvalidFormat::NonZeroReserved { value } => {
+                write!(f, "reserved header field is {value:#x} instead of 0")
+            }
+            InvalidFormat::WrongLength { expected, actual } => {
+                write!(f, "expected {expected} bytes of data, found {actual}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidFormat {}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> FrozenPrefixTreeMap<K, V> {
+    /// Serializes the map in the format read by [`MappedMap`].
+    ///
+    /// # Panics
+    ///
+    /// If the map has more than `u32::MAX` nodes or bytes of labels.
+    pub fn to_mapped_bytes(&self) -> Vec<u8> {
+        write(&self.items)
+    }
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> PrefixTreeMap<K, V> {
+    /// Serializes the map in the format read by [`MappedMap`].
+    ///
+    /// # Panics
+    ///
+    /// If the map has more than `u32::MAX` entries, nodes, or bytes of labels.
+    pub fn to_mapped_bytes(&self) -> Vec<u8> {
+        write(&self.iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Serializes the set as a map with empty values, in the format read by
+    /// [`MappedMap`].
+    ///
+    /// # Panics
+    ///
+    /// If the set has more than `u32::MAX` items, nodes, or bytes of labels.
+    pub fn to_mapped_bytes(&self) -> Vec<u8> {
+        write(&self.iter().map(|item| (item, [0_u8; 0])).collect::<Vec<_>>())
+    }
+}
+
+/// Serializes the entries, which must be sorted by key and free of duplicates.
+fn write<K: AsRef<[u8]>, V: AsRef<[u8]>>(items: &[(K, V)]) -> Vec<u8> {
+    let Layout { nodes, fragments, tails } = Layout::new(items);
+    let data_len: usize = items
+        .iter()
+        .map(|(key, value)| key.as_ref().len() + value.as_ref().len())
+        .sum();
+    let mut bytes = Vec::with_capacity(
+        HEADER_LEN
+        + nodes.len() * (NODE_LEN + 1)
+        + tails.len()
+        + items.len() * ITEM_LEN
+        + data_len
+    );
+
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    // reserved
+    bytes.extend_from_slice(&0_u32.to_le_bytes());
+
+    for len in [nodes.len(), items.len(), tails.len(), data_len] {
+        bytes.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+
+    for node in &nodes {
+        let fields = [
+            node.tail_start,
+            node.tail_end,
+            node.children_start,
+            node.children_end,
+            node.items_start,
+            node.items_end,
+        ];
+
+        for field in fields {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+
+        bytes.push(u8::from(node.has_item));
+    }
+
+    bytes.extend_from_slice(&fragments);
+    bytes.extend_from_slice(&tails);
+
+    let mut offset = 0;
+
+    for (key, value) in items {
+        let key_start = offset;
+        let value_start = key_start + key.as_ref().len();
+        let value_end = value_start + value.as_ref().len();
+
+        for field in [key_start, value_start, value_end] {
+            bytes.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+
+        offset = value_end;
+    }
+
+    for (key, value) in items {
+        bytes.extend_from_slice(key.as_ref());
+        bytes.extend_from_slice(value.as_ref());
+    }
+
+    bytes
+}
+
+/// The nodes of the tree and their labels, as laid out in the format.
+struct Layout {
+    nodes: Vec<Node>,
+    fragments: Vec<u8>,
+    tails: Vec<u8>,
+}
+
+impl Layout {
+    /// Builds the tree from the entries, which must be sorted by key and
+    /// free of duplicates, with the nodes in breadth-first order.
+    fn new<K: AsRef<[u8]>, V>(items: &[(K, V)]) -> Self {
+        let mut nodes = Vec::new();
+        let mut fragments = Vec::new();
+        let mut tails = Vec::new();
+        // the children of the root start right after it
+        let mut children_start = 1;
+
+        for node in BreadthFirst::new(items) {
+            let tail_start = tails.len();
+            let children_end = children_start + node.child_count;
+
+            tails.extend_from_slice(node.tail);
+            fragments.push(node.fragment);
+            nodes.push(Node {
+                tail_start: to_index(tail_start),
+                tail_end: to_index(tails.len()),
+                children_start: to_index(children_start),
+                children_end: to_index(children_end),
+                items_start: to_index(node.items.start),
+                items_end: to_index(node.items.end),
+                has_item: node.has_item,
+            });
+
+            children_start = children_end;
+        }
+
+        Layout { nodes, fragments, tails }
+    }
+}
+
+/// A node of the serialized tree, referring to ranges of the other sections.
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    tail_start: u32,
+    tail_end: u32,
+    children_start: u32,
+    children_end: u32,
+    /// The entries in the subtree. If the node has an entry itself,
+    /// then it comes first.
+    items_start: u32,
+    items_end: u32,
+    has_item: bool,
+}
+
+impl Node {
+    fn tail(&self) -> Range<usize> {
+        self.tail_start as usize..self.tail_end as usize
+    }
+
+    fn children(&self) -> Range<usize> {
+        self.children_start as usize..self.children_end as usize
+    }
+
+    fn items(&self) -> Range<usize> {
+        self.items_start as usize..self.items_end as usize
+    }
+}
+
+fn to_index(index: usize) -> u32 {
+    u32::try_from(index).expect("serialized prefix tree too large")
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries() {
+        let keys: Vec<Vec<u8>> = (0..400_u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> (i % 24)).to_le_bytes()[..i as usize % 5].to_vec())
+            .collect();
+        let map: PrefixTreeMap<Vec<u8>, Vec<u8>> = keys
+            .iter()
+            .map(|key| (key.clone(), key.repeat(2)))
+            .collect();
+        let bytes = map.to_mapped_bytes();
+        let mapped = MappedMap::new(bytes.as_slice()).unwrap();
+
+        assert_eq!(mapped.len(), map.len());
+        assert!(mapped.iter().eq(map.iter().map(|(key, value)| (key.as_slice(), value.as_slice()))));
+
+        for key in &keys {
+            assert_eq!(mapped.get(key), map.get(key).map(Vec::as_slice));
+
+            for len in 0..=key.len() {
+                let prefix = &key[..len];
+                let expected = map.prefix_iter(prefix).map(|(key, value)| (key.as_slice(), value.as_slice()));
+
+                assert!(mapped.prefix_iter(prefix).eq(expected));
+                assert_eq!(mapped.count_prefix(prefix), map.count_prefix(prefix));
+
+                let missing = [prefix, &[0xff]].concat();
+                assert_eq!(mapped.contains_key(&missing), map.contains_key(&missing));
+            }
+        }
+
+        let set = PrefixTreeSet::from(["", "ab", "abc"]);
+        let bytes = set.to_mapped_bytes();
+        let mapped = MappedMap::new(bytes).unwrap();
+
+        assert!(mapped.contains_key(""));
+        assert!(!mapped.contains_key("a"));
+        assert_eq!(mapped.get("abc"), Some(b"".as_slice()));
+        assert_eq!(mapped.iter().map(|(key, _)| key).collect::<Vec<_>>(), [b"".as_slice(), b"ab", b"abc"]);
+
+        let empty = PrefixTreeMap::<&str, &str>::new().to_mapped_bytes();
+        let mapped = MappedMap::new(empty).unwrap();
+
+        assert!(mapped.is_empty());
+        assert_eq!(mapped.get(""), None);
+        assert_eq!(mapped.prefix_iter("").count(), 0);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let map = PrefixTreeMap::from([("foo", "bar"), ("food", "qux"), ("other", "")]);
+        let bytes = map.to_mapped_bytes();
+
+        assert_eq!(MappedMap::new(&bytes[..10]).unwrap_err(), InvalidFormat::BadMagic);
+        assert!(matches!(MappedMap::new(&bytes[..bytes.len() - 1]), Err(InvalidFormat::WrongLength { .. })));
+
+        let mut future = bytes.clone();
+        future[8] = 2;
+        assert_eq!(MappedMap::new(future).unwrap_err(), InvalidFormat::UnsupportedVersion { version: 2 });
+
+        let mut reserved = bytes.clone();
+        reserved[13] = 1;
+        assert_eq!(MappedMap::new(reserved).unwrap_err(), InvalidFormat::NonZeroReserved { value: 0x100 });
+
+        // corrupting any single byte after the header doesn't cause a panic
+        for index in HEADER_LEN..bytes.len() {
+            for value in [0x00, 0x01, 0x7f, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[index] = value;
+
+                let mapped = MappedMap::new(corrupted).unwrap();
+                let _ = mapped.get("food");
+                let _ = mapped.count_prefix("fo");
+                let _ = mapped.prefix_iter("fo").count();
+                let _ = mapped.iter().rev().count();
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Multi-pattern substring search with the Aho-Corasick algorithm.
+//!
+//! A prefix tree finds the keys that are prefixes of a query in a single
+//! descent. To find the keys occurring *anywhere* in a haystack, a
+//! [`Matcher`] extends the tree with failure links: when the next byte of
+//! the haystack has no matching edge, the search continues from the node of
+//! the longest proper suffix of the bytes read so far that is also a prefix
+//! of a key, instead of starting over. This reports all occurrences of all
+//! keys in a single pass over the haystack.
+//!
+//! ```
+//! # use pfx::PrefixTreeSet;
+//! let set = PrefixTreeSet::from(["he", "she", "his", "hers"]);
+//! let matcher = set.build_matcher();
+//!
+//! let found: Vec<_> = matcher
+//!     .find_iter("ushers")
+//!     .map(|m| (set.get_index(m.pattern).copied().unwrap(), m.start..m.end))
+//!     .collect();
+//!
+//! assert_eq!(found, [("she", 1..4), ("he", 2..4), ("hers", 2..6)]);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::iter::FusedIterator;
+use core::ops::Range;
+use alloc::vec::Vec;
+use alloc::collections::VecDeque;
+use crate::set::PrefixTreeSet;
+
+
+/// An Aho-Corasick automaton, finding all occurrences of a set of byte
+/// strings (patterns) in a haystack. Created by [`PrefixTreeSet::build_matcher()`].
+///
+/// Patterns are identified by their index in lexicographic order, which is
+/// also their index in the set the matcher was built from.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Matcher {
+    /// The nodes of the prefix tree of the patterns in breadth-first order,
+    /// so the root is at index 0, and failure links always point backwards.
+    states: Vec<State>,
+    /// The labels of all edges of the tree, grouped by source state, each
+    /// group sorted in ascending order.
+    labels: Vec<u8>,
+    /// The target states of all edges, parallel to `labels`.
+    targets: Vec<u32>,
+    pattern_count: usize,
+}
+
+impl Matcher {
+    /// Returns the number of patterns.
+    pub fn pattern_count(&self) -> usize {
+        self.pattern_count
+    }
+
+    /// Returns the number of states of the automaton.
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// An iterator over all occurrences of all patterns in `haystack`,
+    /// including overlapping ones.
+    ///
+    /// Matches are yielded in increasing order of their end, and matches
+    /// ending at the same position are yielded from the longest to the
+    /// shortest. The empty pattern, if any, matches at every position.
+    pub fn find_iter<'a, H>(&'a self, haystack: &'a H) -> FindIter<'a>
+    where
+        H: ?Sized + AsRef<[u8]>,
+    {
+        FindIter {
+            matcher: self,
+            haystack: haystack.as_ref(),
+            position: 0,
+            state: 0,
+            output: self.outputs_of(0),
+        }
+    }
+
+    /// Returns `true` if any pattern occurs in `haystack`. This stops at the
+    /// first match.
+    pub fn is_match<H>(&self, haystack: &H) -> bool
+    where
+        H: ?Sized + AsRef<[u8]>,
+    {
+        self.find_iter(haystack).next().is_some()
+    }
+
+    /// Builds the automaton from patterns sorted in strictly ascending order.
+    fn from_sorted(patterns: &[&[u8]]) -> Self {
+        // since the patterns are sorted and distinct, only the first of the
+        // patterns below a state may end there
+        let pattern_at = |below: &Range<usize>, depth: usize| {
+            patterns.get(below.start).filter(|pattern| pattern.len() == depth).map(|_| to_index(below.start))
+        };
+        let mut matcher = Matcher {
+            states: Vec::from([State {
+                pattern: pattern_at(&(0..patterns.len()), 0),
+                ..State::default()
+            }]),
+            labels: Vec::new(),
+            targets: Vec::new(),
+            pattern_count: patterns.len(),
+        };
+
+        // the patterns below each state, in the order of the states
+        let mut queue = VecDeque::new();
+        queue.push_back(0..patterns.len());
+        let mut state = 0;
+
+        while let Some(mut below) = queue.pop_front() {
+            let depth = matcher.states[state].depth as usize;
+
+            if matcher.states[state].pattern.is_some() {
+                below.start += 1;
+            }
+
+            matcher.states[state].edges_start = to_index(matcher.labels.len());
+
+            while below.start < below.end {
+                let label = patterns[below.start][depth];
+                let len = patterns[below.clone()].partition_point(|pattern| pattern[depth] == label);
+
+                // the states of the failure link and of everything along its
+                // chain are shallower, so their edges are complete by now
+                let fail = if state == 0 {
+                    0
+                } else {
+                    matcher.next_state(matcher.states[state].fail, label)
+                };
+                let output = matcher.outputs_of(fail);
+                let child = below.start..below.start + len;
+
+                // patterns are recorded when their state is created, since
+                // deeper states may already have a failure link to it before
+                // it is visited
+                matcher.labels.push(label);
+                matcher.targets.push(to_index(matcher.states.len()));
+                matcher.states.push(State {
+                    depth: to_index(depth + 1),
+                    fail,
+                    pattern: pattern_at(&child, depth + 1),
+                    output,
+                    ..State::default()
+                });
+                queue.push_back(child);
+                below.start += len;
+            }
+
+            matcher.states[state].edges_end = to_index(matcher.labels.len());
+            state += 1;
+        }
+
+        matcher
+    }
+
+    /// Follows the edge labelled `label` from `state`, if any.
+    fn edge(&self, state: u32, label: u8) -> Option<u32> {
+        let edges = self.states[state as usize].edges();
+        let offset = self.labels[edges.clone()].binary_search(&label).ok()?;
+
+        Some(self.targets[edges.start + offset])
+    }
+
+    /// Finds the state after reading `label` in `state`, following failure
+    /// links as long as there is no edge for it.
+    fn next_state(&self, mut state: u32, label: u8) -> u32 {
+        loop {
+            if let Some(target) = self.edge(state, label) {
+                return target;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state as usize].fail;
+        }
+    }
+
+    /// Returns the deepest state along the failure links starting with (and
+    /// including) `state` at which a pattern ends.
+    fn outputs_of(&self, state: u32) -> Option<u32> {
+        let state_ref = &self.states[state as usize];
+        state_ref.pattern.map(|_| state).or(state_ref.output)
+    }
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher::from_sorted(&[])
+    }
+}
+
+impl Debug for Matcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Matcher")
+            .field("pattern_count", &self.pattern_count)
+            .field("state_count", &self.states.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Builds an Aho-Corasick automaton for finding the items of this set in
+    /// other byte strings. See the [module-level documentation](crate::matcher).
+    ///
+    /// This takes time proportional to the total length of the items, times
+    /// a logarithmic factor.
+    pub fn build_matcher(&self) -> Matcher {
+        let patterns: Vec<&[u8]> = self.iter().map(AsRef::as_ref).collect();
+        Matcher::from_sorted(&patterns)
+    }
+}
+
+/// A node of the prefix tree of the patterns, with its failure link.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+struct State {
+    edges_start: u32,
+    edges_end: u32,
+    /// The length of the bytes leading to this state.
+    depth: u32,
+    /// The state of the longest proper suffix of the bytes leading to this
+    /// one which is also a prefix of a pattern.
+    fail: u32,
+    /// The index of the pattern ending in this state, if any.
+    pattern: Option<u32>,
+    /// The next state along the failure links at which a pattern ends.
+    output: Option<u32>,
+}
+
+impl State {
+    fn edges(&self) -> Range<usize> {
+        self.edges_start as usize..self.edges_end as usize
+    }
+}
+
+fn to_index(index: usize) -> u32 {
+    u32::try_from(index).expect("automaton too large")
+}
+
+/// An occurrence of a pattern in a haystack. Yielded by [`Matcher::find_iter`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Match {
+    /// The index of the pattern.
+    pub pattern: usize,
+    /// The index in the haystack at which the occurrence starts.
+    pub start: usize,
+    /// The index in the haystack just past the occurrence.
+    pub end: usize,
+}
+
+/// Iterator over the occurrences of patterns in a haystack.
+/// Created by [`Matcher::find_iter`].
+#[derive(Clone)]
+pub struct FindIter<'a> {
+    matcher: &'a Matcher,
+    haystack: &'a [u8],
+    /// The number of bytes of the haystack read so far.
+    position: usize,
+    /// The state after reading them.
+    state: u32,
+    /// The next state along the failure links of `state` at which a pattern
+    /// ends, which is yet to be reported.
+    output: Option<u32>,
+}
+
+impl Debug for FindIter<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl Iterator for FindIter<'_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(output) = self.output {
+                let state = &self.matcher.states[output as usize];
+                let pattern = state.pattern.expect("output state without pattern");
+
+                self.output = state.output;
+
+                return Some(Match {
+                    pattern: pattern as usize,
+                    start: self.position - state.depth as usize,
+                    end: self.position,
+                });
+            }
+
+            let &label = self.haystack.get(self.position)?;
+
+            self.position += 1;
+            self.state = self.matcher.next_state(self.state, label);
+            self.output = self.matcher.outputs_of(self.state);
+        }
+    }
+}
+
+impl FusedIterator for FindIter<'_> {}
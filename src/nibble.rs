@@ -0,0 +1,311 @@
+//! A map which branches on the nibbles (half-bytes) of its keys.
+//!
+//! Every node of a [`NibblePrefixTreeMap`] has at most 16 children, one for
+//! each possible nibble, at the cost of twice the depth of a tree branching
+//! on whole bytes. For random binary keys, this keeps nodes small and the
+//! search within them cheap. Keys are still stored and looked up as ordinary
+//! byte strings: the tree reads their nibbles in place.
+//!
+//! The labels of the edges are stored packed, two nibbles per byte, so the
+//! path-compressed parts of the keys take about as much memory as in a
+//! [`PrefixTreeMap`] branching on bytes. There are more nodes, since the
+//! tree branches twice per byte, but none of them has more than 16
+//! children, which are stored without spare capacity. Which of the two maps
+//! is smaller thus depends on the keys: for random binary keys, the nibble
+//! map takes less memory from about five to about fifty thousand keys,
+//! when the nodes of a byte tree below its root have more than 16 children
+//! each, but nowhere near 256. For fewer or more keys, its additional nodes
+//! make it up to about a tenth larger.
+//!
+//! ```
+//! # use pfx::nibble::NibblePrefixTreeMap;
+//! let mut map = NibblePrefixTreeMap::new();
+//!
+//! map.insert(&b"\x12\x34"[..], 1);
+//! map.insert(b"\x12\xff", 2);
+//! map.insert(b"\x1f", 3);
+//!
+//! assert_eq!(map.get(b"\x12\x34"), Some(&1));
+//! assert_eq!(map.prefix_iter(b"\x12").count(), 2);
+//!
+//! // prefixes may also end in the middle of a byte
+//! assert_eq!(map.prefix_iter_nibbles(b"\x10", 1).count(), 3);
+//! assert_eq!(map.prefix_iter_nibbles(b"\x12\xf0", 3).count(), 1);
+//!
+//! let keys: Vec<_> = map.keys().copied().collect();
+//! assert_eq!(keys, [&b"\x12\x34"[..], b"\x12\xff", b"\x1f"]);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use alloc::vec::Vec;
+use crate::map::{self, PrefixTreeMap, PrefixIter, Label, Labeling, Packing, Tail, nibble_at};
+
+
+/// A map from byte strings to arbitrary values, of which the tree branches
+/// on the nibbles of the keys, high nibble first. See the [module-level
+/// documentation](self).
+///
+/// Iteration proceeds in lexicographic order of the keys, which is the same
+/// for their bytes and their nibbles.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct NibblePrefixTreeMap<K, V> {
+    /// The labels of this map are nibbles, packed two per byte, so it must
+    /// only be accessed through the methods taking a `Label`.
+    map: PrefixTreeMap<K, V>,
+}
+
+impl<K, V> Default for NibblePrefixTreeMap<K, V> {
+    fn default() -> Self {
+        NibblePrefixTreeMap::new()
+    }
+}
+
+impl<K, V> NibblePrefixTreeMap<K, V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        NibblePrefixTreeMap { map: PrefixTreeMap::new() }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// An iterator over borrowed key-value pairs, in lexicographic order.
+    pub fn iter(&self) -> map::Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// An iterator over the keys, in lexicographic order.
+    pub fn keys(&self) -> map::Keys<'_, K, V> {
+        self.map.keys()
+    }
+
+    /// An iterator over the values, in lexicographic order of their keys.
+    pub fn values(&self) -> map::Values<'_, K, V> {
+        self.map.values()
+    }
+    /// See [`PrefixTreeMap::allocated_bytes`].
+    #[cfg(test)]
+    pub(crate) fn allocated_bytes(&self) -> usize {
+        self.map.allocated_bytes()
+    }
+}
+
+impl<K: AsRef<[u8]>, V> NibblePrefixTreeMap<K, V> {
+    /// Return a reference to the original key and value, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get_entry_by(Nibbles::of(key.as_ref()))
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Return a mutable reference to the value, if found.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get_mut_by(Nibbles::of(key.as_ref()))
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// Replaces and returns the previous value, if any.
+    ///
+    /// This leaves the key in the map untouched if it already exists.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.map.insert_by::<Nibbles<'_>>(key, value)
+    }
+
+    /// If the key exists in the map, return the original key and the corresponding value.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.remove_entry_by(Nibbles::of(key.as_ref()))
+    }
+
+    /// If the key exists in the map, return the corresponding value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.remove_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns the number of keys starting with the given prefix. This only
+    /// takes time proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.count_prefix_by(Nibbles::of(prefix.as_ref()))
+    }
+
+    /// An iterator over borrowed key-value pairs of which the key starts
+    /// with the given prefix, in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> PrefixIter<'_, K, V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix_iter_by(Nibbles::of(prefix.as_ref()))
+    }
+
+    /// Like [`NibblePrefixTreeMap::prefix_iter`], but only the first
+    /// `nibble_len` nibbles of `prefix` are significant, so that the prefix
+    /// may end in the middle of a byte.
+    ///
+    /// # Panics
+    ///
+    /// If `prefix` is shorter than `nibble_len` nibbles.
+    pub fn prefix_iter_nibbles(&self, prefix: &[u8], nibble_len: usize) -> PrefixIter<'_, K, V> {
+        self.map.prefix_iter_by(Nibbles::new(prefix, nibble_len))
+    }
+}
+
+impl<K: AsRef<[u8]>, V> FromIterator<(K, V)> for NibblePrefixTreeMap<K, V> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = NibblePrefixTreeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: AsRef<[u8]>, V> Extend<(K, V)> for NibblePrefixTreeMap<K, V> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for NibblePrefixTreeMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = map::IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a NibblePrefixTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for NibblePrefixTreeMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// The first `end` nibbles of a byte string, from the `start`-th one on,
+/// read in place.
+#[derive(Clone, Copy)]
+struct Nibbles<'a> {
+    bytes: &'a [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Nibbles<'a> {
+    fn new(bytes: &'a [u8], nibble_len: usize) -> Self {
+        assert!(nibble_len <= bytes.len() * 2, "{nibble_len} nibbles out of range for {} bytes", bytes.len());
+
+        Nibbles { bytes, start: 0, end: nibble_len }
+    }
+
+    fn of(bytes: &'a [u8]) -> Self {
+        Nibbles::new(bytes, bytes.len() * 2)
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        nibble_at(self.bytes, index)
+    }
+}
+
+impl Label for Nibbles<'_> {
+    fn next_symbol(self) -> Option<(u8, Self)> {
+        (self.start < self.end).then(|| (self.get(self.start), self.skip(1)))
+    }
+
+    fn symbol_count(self) -> usize {
+        self.end - self.start
+    }
+
+    fn common_prefix_len(self, tail: Tail<'_>) -> usize {
+        (self.start..self.end)
+            .zip(0..tail.len())
+            .take_while(|&(index, tail_index)| self.get(index) == tail.get(tail_index))
+            .count()
+    }
+
+    fn skip(self, len: usize) -> Self {
+        Nibbles { start: self.start + len, ..self }
+    }
+
+    /// Copies the bytes holding the nibbles, and clears the halves of the
+    /// first and last ones which hold other nibbles.
+    fn to_tail(self) -> (Vec<u8>, Packing) {
+        if self.start == self.end {
+            return (Vec::new(), Packing::Nibbles { odd_start: false, odd_end: false });
+        }
+
+        let mut bytes = self.bytes[self.start / 2..self.end.div_ceil(2)].to_vec();
+        let odd_start = self.start % 2 == 1;
+        let odd_end = self.end % 2 == 1;
+
+        if odd_start {
+            bytes[0] &= 0x0f;
+        }
+        if odd_end {
+            let last = bytes.len() - 1;
+            bytes[last] &= 0xf0;
+        }
+
+        (bytes, Packing::Nibbles { odd_start, odd_end })
+    }
+}
+
+impl<K: ?Sized + AsRef<[u8]>> Labeling<K> for Nibbles<'_> {
+    type Label<'a> = Nibbles<'a> where K: 'a;
+
+    fn label(key: &K) -> Nibbles<'_> {
+        Nibbles::of(key.as_ref())
+    }
+}
@@ -0,0 +1,392 @@
+//! Maps and sets that normalize their keys, e.g. for case-insensitive lookups.
+//!
+//! A [`NormalizedMap`] passes every key, whether inserted or looked up, through
+//! the same [`Normalizer`], and arranges the entries by the normalized bytes.
+//! The original keys are kept, so they can still be retrieved as inserted.
+//!
+//! ```
+//! # use pfx::normalized::{NormalizedMap, AsciiCaseInsensitive};
+//! let mut headers = NormalizedMap::new(AsciiCaseInsensitive);
+//!
+//! headers.insert("Content-Type", "text/html");
+//! headers.insert("Content-Length", "1024");
+//! headers.insert("X-Request-Id", "42");
+//!
+//! assert_eq!(headers.get("content-type"), Some(&"text/html"));
+//! assert_eq!(headers.get_key_value("CONTENT-LENGTH"), Some((&"Content-Length", &"1024")));
+//! assert_eq!(headers.prefix_iter("CONTENT-").count(), 2);
+//!
+//! // an equivalent key replaces the value, but keeps the original key
+//! assert_eq!(headers.insert("x-request-id", "43"), Some("42"));
+//! assert_eq!(headers.keys().collect::<Vec<_>>(), [&"Content-Length", &"Content-Type", &"X-Request-Id"]);
+//! ```
+
+use core::mem;
+use core::fmt::{self, Debug, Formatter};
+use alloc::vec::Vec;
+use crate::map::PrefixTreeMap;
+
+
+/// Transforms keys into their normalized form, by which they are compared.
+///
+/// For prefix queries to work, normalization must preserve prefixes: the
+/// normalized form of a key must start with the normalized form of each
+/// prefix of the key that is used in a query. This holds for byte-by-byte
+/// transformations, such as ASCII case folding.
+///
+/// This is implemented for `Fn(&[u8], &mut Vec<u8>)` closures.
+pub trait Normalizer {
+    /// Appends the normalized form of `key` to `out`.
+    fn normalize(&self, key: &[u8], out: &mut Vec<u8>);
+}
+
+impl<F> Normalizer for F
+where
+    F: Fn(&[u8], &mut Vec<u8>)
+{
+    fn normalize(&self, key: &[u8], out: &mut Vec<u8>) {
+        self(key, out);
+    }
+}
+
+/// Normalizes keys by converting ASCII letters to lowercase, leaving all
+/// other bytes unchanged.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct AsciiCaseInsensitive;
+
+impl Normalizer for AsciiCaseInsensitive {
+    fn normalize(&self, key: &[u8], out: &mut Vec<u8>) {
+        out.extend(key.iter().map(u8::to_ascii_lowercase));
+    }
+}
+
+/// A map of which the keys are normalized by a [`Normalizer`] before
+/// insertion and lookup. See the [module-level documentation](self).
+///
+/// Iteration proceeds in lexicographic order of the normalized keys.
+#[derive(Clone)]
+pub struct NormalizedMap<K, V, N> {
+    /// The original keys and the values, by the normalized keys.
+    map: PrefixTreeMap<Vec<u8>, (K, V)>,
+    normalizer: N,
+}
+
+impl<K, V, N: Default> Default for NormalizedMap<K, V, N> {
+    fn default() -> Self {
+        NormalizedMap::new(N::default())
+    }
+}
+
+impl<K, V, N> NormalizedMap<K, V, N> {
+    /// Creates an empty map, normalizing keys with `normalizer`.
+    pub const fn new(normalizer: N) -> Self {
+        NormalizedMap {
+            map: PrefixTreeMap::new(),
+            normalizer,
+        }
+    }
+
+    /// Returns the normalizer of the keys.
+    pub fn normalizer(&self) -> &N {
+        &self.normalizer
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all entries, retaining the normalizer.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// An iterator over pairs of references to the original keys and the
+    /// corresponding values.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator + '_ {
+        self.map.values().map(|(key, value)| (key, value))
+    }
+
+    /// An iterator over the original keys.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator + '_ {
+        self.map.values().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator + '_ {
+        self.map.values().map(|(_key, value)| value)
+    }
+}
+
+impl<K, V, N: Normalizer> NormalizedMap<K, V, N> {
+    /// Returns the normalized form of `key`.
+    pub fn normalize_key<Q>(&self, key: &Q) -> Vec<u8>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut normalized = Vec::new();
+        self.normalizer.normalize(key.as_ref(), &mut normalized);
+        normalized
+    }
+
+    /// Return a reference to the value of the key equivalent to `key`, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_key_value(key).map(|(_key, value)| value)
+    }
+
+    /// Return references to the original key equivalent to `key` and its
+    /// value, if found.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let (key, value) = self.map.get(&self.normalize_key(key))?;
+        Some((key, value))
+    }
+
+    /// Return a mutable reference to the value of the key equivalent to
+    /// `key`, if found.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let normalized = self.normalize_key(key);
+        self.map.get_mut(&normalized).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if and only if a key equivalent to `key` is found.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_key(&self.normalize_key(key))
+    }
+
+    /// Inserts a key-value pair. If an equivalent key was already present,
+    /// its value is replaced and returned, but the original key is kept.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]>,
+    {
+        let normalized = self.normalize_key(&key);
+
+        match self.map.get_mut(&normalized) {
+            Some((_key, old_value)) => Some(mem::replace(old_value, value)),
+            None => {
+                self.map.insert(normalized, (key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes the key equivalent to `key`, returning its value, if found.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.remove_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Removes the key equivalent to `key`, returning the original key and
+    /// its value, if found.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let normalized = self.normalize_key(key);
+        self.map.remove(&normalized)
+    }
+
+    /// An iterator over the entries of which the normalized key starts with
+    /// the normalized `prefix`.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map
+            .prefix_iter(&self.normalize_key(prefix))
+            .map(|(_normalized, (key, value))| (key, value))
+    }
+}
+
+impl<K, V, N> FromIterator<(K, V)> for NormalizedMap<K, V, N>
+where
+    K: AsRef<[u8]>,
+    N: Normalizer + Default,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = NormalizedMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: AsRef<[u8]>, V, N: Normalizer> Extend<(K, V)> for NormalizedMap<K, V, N> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V, N> IntoIterator for NormalizedMap<K, V, N> {
+    type Item = (K, V);
+    type IntoIter = crate::map::IntoValues<Vec<u8>, (K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_values()
+    }
+}
+
+impl<K: Debug, V: Debug, N> Debug for NormalizedMap<K, V, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A set of which the items are normalized by a [`Normalizer`] before
+/// insertion and lookup. See the [module-level documentation](self).
+///
+/// Iteration proceeds in lexicographic order of the normalized items.
+#[derive(Clone)]
+pub struct NormalizedSet<T, N> {
+    map: NormalizedMap<T, (), N>,
+}
+
+impl<T, N: Default> Default for NormalizedSet<T, N> {
+    fn default() -> Self {
+        NormalizedSet::new(N::default())
+    }
+}
+
+impl<T, N> NormalizedSet<T, N> {
+    /// Creates an empty set, normalizing items with `normalizer`.
+    pub const fn new(normalizer: N) -> Self {
+        NormalizedSet { map: NormalizedMap::new(normalizer) }
+    }
+
+    /// Returns the normalizer of the items.
+    pub fn normalizer(&self) -> &N {
+        self.map.normalizer()
+    }
+
+    /// Returns the number of items in the set.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if the set contains no items.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all items, retaining the normalizer.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// An iterator over the original items.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_ {
+        self.map.keys()
+    }
+}
+
+impl<T, N: Normalizer> NormalizedSet<T, N> {
+    /// Returns the original item equivalent to `item`, if found.
+    pub fn get<Q>(&self, item: &Q) -> Option<&T>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.get_key_value(item).map(|(item, ())| item)
+    }
+
+    /// Returns `true` if and only if an item equivalent to `item` is found.
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_key(item)
+    }
+
+    /// Inserts the item if no equivalent item existed.
+    ///
+    /// Returns `true` if an insertion happened, and `false` if an equivalent
+    /// item already existed, in which case that one is kept.
+    pub fn insert(&mut self, item: T) -> bool
+    where
+        T: AsRef<[u8]>,
+    {
+        self.map.insert(item, ()).is_none()
+    }
+
+    /// Removes and returns the original item equivalent to `item`, if found.
+    pub fn take<Q>(&mut self, item: &Q) -> Option<T>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.remove_entry(item).map(|(item, ())| item)
+    }
+
+    /// Removes the item equivalent to `item`. Returns `true` if it was found.
+    pub fn remove<Q>(&mut self, item: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.take(item).is_some()
+    }
+
+    /// An iterator over the items of which the normalized form starts with
+    /// the normalized `prefix`.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.prefix_iter(prefix).map(|(item, ())| item)
+    }
+}
+
+impl<T, N> FromIterator<T> for NormalizedSet<T, N>
+where
+    T: AsRef<[u8]>,
+    N: Normalizer + Default,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>
+    {
+        let mut set = NormalizedSet::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T: AsRef<[u8]>, N: Normalizer> Extend<T> for NormalizedSet<T, N> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>
+    {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Debug, N> Debug for NormalizedSet<T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,453 @@
+//! Persistent prefix trees, with cheap snapshots.
+//!
+//! The nodes of a [`PersistentPrefixTreeMap`] are reference-counted and
+//! shared between copies of the map, so cloning a map takes constant time,
+//! regardless of its size. Mutating a copy only duplicates the nodes along
+//! the path to the changed key (copy-on-write), and leaves the rest shared.
+//! This makes it possible to take consistent point-in-time snapshots for
+//! readers, while a writer keeps updating the map.
+//!
+//! ```
+//! # use pfx::persistent::PersistentPrefixTreeMap;
+//! let mut map = PersistentPrefixTreeMap::new();
+//!
+//! map.insert("tea", 1);
+//! map.insert("ten", 2);
+//!
+//! let snapshot = map.clone();
+//!
+//! map.insert("to", 3);
+//! map.remove("tea");
+//!
+//! assert_eq!(map.iter().map(|(&key, _)| key).collect::<Vec<_>>(), ["ten", "to"]);
+//! assert_eq!(snapshot.iter().map(|(&key, _)| key).collect::<Vec<_>>(), ["tea", "ten"]);
+//!
+//! // versions can also be derived without touching the original
+//! let next = snapshot.update("tin", 4);
+//! assert_eq!(next.len(), 3);
+//! assert_eq!(snapshot.len(), 2);
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::hash::{Hash, Hasher};
+use core::iter;
+use core::mem;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use crate::map::PrefixTreeMap;
+use crate::edge::{Edge, EdgeMut};
+
+
+/// A map from byte strings to arbitrary values, the nodes of which are shared
+/// between its copies. Cloning is O(1), and `insert()` and `remove()` copy at
+/// most the nodes along the path to the key, which is O(key length).
+///
+/// Since the entries of shared nodes can't be moved out, mutation requires
+/// the keys and values to be `Clone`.
+///
+/// Edges without branching are kept path-compressed after every insertion
+/// and removal, so the shape of the tree (and thus equality and hashing) is
+/// determined solely by the entries.
+pub struct PersistentPrefixTreeMap<K, V> {
+    root: Arc<Node<K, V>>,
+}
+
+impl<K, V> PersistentPrefixTreeMap<K, V> {
+    /// Creates an empty map. The same as `Default`.
+    pub fn new() -> Self {
+        PersistentPrefixTreeMap {
+            root: Arc::new(Node::with_label(0, Vec::new())),
+        }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub fn len(&self) -> usize {
+        self.root.count
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all entries from the map. Other versions are not affected.
+    pub fn clear(&mut self) {
+        *self = PersistentPrefixTreeMap::new();
+    }
+
+    /// Returns `true` if the two maps are versions sharing the same root,
+    /// i.e. one is an unmodified copy of the other. This implies equality,
+    /// but equal maps don't necessarily share their root.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.root, &other.root)
+    }
+
+    /// Return a reference to the original key and value, if found.
+    pub fn get_entry<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let (key, value) = self.root.search(key.as_ref())?.item.as_ref()?;
+        Some((key, value))
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).map(|(_key, value)| value)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get_entry(key).is_some()
+    }
+
+    /// Returns the number of keys starting with `prefix`. This takes time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root.search_prefix(prefix.as_ref()).map_or(0, |(node, _rest)| node.count)
+    }
+
+    /// An iterator over the entries, in lexicographic order of the keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.root.iter()
+    }
+
+    /// An iterator over the keys, in lexicographic order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values, in lexicographic order of the keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_key, value)| value)
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl Iterator<Item = (&K, &V)> + '_
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root
+            .search_prefix(prefix.as_ref())
+            .into_iter()
+            .flat_map(|(node, _rest)| node.iter())
+    }
+}
+
+impl<K, V> PersistentPrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    /// Inserts the value under the key. Returns the old value, if any.
+    ///
+    /// Nodes along the path to the key which are shared with other versions
+    /// are copied first, so other versions are not affected.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let bytes = key.as_ref().to_vec();
+        Arc::make_mut(&mut self.root).insert(&bytes, (key, value))
+    }
+
+    /// If the key exists in the map, removes it and returns its value.
+    /// Other versions are not affected.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        // don't unshare any nodes if there's nothing to remove
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let (_key, value) = Arc::make_mut(&mut self.root).remove(key.as_ref())?;
+        Some(value)
+    }
+
+    /// Returns a new version of the map with the value inserted under the
+    /// key, leaving `self` unchanged.
+    #[must_use]
+    pub fn update(&self, key: K, value: V) -> Self {
+        let mut map = self.clone();
+        map.insert(key, value);
+        map
+    }
+
+    /// Returns a new version of the map without the key, leaving `self` unchanged.
+    #[must_use]
+    pub fn without<Q>(&self, key: &Q) -> Self
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut map = self.clone();
+        map.remove(key);
+        map
+    }
+}
+
+impl<K, V> Clone for PersistentPrefixTreeMap<K, V> {
+    /// Creates another version sharing all nodes, in constant time.
+    fn clone(&self) -> Self {
+        PersistentPrefixTreeMap {
+            root: Arc::clone(&self.root),
+        }
+    }
+}
+
+impl<K, V> Default for PersistentPrefixTreeMap<K, V> {
+    fn default() -> Self {
+        PersistentPrefixTreeMap::new()
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for PersistentPrefixTreeMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len() == other.len() && self.iter().eq(other.iter()))
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for PersistentPrefixTreeMap<K, V> {}
+
+impl<K: Hash, V: Hash> Hash for PersistentPrefixTreeMap<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        self.iter().for_each(|item| item.hash(state));
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PersistentPrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        let mut map = PersistentPrefixTreeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V> Extend<(K, V)> for PersistentPrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> From<PrefixTreeMap<K, V>> for PersistentPrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    fn from(map: PrefixTreeMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> From<&PersistentPrefixTreeMap<K, V>> for PrefixTreeMap<K, V>
+where
+    K: Clone + AsRef<[u8]>,
+    V: Clone,
+{
+    fn from(map: &PersistentPrefixTreeMap<K, V>) -> Self {
+        map.iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for PersistentPrefixTreeMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A node of the tree, with the edges labelled in the same way as in
+/// [`PrefixTreeMap`]. Apart from the root, every node either has an
+/// entry or at least two children.
+#[derive(Clone)]
+struct Node<K, V> {
+    item: Option<(K, V)>,
+    /// The number of entries in the subtree rooted at this node.
+    count: usize,
+    key_fragment: u8,
+    tail: Vec<u8>,
+    children: Vec<Arc<Node<K, V>>>,
+}
+
+impl<K, V> Node<K, V> {
+    const fn with_label(key_fragment: u8, tail: Vec<u8>) -> Self {
+        Node {
+            item: None,
+            count: 0,
+            key_fragment,
+            tail,
+            children: Vec::new(),
+        }
+    }
+
+    /// Iterates over the entries of the subtree in pre-order.
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let mut stack = Vec::from([self]);
+
+        iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev().map(Arc::as_ref));
+
+                if let Some((key, value)) = node.item.as_ref() {
+                    return Some((key, value));
+                }
+            }
+
+            None
+        })
+    }
+}
+
+impl<K: Clone, V: Clone> Node<K, V> {
+    /// Inserts the entry under `bytes`, splitting edges as necessary, and
+    /// copying the shared nodes along the way. Returns the old value, if any.
+    fn insert(&mut self, bytes: &[u8], item: (K, V)) -> Option<V> {
+        let mut path = Vec::new();
+
+        if self.locate(bytes, &mut path) {
+            let (_key, value) = self.descendant_mut(&path).item.replace(item)?;
+            return Some(value);
+        }
+
+        let mut node = self;
+        let mut rest = bytes;
+
+        node.count += 1;
+
+        while !rest.is_empty() {
+            let (index, after) = node.branch(rest, Node::with_label);
+
+            node = node.child_mut(index);
+            node.count += 1;
+            rest = after;
+        }
+
+        node.item = Some(item);
+
+        None
+    }
+
+    /// Removes the entry under `bytes`, then removes or merges the nodes
+    /// along the path which are no longer needed. The caller makes sure
+    /// that the key exists, so that no node is copied in vain.
+    fn remove(&mut self, bytes: &[u8]) -> Option<(K, V)> {
+        let mut path = Vec::new();
+
+        if !self.locate(bytes, &mut path) {
+            return None;
+        }
+
+        let mut node = &mut *self;
+        node.count -= 1;
+
+        for &index in &path {
+            node = node.child_mut(index);
+            node.count -= 1;
+        }
+
+        let item = node.item.take();
+        self.tidy_at(&path);
+        item
+    }
+}
+
+impl<K, V> Edge for Node<K, V> {
+    type Symbol = u8;
+    type Child = Arc<Self>;
+
+    fn key_fragment(&self) -> u8 {
+        self.key_fragment
+    }
+
+    fn tail(&self) -> &[u8] {
+        &self.tail
+    }
+
+    fn children(&self) -> &[Arc<Self>] {
+        &self.children
+    }
+
+    fn has_item(&self) -> bool {
+        self.item.is_some()
+    }
+}
+
+/// Children shared with other versions are copied before being modified.
+impl<K: Clone, V: Clone> EdgeMut for Node<K, V> {
+    fn tail_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.tail
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Self {
+        Arc::make_mut(&mut self.children[index])
+    }
+
+    fn insert_child(&mut self, index: usize, child: Self) {
+        self.children.insert(index, Arc::new(child));
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children.remove(index);
+    }
+
+    fn push_down(&mut self, key_fragment: u8, tail: Vec<u8>) {
+        let lower = Node {
+            item: self.item.take(),
+            count: self.count,
+            key_fragment,
+            tail,
+            children: mem::take(&mut self.children),
+        };
+
+        self.children.push(Arc::new(lower));
+    }
+
+    fn pull_up(&mut self) -> (u8, Vec<u8>) {
+        let child = self.children.pop().expect("no child to pull up");
+        let mut child = Arc::try_unwrap(child).unwrap_or_else(|shared| Node::clone(&shared));
+
+        self.item = child.item.take();
+        self.children = mem::take(&mut child.children);
+
+        (child.key_fragment, mem::take(&mut child.tail))
+    }
+}
+
+/// The nodes which are not shared with other versions are detached and
+/// dropped one by one from an explicit stack, because the default recursive
+/// drop glue could overflow the call stack for trees with very long keys.
+impl<K, V> Drop for Node<K, V> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+
+        while let Some(child) = stack.pop() {
+            if let Ok(mut node) = Arc::try_unwrap(child) {
+                stack.append(&mut node.children);
+            }
+        }
+    }
+}
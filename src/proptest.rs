@@ -0,0 +1,261 @@
+//! Strategies for generating maps and sets with [`proptest`].
+//!
+//! Like the strategies for the standard collections in `proptest::collection`,
+//! these generate a vector of elements and collect it, so failing cases shrink
+//! by removing entries and by shrinking the remaining keys and values.
+//!
+//! ```
+//! # use pfx::proptest::prefix_tree_map;
+//! use proptest::prelude::*;
+//! use proptest::test_runner::TestRunner;
+//!
+//! let strategy = prefix_tree_map("[a-c]{0,4}", any::<u8>(), 0..32);
+//!
+//! TestRunner::default().run(&strategy, |map| {
+//!     let count = map.prefix_iter("a").count();
+//!     prop_assert_eq!(count, map.keys().filter(|key| key.starts_with('a')).count());
+//!     Ok(())
+//! }).unwrap();
+//! ```
+
+use core::fmt::Debug;
+use alloc::vec::Vec;
+use proptest::collection::{self, SizeRange, VecStrategy, VecValueTree};
+use proptest::strategy::{statics, NewTree, Strategy, ValueTree};
+use proptest::test_runner::TestRunner;
+use proptest::tuple::TupleValueTree;
+use crate::map::PrefixTreeMap;
+use crate::set::PrefixTreeSet;
+
+
+/// Creates a strategy to generate maps with keys and values drawn from `key`
+/// and `value`, respectively, and with a number of entries in `size`.
+///
+/// Cases with duplicate keys are rejected locally if they would make the map
+/// smaller than the minimum size.
+pub fn prefix_tree_map<K, V>(key: K, value: V, size: impl Into<SizeRange>) -> PrefixTreeMapStrategy<K, V>
+where
+    K: Strategy,
+    K::Value: AsRef<[u8]>,
+    V: Strategy,
+{
+    let size = size.into();
+    let min_size = MinSize(size.start());
+    let entries = statics::Map::new(collection::vec((key, value), size), VecToMap);
+
+    PrefixTreeMapStrategy(statics::Filter::new(entries, "PrefixTreeMap minimum size".into(), min_size))
+}
+
+/// Creates a strategy to generate sets with items drawn from `item`, and
+/// with a number of items in `size`.
+///
+/// Cases with duplicate items are rejected locally if they would make the
+/// set smaller than the minimum size.
+pub fn prefix_tree_set<T>(item: T, size: impl Into<SizeRange>) -> PrefixTreeSetStrategy<T>
+where
+    T: Strategy,
+    T::Value: AsRef<[u8]>,
+{
+    let size = size.into();
+    let min_size = MinSize(size.start());
+    let items = statics::Map::new(collection::vec(item, size), VecToSet);
+
+    PrefixTreeSetStrategy(statics::Filter::new(items, "PrefixTreeSet minimum size".into(), min_size))
+}
+
+/// Strategy for generating maps. Created by [`prefix_tree_map`].
+#[derive(Clone, Debug)]
+pub struct PrefixTreeMapStrategy<K: Strategy, V: Strategy>(
+    CollectMap<VecStrategy<(K, V)>>,
+);
+
+impl<K, V> Strategy for PrefixTreeMapStrategy<K, V>
+where
+    K: Strategy,
+    K::Value: AsRef<[u8]>,
+    V: Strategy,
+{
+    type Tree = PrefixTreeMapValueTree<K::Tree, V::Tree>;
+    type Value = PrefixTreeMap<K::Value, V::Value>;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        self.0.new_tree(runner).map(PrefixTreeMapValueTree)
+    }
+}
+
+/// Value tree of generated maps, shrinking the underlying entries.
+#[derive(Clone, Debug)]
+pub struct PrefixTreeMapValueTree<K: ValueTree, V: ValueTree>(
+    CollectMap<VecValueTree<TupleValueTree<(K, V)>>>,
+);
+
+impl<K, V> ValueTree for PrefixTreeMapValueTree<K, V>
+where
+    K: ValueTree,
+    K::Value: AsRef<[u8]>,
+    V: ValueTree,
+{
+    type Value = PrefixTreeMap<K::Value, V::Value>;
+
+    fn current(&self) -> Self::Value {
+        self.0.current()
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.0.simplify()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.0.complicate()
+    }
+}
+
+/// Strategy for generating sets. Created by [`prefix_tree_set`].
+#[derive(Clone, Debug)]
+pub struct PrefixTreeSetStrategy<T: Strategy>(
+    CollectSet<VecStrategy<T>>,
+);
+
+impl<T> Strategy for PrefixTreeSetStrategy<T>
+where
+    T: Strategy,
+    T::Value: AsRef<[u8]>,
+{
+    type Tree = PrefixTreeSetValueTree<T::Tree>;
+    type Value = PrefixTreeSet<T::Value>;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        self.0.new_tree(runner).map(PrefixTreeSetValueTree)
+    }
+}
+
+/// Value tree of generated sets, shrinking the underlying items.
+#[derive(Clone, Debug)]
+pub struct PrefixTreeSetValueTree<T: ValueTree>(
+    CollectSet<VecValueTree<T>>,
+);
+
+impl<T> ValueTree for PrefixTreeSetValueTree<T>
+where
+    T: ValueTree,
+    T::Value: AsRef<[u8]>,
+{
+    type Value = PrefixTreeSet<T::Value>;
+
+    fn current(&self) -> Self::Value {
+        self.0.current()
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.0.simplify()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.0.complicate()
+    }
+}
+
+/// Collects the vectors generated by `S` into maps of at least the minimum size.
+type CollectMap<S> = statics::Filter<statics::Map<S, VecToMap>, MinSize>;
+
+/// Collects the vectors generated by `S` into sets of at least the minimum size.
+type CollectSet<S> = statics::Filter<statics::Map<S, VecToSet>, MinSize>;
+
+/// Collects the generated entries into a map.
+#[derive(Clone, Copy, Debug)]
+struct VecToMap;
+
+impl<K, V> statics::MapFn<Vec<(K, V)>> for VecToMap
+where
+    K: AsRef<[u8]> + Debug,
+    V: Debug,
+{
+    type Output = PrefixTreeMap<K, V>;
+
+    fn apply(&self, entries: Vec<(K, V)>) -> Self::Output {
+        entries.into_iter().collect()
+    }
+}
+
+/// Collects the generated items into a set.
+#[derive(Clone, Copy, Debug)]
+struct VecToSet;
+
+impl<T> statics::MapFn<Vec<T>> for VecToSet
+where
+    T: AsRef<[u8]> + Debug,
+{
+    type Output = PrefixTreeSet<T>;
+
+    fn apply(&self, items: Vec<T>) -> Self::Output {
+        items.into_iter().collect()
+    }
+}
+
+/// Rejects maps and sets that ended up too small because of duplicates.
+#[derive(Clone, Copy, Debug)]
+struct MinSize(usize);
+
+impl<K, V> statics::FilterFn<PrefixTreeMap<K, V>> for MinSize {
+    fn apply(&self, map: &PrefixTreeMap<K, V>) -> bool {
+        map.len() >= self.0
+    }
+}
+
+impl<T> statics::FilterFn<PrefixTreeSet<T>> for MinSize {
+    fn apply(&self, set: &PrefixTreeSet<T>) -> bool {
+        set.len() >= self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use proptest::prelude::*;
+    use proptest::test_runner::{TestError, TestRunner};
+    use super::{prefix_tree_map, prefix_tree_set};
+
+    #[test]
+    fn sizes_within_range() {
+        let mut runner = TestRunner::deterministic();
+        let strategy = prefix_tree_map("[a-z]{0,3}", any::<u32>(), 5..10);
+
+        runner.run(&strategy, |map| {
+            prop_assert!((5..10).contains(&map.len()));
+            prop_assert_eq!(map.check_invariants(), Ok(()));
+            Ok(())
+        }).unwrap();
+
+        runner.run(&prefix_tree_set(any::<[u8; 2]>(), 3), |set| {
+            prop_assert_eq!(set.len(), 3);
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn failures_shrink_to_minimal_case() {
+        let mut runner = TestRunner::deterministic();
+        let strategy = prefix_tree_map("[a-c]{0,4}", 0..100_u32, 0..20);
+
+        let result = runner.run(&strategy, |map| {
+            prop_assert!(map.keys().all(|key: &String| !key.contains('b')));
+            Ok(())
+        });
+
+        let Err(TestError::Fail(_, map)) = result else {
+            panic!("property should have failed");
+        };
+        assert!(map.iter().eq([(&String::from("b"), &0)]));
+
+        let result = TestRunner::deterministic().run(&prefix_tree_set("[a-c]{0,4}", 0..20), |set| {
+            prop_assert!(set.len() < 3);
+            Ok(())
+        });
+
+        let Err(TestError::Fail(_, set)) = result else {
+            panic!("property should have failed");
+        };
+        assert_eq!(set.len(), 3);
+        assert!(set.iter().all(|item| item.len() <= 1));
+    }
+}
@@ -0,0 +1,393 @@
+//! Routing of slash-separated paths, e.g. URL paths, to values by templates.
+//!
+//! A [`PrefixRouter`] matches paths against templates such as
+//! `/users/:id/posts/*rest`, made of `/`-separated segments, each of which is
+//! either
+//!
+//! * static, matching only the same segment,
+//! * a parameter (`:name`), matching any single non-empty segment, or
+//! * a wildcard (`*name`), which must be the last segment of the template,
+//!   matching the non-empty rest of the path, slashes included.
+//!
+//! The segments matched by parameters and wildcards are captured by name.
+//!
+//! At every segment, static segments take precedence over parameters, which
+//! take precedence over wildcards. If the preferred alternative doesn't lead
+//! to a match for the rest of the path, the next one is tried, so the most
+//! specific matching template wins.
+//!
+//! ```
+//! # use pfx::router::PrefixRouter;
+//! let mut router = PrefixRouter::new();
+//!
+//! router.insert("/users/new", "new user form").unwrap();
+//! router.insert("/users/:id", "show user").unwrap();
+//! router.insert("/users/:id/edit", "edit user").unwrap();
+//! router.insert("/static/*file", "serve file").unwrap();
+//!
+//! let found = router.route("/users/42").unwrap();
+//! assert_eq!(*found.value, "show user");
+//! assert_eq!(found.params.get("id"), Some("42"));
+//!
+//! assert_eq!(*router.route("/users/new").unwrap().value, "new user form");
+//! assert_eq!(*router.route("/users/new/edit").unwrap().value, "edit user");
+//!
+//! let found = router.route("/static/css/main.css").unwrap();
+//! assert_eq!(found.params.get("file"), Some("css/main.css"));
+//!
+//! assert!(router.route("/users").is_none());
+//! ```
+
+use core::fmt::{self, Debug, Display, Formatter};
+use core::mem;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use crate::map::PrefixTreeMap;
+
+
+/// Routes paths to values by templates with parameters and wildcards.
+/// See the [module-level documentation](self) for the syntax and the
+/// precedence rules.
+///
+/// Paths and templates are split at every `/` after an optional leading
+/// one, so `/a/b` and `a/b` are the same, but `/a/b/` has an additional,
+/// empty last segment. Segments are compared as they are, without decoding.
+pub struct PrefixRouter<V> {
+    root: RouteNode<V>,
+    len: usize,
+}
+
+impl<V> PrefixRouter<V> {
+    /// Creates an empty router. The same as `Default`.
+    pub const fn new() -> Self {
+        PrefixRouter {
+            root: RouteNode::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of templates.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no templates.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a template, routing the paths it matches to `value`. If the same
+    /// template was already present, its value is replaced and returned.
+    ///
+    /// Fails if the template is malformed, or if it has a parameter or a
+    /// wildcard at the same position as an existing template, but with a
+    /// different name.
+    pub fn insert(&mut self, template: &str, value: V) -> Result<Option<V>, RouteError> {
+        let segments = parse_template(template)?;
+        let mut node = &mut self.root;
+
+        for segment in segments {
+            match segment {
+                TemplateSegment::Static(segment) => {
+                    node = node.statics.entry(segment.to_string()).or_insert_with(|| Box::new(RouteNode::new()));
+                }
+                TemplateSegment::Param(name) => {
+                    let (existing, child) = &mut **node.param.get_or_insert_with(|| {
+                        Box::new((name.to_string(), RouteNode::new()))
+                    });
+
+                    if existing != name {
+                        return Err(RouteError::ConflictingName {
+                            existing: existing.clone(),
+                            name: name.to_string(),
+                        });
+                    }
+
+                    node = child;
+                }
+                TemplateSegment::Wildcard(name) => {
+                    let Some((existing, old_value)) = node.wildcard.as_mut() else {
+                        node.wildcard = Some((name.to_string(), value));
+                        self.len += 1;
+                        return Ok(None);
+                    };
+
+                    if existing != name {
+                        return Err(RouteError::ConflictingName {
+                            existing: existing.clone(),
+                            name: name.to_string(),
+                        });
+                    }
+
+                    return Ok(Some(mem::replace(old_value, value)));
+                }
+            }
+        }
+
+        let old_value = node.value.replace(value);
+        self.len += usize::from(old_value.is_none());
+        Ok(old_value)
+    }
+
+    /// Finds the value of the most specific template matching `path`, along
+    /// with the segments captured by its parameters and wildcard.
+    ///
+    /// The search backtracks over the alternatives at each segment using an
+    /// explicit stack instead of recursion, so paths may be arbitrarily deep.
+    pub fn route<'r, 'p>(&'r self, path: &'p str) -> Option<RouteMatch<'r, 'p, V>> {
+        let segments = split_path(path);
+        let mut params = Vec::new();
+
+        // alternatives still to be tried, the most specific one on the top
+        let mut stack = Vec::from([Step::Node {
+            node: &self.root,
+            index: 0,
+            captured: 0,
+            capture: None,
+        }]);
+
+        while let Some(step) = stack.pop() {
+            let (node, index) = match step {
+                Step::Node { node, index, captured, capture } => {
+                    params.truncate(captured);
+                    params.extend(capture);
+                    (node, index)
+                }
+                Step::Wildcard { name, value, captured, rest } => {
+                    params.truncate(captured);
+                    params.push((name, rest));
+                    return Some(RouteMatch { value, params: Params(params) });
+                }
+            };
+
+            let Some(&(start, segment)) = segments.get(index) else {
+                match node.value.as_ref() {
+                    Some(value) => return Some(RouteMatch { value, params: Params(params) }),
+                    None => continue,
+                }
+            };
+
+            let captured = params.len();
+
+            let rest = &path[start..];
+
+            // pushed in reverse order of precedence
+            if let Some((name, value)) = node.wildcard.as_ref().filter(|_| !rest.is_empty()) {
+                stack.push(Step::Wildcard { name, value, captured, rest });
+            }
+
+            if let Some((name, child)) = node.param.as_deref().filter(|_| !segment.is_empty()) {
+                stack.push(Step::Node {
+                    node: child,
+                    index: index + 1,
+                    captured,
+                    capture: Some((name, segment)),
+                });
+            }
+
+            if let Some(child) = node.statics.get(segment) {
+                stack.push(Step::Node {
+                    node: child,
+                    index: index + 1,
+                    captured,
+                    capture: None,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl<V> Default for PrefixRouter<V> {
+    fn default() -> Self {
+        PrefixRouter::new()
+    }
+}
+
+impl<V> Debug for PrefixRouter<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrefixRouter")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The result of a successful [`PrefixRouter::route`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RouteMatch<'r, 'p, V> {
+    /// The value of the matching template.
+    pub value: &'r V,
+    /// The segments captured by the parameters and the wildcard.
+    pub params: Params<'r, 'p>,
+}
+
+/// The parts of a path captured by the parameters and the wildcard of a
+/// template, in the order of the template.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Params<'r, 'p>(Vec<(&'r str, &'p str)>);
+
+impl<'r, 'p> Params<'r, 'p> {
+    /// Returns the part of the path captured by the given name, if any.
+    pub fn get(&self, name: &str) -> Option<&'p str> {
+        self.0.iter().find(|&&(param, _)| param == name).map(|&(_, value)| value)
+    }
+
+    /// Returns the number of captured parameters.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if nothing was captured.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over the names and the captured parts of the path.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&'r str, &'p str)> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// The reason why a template couldn't be added to a [`PrefixRouter`].
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum RouteError {
+    /// A parameter or a wildcard had no name.
+    EmptyName,
+    /// A wildcard wasn't the last segment of the template.
+    WildcardNotLast,
+    /// The same name was used for several parameters or the wildcard.
+    DuplicateName {
+        name: String,
+    },
+    /// A parameter or a wildcard was named `name`, but an existing template
+    /// had a parameter or a wildcard named `existing` at the same position.
+    ConflictingName {
+        existing: String,
+        name: String,
+    },
+}
+
+impl Display for RouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::EmptyName => {
+                f.write_str("parameter or wildcard without a name")
+            }
+            RouteError::WildcardNotLast => {
+                f.write_str("wildcard is not the last segment")
+            }
+            RouteError::DuplicateName { name } => {
+                write!(f, "name `{name}` is used more than once")
+            }
+            RouteError::ConflictingName { existing, name } => {
+                write!(f, "`{name}` conflicts with existing `{existing}` at the same position")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RouteError {}
+
+/// A node of the routing tree, corresponding to a sequence of segments.
+struct RouteNode<V> {
+    /// The value of the template ending here.
+    value: Option<V>,
+    /// The children for static segments, by the segment.
+    statics: PrefixTreeMap<String, Box<RouteNode<V>>>,
+    /// The name and the child for a parameter.
+    param: Option<Box<(String, RouteNode<V>)>>,
+    /// The name and the value of a template ending in a wildcard here.
+    wildcard: Option<(String, V)>,
+}
+
+impl<V> RouteNode<V> {
+    const fn new() -> Self {
+        RouteNode {
+            value: None,
+            statics: PrefixTreeMap::new(),
+            param: None,
+            wildcard: None,
+        }
+    }
+}
+
+/// An alternative to be tried by [`PrefixRouter::route`].
+enum Step<'r, 'p, V> {
+    /// Match the segments from `index` on against the subtree of `node`,
+    /// keeping the first `captured` parameters captured so far, and adding
+    /// `capture` if the edge leading to `node` is a parameter.
+    Node {
+        node: &'r RouteNode<V>,
+        index: usize,
+        captured: usize,
+        capture: Option<(&'r str, &'p str)>,
+    },
+    /// Capture the rest of the path by a wildcard.
+    Wildcard {
+        name: &'r str,
+        value: &'r V,
+        captured: usize,
+        rest: &'p str,
+    },
+}
+
+enum TemplateSegment<'t> {
+    Static(&'t str),
+    Param(&'t str),
+    Wildcard(&'t str),
+}
+
+/// Splits a path into its segments, along with their start index.
+fn split_path(path: &str) -> Vec<(usize, &str)> {
+    let offset = usize::from(path.starts_with('/'));
+    let path = &path[offset..];
+
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let mut start = offset;
+
+    path.split('/')
+        .map(|segment| {
+            let item = (start, segment);
+            start += segment.len() + 1;
+            item
+        })
+        .collect()
+}
+
+fn parse_template(template: &str) -> Result<Vec<TemplateSegment<'_>>, RouteError> {
+    let segments = split_path(template);
+    let mut names: Vec<&str> = Vec::new();
+
+    segments
+        .iter()
+        .enumerate()
+        .map(|(index, &(_start, segment))| {
+            let (segment, name) = if let Some(name) = segment.strip_prefix(':') {
+                (TemplateSegment::Param(name), name)
+            } else if let Some(name) = segment.strip_prefix('*') {
+                if index + 1 < segments.len() {
+                    return Err(RouteError::WildcardNotLast);
+                }
+                (TemplateSegment::Wildcard(name), name)
+            } else {
+                return Ok(TemplateSegment::Static(segment));
+            };
+
+            if name.is_empty() {
+                return Err(RouteError::EmptyName);
+            }
+            if names.contains(&name) {
+                return Err(RouteError::DuplicateName { name: name.to_string() });
+            }
+
+            names.push(name);
+            Ok(segment)
+        })
+        .collect()
+}
@@ -0,0 +1,251 @@
+//! A map from keys to scores, for ranked autocompletion.
+//!
+//! Every node of the tree caches the greatest score in its subtree, so the
+//! best-scored keys with a given prefix can be found by exploring the most
+//! promising subtrees first, without visiting the rest of the subtree.
+//!
+//! ```
+//! # use pfx::scored::ScoredMap;
+//! let mut completions = ScoredMap::new();
+//!
+//! completions.insert("rust", 90);
+//! completions.insert("rustc", 40);
+//! completions.insert("rustup", 75);
+//! completions.insert("ruby", 80);
+//!
+//! assert_eq!(completions.top_k_with_prefix("rus", 2), [(&"rust", &90), (&"rustup", &75)]);
+//! assert_eq!(completions.max_score_with_prefix("ru"), Some(&90));
+//! ```
+
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Formatter};
+use alloc::vec::Vec;
+use alloc::collections::BinaryHeap;
+use crate::aggregate::{AggregateNode, Iter, Max};
+use crate::edge::Edge;
+
+
+/// A map from byte strings to scores, which keeps track of the greatest score
+/// under every prefix.
+///
+/// The tree is that of an [`AggregateMap`](crate::aggregate::AggregateMap)
+/// aggregating the scores with [`Max`], so every node caches the greatest
+/// score of its subtree, which is `None` only for the root of an empty map.
+///
+/// Scores can't be modified in place, since that would invalidate the cached
+/// maxima. Insert the key again with the new score instead.
+#[derive(Clone)]
+pub struct ScoredMap<K, S> {
+    root: Node<K, S>,
+    len: usize,
+}
+
+type Node<K, S> = AggregateNode<K, S, Option<S>>;
+
+impl<K, S> Default for ScoredMap<K, S> {
+    fn default() -> Self {
+        ScoredMap::new()
+    }
+}
+
+impl<K, S> ScoredMap<K, S> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        ScoredMap {
+            root: AggregateNode::new(0, Vec::new(), None),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries (key-score pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return a reference to the score, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&S>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.root
+            .search(key.as_ref())?
+            .item
+            .as_ref()
+            .map(|(_key, score)| score)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the greatest score among the keys starting with `prefix`, or
+    /// `None` if there are no such keys. This takes time proportional to the
+    /// length of the prefix.
+    pub fn max_score_with_prefix<Q>(&self, prefix: &Q) -> Option<&S>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let (node, _rest) = self.root.search_prefix(prefix.as_ref())?;
+        node.aggregate.as_ref()
+    }
+
+    /// An iterator over pairs of references to keys and the corresponding scores.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the byte sequence of keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &S)> + '_ {
+        Iter::new(&self.root, self.len)
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord + Clone> ScoredMap<K, S> {
+    /// Inserts the key with the given score. Returns the old score, if any.
+    pub fn insert(&mut self, key: K, score: S) -> Option<S> {
+        let bytes = key.as_ref().to_vec();
+        let len = &mut self.len;
+
+        self.root.update(&bytes, true, &Max, |item| {
+            let old = item.replace((key, score));
+            *len += usize::from(old.is_none());
+            old.map(|(_key, score)| score)
+        }).flatten()
+    }
+
+    /// If the key exists in the map, removes it and returns its score.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<S>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let len = &mut self.len;
+
+        self.root.update(key.as_ref(), false, &Max, |item| {
+            let (_key, score) = item.take()?;
+            *len -= 1;
+            Some(score)
+        }).flatten()
+    }
+
+    /// Returns the `k` entries with the greatest scores among those of which
+    /// the key starts with `prefix`, in descending order of their scores.
+    /// Among entries with equal scores, those with smaller keys come first.
+    ///
+    /// This is a best-first search guided by the cached maxima, so subtrees
+    /// are only expanded if they may contain one of the top `k` entries.
+    pub fn top_k_with_prefix<Q>(&self, prefix: &Q, k: usize) -> Vec<(&K, &S)>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        let mut top = Vec::new();
+        let mut heap = BinaryHeap::new();
+
+        if let Some((node, _rest)) = self.root.search_prefix(prefix.as_ref()) {
+            heap.extend(Candidate::subtree(node));
+        }
+
+        while top.len() < k {
+            let Some(candidate) = heap.pop() else {
+                break;
+            };
+
+            match candidate.kind {
+                CandidateKind::Item(key) => top.push((key, candidate.score)),
+                CandidateKind::Subtree(node) => {
+                    if let Some((key, score)) = node.item.as_ref() {
+                        heap.push(Candidate { score, kind: CandidateKind::Item(key) });
+                    }
+
+                    heap.extend(node.children.iter().filter_map(Candidate::subtree));
+                }
+            }
+        }
+
+        top
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord + Clone> FromIterator<(K, S)> for ScoredMap<K, S> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, S)>
+    {
+        let mut map = ScoredMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord + Clone> Extend<(K, S)> for ScoredMap<K, S> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, S)>
+    {
+        for (key, score) in iter {
+            self.insert(key, score);
+        }
+    }
+}
+
+impl<K: Debug, S: Debug> Debug for ScoredMap<K, S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// An item or a subtree waiting to be visited by [`ScoredMap::top_k_with_prefix`],
+/// prioritized by its (greatest) score.
+struct Candidate<'a, K, S> {
+    score: &'a S,
+    kind: CandidateKind<'a, K, S>,
+}
+
+enum CandidateKind<'a, K, S> {
+    Item(&'a K),
+    Subtree(&'a Node<K, S>),
+}
+
+impl<'a, K, S> Candidate<'a, K, S> {
+    fn subtree(node: &'a Node<K, S>) -> Option<Self> {
+        let score = node.aggregate.as_ref()?;
+        Some(Candidate { score, kind: CandidateKind::Subtree(node) })
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord> Ord for Candidate<'_, K, S> {
+    /// Subtrees are expanded before items of equal score, so that all the
+    /// items with that score are known by the time the first one is taken,
+    /// and ties can be broken in favor of the smallest key.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.cmp(other.score).then_with(|| {
+            match (&self.kind, &other.kind) {
+                (CandidateKind::Item(lhs), CandidateKind::Item(rhs)) => {
+                    rhs.as_ref().cmp(lhs.as_ref())
+                }
+                (CandidateKind::Item(_), CandidateKind::Subtree(_)) => Ordering::Less,
+                (CandidateKind::Subtree(_), CandidateKind::Item(_)) => Ordering::Greater,
+                (CandidateKind::Subtree(_), CandidateKind::Subtree(_)) => Ordering::Equal,
+            }
+        })
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord> PartialOrd for Candidate<'_, K, S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord> PartialEq for Candidate<'_, K, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: AsRef<[u8]>, S: Ord> Eq for Candidate<'_, K, S> {}
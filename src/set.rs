@@ -2,8 +2,11 @@
 
 use core::iter::FusedIterator;
 use core::fmt::{self, Debug, Formatter};
-use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
-use crate::map::{PrefixTreeMap, NodeIntoIter, NodeIter, Keys, IntoKeys};
+use core::ops::{Range, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Sub, SubAssign};
+use alloc::vec::Vec;
+use crate::map::{self, PrefixTreeMap, InvariantViolation, Keys, IntoKeys, Drain as MapDrain, ExtractCursor, BranchPoints, DistinctPrefixes, Unmatched};
+use crate::builder::InvalidKey;
+use crate::text::{self, Delimiter};
 
 
 /// An ordered set based on a prefix tree.
@@ -18,6 +21,14 @@ impl<T> PrefixTreeSet<T> {
         PrefixTreeSet { map: PrefixTreeMap::new() }
     }
 
+    pub(crate) const fn from_map(map: PrefixTreeMap<T, ()>) -> Self {
+        PrefixTreeSet { map }
+    }
+
+    pub(crate) fn into_map(self) -> PrefixTreeMap<T, ()> {
+        self.map
+    }
+
     /// Returns the number of items in this set.
     pub const fn len(&self) -> usize {
         self.map.len()
@@ -36,6 +47,14 @@ impl<T> PrefixTreeSet<T> {
         self.map.contains_key(item)
     }
 
+    /// Returns a reference to the stored item equal to `item`, if any.
+    pub fn get<Q>(&self, item: &Q) -> Option<&T>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        self.map.get_entry(item).map(|(item, ())| item)
+    }
+
     /// Returns `true` iff there are any keys with the given prefix in the set.
     /// This is more efficient than creating a prefix iterator and checking
     /// whether it is empty.
@@ -46,6 +65,89 @@ impl<T> PrefixTreeSet<T> {
         self.map.contains_prefix(key)
     }
 
+    /// Returns the number of items starting with the given prefix, in time
+    /// proportional to the length of the prefix.
+    pub fn count_prefix<Q>(&self, prefix: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.count_prefix(prefix)
+    }
+
+    /// Returns the longest byte string that all items in the set start with.
+    pub fn longest_common_prefix(&self) -> Vec<u8> {
+        self.map.longest_common_prefix()
+    }
+
+    /// Returns the longest byte string that all items starting with `prefix`
+    /// start with, or `None` if there are no such items.
+    pub fn longest_common_prefix_under<Q>(&self, prefix: &Q) -> Option<Vec<u8>>
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.longest_common_prefix_under(prefix)
+    }
+
+    /// Returns `true` iff any of the items in the set is a prefix of `query`
+    /// (including `query` itself). This stops at the first such item found
+    /// while descending along `query`.
+    pub fn contains_any_prefix_of<Q>(&self, query: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.contains_any_prefix_of(query)
+    }
+
+    /// Returns `true` if and only if all of the given items are found in the set.
+    /// See [`PrefixTreeMap::contains_all`] for details.
+    pub fn contains_all<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.map.contains_all(items)
+    }
+
+    /// Returns `true` if and only if any of the given items is found in the set.
+    /// See [`PrefixTreeMap::contains_any`] for details.
+    pub fn contains_any<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.map.contains_any(items)
+    }
+
+    /// Returns the number of items found in both `self` and `other`,
+    /// without building the intersection itself.
+    pub fn intersection_len<U>(&self, other: &PrefixTreeSet<U>) -> usize {
+        self.map.intersection_len(&other.map)
+    }
+
+    /// Returns the number of items found in `self`, `other`, or both,
+    /// without building the union itself.
+    pub fn union_len<U>(&self, other: &PrefixTreeSet<U>) -> usize {
+        self.map.union_len(&other.map)
+    }
+
+    /// Returns the number of items found in `self` but not in `other`,
+    /// without building the difference itself.
+    pub fn difference_len<U>(&self, other: &PrefixTreeSet<U>) -> usize {
+        self.map.difference_len(&other.map)
+    }
+
+    /// Returns the number of items found in exactly one of `self` and `other`,
+    /// without building the symmetric difference itself.
+    pub fn symmetric_difference_len<U>(&self, other: &PrefixTreeSet<U>) -> usize {
+        self.map.symmetric_difference_len(&other.map)
+    }
+
+    /// Removes the items not found in `other`, walking the two trees side
+    /// by side. See [`PrefixTreeMap::intersect_with`].
+    pub fn intersect_with<U>(&mut self, other: &PrefixTreeSet<U>) {
+        self.map.intersect_with(&other.map);
+    }
+
     /// Removes a key if it existed. Returns `true` if a removal happened,
     /// and `false` if the key did not exist in the first place.
     pub fn remove<Q>(&mut self, key: &Q) -> bool
@@ -55,6 +157,14 @@ impl<T> PrefixTreeSet<T> {
         self.map.remove(key).is_some()
     }
 
+    /// Removes and returns the stored item equal to `item`, if any.
+    pub fn take<Q>(&mut self, item: &Q) -> Option<T>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        self.map.remove_entry(item).map(|(item, ())| item)
+    }
+
     /// Returns an iterator over the borrowed items.
     pub fn iter(&self) -> Iter<'_, T> {
         Iter { keys: self.map.keys() }
@@ -80,22 +190,302 @@ impl<T> PrefixTreeSet<T> {
         PrefixIter { iter: self.map.prefix_iter(key) }
     }
 
+    /// An iterator over the items that are prefixes of `query` (including
+    /// `query` itself), in increasing order of length.
+    /// See [`PrefixTreeMap::prefixes_of`] for details.
+    pub fn prefixes_of<'a, Q>(&'a self, query: &'a Q) -> PrefixesOf<'a, T>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        PrefixesOf { iter: self.map.prefixes_of(query) }
+    }
+
+    /// Splits `text` into tokens by greedy longest-prefix matching, yielding
+    /// the range of the text matched by each token and the matching item.
+    /// See [`PrefixTreeMap::segment_iter`] for details.
+    pub fn segment_iter<'a, Q>(&'a self, text: &'a Q, unmatched: Unmatched) -> SegmentIter<'a, T>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        SegmentIter { iter: self.map.segment_iter(text, unmatched) }
+    }
+
+    /// An iterator over the items within `max_edits` Levenshtein distance of
+    /// `query`, along with that distance, in lexicographic order.
+    /// See [`PrefixTreeMap::fuzzy_iter`] for details.
+    pub fn fuzzy_iter<Q>(&self, query: &Q, max_edits: usize) -> FuzzyIter<'_, T>
+    where
+        Q: ?Sized + AsRef<[u8]>
+    {
+        FuzzyIter { iter: self.map.fuzzy_iter(query, max_edits) }
+    }
+
+    /// Returns the number of distinct prefixes of length `len` among the items.
+    /// See [`PrefixTreeMap::count_distinct_prefixes`] for details.
+    pub fn count_distinct_prefixes(&self, len: usize) -> usize {
+        self.map.count_distinct_prefixes(len)
+    }
+
+    /// An iterator over the distinct prefixes of length `len` among the items,
+    /// along with the number of items having each prefix.
+    /// See [`PrefixTreeMap::iter_distinct_prefixes`] for details.
+    pub fn iter_distinct_prefixes(&self, len: usize) -> DistinctPrefixes {
+        self.map.iter_distinct_prefixes(len)
+    }
+
+    /// Returns the lexicographically smallest byte string of length `len`
+    /// that is not in the set, or `None` if all of them are present.
+    /// See [`PrefixTreeMap::find_absent_key`] for details.
+    pub fn find_absent_key(&self, len: usize) -> Option<Vec<u8>> {
+        self.map.find_absent_key(len)
+    }
+
+    /// An iterator over the points where the tree branches.
+    /// See [`PrefixTreeMap::iter_branch_points`] for details.
+    pub fn iter_branch_points(&self) -> BranchPoints {
+        self.map.iter_branch_points()
+    }
+
+    /// Returns the `n`-th item in lexicographic order (starting from 0),
+    /// or `None` if `n` is not less than the length of the set.
+    pub fn get_index(&self, n: usize) -> Option<&T> {
+        self.map.get_index(n).map(|(item, ())| item)
+    }
+
+    /// Returns the number of items less than `item`, i.e. the index that
+    /// `item` has or would have in lexicographic order.
+    pub fn rank<Q>(&self, item: &Q) -> usize
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.rank(item)
+    }
+
+    /// Returns `true` if no item is a strict prefix of another item.
+    pub fn is_prefix_free(&self) -> bool {
+        self.map.is_prefix_free()
+    }
+
+    /// Returns a pair of items of which the first is a strict prefix of the
+    /// second, or `None` if there is no such pair.
+    /// See [`PrefixTreeMap::find_prefix_pair`] for which pair is returned.
+    pub fn find_prefix_pair(&self) -> Option<(&T, &T)> {
+        self.map.find_prefix_pair()
+    }
+
+    /// Returns the smallest item, if any.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|(item, ())| item)
+    }
+
+    /// Returns the greatest item, if any.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|(item, ())| item)
+    }
+
+    /// Removes and returns the smallest item, if any.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(item, ())| item)
+    }
+
+    /// Removes and returns the greatest item, if any.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(item, ())| item)
+    }
+
     /// Removes all internal nodes which are not useful.
     /// See the documentation of [`crate::map::PrefixTreeMap::compact`]
     /// for more details on why this is useful.
     pub fn compact(&mut self) {
         self.map.compact();
     }
+
+    /// Releases excess capacity. See the documentation of
+    /// [`crate::map::PrefixTreeMap::shrink_to_fit`] for more details.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    /// Retains only the items for which `f` returns `true`, visiting them
+    /// in lexicographic order. See [`PrefixTreeMap::retain`].
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.map.retain(|item, ()| f(item));
+    }
+
+    /// Removes all items that start with `prefix`, and returns them as a new set.
+    /// See [`PrefixTreeMap::remove_prefix`].
+    pub fn remove_prefix<Q>(&mut self, prefix: &Q) -> Self
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        PrefixTreeSet { map: self.map.remove_prefix(prefix) }
+    }
+
+    /// Retains only the items that start with `prefix`.
+    /// See [`PrefixTreeMap::retain_prefix`].
+    pub fn retain_prefix<Q>(&mut self, prefix: &Q)
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        self.map.retain_prefix(prefix);
+    }
+
+    /// Splits the set in two at the given item. Returns a set with all the
+    /// items greater than or equal to `item`, and leaves the rest in `self`.
+    /// See [`PrefixTreeMap::split_off`].
+    pub fn split_off<Q>(&mut self, item: &Q) -> Self
+    where
+        Q: ?Sized + AsRef<[u8]>,
+    {
+        PrefixTreeSet { map: self.map.split_off(item) }
+    }
+
+    /// Removes all items from the set. See [`PrefixTreeMap::clear`].
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Removes all items from the set, returning them as an iterator, in
+    /// lexicographic order. See [`PrefixTreeMap::drain`].
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { iter: self.map.drain() }
+    }
+
+    /// An iterator which removes and yields the items for which `pred`
+    /// returns `true`, in lexicographic order. See [`PrefixTreeMap::extract_if`].
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, T, F>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        ExtractIf {
+            cursor: ExtractCursor::new(&mut self.map),
+            pred,
+        }
+    }
 }
 
 impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
     /// Inserts the key if it did not exist.
     ///
     /// Returns `true` if an insertion happened, and `false` if the key already existed.
+    ///
+    /// # Panics
+    ///
+    /// If the set has key validation rules, and `key` violates them.
     pub fn insert(&mut self, key: T) -> bool {
         self.map.insert(key, ()).is_none()
     }
 
+    /// Adds the item to the set, replacing the existing equal item, if any,
+    /// which is then returned.
+    ///
+    /// # Panics
+    ///
+    /// If the set has key validation rules, and `item` violates them.
+    pub fn replace(&mut self, item: T) -> Option<T> {
+        self.map.replace_key(item, ())
+    }
+
+    /// Like [`PrefixTreeSet::insert`], but returns an error instead of panicking
+    /// if `key` violates the validation rules of the set.
+    pub fn try_insert(&mut self, key: T) -> Result<bool, InvalidKey> {
+        self.map.try_insert(key, ()).map(|old| old.is_none())
+    }
+
+    /// Returns the item equal to `value`, inserting the one created by `f`
+    /// if there is none. The set is only traversed once, and `f` is only
+    /// called if the item is missing, e.g. for interning strings:
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeSet;
+    /// let mut pool: PrefixTreeSet<String> = PrefixTreeSet::new();
+    ///
+    /// let first = pool.get_or_insert_with("foo", str::to_owned).as_ptr();
+    /// let second = pool.get_or_insert_with("foo", |_| unreachable!()).as_ptr();
+    ///
+    /// assert_eq!(first, second);
+    /// assert_eq!(pool.len(), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the set has key validation rules, and `value` violates them,
+    /// or if the bytes of the item returned by `f` differ from those of `value`.
+    pub fn get_or_insert_with<Q, F>(&mut self, value: &Q, f: F) -> &T
+    where
+        Q: ?Sized + AsRef<[u8]>,
+        F: FnOnce(&Q) -> T,
+    {
+        match self.map.entry_ref(value) {
+            map::EntryRef::Occupied(entry) => entry.into_key(),
+            map::EntryRef::Vacant(entry) => {
+                let item = f(value);
+                assert_eq!(item.as_ref(), value.as_ref(), "item doesn't match the value");
+                entry.insert_entry(item, ()).0
+            }
+        }
+    }
+
+    /// Builds a set from items sorted in lexicographic order, faster than
+    /// collecting unsorted items. See [`PrefixTreeMap::from_sorted_iter`].
+    ///
+    /// # Panics
+    ///
+    /// With debug assertions enabled, if the items are not sorted.
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        PrefixTreeSet::from_map(PrefixTreeMap::from_sorted_iter(iter.into_iter().map(|item| (item, ()))))
+    }
+
+    /// Inserts items sorted in lexicographic order, appending the ones
+    /// greater than every item already in the set without searching.
+    /// See [`PrefixTreeMap::extend_sorted`].
+    ///
+    /// # Panics
+    ///
+    /// If the set has key validation rules, and an item violates them. With
+    /// debug assertions enabled, also if the items are not sorted.
+    pub fn extend_sorted<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.map.extend_sorted(iter.into_iter().map(|item| (item, ())));
+    }
+
+    /// Moves all items from `other` into `self`, leaving `other` empty.
+    /// See [`PrefixTreeMap::append`].
+    ///
+    /// # Panics
+    ///
+    /// If `self` has key validation rules, and an item in `other` violates them.
+    pub fn append(&mut self, other: &mut Self) {
+        self.map.append(&mut other.map);
+    }
+
+    /// Like [`PrefixTreeSet::append`], but returns an error instead of panicking
+    /// if an item in `other` violates the validation rules of `self`.
+    pub fn try_append(&mut self, other: &mut Self) -> Result<(), InvalidKey> {
+        self.map.try_append(&mut other.map)
+    }
+
+    /// Builds a set from the tokens of `corpus`, separated by `delimiter`.
+    ///
+    /// Empty tokens (e.g. between consecutive delimiters) are skipped. Tokens are
+    /// converted to `T` directly, so e.g. a `PrefixTreeSet<&str>` borrowing from
+    /// the corpus can be built without allocating any strings.
+    pub fn from_text<'a, D>(corpus: &'a str, delimiter: D) -> Self
+    where
+        T: From<&'a str>,
+        D: Delimiter,
+    {
+        text::tokens(corpus, delimiter).map(T::from).collect()
+    }
+
     /// Takes the union of `self` with another set of elements.
     /// Elements that already exist in `self` will be overwritten by `other`.
     pub fn union<I>(mut self, other: I) -> Self
@@ -125,10 +515,9 @@ impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
     where
         I: IntoIterator<Item = T>,
     {
-        other
-            .into_iter()
-            .filter(|key| self.contains(key))
-            .collect()
+        let mut result = PrefixTreeSet::from_map(self.map.new_like());
+        result.extend(other.into_iter().filter(|key| self.contains(key)));
+        result
     }
 
     /// Removes the items of `other` from `self`.
@@ -166,6 +555,92 @@ impl<T: AsRef<[u8]>> PrefixTreeSet<T> {
     {
         self.map.symmetric_difference_in_place(other.into_iter().map(|item| (item, ())));
     }
+
+    /// Returns an iterator over the borrowed items, viewed as byte strings.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeSet;
+    /// let set = PrefixTreeSet::from([String::from("foo"), String::from("bar")]);
+    /// assert!(set.keys_as_bytes().eq([b"bar", b"foo"]));
+    /// ```
+    pub fn keys_as_bytes(&self) -> KeysAsBytes<'_, T> {
+        KeysAsBytes { keys: self.map.keys_as_bytes() }
+    }
+
+    /// Lazily visits the items of `self` and `other` in lexicographic order,
+    /// without duplicates, and without consuming or cloning either set.
+    /// Items found in both sets are yielded from `self`.
+    ///
+    /// ```
+    /// # use pfx::PrefixTreeSet;
+    /// let a = PrefixTreeSet::from(["bar", "foo"]);
+    /// let b = PrefixTreeSet::from(["baz", "foo"]);
+    ///
+    /// assert!(a.union_iter(&b).eq(&["bar", "baz", "foo"]));
+    /// assert!(a.intersection_iter(&b).eq(&["foo"]));
+    /// assert!(a.difference_iter(&b).eq(&["bar"]));
+    /// assert!(a.symmetric_difference_iter(&b).eq(&["bar", "baz"]));
+    /// ```
+    pub fn union_iter<'a>(&'a self, other: &'a Self) -> Union<'a, T> {
+        Union { merge: Merge::new(self, other) }
+    }
+
+    /// Lazily visits the items of `self` that are also found in `other`,
+    /// in lexicographic order.
+    pub fn intersection_iter<'a>(&'a self, other: &'a Self) -> Intersection<'a, T> {
+        Intersection { merge: Merge::new(self, other) }
+    }
+
+    /// Lazily visits the items of `self` that are not found in `other`,
+    /// in lexicographic order.
+    pub fn difference_iter<'a>(&'a self, other: &'a Self) -> Difference<'a, T> {
+        Difference { merge: Merge::new(self, other) }
+    }
+
+    /// Lazily visits the items found in exactly one of `self` and `other`,
+    /// in lexicographic order.
+    pub fn symmetric_difference_iter<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T> {
+        SymmetricDifference { merge: Merge::new(self, other) }
+    }
+
+    /// Verifies the structural invariants of the underlying tree. See
+    /// [`PrefixTreeMap::check_invariants`] for details.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        self.map.check_invariants()
+    }
+}
+
+/// Non-consuming versions of the set operations. These leave both operands
+/// intact, and only clone the items that end up in the result.
+impl<T: Clone + AsRef<[u8]>> PrefixTreeSet<T> {
+    /// Takes the union of `self` and `other` without consuming either of them.
+    pub fn union_ref(&self, other: &Self) -> Self {
+        PrefixTreeSet { map: self.map.union_ref(&other.map) }
+    }
+
+    /// Takes the intersection of `self` and `other` without consuming either of them.
+    pub fn intersection_ref(&self, other: &Self) -> Self {
+        PrefixTreeSet { map: self.map.intersection_ref(&other.map) }
+    }
+
+    /// Returns the items of `self` that are not found in `other`.
+    pub fn difference_ref(&self, other: &Self) -> Self {
+        PrefixTreeSet { map: self.map.difference_ref(&other.map) }
+    }
+
+    /// Returns the items of either set that are not found in the other set.
+    pub fn symmetric_difference_ref(&self, other: &Self) -> Self {
+        PrefixTreeSet { map: self.map.symmetric_difference_ref(&other.map) }
+    }
+}
+
+/// Specialized lookups for sets of fixed-width byte arrays.
+/// See the corresponding methods of [`PrefixTreeMap`] for details.
+impl<const N: usize> PrefixTreeSet<[u8; N]> {
+    /// Returns `true` if the fixed-width item is found in the set, `false` otherwise.
+    pub fn contains_fixed(&self, item: &[u8; N]) -> bool {
+        self.map.contains_fixed_key(item)
+    }
 }
 
 impl<T> Default for PrefixTreeSet<T> {
@@ -203,6 +678,16 @@ impl<T: AsRef<[u8]>> Extend<T> for PrefixTreeSet<T> {
     }
 }
 
+/// Clones the items, like the standard collections.
+impl<'a, T: Clone + AsRef<[u8]>> Extend<&'a T> for PrefixTreeSet<T> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = &'a T>
+    {
+        self.union_in_place(iter.into_iter().cloned());
+    }
+}
+
 impl<T> IntoIterator for PrefixTreeSet<T> {
     type IntoIter = IntoIter<T>;
     type Item = T;
@@ -254,6 +739,18 @@ where
     }
 }
 
+/// Produces the difference of `self` and `other`.
+impl<T, I> SubAssign<I> for PrefixTreeSet<T>
+where
+    T: AsRef<[u8]>,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    fn sub_assign(&mut self, other: I) {
+        self.difference_in_place(other);
+    }
+}
+
 /// Produces the intersection of `self` and `other`.
 impl<T, I> BitAnd<I> for PrefixTreeSet<T>
 where
@@ -306,6 +803,68 @@ where
     }
 }
 
+/// Produces the difference of `self` and `other`.
+impl<T, I> Sub<I> for PrefixTreeSet<T>
+where
+    T: AsRef<[u8]>,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Output = Self;
+
+    fn sub(self, other: I) -> Self::Output {
+        self.difference(other)
+    }
+}
+
+/// Produces the intersection of `self` and `other`, leaving both intact.
+impl<T> BitAnd<&PrefixTreeSet<T>> for &PrefixTreeSet<T>
+where
+    T: Clone + AsRef<[u8]>,
+{
+    type Output = PrefixTreeSet<T>;
+
+    fn bitand(self, other: &PrefixTreeSet<T>) -> Self::Output {
+        self.intersection_ref(other)
+    }
+}
+
+/// Produces the union of `self` and `other`, leaving both intact.
+impl<T> BitOr<&PrefixTreeSet<T>> for &PrefixTreeSet<T>
+where
+    T: Clone + AsRef<[u8]>,
+{
+    type Output = PrefixTreeSet<T>;
+
+    fn bitor(self, other: &PrefixTreeSet<T>) -> Self::Output {
+        self.union_ref(other)
+    }
+}
+
+/// Produces the symmetric difference of `self` and `other`, leaving both intact.
+impl<T> BitXor<&PrefixTreeSet<T>> for &PrefixTreeSet<T>
+where
+    T: Clone + AsRef<[u8]>,
+{
+    type Output = PrefixTreeSet<T>;
+
+    fn bitxor(self, other: &PrefixTreeSet<T>) -> Self::Output {
+        self.symmetric_difference_ref(other)
+    }
+}
+
+/// Produces the difference of `self` and `other`, leaving both intact.
+impl<T> Sub<&PrefixTreeSet<T>> for &PrefixTreeSet<T>
+where
+    T: Clone + AsRef<[u8]>,
+{
+    type Output = PrefixTreeSet<T>;
+
+    fn sub(self, other: &PrefixTreeSet<T>) -> Self::Output {
+        self.difference_ref(other)
+    }
+}
+
 impl<T: Debug> Debug for PrefixTreeSet<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_set().entries(self).finish()
@@ -313,7 +872,6 @@ impl<T: Debug> Debug for PrefixTreeSet<T> {
 }
 
 /// An iterator over the owned items of this set.
-#[derive(Debug)]
 pub struct IntoIter<T> {
     keys: IntoKeys<T, ()>,
 }
@@ -330,6 +888,12 @@ impl<T: Clone> Clone for IntoIter<T> {
     }
 }
 
+impl<T: Debug> Debug for IntoIter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.keys, f)
+    }
+}
+
 impl<T> Iterator for IntoIter<T> {
     type Item = T;
 
@@ -342,6 +906,12 @@ impl<T> Iterator for IntoIter<T> {
     }
 }
 
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.keys.next_back()
+    }
+}
+
 impl<T> FusedIterator for IntoIter<T> {}
 
 impl<T> ExactSizeIterator for IntoIter<T> {
@@ -351,7 +921,6 @@ impl<T> ExactSizeIterator for IntoIter<T> {
 }
 
 /// An iterator over the borrowed items of this set.
-#[derive(Debug)]
 pub struct Iter<'a, T> {
     keys: Keys<'a, T, ()>,
 }
@@ -368,9 +937,15 @@ impl<T> Clone for Iter<'_, T> {
     }
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-
+impl<T: Debug> Debug for Iter<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.keys, f)
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
     fn next(&mut self) -> Option<Self::Item> {
         self.keys.next()
     }
@@ -380,6 +955,12 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 }
 
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.keys.next_back()
+    }
+}
+
 impl<T> FusedIterator for Iter<'_, T> {}
 
 impl<T> ExactSizeIterator for Iter<'_, T> {
@@ -388,15 +969,145 @@ impl<T> ExactSizeIterator for Iter<'_, T> {
     }
 }
 
+/// An iterator over the borrowed items of this set as byte strings.
+pub struct KeysAsBytes<'a, T> {
+    keys: map::KeysAsBytes<'a, T, ()>,
+}
+
+impl<T> Default for KeysAsBytes<'_, T> {
+    fn default() -> Self {
+        KeysAsBytes { keys: map::KeysAsBytes::default() }
+    }
+}
+
+impl<T> Clone for KeysAsBytes<'_, T> {
+    fn clone(&self) -> Self {
+        KeysAsBytes { keys: self.keys.clone() }
+    }
+}
+
+impl<T: AsRef<[u8]>> Debug for KeysAsBytes<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.keys, f)
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for KeysAsBytes<'a, T> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.keys.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<T: AsRef<[u8]>> DoubleEndedIterator for KeysAsBytes<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.keys.next_back()
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for KeysAsBytes<'_, T> {}
+
+impl<T: AsRef<[u8]>> ExactSizeIterator for KeysAsBytes<'_, T> {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// An iterator over the items removed from a set by [`PrefixTreeSet::drain`].
+pub struct Drain<'a, T> {
+    iter: MapDrain<'a, T, ()>,
+}
+
+impl<T> Default for Drain<'_, T> {
+    fn default() -> Self {
+        Drain { iter: MapDrain::default() }
+    }
+}
+
+impl<T: Debug> Debug for Drain<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.for_each_remaining(&mut |key, ()| { list.entry(key); });
+        list.finish()
+    }
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, ()) = self.iter.next()?;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, ()) = self.iter.next_back()?;
+        Some(key)
+    }
+}
+
+impl<T> FusedIterator for Drain<'_, T> {}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// An iterator removing and yielding the items selected by a predicate.
+/// Created by [`PrefixTreeSet::extract_if`].
+pub struct ExtractIf<'a, T, F> {
+    cursor: ExtractCursor<'a, T, ()>,
+    pred: F,
+}
+
+impl<T, F> Debug for ExtractIf<'_, T, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractIf").finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Iterator for ExtractIf<'_, T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+        let (key, ()) = self.cursor.next_matching(&mut |key, ()| pred(key))?;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.cursor.max_remaining()))
+    }
+}
+
+impl<T, F> FusedIterator for ExtractIf<'_, T, F>
+where
+    F: FnMut(&T) -> bool,
+{}
+
 /// An iterator over values of a subtree, i.e., a set of elements sharing a common prefix.
-#[derive(Debug)]
 pub struct IntoPrefixIter<T> {
-    iter: NodeIntoIter<T, ()>,
+    iter: map::IntoPrefixIter<T, ()>,
 }
 
 impl<T> Default for IntoPrefixIter<T> {
     fn default() -> Self {
-        IntoPrefixIter { iter: NodeIntoIter::default() }
+        IntoPrefixIter { iter: map::IntoPrefixIter::default() }
     }
 }
 
@@ -406,6 +1117,14 @@ impl<T: Clone> Clone for IntoPrefixIter<T> {
     }
 }
 
+impl<T: Debug> Debug for IntoPrefixIter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        self.iter.for_each_remaining(&mut |key, ()| { list.entry(key); });
+        list.finish()
+    }
+}
+
 impl<T> Iterator for IntoPrefixIter<T> {
     type Item = T;
 
@@ -419,17 +1138,29 @@ impl<T> Iterator for IntoPrefixIter<T> {
     }
 }
 
+impl<T> DoubleEndedIterator for IntoPrefixIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, ()) = self.iter.next_back()?;
+        Some(key)
+    }
+}
+
 impl<T> FusedIterator for IntoPrefixIter<T> {}
 
+impl<T> ExactSizeIterator for IntoPrefixIter<T> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
 /// An iterator over references in a subtree, i.e., a set of elements sharing a common prefix.
-#[derive(Debug)]
 pub struct PrefixIter<'a, T> {
-    iter: NodeIter<'a, T, ()>,
+    iter: map::PrefixIter<'a, T, ()>,
 }
 
 impl<T> Default for PrefixIter<'_, T> {
     fn default() -> Self {
-        PrefixIter { iter: NodeIter::default() }
+        PrefixIter { iter: map::PrefixIter::default() }
     }
 }
 
@@ -439,6 +1170,12 @@ impl<T> Clone for PrefixIter<'_, T> {
     }
 }
 
+impl<T: Debug> Debug for PrefixIter<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
 impl<'a, T> Iterator for PrefixIter<'a, T> {
     type Item = &'a T;
 
@@ -452,8 +1189,557 @@ impl<'a, T> Iterator for PrefixIter<'a, T> {
     }
 }
 
+impl<T> DoubleEndedIterator for PrefixIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, ()) = self.iter.next_back()?;
+        Some(key)
+    }
+}
+
 impl<T> FusedIterator for PrefixIter<'_, T> {}
 
+impl<T> ExactSizeIterator for PrefixIter<'_, T> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+/// An iterator over the items that are prefixes of a given query.
+/// Created by [`PrefixTreeSet::prefixes_of`].
+pub struct PrefixesOf<'a, T> {
+    iter: map::PrefixesOf<'a, T, ()>,
+}
+
+impl<T> Default for PrefixesOf<'_, T> {
+    fn default() -> Self {
+        PrefixesOf { iter: map::PrefixesOf::default() }
+    }
+}
+
+impl<T> Clone for PrefixesOf<'_, T> {
+    fn clone(&self) -> Self {
+        PrefixesOf { iter: self.iter.clone() }
+    }
+}
+
+impl<T: Debug> Debug for PrefixesOf<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T> Iterator for PrefixesOf<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, ()) = self.iter.next()?;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> FusedIterator for PrefixesOf<'_, T> {}
+
+/// An iterator over the tokens of a text, split by greedy longest-prefix
+/// matching. Created by [`PrefixTreeSet::segment_iter`].
+pub struct SegmentIter<'a, T> {
+    iter: map::SegmentIter<'a, T, ()>,
+}
+
+impl<'a, T> SegmentIter<'a, T> {
+    /// Returns the part of the text which hasn't been matched or skipped yet.
+    pub fn remainder(&self) -> &'a [u8] {
+        self.iter.remainder()
+    }
+}
+
+impl<T> Default for SegmentIter<'_, T> {
+    fn default() -> Self {
+        SegmentIter { iter: map::SegmentIter::default() }
+    }
+}
+
+impl<T> Clone for SegmentIter<'_, T> {
+    fn clone(&self) -> Self {
+        SegmentIter { iter: self.iter.clone() }
+    }
+}
+
+impl<T: Debug> Debug for SegmentIter<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T> Iterator for SegmentIter<'a, T> {
+    type Item = (Range<usize>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (range, item, ()) = self.iter.next()?;
+        Some((range, item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> FusedIterator for SegmentIter<'_, T> {}
+
+/// Iterator over the items within a given edit distance of a query.
+/// Created by [`PrefixTreeSet::fuzzy_iter`].
+pub struct FuzzyIter<'a, T> {
+    iter: map::FuzzyIter<'a, T, ()>,
+}
+
+impl<T> Default for FuzzyIter<'_, T> {
+    fn default() -> Self {
+        FuzzyIter { iter: map::FuzzyIter::default() }
+    }
+}
+
+impl<T> Clone for FuzzyIter<'_, T> {
+    fn clone(&self) -> Self {
+        FuzzyIter { iter: self.iter.clone() }
+    }
+}
+
+impl<T: Debug> Debug for FuzzyIter<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T> Iterator for FuzzyIter<'a, T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (item, (), distance) = self.iter.next()?;
+        Some((item, distance))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> FusedIterator for FuzzyIter<'_, T> {}
+
+/// Walks two sets side by side in lexicographic order, pairing up equal
+/// items. The building block of the lazy set operations.
+struct Merge<'a, T> {
+    zip: map::ZipByKey<'a, T, (), T, ()>,
+}
+
+impl<'a, T: AsRef<[u8]>> Merge<'a, T> {
+    fn new(lhs: &'a PrefixTreeSet<T>, rhs: &'a PrefixTreeSet<T>) -> Self {
+        Merge { zip: lhs.map.zip_by_key(&rhs.map) }
+    }
+
+    /// The number of items not yet visited in each set.
+    fn remaining(&self) -> (usize, usize) {
+        self.zip.remaining()
+    }
+}
+
+impl<T> Clone for Merge<'_, T> {
+    fn clone(&self) -> Self {
+        Merge { zip: self.zip.clone() }
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for Merge<'a, T> {
+    type Item = (Option<&'a T>, Option<&'a T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (lhs, rhs) = self.zip.next()?;
+        Some((lhs.map(|(item, ())| item), rhs.map(|(item, ())| item)))
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for Merge<'_, T> {}
+
+/// Lazy iterator over the union of two sets.
+/// Created by [`PrefixTreeSet::union_iter`].
+pub struct Union<'a, T> {
+    merge: Merge<'a, T>,
+}
+
+impl<T> Clone for Union<'_, T> {
+    fn clone(&self) -> Self {
+        Union { merge: self.merge.clone() }
+    }
+}
+
+impl<T: AsRef<[u8]> + Debug> Debug for Union<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for Union<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (lhs, rhs) = self.merge.next()?;
+        lhs.or(rhs)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lhs, rhs) = self.merge.remaining();
+        (lhs.max(rhs), Some(lhs + rhs))
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for Union<'_, T> {}
+
+/// Lazy iterator over the intersection of two sets.
+/// Created by [`PrefixTreeSet::intersection_iter`].
+pub struct Intersection<'a, T> {
+    merge: Merge<'a, T>,
+}
+
+impl<T> Clone for Intersection<'_, T> {
+    fn clone(&self) -> Self {
+        Intersection { merge: self.merge.clone() }
+    }
+}
+
+impl<T: AsRef<[u8]> + Debug> Debug for Intersection<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for Intersection<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merge.find_map(|pair| match pair {
+            (Some(lhs), Some(_)) => Some(lhs),
+            _ => None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lhs, rhs) = self.merge.remaining();
+        (0, Some(lhs.min(rhs)))
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for Intersection<'_, T> {}
+
+/// Lazy iterator over the difference of two sets.
+/// Created by [`PrefixTreeSet::difference_iter`].
+pub struct Difference<'a, T> {
+    merge: Merge<'a, T>,
+}
+
+impl<T> Clone for Difference<'_, T> {
+    fn clone(&self) -> Self {
+        Difference { merge: self.merge.clone() }
+    }
+}
+
+impl<T: AsRef<[u8]> + Debug> Debug for Difference<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for Difference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // once `self` is exhausted, there is no point in walking `other`
+        if self.merge.remaining().0 == 0 {
+            return None;
+        }
+        self.merge.find_map(|pair| match pair {
+            (Some(lhs), None) => Some(lhs),
+            _ => None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lhs, rhs) = self.merge.remaining();
+        (lhs.saturating_sub(rhs), Some(lhs))
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for Difference<'_, T> {}
+
+/// Lazy iterator over the symmetric difference of two sets.
+/// Created by [`PrefixTreeSet::symmetric_difference_iter`].
+pub struct SymmetricDifference<'a, T> {
+    merge: Merge<'a, T>,
+}
+
+impl<T> Clone for SymmetricDifference<'_, T> {
+    fn clone(&self) -> Self {
+        SymmetricDifference { merge: self.merge.clone() }
+    }
+}
+
+impl<T: AsRef<[u8]> + Debug> Debug for SymmetricDifference<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<'a, T: AsRef<[u8]>> Iterator for SymmetricDifference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.merge.find_map(|pair| match pair {
+            (Some(item), None) | (None, Some(item)) => Some(item),
+            _ => None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lhs, rhs) = self.merge.remaining();
+        (0, Some(lhs + rhs))
+    }
+}
+
+impl<T: AsRef<[u8]>> FusedIterator for SymmetricDifference<'_, T> {}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+pub mod defmt {
+    use defmt::{Format, Formatter};
+    use crate::map::defmt::write_key_sample;
+    use crate::set::PrefixTreeSet;
+
+
+    /// Formats a summary of the set: its length and the first few items.
+    impl<T: AsRef<[u8]>> Format for PrefixTreeSet<T> {
+        fn format(&self, fmt: Formatter<'_>) {
+            defmt::write!(fmt, "PrefixTreeSet {{ len: {=usize}, items: ", self.len());
+            write_key_sample(fmt, self.iter().map(AsRef::as_ref), self.len());
+            defmt::write!(fmt, " }}");
+        }
+    }
+}
+
+/// Zero-copy archiving with `rkyv`. Sets are archived as an
+/// `ArchivedBTreeSet`, with the same requirements as maps.
+#[cfg(feature = "rkyv")]
+#[doc(hidden)]
+pub mod rkyv {
+    use core::ops::ControlFlow;
+    use rkyv::{
+        Archive, Serialize, Deserialize, Place,
+        collections::btree_set::{ArchivedBTreeSet, BTreeSetResolver},
+        rancor::{Fallible, Source},
+        ser::{Allocator, Writer},
+    };
+    use crate::set::PrefixTreeSet;
+
+
+    impl<T> Archive for PrefixTreeSet<T>
+    where
+        T: Archive,
+        T::Archived: Ord,
+    {
+        type Archived = ArchivedBTreeSet<T::Archived>;
+        type Resolver = BTreeSetResolver;
+
+        fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+            ArchivedBTreeSet::resolve_from_len(self.len(), resolver, out);
+        }
+    }
+
+    impl<T, S> Serialize<S> for PrefixTreeSet<T>
+    where
+        T: Serialize<S>,
+        T::Archived: Ord,
+        S: Fallible + Allocator + Writer + ?Sized,
+        S::Error: Source,
+    {
+        fn serialize(&self, ser: &mut S) -> Result<Self::Resolver, S::Error> {
+            ArchivedBTreeSet::<T::Archived>::serialize_from_ordered_iter::<_, T, _>(self.iter(), ser)
+        }
+    }
+
+    impl<T, D> Deserialize<PrefixTreeSet<T>, D> for ArchivedBTreeSet<T::Archived>
+    where
+        T: Archive + AsRef<[u8]>,
+        T::Archived: Deserialize<T, D> + Ord,
+        D: Fallible + ?Sized,
+    {
+        fn deserialize(&self, de: &mut D) -> Result<PrefixTreeSet<T>, D::Error> {
+            let mut set = PrefixTreeSet::new();
+            let error = self.visit(|item| {
+                match item.deserialize(de) {
+                    Ok(item) => {
+                        set.insert(item);
+                        ControlFlow::Continue(())
+                    }
+                    Err(error) => ControlFlow::Break(error),
+                }
+            });
+
+            match error {
+                Some(error) => Err(error),
+                None => Ok(set),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rkyv::rancor::Error;
+        use crate::set::PrefixTreeSet;
+
+        #[test]
+        fn rkyv_roundtrip() {
+            let set = PrefixTreeSet::from([b"".to_vec(), b"abc".to_vec(), b"abd".to_vec(), b"\xff".to_vec()]);
+            let bytes = rkyv::to_bytes::<Error>(&set).unwrap();
+            let archived = rkyv::access::<rkyv::Archived<PrefixTreeSet<Vec<u8>>>, Error>(&bytes).unwrap();
+
+            assert_eq!(archived.len(), 4);
+            assert!(archived.contains_key(b"abd".as_slice()));
+            assert!(!archived.contains_key(b"ab".as_slice()));
+
+            let copy: PrefixTreeSet<Vec<u8>> = rkyv::deserialize::<_, Error>(archived).unwrap();
+            assert_eq!(copy, set);
+        }
+    }
+}
+
+/// Native `bincode` 2 encoding, in the same format as a `BTreeSet`.
+#[cfg(feature = "bincode")]
+#[doc(hidden)]
+pub mod bincode {
+    use core::mem;
+    use bincode::{
+        Encode, Decode, BorrowDecode,
+        enc::Encoder,
+        de::{Decoder, BorrowDecoder},
+        error::{EncodeError, DecodeError},
+    };
+    use crate::map::bincode::decode_len;
+    use crate::set::PrefixTreeSet;
+
+
+    impl<T: Encode> Encode for PrefixTreeSet<T> {
+        fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+            (self.len() as u64).encode(encoder)?;
+
+            for item in self {
+                item.encode(encoder)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    impl<T, C> Decode<C> for PrefixTreeSet<T>
+    where
+        T: Decode<C> + AsRef<[u8]>,
+    {
+        fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let len = decode_len::<T, _>(decoder)?;
+            let mut set = PrefixTreeSet::new();
+
+            for _ in 0..len {
+                decoder.unclaim_bytes_read(mem::size_of::<T>());
+                set.insert(T::decode(decoder)?);
+            }
+
+            Ok(set)
+        }
+    }
+
+    impl<'de, T, C> BorrowDecode<'de, C> for PrefixTreeSet<T>
+    where
+        T: BorrowDecode<'de, C> + AsRef<[u8]>,
+    {
+        fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let len = decode_len::<T, _>(decoder)?;
+            let mut set = PrefixTreeSet::new();
+
+            for _ in 0..len {
+                decoder.unclaim_bytes_read(mem::size_of::<T>());
+                set.insert(T::borrow_decode(decoder)?);
+            }
+
+            Ok(set)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeSet;
+        use bincode::config;
+        use crate::set::PrefixTreeSet;
+
+        #[test]
+        fn bincode_roundtrip() {
+            let set = PrefixTreeSet::from([b"".to_vec(), b"abc".to_vec(), b"abd".to_vec(), b"\xff".to_vec()]);
+            let bytes = bincode::encode_to_vec(&set, config::standard()).unwrap();
+
+            let btree: BTreeSet<Vec<u8>> = set.iter().cloned().collect();
+            assert_eq!(bincode::encode_to_vec(&btree, config::standard()).unwrap(), bytes);
+
+            let (copy, _): (PrefixTreeSet<Vec<u8>>, _) = bincode::decode_from_slice(&bytes, config::standard()).unwrap();
+            assert_eq!(copy, set);
+
+            let (borrowed, _): (PrefixTreeSet<&[u8]>, _) = bincode::borrow_decode_from_slice(&bytes, config::standard()).unwrap();
+            assert!(borrowed.iter().copied().eq(set.iter().map(Vec::as_slice)));
+        }
+    }
+}
+
+/// The structure-preserving serialized format of [`crate::map::structural`],
+/// for sets. Use with `#[serde(with = "pfx::set::structural")]`.
+#[cfg(feature = "serde")]
+pub mod structural {
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use crate::map;
+    use crate::set::PrefixTreeSet;
+
+    /// Serializes the set along with its structure.
+    pub fn serialize<T, S>(set: &PrefixTreeSet<T>, ser: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        map::structural::serialize(&set.map, ser)
+    }
+
+    /// Deserializes a set serialized by [`serialize()`].
+    pub fn deserialize<'de, T, D>(de: D) -> Result<PrefixTreeSet<T>, D::Error>
+    where
+        T: Deserialize<'de> + AsRef<[u8]>,
+        D: Deserializer<'de>,
+    {
+        map::structural::deserialize(de).map(PrefixTreeSet::from_map)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::set::PrefixTreeSet;
+
+        #[test]
+        fn structural_roundtrip() {
+            let words = PrefixTreeSet::from(["", "zoo", "zoom", "zoology"].map(String::from));
+            let mut json = Vec::new();
+            super::serialize(&words, &mut serde_json::Serializer::new(&mut json)).unwrap();
+
+            let copy: PrefixTreeSet<String> =
+                super::deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap();
+
+            assert_eq!(copy, words);
+        }
+    }
+}
+
 #[cfg(feature = "serde")]
 #[doc(hidden)]
 pub mod serde {
@@ -536,6 +1822,16 @@ pub mod serde {
             assert!(pfx_seq.iter().eq(&std_seq));
         }
 
+        #[test]
+        fn borrowed_items() {
+            let json = r#"["foo", "bar", "foobar"]"#;
+            let input = json.as_bytes().as_ptr_range();
+            let set: PrefixTreeSet<&str> = serde_json::from_str(json).unwrap();
+
+            assert_eq!(set.len(), 3);
+            assert!(set.iter().all(|item| input.contains(&item.as_ptr())));
+        }
+
         #[test]
         fn pfx_to_std() {
             let pfx_seq = PrefixTreeSet::from([
@@ -552,3 +1848,107 @@ pub mod serde {
         }
     }
 }
+
+/// Iteration over the items accepted by a deterministic finite automaton.
+/// See [`crate::map::automaton`] for details.
+#[cfg(feature = "regex-automata")]
+pub mod automaton {
+    use core::fmt::{self, Debug, Formatter};
+    use core::iter::FusedIterator;
+    use regex_automata::dfa::{Automaton, StartError};
+    use crate::map::automaton::AutomatonIter as MapAutomatonIter;
+    use crate::set::PrefixTreeSet;
+
+
+    impl<T> PrefixTreeSet<T> {
+        /// An iterator over the items wholly matched by `dfa`, in
+        /// lexicographic order. See [`crate::PrefixTreeMap::automaton_iter`]
+        /// for details.
+        pub fn automaton_iter<A: Automaton>(&self, dfa: A) -> Result<AutomatonIter<'_, T, A>, StartError> {
+            Ok(AutomatonIter { iter: self.map.automaton_iter(dfa)? })
+        }
+    }
+
+    /// Iterator over the items accepted by an automaton.
+    /// Created by [`PrefixTreeSet::automaton_iter`].
+    pub struct AutomatonIter<'a, T, A> {
+        iter: MapAutomatonIter<'a, T, (), A>,
+    }
+
+    impl<T, A: Clone> Clone for AutomatonIter<'_, T, A> {
+        fn clone(&self) -> Self {
+            AutomatonIter { iter: self.iter.clone() }
+        }
+    }
+
+    impl<T: Debug, A: Automaton + Clone> Debug for AutomatonIter<'_, T, A> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.debug_list().entries(self.clone()).finish()
+        }
+    }
+
+    impl<'a, T, A: Automaton> Iterator for AutomatonIter<'a, T, A> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let (item, ()) = self.iter.next()?;
+            Some(item)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.iter.size_hint()
+        }
+    }
+
+    impl<T, A: Automaton> FusedIterator for AutomatonIter<'_, T, A> {}
+}
+
+/// Uniform random sampling of items.
+#[cfg(feature = "rand")]
+mod sample {
+    use rand::Rng;
+    use crate::set::PrefixTreeSet;
+
+
+    impl<T> PrefixTreeSet<T> {
+        /// Returns an item chosen uniformly at random, or `None` if the set
+        /// is empty. See [`crate::PrefixTreeMap::sample`] for details.
+        pub fn sample<R>(&self, rng: &mut R) -> Option<&T>
+        where
+            R: ?Sized + Rng,
+        {
+            self.map.sample(rng).map(|(item, ())| item)
+        }
+
+        /// Returns an item chosen uniformly at random among those starting
+        /// with `prefix`, or `None` if there are none.
+        pub fn sample_with_prefix<Q, R>(&self, prefix: &Q, rng: &mut R) -> Option<&T>
+        where
+            Q: ?Sized + AsRef<[u8]>,
+            R: ?Sized + Rng,
+        {
+            self.map.sample_with_prefix(prefix, rng).map(|(item, ())| item)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use crate::set::PrefixTreeSet;
+
+        #[test]
+        fn sample() {
+            let mut rng = SmallRng::seed_from_u64(1);
+            let set = PrefixTreeSet::from(["tea", "ted", "ten", "to"]);
+
+            for _ in 0..100 {
+                assert!(set.contains(set.sample(&mut rng).unwrap()));
+                assert!(set.sample_with_prefix("te", &mut rng).unwrap().starts_with("te"));
+            }
+
+            assert_eq!(set.sample_with_prefix("x", &mut rng), None);
+            assert_eq!(PrefixTreeSet::<&str>::new().sample(&mut rng), None);
+        }
+    }
+}
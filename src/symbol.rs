@@ -0,0 +1,420 @@
+//! A map keyed by sequences of arbitrary symbols.
+//!
+//! A [`crate::PrefixTreeMap`] branches on the bytes of its keys, so keys
+//! made of wider symbols, such as `u32` token IDs or `char`s, have to be
+//! encoded as byte strings first, and the tree then branches on the bytes of
+//! the encoding. The edges of a [`SymbolMap`] are labelled by symbols of any
+//! type which is `Ord + Copy` instead, and the tree branches on whole symbols.
+//!
+//! Like an [`ImplicitMap`](crate::implicit::ImplicitMap), it only stores the
+//! labels of the edges, and reconstructs the keys, as vectors of symbols,
+//! during iteration. It offers the basic operations of a map and prefix
+//! iteration; the rest of the API of [`crate::PrefixTreeMap`] (ranges,
+//! cursors, entries, set operations, serialization) is only available for
+//! byte strings.
+//!
+//! ```
+//! # use pfx::symbol::SymbolMap;
+//! let mut map = SymbolMap::new();
+//!
+//! map.insert(&[17_u32, 4000, 2], "a b c");
+//! map.insert(&[17, 4000], "a b");
+//! map.insert(&[17, 3], "a d");
+//! map.insert(&[256], "e");
+//!
+//! assert_eq!(map.get(&[17, 4000]), Some(&"a b"));
+//! assert_eq!(
+//!     map.prefix_iter(&[17, 4000]).collect::<Vec<_>>(),
+//!     [(vec![17, 4000], &"a b"), (vec![17, 4000, 2], &"a b c")],
+//! );
+//! assert!(map.keys().eq([vec![17, 3], vec![17, 4000], vec![17, 4000, 2], vec![256]]));
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::hash::{Hash, Hasher};
+use core::iter;
+use core::mem;
+use alloc::vec::Vec;
+use crate::edge::{Edge, EdgeMut};
+
+
+/// A map from sequences of symbols of type `S` to arbitrary values, storing
+/// only the values and the edge labels of the tree, but not the keys
+/// themselves.
+///
+/// Edges without branching are kept path-compressed after every insertion
+/// and removal, so the shape of the tree is determined solely by the entries.
+#[derive(Clone)]
+pub struct SymbolMap<S, V> {
+    /// The value under the empty key, which is the only one not below an edge.
+    value: Option<V>,
+    /// Created along with the first non-empty key, because its label, while
+    /// meaningless, has to be some symbol. It never has a value of its own.
+    root: Option<Node<S, V>>,
+    len: usize,
+}
+
+impl<S: Ord + Copy, V> Default for SymbolMap<S, V> {
+    fn default() -> Self {
+        SymbolMap::new()
+    }
+}
+
+impl<S: Ord + Copy, V> SymbolMap<S, V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        SymbolMap {
+            value: None,
+            root: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if and only if this map contains no entries.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        *self = SymbolMap::new();
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        match key.as_ref() {
+            [] => self.value.as_ref(),
+            symbols => self.root.as_ref()?.search(symbols)?.value.as_ref(),
+        }
+    }
+
+    /// Return a mutable reference to the value, if found.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        let symbols = key.as_ref();
+
+        if symbols.is_empty() {
+            return self.value.as_mut();
+        }
+
+        let root = self.root.as_mut()?;
+        let mut path = Vec::new();
+
+        if root.locate(symbols, &mut path) {
+            root.descendant_mut(&path).value.as_mut()
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Inserts the value under the key. Returns the old value, if any.
+    pub fn insert<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        let old_value = match key.as_ref() {
+            [] => self.value.replace(value),
+            symbols @ [first, ..] => self.root
+                .get_or_insert_with(|| Node::with_label(*first, Vec::new()))
+                .insert(symbols, value),
+        };
+
+        if old_value.is_none() {
+            self.len += 1;
+        }
+
+        old_value
+    }
+
+    /// If the key exists in the map, removes it and returns its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        let value = match key.as_ref() {
+            [] => self.value.take()?,
+            symbols => self.root.as_mut()?.remove(symbols)?,
+        };
+
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// An iterator over pairs of reconstructed keys and references to the
+    /// corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order, as determined by the sequence of symbols of keys.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<S>, &V)> + '_ {
+        self.prefix_iter(&[])
+    }
+
+    /// An iterator over the reconstructed keys, in lexicographic order.
+    pub fn keys(&self) -> impl Iterator<Item = Vec<S>> + '_ {
+        self.iter().map(|(key, _value)| key)
+    }
+
+    /// An iterator over the values, in the lexicographic order of the keys.
+    /// This doesn't need to reconstruct the keys.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        let mut stack = Vec::from_iter(&self.root);
+
+        self.value.iter().chain(iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.iter().rev());
+
+                if let Some(value) = node.value.as_ref() {
+                    return Some(value);
+                }
+            }
+
+            None
+        }))
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter<Q>(&self, prefix: &Q) -> impl Iterator<Item = (Vec<S>, &V)> + '_
+    where
+        Q: ?Sized + AsRef<[S]>,
+    {
+        let prefix = prefix.as_ref();
+
+        // the empty key only starts with the empty prefix
+        let value = self.value.as_ref().filter(|_| prefix.is_empty());
+
+        // the full key of the node found, which may be longer than `prefix`
+        let found = self.root.as_ref().and_then(|root| root.search_prefix(prefix)).map(|(node, rest)| {
+            let mut key = prefix.to_vec();
+            key.extend_from_slice(rest);
+            (node, key)
+        });
+
+        value
+            .map(|value| (Vec::new(), value))
+            .into_iter()
+            .chain(found.into_iter().flat_map(|(node, key)| node.iter(key)))
+    }
+}
+
+impl<Q, S, V> FromIterator<(Q, V)> for SymbolMap<S, V>
+where
+    Q: AsRef<[S]>,
+    S: Ord + Copy,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (Q, V)>
+    {
+        let mut map = SymbolMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<Q, S, V> Extend<(Q, V)> for SymbolMap<S, V>
+where
+    Q: AsRef<[S]>,
+    S: Ord + Copy,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (Q, V)>
+    {
+        for (key, value) in iter {
+            self.insert(&key, value);
+        }
+    }
+}
+
+impl<S: Ord + Copy, V: PartialEq> PartialEq for SymbolMap<S, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<S: Ord + Copy, V: Eq> Eq for SymbolMap<S, V> {}
+
+impl<S: Ord + Copy + Hash, V: Hash> Hash for SymbolMap<S, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.iter().for_each(|entry| entry.hash(state));
+    }
+}
+
+/// Keys are formatted as lists of symbols.
+impl<S: Ord + Copy + Debug, V: Debug> Debug for SymbolMap<S, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A node of the tree, with the edges labelled in the same way as in
+/// [`crate::PrefixTreeMap`], except that the symbols are of type `S` instead
+/// of bytes. Apart from the root, every node either has a value or at least
+/// two children.
+#[derive(Clone)]
+struct Node<S, V> {
+    value: Option<V>,
+    key_fragment: S,
+    tail: Vec<S>,
+    children: Vec<Node<S, V>>,
+}
+
+impl<S: Ord + Copy, V> Node<S, V> {
+    const fn with_label(key_fragment: S, tail: Vec<S>) -> Self {
+        Node {
+            value: None,
+            key_fragment,
+            tail,
+            children: Vec::new(),
+        }
+    }
+
+    /// Iterates over the subtree in pre-order, given the full key of `self`.
+    /// The keys of the descendants are built in the same buffer, by replacing
+    /// the labels of the nodes already left behind.
+    fn iter(&self, mut key: Vec<S>) -> impl Iterator<Item = (Vec<S>, &V)> + '_ {
+        // along with each node, the length of the key of its parent,
+        // or `None` for `self`, the key of which is already complete
+        let mut stack = Vec::from([(self, None)]);
+
+        iter::from_fn(move || {
+            while let Some((node, parent_len)) = stack.pop() {
+                if let Some(parent_len) = parent_len {
+                    key.truncate(parent_len);
+                    key.push(node.key_fragment);
+                    key.extend_from_slice(&node.tail);
+                }
+
+                let len = key.len();
+                stack.extend(node.children.iter().rev().map(|child| (child, Some(len))));
+
+                if let Some(value) = node.value.as_ref() {
+                    return Some((key.clone(), value));
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Inserts the value under `symbols`, splitting edges as necessary.
+    /// Returns the old value, if any.
+    fn insert(&mut self, symbols: &[S], value: V) -> Option<V> {
+        let mut node = self;
+        let mut rest = symbols;
+
+        while !rest.is_empty() {
+            let (index, after) = node.branch(rest, Node::with_label);
+
+            node = &mut node.children[index];
+            rest = after;
+        }
+
+        node.value.replace(value)
+    }
+
+    /// Removes the value under `symbols`, then removes or merges the nodes
+    /// along the path which are no longer needed.
+    fn remove(&mut self, symbols: &[S]) -> Option<V> {
+        let mut path = Vec::new();
+
+        if !self.locate(symbols, &mut path) {
+            return None;
+        }
+
+        let value = self.descendant_mut(&path).value.take();
+        self.tidy_at(&path);
+        value
+    }
+}
+
+impl<S: Ord + Copy, V> Edge for Node<S, V> {
+    type Symbol = S;
+    type Child = Self;
+
+    fn key_fragment(&self) -> S {
+        self.key_fragment
+    }
+
+    fn tail(&self) -> &[S] {
+        &self.tail
+    }
+
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
+
+    fn has_item(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+impl<S: Ord + Copy, V> EdgeMut for Node<S, V> {
+    fn tail_mut(&mut self) -> &mut Vec<S> {
+        &mut self.tail
+    }
+
+    fn child_mut(&mut self, index: usize) -> &mut Self {
+        &mut self.children[index]
+    }
+
+    fn insert_child(&mut self, index: usize, child: Self) {
+        self.children.insert(index, child);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children.remove(index);
+    }
+
+    fn push_down(&mut self, key_fragment: S, tail: Vec<S>) {
+        let lower = Node {
+            value: self.value.take(),
+            key_fragment,
+            tail,
+            children: mem::take(&mut self.children),
+        };
+
+        self.children.push(lower);
+    }
+
+    fn pull_up(&mut self) -> (S, Vec<S>) {
+        let mut child = self.children.pop().expect("no child to pull up");
+
+        self.value = child.value.take();
+        self.children = mem::take(&mut child.children);
+
+        (child.key_fragment, mem::take(&mut child.tail))
+    }
+}
+
+/// The descendants are detached and dropped one by one from an explicit
+/// stack, because the default recursive drop glue could overflow the call
+/// stack for trees with very long keys.
+impl<S, V> Drop for Node<S, V> {
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
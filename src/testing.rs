@@ -0,0 +1,243 @@
+//! Differential testing of [`PrefixTreeMap`] against [`BTreeMap`].
+//!
+//! This module is intended for downstream crates (and pfx itself) that want to
+//! fuzz or property-test their usage of the prefix tree. A [`ModelChecker`] applies
+//! every [`Op`] to both a `PrefixTreeMap` and a simple reference model based on
+//! `BTreeMap`, and panics as soon as their observable behavior differs.
+//!
+//! ```
+//! # use pfx::testing::{Op, check_ops};
+//! check_ops([
+//!     Op::Insert("abc", 1),
+//!     Op::Insert("ab", 2),
+//!     Op::EntryOrInsert("abd", 3),
+//!     Op::Remove("abc"),
+//!     Op::PrefixIter("ab"),
+//!     Op::Compact,
+//! ]);
+//! ```
+
+use core::fmt::Debug;
+use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
+use crate::map::PrefixTreeMap;
+
+
+/// A single operation to be applied to both the map and the reference model.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Op<K, V> {
+    /// `map.insert(key, value)`
+    Insert(K, V),
+    /// `map.remove(key)`
+    Remove(K),
+    /// `map.get(key)`
+    Get(K),
+    /// `map.contains_key(key)`
+    ContainsKey(K),
+    /// `map.entry(key).or_insert(value)`
+    EntryOrInsert(K, V),
+    /// `map.entry(key).remove()`
+    EntryRemove(K),
+    /// `map.prefix_iter(prefix)`, compared in its entirety
+    PrefixIter(K),
+    /// `map.contains_prefix(prefix)`
+    ContainsPrefix(K),
+    /// `map.compact()`
+    Compact,
+}
+
+/// A `PrefixTreeMap` paired with a `BTreeMap`-based reference model.
+#[derive(Clone, Debug)]
+pub struct ModelChecker<K, V> {
+    map: PrefixTreeMap<K, V>,
+    model: BTreeMap<Vec<u8>, V>,
+}
+
+impl<K, V> Default for ModelChecker<K, V> {
+    fn default() -> Self {
+        ModelChecker::new()
+    }
+}
+
+impl<K, V> ModelChecker<K, V> {
+    /// Creates a checker with an empty map and an empty model.
+    pub const fn new() -> Self {
+        ModelChecker {
+            map: PrefixTreeMap::new(),
+            model: BTreeMap::new(),
+        }
+    }
+
+    /// The map under test.
+    pub fn map(&self) -> &PrefixTreeMap<K, V> {
+        &self.map
+    }
+
+    /// Returns the map under test, consuming the checker.
+    pub fn into_map(self) -> PrefixTreeMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> ModelChecker<K, V>
+where
+    K: AsRef<[u8]> + Debug,
+    V: Clone + PartialEq + Debug,
+{
+    /// Applies `op` to both the map and the model, then checks that the
+    /// result of the operation and the resulting contents are the same.
+    ///
+    /// # Panics
+    ///
+    /// If the map and the model disagree.
+    pub fn apply(&mut self, op: Op<K, V>) {
+        match op {
+            Op::Insert(key, value) => {
+                let expected = self.model.insert(key.as_ref().to_vec(), value.clone());
+                let actual = self.map.insert(key, value);
+                assert_eq!(actual, expected, "insert() returned a different old value");
+            }
+            Op::Remove(key) => {
+                let expected = self.model.remove(key.as_ref());
+                let actual = self.map.remove(&key);
+                assert_eq!(actual, expected, "remove({key:?}) returned a different value");
+            }
+            Op::Get(key) => {
+                let expected = self.model.get(key.as_ref());
+                let actual = self.map.get(&key);
+                assert_eq!(actual, expected, "get({key:?}) returned a different value");
+            }
+            Op::ContainsKey(key) => {
+                let expected = self.model.contains_key(key.as_ref());
+                let actual = self.map.contains_key(&key);
+                assert_eq!(actual, expected, "contains_key({key:?}) disagrees");
+            }
+            Op::EntryOrInsert(key, value) => {
+                let expected = self.model
+                    .entry(key.as_ref().to_vec())
+                    .or_insert_with(|| value.clone())
+                    .clone();
+                let actual = self.map.entry(key).or_insert(value);
+                assert_eq!(*actual, expected, "entry().or_insert() yielded a different value");
+            }
+            Op::EntryRemove(key) => {
+                let expected = self.model.remove(key.as_ref());
+                let actual = self.map.entry(key).remove();
+                assert_eq!(actual, expected, "entry().remove() returned a different value");
+            }
+            Op::PrefixIter(prefix) => {
+                let prefix = prefix.as_ref();
+                let expected = self.model
+                    .range(prefix.to_vec()..)
+                    .take_while(|(key, _)| key.starts_with(prefix));
+                let actual = self.map
+                    .prefix_iter(prefix)
+                    .map(|(key, value)| (key.as_ref(), value));
+
+                assert!(
+                    actual.eq(expected.map(|(key, value)| (key.as_slice(), value))),
+                    "prefix_iter({prefix:?}) yielded different entries",
+                );
+            }
+            Op::ContainsPrefix(prefix) => {
+                let prefix = prefix.as_ref();
+                let expected = self.model
+                    .range(prefix.to_vec()..)
+                    .next()
+                    .is_some_and(|(key, _)| key.starts_with(prefix));
+                let actual = self.map.contains_prefix(prefix);
+                assert_eq!(actual, expected, "contains_prefix({prefix:?}) disagrees");
+            }
+            Op::Compact => {
+                self.map.compact();
+            }
+        }
+
+        self.check();
+    }
+
+    /// Checks that the map contains exactly the same entries as the model,
+    /// in the same order, and that its internal structure is valid.
+    ///
+    /// # Panics
+    ///
+    /// If the map and the model disagree, or the map is invalid.
+    pub fn check(&self) {
+        if let Err(violation) = self.map.check_invariants() {
+            panic!("invalid tree: {violation}");
+        }
+
+        assert_eq!(self.map.len(), self.model.len(), "len() disagrees");
+        assert_eq!(self.map.iter().len(), self.model.len(), "iter().len() disagrees");
+
+        let actual = self.map.iter().map(|(key, value)| (key.as_ref(), value));
+        let expected = self.model.iter().map(|(key, value)| (key.as_slice(), value));
+
+        assert!(actual.eq(expected), "iteration yielded different entries");
+    }
+}
+
+/// Applies all operations to a fresh [`ModelChecker`], and returns the resulting map.
+///
+/// # Panics
+///
+/// If the map and the model disagree at any point.
+pub fn check_ops<K, V, I>(ops: I) -> PrefixTreeMap<K, V>
+where
+    K: AsRef<[u8]> + Debug,
+    V: Clone + PartialEq + Debug,
+    I: IntoIterator<Item = Op<K, V>>,
+{
+    let mut checker = ModelChecker::new();
+
+    for op in ops {
+        checker.apply(op);
+    }
+
+    checker.into_map()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::{Op, check_ops};
+
+    /// Generates a deterministic pseudo-random sequence of operations
+    /// on short keys over a small alphabet, so that they share prefixes.
+    fn random_ops(seed: u64, count: usize) -> Vec<Op<Vec<u8>, u32>> {
+        let mut state = seed;
+        let mut next = move || {
+            // xorshift64*
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        };
+
+        (0..count)
+            .map(|_| {
+                let key_len = (next() % 6) as usize;
+                let key: Vec<u8> = (0..key_len).map(|_| b"abc\0\xff"[(next() % 5) as usize]).collect();
+                let value = (next() % 1000) as u32;
+
+                match next() % 9 {
+                    0 | 1 => Op::Insert(key, value),
+                    2 => Op::Remove(key),
+                    3 => Op::Get(key),
+                    4 => Op::ContainsKey(key),
+                    5 => Op::EntryOrInsert(key, value),
+                    6 => Op::EntryRemove(key),
+                    7 => if value % 2 == 0 { Op::PrefixIter(key) } else { Op::ContainsPrefix(key) },
+                    _ => Op::Compact,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_ops_agree_with_model() {
+        for seed in 1..=32 {
+            check_ops(random_ops(seed, 500));
+        }
+    }
+}
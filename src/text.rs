@@ -0,0 +1,47 @@
+//! Helpers for building maps and sets directly from raw text.
+
+/// Decides which characters separate the tokens of a text.
+///
+/// This is implemented for single characters, arrays and slices of characters
+/// (any of which is a delimiter), and for `FnMut(char) -> bool` predicates.
+pub trait Delimiter {
+    /// Returns `true` if `c` separates two tokens.
+    fn is_delimiter(&mut self, c: char) -> bool;
+}
+
+impl Delimiter for char {
+    fn is_delimiter(&mut self, c: char) -> bool {
+        *self == c
+    }
+}
+
+impl<const N: usize> Delimiter for [char; N] {
+    fn is_delimiter(&mut self, c: char) -> bool {
+        self.contains(&c)
+    }
+}
+
+impl Delimiter for &[char] {
+    fn is_delimiter(&mut self, c: char) -> bool {
+        self.contains(&c)
+    }
+}
+
+impl<F> Delimiter for F
+where
+    F: FnMut(char) -> bool
+{
+    fn is_delimiter(&mut self, c: char) -> bool {
+        self(c)
+    }
+}
+
+/// Splits `corpus` at every delimiter, skipping empty tokens.
+pub(crate) fn tokens<D>(corpus: &str, mut delimiter: D) -> impl Iterator<Item = &str>
+where
+    D: Delimiter
+{
+    corpus
+        .split(move |c| delimiter.is_delimiter(c))
+        .filter(|token| !token.is_empty())
+}
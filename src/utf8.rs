@@ -0,0 +1,351 @@
+//! Maps and sets of strings, with text-oriented queries.
+//!
+//! [`PrefixTreeMap`] treats keys as byte strings, so some of its results may
+//! end in the middle of a multi-byte UTF-8 sequence: e.g. the longest common
+//! prefix of `"é"` and `"è"` is the single byte `0xc3`. The wrappers in this
+//! module only accept `String` keys and `&str` queries, and every string they
+//! return ends on a char boundary, so such partial code points never appear.
+//!
+//! ```
+//! # use pfx::utf8::StrPrefixTreeMap;
+//! let mut map = StrPrefixTreeMap::new();
+//!
+//! map.insert("résumé".into(), 1);
+//! map.insert("résister".into(), 2);
+//! map.insert("rè".into(), 3);
+//!
+//! assert_eq!(map.longest_common_prefix(), "r");
+//! assert_eq!(map.longest_common_prefix_under("ré"), Some("rés".into()));
+//! assert_eq!(map.prefix_iter("rés").map(|(key, _)| key).collect::<Vec<_>>(), ["résister", "résumé"]);
+//! assert_eq!(map.longest_prefix_of("rèsumé"), Some(("rè", &3)));
+//! ```
+
+use core::fmt::{self, Debug, Formatter};
+use core::ops::Range;
+use alloc::vec::Vec;
+use alloc::string::String;
+use crate::map::{self, PrefixTreeMap, Unmatched};
+
+
+/// A map with `String` keys. See the [module-level documentation](self).
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StrPrefixTreeMap<V> {
+    map: PrefixTreeMap<String, V>,
+}
+
+impl<V> StrPrefixTreeMap<V> {
+    /// Creates an empty map. The same as `Default`.
+    pub const fn new() -> Self {
+        StrPrefixTreeMap { map: PrefixTreeMap::new() }
+    }
+
+    /// Returns the number of entries (key-value pairs) in the map.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if this map contains no key-value pairs.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Inserts a key-value pair. Returns the old value if the key was
+    /// already present.
+    pub fn insert(&mut self, key: String, value: V) -> Option<V> {
+        self.map.insert(key, value)
+    }
+
+    /// Return a reference to the value, if found.
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// Return a mutable reference to the value, if found.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    /// Returns `true` if and only if the given key is found in the map.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Removes the key, returning its value, if found.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    /// Removes the key, returning the owned key and its value, if found.
+    pub fn remove_entry(&mut self, key: &str) -> Option<(String, V)> {
+        self.map.remove_entry(key)
+    }
+
+    /// An iterator over the keys and the corresponding values.
+    ///
+    /// Iteration proceeds in lexicographic order of the bytes of the keys,
+    /// which is the same as the order of their code points.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, &V)> + ExactSizeIterator + '_ {
+        self.map.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// An iterator over the keys, in lexicographic order.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator + '_ {
+        self.map.keys().map(String::as_str)
+    }
+
+    /// An iterator over the values, in lexicographic order of the keys.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator + '_ {
+        self.map.values()
+    }
+
+    /// An iterator over the entries of which the key starts with `prefix`,
+    /// in lexicographic order.
+    pub fn prefix_iter(&self, prefix: &str) -> impl DoubleEndedIterator<Item = (&str, &V)> + ExactSizeIterator + '_ {
+        self.map.prefix_iter(prefix).map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Returns the number of keys starting with `prefix`.
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.map.count_prefix(prefix)
+    }
+
+    /// An iterator over the entries of which the key is a prefix of `query`
+    /// (including `query` itself), in increasing order of key length.
+    pub fn prefixes_of<'a>(&'a self, query: &'a str) -> impl Iterator<Item = (&'a str, &'a V)> + 'a {
+        self.map.prefixes_of(query).map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Returns the entry with the longest key that is a prefix of `query`
+    /// (including `query` itself), if any.
+    pub fn longest_prefix_of(&self, query: &str) -> Option<(&str, &V)> {
+        // `prefixes_of()` ties the lifetime of the results to that of `query`,
+        // so the entry is looked up again, by its index
+        let len = self.map.prefixes_of(query).last()?.0.len();
+        let (key, value) = self.map.get_index(self.map.rank(&query[..len]))?;
+
+        Some((key.as_str(), value))
+    }
+
+    /// Returns the longest string that all keys in the map start with.
+    /// For an empty map, this is the empty string.
+    pub fn longest_common_prefix(&self) -> String {
+        to_char_boundary(self.map.longest_common_prefix())
+    }
+
+    /// Returns the longest string that all keys starting with `prefix` start
+    /// with, or `None` if there are no such keys. The result always starts
+    /// with `prefix`.
+    pub fn longest_common_prefix_under(&self, prefix: &str) -> Option<String> {
+        self.map.longest_common_prefix_under(prefix).map(to_char_boundary)
+    }
+
+    /// Splits `text` into tokens by greedy longest-prefix matching.
+    /// See [`PrefixTreeMap::segment_iter`] for details.
+    ///
+    /// Since every key is a whole string, the ranges of the tokens always
+    /// start and end on char boundaries of `text`.
+    pub fn segment_iter<'a>(&'a self, text: &'a str, unmatched: Unmatched) -> impl Iterator<Item = (Range<usize>, &'a str, &'a V)> + 'a {
+        self.map
+            .segment_iter(text, unmatched)
+            .map(|(range, key, value)| (range, key.as_str(), value))
+    }
+
+    /// Returns the underlying map.
+    pub fn as_map(&self) -> &PrefixTreeMap<String, V> {
+        &self.map
+    }
+
+    /// Returns the underlying map.
+    pub fn into_map(self) -> PrefixTreeMap<String, V> {
+        self.map
+    }
+}
+
+impl<V> From<PrefixTreeMap<String, V>> for StrPrefixTreeMap<V> {
+    fn from(map: PrefixTreeMap<String, V>) -> Self {
+        StrPrefixTreeMap { map }
+    }
+}
+
+impl<S: Into<String>, V> FromIterator<(S, V)> for StrPrefixTreeMap<V> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (S, V)>
+    {
+        let mut map = StrPrefixTreeMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<S: Into<String>, V> Extend<(S, V)> for StrPrefixTreeMap<V> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (S, V)>
+    {
+        self.map.extend(iter.into_iter().map(|(key, value)| (key.into(), value)));
+    }
+}
+
+impl<V> IntoIterator for StrPrefixTreeMap<V> {
+    type Item = (String, V);
+    type IntoIter = map::IntoIter<String, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_iter()
+    }
+}
+
+impl<V: Debug> Debug for StrPrefixTreeMap<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// A set of strings. See the [module-level documentation](self).
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StrPrefixTreeSet {
+    map: StrPrefixTreeMap<()>,
+}
+
+impl StrPrefixTreeSet {
+    /// Creates an empty set. The same as `Default`.
+    pub const fn new() -> Self {
+        StrPrefixTreeSet { map: StrPrefixTreeMap::new() }
+    }
+
+    /// Returns the number of items in the set.
+    pub const fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if and only if the set contains no items.
+    pub const fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all items.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Inserts the item if it did not exist.
+    ///
+    /// Returns `true` if an insertion happened, and `false` if the item already existed.
+    pub fn insert(&mut self, item: String) -> bool {
+        self.map.insert(item, ()).is_none()
+    }
+
+    /// Returns `true` if and only if the given item is found in the set.
+    pub fn contains(&self, item: &str) -> bool {
+        self.map.contains_key(item)
+    }
+
+    /// Removes and returns the owned item, if found.
+    pub fn take(&mut self, item: &str) -> Option<String> {
+        self.map.remove_entry(item).map(|(item, ())| item)
+    }
+
+    /// Removes the item. Returns `true` if it was found.
+    pub fn remove(&mut self, item: &str) -> bool {
+        self.map.remove(item).is_some()
+    }
+
+    /// An iterator over the items, in lexicographic order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator + '_ {
+        self.map.keys()
+    }
+
+    /// An iterator over the items starting with `prefix`, in lexicographic order.
+    pub fn prefix_iter(&self, prefix: &str) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator + '_ {
+        self.map.prefix_iter(prefix).map(|(item, ())| item)
+    }
+
+    /// Returns the number of items starting with `prefix`.
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.map.count_prefix(prefix)
+    }
+
+    /// An iterator over the items that are prefixes of `query` (including
+    /// `query` itself), in increasing order of length.
+    pub fn prefixes_of<'a>(&'a self, query: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.map.prefixes_of(query).map(|(item, ())| item)
+    }
+
+    /// Returns the longest item that is a prefix of `query` (including
+    /// `query` itself), if any.
+    pub fn longest_prefix_of(&self, query: &str) -> Option<&str> {
+        self.map.longest_prefix_of(query).map(|(item, ())| item)
+    }
+
+    /// Returns the longest string that all items start with.
+    /// For an empty set, this is the empty string.
+    pub fn longest_common_prefix(&self) -> String {
+        self.map.longest_common_prefix()
+    }
+
+    /// Returns the longest string that all items starting with `prefix`
+    /// start with, or `None` if there are no such items.
+    pub fn longest_common_prefix_under(&self, prefix: &str) -> Option<String> {
+        self.map.longest_common_prefix_under(prefix)
+    }
+
+    /// Splits `text` into tokens by greedy longest-prefix matching, yielding
+    /// the range of the text matched by each token and the matching item.
+    /// See [`PrefixTreeMap::segment_iter`] for details.
+    pub fn segment_iter<'a>(&'a self, text: &'a str, unmatched: Unmatched) -> impl Iterator<Item = (Range<usize>, &'a str)> + 'a {
+        self.map.segment_iter(text, unmatched).map(|(range, item, ())| (range, item))
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for StrPrefixTreeSet {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = S>
+    {
+        let mut set = StrPrefixTreeSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<S: Into<String>> Extend<S> for StrPrefixTreeSet {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = S>
+    {
+        self.map.extend(iter.into_iter().map(|item| (item, ())));
+    }
+}
+
+impl IntoIterator for StrPrefixTreeSet {
+    type Item = String;
+    type IntoIter = map::IntoKeys<String, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_map().into_keys()
+    }
+}
+
+impl Debug for StrPrefixTreeSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Converts a prefix of a string to a string, dropping the incomplete code
+/// point at its end, if any.
+fn to_char_boundary(mut bytes: Vec<u8>) -> String {
+    let valid_up_to = match core::str::from_utf8(&bytes) {
+        Ok(_) => bytes.len(),
+        Err(error) => error.valid_up_to(),
+    };
+
+    bytes.truncate(valid_up_to);
+    String::from_utf8(bytes).expect("prefix of a string truncated to a char boundary")
+}